@@ -0,0 +1,8 @@
+#!/usr/bin/env rusti
+// rusti: -L .
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    println!("{}", args.join(" "));
+    std::process::exit(3);
+}
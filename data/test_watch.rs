@@ -0,0 +1,4 @@
+// Define a function called each time the file changes
+fn watched() {
+    println!("watched");
+}
@@ -19,13 +19,14 @@ use llvm;
 use rustc::hir::def_id::LOCAL_CRATE;
 use rustc::middle::exported_symbols::SymbolExportLevel;
 use rustc::session::config::{self, Lto};
+use rustc::session::Session;
 use rustc::util::common::time;
 use time_graph::Timeline;
 use {ModuleTranslation, ModuleLlvm, ModuleKind, ModuleSource};
 
 use libc;
 
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::ptr;
 use std::slice;
 use std::sync::Arc;
@@ -76,7 +77,7 @@ impl LtoModuleTranslation {
                 let config = cgcx.config(trans.kind);
                 let llmod = trans.llvm().unwrap().llmod;
                 let tm = trans.llvm().unwrap().tm;
-                run_pass_manager(cgcx, tm, llmod, config, false);
+                run_pass_manager(cgcx.time_passes, tm, llmod, config, false);
                 timeline.record("fat-done");
                 Ok(trans)
             }
@@ -290,6 +291,150 @@ fn fat_lto(cgcx: &CodegenContext,
     }])
 }
 
+/// Links modules which were translated in memory into the costliest of them,
+/// which is returned. As for fat LTO, the other modules are serialized to
+/// bitcode and parsed into its context.
+pub fn link_in_memory(sess: &Session, mut modules: Vec<ModuleLlvm>) -> ModuleLlvm {
+    let (_, costliest_module) = modules.iter()
+        .enumerate()
+        .map(|(i, module)| (unsafe { llvm::LLVMRustModuleCost(module.llmod) }, i))
+        .max()
+        .expect("must be trans'ing at least one module");
+    let module = modules.remove(costliest_module);
+
+    {
+        let mut linker = Linker::new(module.llmod);
+
+        for other in modules {
+            let buffer = ModuleBuffer::new(other.llmod);
+
+            if linker.add(buffer.data()).is_err() {
+                sess.fatal(&format!("failed to link in-memory module: {}",
+                                    llvm::last_error().unwrap_or_default()));
+            }
+        }
+    }
+
+    module
+}
+
+/// Optimizes modules which were translated in memory with ThinLTO, as for
+/// `-C lto=thin`, so that functions of one module may be inlined into the
+/// others before they are linked together by `link_in_memory`.
+///
+/// Every symbol which a module defines is preserved, as the execution engine
+/// may look up any of them.
+pub fn thin_lto_in_memory(sess: &Session, modules: Vec<ModuleLlvm>) -> Vec<ModuleLlvm> {
+    unsafe {
+        let mut thin_buffers = Vec::with_capacity(modules.len());
+        let mut module_names = Vec::with_capacity(modules.len());
+        let mut thin_modules = Vec::with_capacity(modules.len());
+        let mut symbols = Vec::new();
+
+        for (i, module) in modules.iter().enumerate() {
+            let name = CString::new(format!("in-memory.{}", i)).unwrap();
+            let buffer = ThinBuffer::new(module.llmod);
+            thin_modules.push(llvm::ThinLTOModule {
+                identifier: name.as_ptr(),
+                data: buffer.data().as_ptr(),
+                len: buffer.data().len(),
+            });
+            thin_buffers.push(buffer);
+            module_names.push(name);
+            symbols.extend(defined_symbols(module.llmod));
+        }
+
+        let symbol_white_list = symbols.iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+
+        let data = llvm::LLVMRustCreateThinLTOData(
+            thin_modules.as_ptr(),
+            thin_modules.len() as u32,
+            symbol_white_list.as_ptr(),
+            symbol_white_list.len() as u32,
+        );
+        if data.is_null() {
+            sess.fatal("failed to prepare thin LTO context");
+        }
+        let data = ThinData(data);
+
+        // The modules serialized above are parsed anew, each in a context of
+        // its own, and the modules they were serialized from are dropped
+        drop(modules);
+
+        let config = write::in_memory_config(sess);
+
+        module_names.iter().zip(&thin_buffers).map(|(name, buffer)| {
+            let llcx = llvm::LLVMRustContextCreate(sess.fewer_names());
+            let llmod = llvm::LLVMRustParseBitcodeForThinLTO(
+                llcx,
+                buffer.data().as_ptr(),
+                buffer.data().len(),
+                name.as_ptr(),
+            );
+            if llmod.is_null() {
+                sess.fatal(&format!("failed to parse in-memory module for thin LTO: {}",
+                                    llvm::last_error().unwrap_or_default()));
+            }
+            let module = ModuleLlvm {
+                llmod,
+                llcx,
+                tm: write::create_target_machine(sess),
+            };
+
+            if !llvm::LLVMRustPrepareThinLTORename(data.0, llmod) ||
+                    !llvm::LLVMRustPrepareThinLTOResolveWeak(data.0, llmod) ||
+                    !llvm::LLVMRustPrepareThinLTOInternalize(data.0, llmod) ||
+                    !llvm::LLVMRustPrepareThinLTOImport(data.0, llmod) {
+                sess.fatal("failed to prepare thin LTO module");
+            }
+
+            // As in `ThinModule::optimize`, merge the `DICompileUnit`s which
+            // importing may have brought into the module
+            let mut cu1 = ptr::null_mut();
+            let mut cu2 = ptr::null_mut();
+            llvm::LLVMRustThinLTOGetDICompileUnit(llmod, &mut cu1, &mut cu2);
+            if !cu2.is_null() {
+                sess.fatal("multiple source DICompileUnits found");
+            }
+            llvm::LLVMRustThinLTOPatchDICompileUnit(llmod, cu1);
+
+            run_pass_manager(sess.time_passes(), module.tm, llmod, &config, true);
+
+            // Functions imported from other modules are defined by those
+            // modules, with which this one is about to be linked
+            llvm::LLVMRustThinLTORemoveAvailableExternally(llmod);
+
+            module
+        }).collect()
+    }
+}
+
+/// Returns the names of the symbols of external linkage defined by a module.
+unsafe fn defined_symbols(llmod: ModuleRef) -> Vec<CString> {
+    unsafe fn push_defined(symbols: &mut Vec<CString>, v: llvm::ValueRef) {
+        if llvm::LLVMIsDeclaration(v) == False &&
+                llvm::LLVMRustGetLinkage(v) == llvm::Linkage::ExternalLinkage {
+            symbols.push(CStr::from_ptr(llvm::LLVMGetValueName(v)).to_owned());
+        }
+    }
+
+    let mut symbols = Vec::new();
+
+    let mut f = llvm::LLVMGetFirstFunction(llmod);
+    while !f.is_null() {
+        push_defined(&mut symbols, f);
+        f = llvm::LLVMGetNextFunction(f);
+    }
+
+    let mut g = llvm::LLVMGetFirstGlobal(llmod);
+    while !g.is_null() {
+        push_defined(&mut symbols, g);
+        g = llvm::LLVMGetNextGlobal(g);
+    }
+
+    symbols
+}
+
 struct Linker(llvm::LinkerRef);
 
 impl Linker {
@@ -447,7 +592,7 @@ fn thin_lto(diag_handler: &Handler,
     }
 }
 
-fn run_pass_manager(cgcx: &CodegenContext,
+fn run_pass_manager(time_passes: bool,
                     tm: TargetMachineRef,
                     llmod: ModuleRef,
                     config: &ModuleConfig,
@@ -498,7 +643,7 @@ fn run_pass_manager(cgcx: &CodegenContext,
         assert!(!pass.is_null());
         llvm::LLVMRustAddPass(pm, pass);
 
-        time(cgcx.time_passes, "LTO passes", ||
+        time(time_passes, "LTO passes", ||
              llvm::LLVMRunPassManager(pm, llmod));
 
         llvm::LLVMDisposePassManager(pm);
@@ -750,7 +895,7 @@ impl ThinModule {
         // little differently.
         info!("running thin lto passes over {}", mtrans.name);
         let config = cgcx.config(mtrans.kind);
-        run_pass_manager(cgcx, tm, llmod, config, true);
+        run_pass_manager(cgcx.time_passes, tm, llmod, config, true);
         cgcx.save_temp_bitcode(&mtrans, "thin-lto-after-pm");
         timeline.record("thin-done");
 
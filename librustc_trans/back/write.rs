@@ -28,7 +28,7 @@ use time_graph::{self, TimeGraph, Timeline};
 use llvm;
 use llvm::{ModuleRef, TargetMachineRef, PassManagerRef, DiagnosticInfoRef};
 use llvm::{SMDiagnosticRef, ContextRef};
-use {CrateTranslation, ModuleSource, ModuleTranslation, CompiledModule, ModuleKind};
+use {CrateTranslation, ModuleLlvm, ModuleSource, ModuleTranslation, CompiledModule, ModuleKind};
 use CrateInfo;
 use rustc::hir::def_id::{CrateNum, LOCAL_CRATE};
 use rustc::ty::TyCtxt;
@@ -2016,6 +2016,78 @@ pub fn run_assembler(cgcx: &CodegenContext, handler: &Handler, assembly: &Path,
     }
 }
 
+/// Runs the optimization passes selected by the session's `-C opt-level`
+/// over a module which was translated in memory, mirroring `optimize` for
+/// modules which are emitted as object files.
+pub fn optimize_in_memory(sess: &Session, module: &ModuleLlvm) {
+    let config = in_memory_config(sess);
+
+    unsafe { optimize_module_in_memory(&config, module) }
+}
+
+/// Optimizes several modules which were translated in memory, as
+/// `optimize_in_memory` does, each as a job on a thread of its own.
+///
+/// Jobs start as tokens of the jobserver inherited from the environment
+/// become available or, without one, as many run at once as there are CPUs.
+pub fn optimize_in_memory_parallel(sess: &Session, modules: Vec<ModuleLlvm>)
+                                   -> Vec<ModuleLlvm> {
+    let config = Arc::new(in_memory_config(sess));
+    let client = sess.jobserver_from_env.clone().unwrap_or_else(|| {
+        Client::new(::num_cpus::get()).expect("failed to create jobserver")
+    });
+
+    let mut jobs = Vec::with_capacity(modules.len());
+
+    for module in modules {
+        let token = match client.acquire() {
+            Ok(token) => token,
+            Err(e) => sess.fatal(&format!("failed to acquire jobserver token: {}", e)),
+        };
+        let config = config.clone();
+
+        jobs.push(thread::spawn(move || {
+            unsafe { optimize_module_in_memory(&config, &module) };
+            drop(token);
+            module
+        }));
+    }
+
+    jobs.into_iter().map(|job| {
+        job.join().unwrap_or_else(|e| ::std::panic::resume_unwind(e))
+    }).collect()
+}
+
+pub fn in_memory_config(sess: &Session) -> ModuleConfig {
+    let mut config = ModuleConfig::new(sess.opts.cg.passes.clone());
+    config.opt_level = Some(get_llvm_opt_level(sess.opts.optimize));
+    config.opt_size = Some(get_llvm_opt_size(sess.opts.optimize));
+    config.set_flags(sess, false);
+    config
+}
+
+unsafe fn optimize_module_in_memory(config: &ModuleConfig, module: &ModuleLlvm) {
+    let opt_level = config.opt_level.unwrap();
+    let llmod = module.llmod;
+    let fpm = llvm::LLVMCreateFunctionPassManagerForModule(llmod);
+    let mpm = llvm::LLVMCreatePassManager();
+
+    llvm::LLVMRustAddAnalysisPasses(module.tm, fpm, llmod);
+    llvm::LLVMRustAddAnalysisPasses(module.tm, mpm, llmod);
+    with_llvm_pmb(llmod, config, opt_level, &mut |b| {
+        llvm::LLVMPassManagerBuilderPopulateFunctionPassManager(b, fpm);
+        llvm::LLVMPassManagerBuilderPopulateModulePassManager(b, mpm);
+    });
+
+    time(config.time_passes, "llvm function passes [in-memory]", ||
+         llvm::LLVMRustRunFunctionPassManager(fpm, llmod));
+    time(config.time_passes, "llvm module passes [in-memory]", ||
+         llvm::LLVMRunPassManager(mpm, llmod));
+
+    llvm::LLVMDisposePassManager(fpm);
+    llvm::LLVMDisposePassManager(mpm);
+}
+
 pub unsafe fn with_llvm_pmb(llmod: ModuleRef,
                             config: &ModuleConfig,
                             opt_level: llvm::CodeGenOptLevel,
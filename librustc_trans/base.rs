@@ -30,12 +30,14 @@ use super::ModuleKind;
 
 use abi;
 use back::link;
+use back::lto;
 use back::symbol_export;
 use back::write::{self, OngoingCrateTranslation, create_target_machine};
 use llvm::{ContextRef, ModuleRef, ValueRef, Vector, get_param};
 use llvm;
 use metadata;
 use rustc::hir::def_id::{CrateNum, DefId, LOCAL_CRATE};
+use rustc::middle::allocator::AllocatorKind;
 use rustc::middle::lang_items::StartFnLangItem;
 use rustc::mir::mono::{Linkage, Visibility, Stats};
 use rustc::middle::cstore::{EncodedMetadata};
@@ -46,7 +48,7 @@ use rustc::dep_graph::{DepNode, DepConstructor};
 use rustc::ty::subst::Kind;
 use rustc::middle::cstore::{self, LinkMeta, LinkagePreference};
 use rustc::util::common::{time, print_time_passes_entry};
-use rustc::session::config::{self, NoDebugInfo};
+use rustc::session::config::{self, Lto, NoDebugInfo};
 use rustc::session::Session;
 use rustc_incremental;
 use allocator;
@@ -934,6 +936,90 @@ pub fn trans_crate<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
     ongoing_translation
 }
 
+/// Translates the local crate into an LLVM module which is kept in memory,
+/// rather than being optimized, written out as object files and linked.
+///
+/// This is used by consumers which hand the resulting module directly to an
+/// LLVM execution engine. No metadata module is produced and nothing is
+/// written to the output directory. Modules are optimized according to the
+/// session's `-C opt-level`, in parallel if there are several codegen units,
+/// with ThinLTO across them if the session's `lto()` is thin, and then linked
+/// together into one. The allocator shim is included only for a crate which
+/// defines its own global allocator.
+pub fn trans_crate_in_memory<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>)
+                                       -> ModuleLlvm {
+    check_for_rustc_errors_attr(tcx);
+
+    let codegen_units =
+        tcx.collect_and_partition_translation_items(LOCAL_CRATE).1;
+
+    let mut modules = Vec::with_capacity(codegen_units.len() + 1);
+
+    // Unless the crate defines a global allocator, no allocator shim is
+    // translated; calls to `__rust_alloc` and friends are resolved by the
+    // execution engine instead, against the loaded standard library or any
+    // functions it maps them to
+    if let Some(kind @ AllocatorKind::Global) = tcx.sess.allocator_kind.get() {
+        unsafe {
+            let (llcx, llmod) =
+                context::create_context_and_module(tcx.sess, "allocator");
+            let module = ModuleLlvm {
+                llmod,
+                llcx,
+                tm: create_target_machine(tcx.sess),
+            };
+            allocator::trans(tcx, &module, kind);
+            modules.push(module);
+        }
+    }
+
+    for cgu in codegen_units.iter() {
+        let (_, module) = time(tcx.sess.time_passes(),
+                               &format!("codegen {}", cgu.name()),
+                               || module_translation(tcx, cgu.clone()));
+
+        match module.source {
+            ModuleSource::Translated(llvm) => modules.push(llvm),
+            ModuleSource::Preexisting(_) =>
+                bug!("in-memory translation produced a preexisting module"),
+        }
+    }
+
+    tcx.sess.abort_if_errors();
+
+    if tcx.sess.opts.optimize != config::OptLevel::No {
+        if modules.len() > 1 {
+            modules = time(tcx.sess.time_passes(), "optimize in parallel", ||
+                           write::optimize_in_memory_parallel(tcx.sess, modules));
+        } else {
+            for module in &modules {
+                write::optimize_in_memory(tcx.sess, module);
+            }
+        }
+    }
+
+    // As when codegen units are written out, ThinLTO lets functions of one
+    // unit be inlined into the others, which optimizing them apart cannot
+    if modules.len() > 1 {
+        match tcx.sess.lto() {
+            Lto::Thin | Lto::ThinLocal => {
+                modules = time(tcx.sess.time_passes(), "thin lto in-memory modules", ||
+                               lto::thin_lto_in_memory(tcx.sess, modules));
+            }
+            Lto::No | Lto::Yes | Lto::Fat => {}
+        }
+    }
+
+    // The allocator shim, if any, and codegen units which were split to be
+    // optimized in parallel are handed to the execution engine as a single module
+    if modules.len() > 1 {
+        time(tcx.sess.time_passes(), "link in-memory modules", ||
+             lto::link_in_memory(tcx.sess, modules))
+    } else {
+        modules.pop().expect("in-memory translation produced no module")
+    }
+}
+
 fn assert_and_save_dep_graph<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>) {
     time(tcx.sess.time_passes(),
          "assert dep graph",
@@ -1130,93 +1216,93 @@ fn compile_codegen_unit<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
     write::submit_translated_module_to_llvm(tcx,
                                             module,
                                             cost);
-    return stats;
-
-    fn module_translation<'a, 'tcx>(
-        tcx: TyCtxt<'a, 'tcx, 'tcx>,
-        cgu: Arc<CodegenUnit<'tcx>>)
-        -> (Stats, ModuleTranslation)
-    {
-        let cgu_name = cgu.name().to_string();
-
-        // Append ".rs" to LLVM module identifier.
-        //
-        // LLVM code generator emits a ".file filename" directive
-        // for ELF backends. Value of the "filename" is set as the
-        // LLVM module identifier.  Due to a LLVM MC bug[1], LLVM
-        // crashes if the module identifier is same as other symbols
-        // such as a function name in the module.
-        // 1. http://llvm.org/bugs/show_bug.cgi?id=11479
-        let llmod_id = format!("{}-{}.rs",
-                               cgu.name(),
-                               tcx.crate_disambiguator(LOCAL_CRATE)
-                                   .to_fingerprint().to_hex());
-
-        // Instantiate translation items without filling out definitions yet...
-        let cx = CodegenCx::new(tcx, cgu, &llmod_id);
-        let module = {
-            let trans_items = cx.codegen_unit
-                                 .items_in_deterministic_order(cx.tcx);
-            for &(trans_item, (linkage, visibility)) in &trans_items {
-                trans_item.predefine(&cx, linkage, visibility);
-            }
+    stats
+}
 
-            // ... and now that we have everything pre-defined, fill out those definitions.
-            for &(trans_item, _) in &trans_items {
-                trans_item.define(&cx);
-            }
+fn module_translation<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    cgu: Arc<CodegenUnit<'tcx>>)
+    -> (Stats, ModuleTranslation)
+{
+    let cgu_name = cgu.name().to_string();
 
-            // If this codegen unit contains the main function, also create the
-            // wrapper here
-            maybe_create_entry_wrapper(&cx);
+    // Append ".rs" to LLVM module identifier.
+    //
+    // LLVM code generator emits a ".file filename" directive
+    // for ELF backends. Value of the "filename" is set as the
+    // LLVM module identifier.  Due to a LLVM MC bug[1], LLVM
+    // crashes if the module identifier is same as other symbols
+    // such as a function name in the module.
+    // 1. http://llvm.org/bugs/show_bug.cgi?id=11479
+    let llmod_id = format!("{}-{}.rs",
+                           cgu.name(),
+                           tcx.crate_disambiguator(LOCAL_CRATE)
+                               .to_fingerprint().to_hex());
+
+    // Instantiate translation items without filling out definitions yet...
+    let cx = CodegenCx::new(tcx, cgu, &llmod_id);
+    let module = {
+        let trans_items = cx.codegen_unit
+                             .items_in_deterministic_order(cx.tcx);
+        for &(trans_item, (linkage, visibility)) in &trans_items {
+            trans_item.predefine(&cx, linkage, visibility);
+        }
 
-            // Run replace-all-uses-with for statics that need it
-            for &(old_g, new_g) in cx.statics_to_rauw.borrow().iter() {
-                unsafe {
-                    let bitcast = llvm::LLVMConstPointerCast(new_g, llvm::LLVMTypeOf(old_g));
-                    llvm::LLVMReplaceAllUsesWith(old_g, bitcast);
-                    llvm::LLVMDeleteGlobal(old_g);
-                }
-            }
+        // ... and now that we have everything pre-defined, fill out those definitions.
+        for &(trans_item, _) in &trans_items {
+            trans_item.define(&cx);
+        }
 
-            // Create the llvm.used variable
-            // This variable has type [N x i8*] and is stored in the llvm.metadata section
-            if !cx.used_statics.borrow().is_empty() {
-                let name = CString::new("llvm.used").unwrap();
-                let section = CString::new("llvm.metadata").unwrap();
-                let array = C_array(Type::i8(&cx).ptr_to(), &*cx.used_statics.borrow());
-
-                unsafe {
-                    let g = llvm::LLVMAddGlobal(cx.llmod,
-                                                val_ty(array).to_ref(),
-                                                name.as_ptr());
-                    llvm::LLVMSetInitializer(g, array);
-                    llvm::LLVMRustSetLinkage(g, llvm::Linkage::AppendingLinkage);
-                    llvm::LLVMSetSection(g, section.as_ptr());
-                }
+        // If this codegen unit contains the main function, also create the
+        // wrapper here
+        maybe_create_entry_wrapper(&cx);
+
+        // Run replace-all-uses-with for statics that need it
+        for &(old_g, new_g) in cx.statics_to_rauw.borrow().iter() {
+            unsafe {
+                let bitcast = llvm::LLVMConstPointerCast(new_g, llvm::LLVMTypeOf(old_g));
+                llvm::LLVMReplaceAllUsesWith(old_g, bitcast);
+                llvm::LLVMDeleteGlobal(old_g);
             }
+        }
 
-            // Finalize debuginfo
-            if cx.sess().opts.debuginfo != NoDebugInfo {
-                debuginfo::finalize(&cx);
+        // Create the llvm.used variable
+        // This variable has type [N x i8*] and is stored in the llvm.metadata section
+        if !cx.used_statics.borrow().is_empty() {
+            let name = CString::new("llvm.used").unwrap();
+            let section = CString::new("llvm.metadata").unwrap();
+            let array = C_array(Type::i8(&cx).ptr_to(), &*cx.used_statics.borrow());
+
+            unsafe {
+                let g = llvm::LLVMAddGlobal(cx.llmod,
+                                            val_ty(array).to_ref(),
+                                            name.as_ptr());
+                llvm::LLVMSetInitializer(g, array);
+                llvm::LLVMRustSetLinkage(g, llvm::Linkage::AppendingLinkage);
+                llvm::LLVMSetSection(g, section.as_ptr());
             }
+        }
 
-            let llvm_module = ModuleLlvm {
-                llcx: cx.llcx,
-                llmod: cx.llmod,
-                tm: create_target_machine(cx.sess()),
-            };
+        // Finalize debuginfo
+        if cx.sess().opts.debuginfo != NoDebugInfo {
+            debuginfo::finalize(&cx);
+        }
 
-            ModuleTranslation {
-                name: cgu_name,
-                source: ModuleSource::Translated(llvm_module),
-                kind: ModuleKind::Regular,
-                llmod_id,
-            }
+        let llvm_module = ModuleLlvm {
+            llcx: cx.llcx,
+            llmod: cx.llmod,
+            tm: create_target_machine(cx.sess()),
         };
 
-        (cx.into_stats(), module)
-    }
+        ModuleTranslation {
+            name: cgu_name,
+            source: ModuleSource::Translated(llvm_module),
+            kind: ModuleKind::Regular,
+            llmod_id,
+        }
+    };
+
+    (cx.into_stats(), module)
 }
 
 pub fn provide(providers: &mut Providers) {
@@ -70,9 +70,11 @@ extern crate tempdir;
 
 use back::bytecode::RLIB_BYTECODE_EXTENSION;
 
+pub use base::trans_crate_in_memory;
 pub use llvm_util::target_features;
 
 use std::any::Any;
+use std::mem;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::mpsc;
@@ -97,7 +99,7 @@ mod back {
     mod command;
     pub mod linker;
     pub mod link;
-    mod lto;
+    pub mod lto;
     pub mod symbol_export;
     pub mod write;
     mod rpath;
@@ -196,8 +198,7 @@ impl TransCrate for LlvmTransCrate {
     }
 
     fn diagnostics(&self) -> &[(&'static str, &'static str)] {
-        //&DIAGNOSTICS
-        &[]
+        &DIAGNOSTICS
     }
 
     fn target_features(&self, sess: &Session) -> Vec<Symbol> {
@@ -367,6 +368,18 @@ pub struct ModuleLlvm {
 unsafe impl Send for ModuleLlvm { }
 unsafe impl Sync for ModuleLlvm { }
 
+impl ModuleLlvm {
+    /// Releases ownership of the LLVM context and module, disposing only of
+    /// the target machine. The caller becomes responsible for the module and
+    /// its context; e.g. by handing the module to an execution engine.
+    pub fn into_raw(self) -> (llvm::ContextRef, llvm::ModuleRef) {
+        let raw = (self.llcx, self.llmod);
+        unsafe { llvm::LLVMRustDisposeTargetMachine(self.tm) };
+        mem::forget(self);
+        raw
+    }
+}
+
 impl Drop for ModuleLlvm {
     fn drop(&mut self) {
         unsafe {
@@ -405,4 +418,4 @@ struct CrateInfo {
     used_crates_dynamic: Vec<(CrateNum, LibSource)>,
 }
 
-//__build_diagnostic_array! { librustc_trans, DIAGNOSTICS }
+__build_diagnostic_array! { librustc_trans, DIAGNOSTICS }
@@ -0,0 +1,100 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Caching of the results of analysis, so that queries made while the
+//! session is unchanged, such as `.impls`, `.methods` and `.layout` of one
+//! type, or repeated requests for types of code, compile it only once.
+//!
+//! The type context itself cannot outlive the compiler's thread, so what
+//! is cached are the answers extracted from it. Results are kept only for
+//! one state of the session, and forgotten as soon as it changes.
+
+use std::collections::HashMap;
+
+use exec::TypeInfo;
+
+/// Results of analysis of programs built from one state of the session
+#[derive(Debug, Default)]
+pub struct AnalysisCache {
+    /// State of the session to which the results belong
+    state: String,
+    /// Layout, impls and methods of types, by type
+    types: HashMap<String, TypeInfo>,
+    /// Types of expressions, by code and offset within it
+    types_at: HashMap<(String, usize), Option<String>>,
+}
+
+impl AnalysisCache {
+    /// Forgets all results, unless they belong to the given state of the session.
+    pub fn update(&mut self, state: &str) {
+        if self.state != state {
+            self.state = state.to_owned();
+            self.types.clear();
+            self.types_at.clear();
+        }
+    }
+
+    /// Returns the layout, impls and methods of a type, computed by `f`
+    /// unless they are cached.
+    ///
+    /// Failures are not cached, so that errors are reported on each query.
+    pub fn type_info<F>(&mut self, ty: &str, f: F) -> Option<TypeInfo>
+            where F: FnOnce() -> Option<TypeInfo> {
+        if let Some(info) = self.types.get(ty) {
+            return Some(info.clone());
+        }
+
+        let info = f();
+
+        if let Some(ref info) = info {
+            self.types.insert(ty.to_owned(), info.clone());
+        }
+
+        info
+    }
+
+    /// Returns the type of the expression at offset `pos` of `code`,
+    /// computed by `f` unless it is cached.
+    pub fn type_at<F>(&mut self, code: &str, pos: usize, f: F) -> Option<String>
+            where F: FnOnce() -> Option<String> {
+        self.types_at.entry((code.to_owned(), pos)).or_insert_with(f).clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use exec::TypeInfo;
+    use layout::TypeLayout;
+
+    use super::AnalysisCache;
+
+    #[test]
+    fn test_cache() {
+        let info = TypeInfo{
+            layout: Ok(TypeLayout::default()),
+            impls: Vec::new(),
+            methods: Vec::new(),
+        };
+
+        let mut cache = AnalysisCache::default();
+        cache.update("a");
+
+        assert_eq!(cache.type_info("u8", || None), None);
+        assert_eq!(cache.type_info("u8", || Some(info.clone())), Some(info.clone()));
+        assert_eq!(cache.type_info("u8", || panic!("analyzed twice")), Some(info.clone()));
+        assert_eq!(cache.type_at("x", 0, || Some("i32".to_owned())), Some("i32".to_owned()));
+        assert_eq!(cache.type_at("x", 0, || panic!("analyzed twice")), Some("i32".to_owned()));
+
+        cache.update("a");
+        assert_eq!(cache.type_info("u8", || None), Some(info.clone()));
+
+        cache.update("b");
+        assert_eq!(cache.type_info("u8", || None), None);
+        assert_eq!(cache.type_at("x", 0, || None), None);
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Files written while running input, such as executables.
+//!
+//! Artifacts are created in the temporary directory with names beginning with
+//! `rusti-`, as are the intermediate files written by the compiler alongside
+//! them. Each is removed once it is no longer used, unless it is kept for
+//! debugging. Sessions created by `.session new` write artifacts in
+//! a directory of their own, also within the temporary directory.
+//! Artifacts of a session which ended abruptly are left behind; `collect`
+//! removes those which have not been modified for some time.
+//!
+//! Each artifact is created with a name of its own, so sessions in separate
+//! processes, even in the same directory, never write to the same file.
+//! Input itself is compiled in memory, and writes no file at all.
+//!
+//! `RUSTI_TARGET_DIR`, or the `target-dir` key of the configuration, names a
+//! directory used in place of the temporary directory, in which dependencies
+//! and other builds reused across sessions are also cached.
+
+use std::env::{home_dir, temp_dir, var_os};
+use std::fs::{create_dir, create_dir_all, read_dir, remove_dir, remove_file};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tempfile::{NamedTempFile, NamedTempFileOptions};
+
+/// Prefix of the name of each artifact
+pub const PREFIX: &'static str = "rusti-";
+
+/// Age, in hours, beyond which unused artifacts are removed by default
+pub const DEFAULT_MAX_AGE_HOURS: u64 = 24;
+
+/// Environment variable naming the directory in which artifacts are written
+pub const TARGET_DIR_VAR: &'static str = "RUSTI_TARGET_DIR";
+
+/// A file written while running input
+pub struct Artifact {
+    /// Removes the file when dropped; `None` if the file is kept
+    file: Option<NamedTempFile>,
+    path: PathBuf,
+}
+
+impl Artifact {
+    /// Creates an empty artifact in the temporary directory.
+    /// If `keep` is `true`, the file is not removed when the artifact is dropped.
+    pub fn create(keep: bool) -> io::Result<Artifact> {
+        Artifact::create_in(&temp_dir(), keep)
+    }
+
+    /// Creates an empty artifact in the given directory, as `create` does.
+    pub fn create_in(dir: &Path, keep: bool) -> io::Result<Artifact> {
+        // The directory of a session may have been removed by `collect`
+        create_dir_all(dir)?;
+
+        let file = NamedTempFileOptions::new().prefix(PREFIX).create_in(dir)?;
+        let path = file.path().to_path_buf();
+
+        let file = if keep {
+            file.persist(&path).map_err(|e| e.error)?;
+            None
+        } else {
+            Some(file)
+        };
+
+        Ok(Artifact{
+            file: file,
+            path: path,
+        })
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the file is kept after the artifact is dropped.
+    pub fn is_kept(&self) -> bool {
+        self.file.is_none()
+    }
+}
+
+/// Returns the directory in which files reused across sessions are cached,
+/// `$XDG_CACHE_HOME/rusti`, or `~/.cache/rusti` if `XDG_CACHE_HOME` is not set.
+pub fn cache_dir() -> Option<PathBuf> {
+    let dir = match var_os("XDG_CACHE_HOME") {
+        Some(ref d) if !d.is_empty() => PathBuf::from(d),
+        _ => home_dir()?.join(".cache"),
+    };
+
+    Some(dir.join("rusti"))
+}
+
+/// Returns the directory named by `RUSTI_TARGET_DIR`, if it is set.
+pub fn env_target_dir() -> Option<PathBuf> {
+    match var_os(TARGET_DIR_VAR) {
+        Some(ref d) if !d.is_empty() => Some(PathBuf::from(d)),
+        _ => None,
+    }
+}
+
+/// Returns the directory in which artifacts are written: `target_dir`,
+/// if one is given, or else the temporary directory.
+pub fn artifact_dir(target_dir: Option<&PathBuf>) -> PathBuf {
+    match target_dir {
+        Some(dir) => dir.clone(),
+        None => temp_dir(),
+    }
+}
+
+/// Returns the directory in which builds reused across sessions, such as
+/// those of dependencies, are cached: `build` within `target_dir`, if one is
+/// given, or else `cache_dir`.
+pub fn build_dir(target_dir: Option<&PathBuf>) -> Option<PathBuf> {
+    match target_dir {
+        Some(dir) => Some(dir.join("build")),
+        None => cache_dir(),
+    }
+}
+
+/// Creates a directory, within the directory of artifacts, in which the
+/// named session writes artifacts.
+pub fn session_dir(name: &str, target_dir: Option<&PathBuf>) -> io::Result<PathBuf> {
+    let name = name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect::<String>();
+
+    let root = artifact_dir(target_dir);
+    create_dir_all(&root)?;
+
+    // Other processes may have sessions of the same name
+    for n in 0.. {
+        let dir = root.join(format!("{}session-{}-{}", PREFIX, name, n));
+
+        match create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    unreachable!()
+}
+
+/// Files removed by `collect`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Collected {
+    /// Number of files removed
+    pub files: usize,
+    /// Total size of the files removed, in bytes
+    pub bytes: u64,
+}
+
+/// Removes artifacts from the directory of artifacts which have not been
+/// modified within `max_age`.
+pub fn collect(max_age: Duration, target_dir: Option<&PathBuf>) -> io::Result<Collected> {
+    let dir = artifact_dir(target_dir);
+
+    if !dir.is_dir() {
+        return Ok(Collected::default());
+    }
+
+    collect_in(&dir, max_age, SystemTime::now())
+}
+
+/// Removes artifacts from a directory which were last modified more than
+/// `max_age` before `now`. Files which cannot be removed are skipped.
+fn collect_in(dir: &Path, max_age: Duration, now: SystemTime) -> io::Result<Collected> {
+    let mut res = Collected::default();
+
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+
+        if !entry.file_name().to_string_lossy().starts_with(PREFIX) {
+            continue;
+        }
+
+        let meta = match entry.metadata() {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+
+        // A file modified in the future is as good as new
+        let age = meta.modified().ok()
+            .and_then(|t| now.duration_since(t).ok())
+            .unwrap_or(Duration::from_secs(0));
+
+        // Directories of sessions are removed once they are empty
+        if meta.is_dir() {
+            if let Ok(collected) = collect_in(&entry.path(), max_age, now) {
+                res.files += collected.files;
+                res.bytes += collected.bytes;
+            }
+
+            if age > max_age {
+                let _ = remove_dir(entry.path());
+            }
+            continue;
+        }
+
+        if !meta.is_file() {
+            continue;
+        }
+
+        if age > max_age && remove_file(entry.path()).is_ok() {
+            res.files += 1;
+            res.bytes += meta.len();
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir, remove_dir_all, File};
+    use std::io::Write;
+    use std::time::{Duration, SystemTime};
+
+    use tempfile::NamedTempFile;
+
+    use super::{collect_in, session_dir, Artifact, Collected};
+
+    #[test]
+    fn test_collect() {
+        // A directory of its own, named after a file which no other test uses
+        let file = NamedTempFile::new().unwrap();
+        let dir = file.path().with_extension("d");
+
+        create_dir(&dir).unwrap();
+
+        File::create(dir.join("rusti-abc")).unwrap().write_all(b"1234").unwrap();
+        File::create(dir.join("rusti-abc.repl0.rcgu.o")).unwrap().write_all(b"56").unwrap();
+        File::create(dir.join("other")).unwrap();
+
+        create_dir(dir.join("rusti-session-main-0")).unwrap();
+        File::create(dir.join("rusti-session-main-0").join("rusti-def")).unwrap()
+            .write_all(b"789").unwrap();
+
+        let now = SystemTime::now();
+
+        assert_eq!(collect_in(&dir, Duration::from_secs(3600), now).unwrap(),
+            Collected::default());
+
+        let later = now + Duration::from_secs(7200);
+
+        assert_eq!(collect_in(&dir, Duration::from_secs(3600), later).unwrap(),
+            Collected{files: 3, bytes: 9});
+        assert!(dir.join("other").exists());
+        assert!(!dir.join("rusti-session-main-0").exists());
+
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_keep() {
+        let path = {
+            let artifact = Artifact::create(false).unwrap();
+            assert!(!artifact.is_kept());
+            artifact.path().to_path_buf()
+        };
+
+        assert!(!path.exists());
+
+        let path = {
+            let artifact = Artifact::create(true).unwrap();
+            assert!(artifact.is_kept());
+            artifact.path().to_path_buf()
+        };
+
+        assert!(path.exists());
+        ::std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_session_dir() {
+        let file = NamedTempFile::new().unwrap();
+        let dir = file.path().with_extension("d");
+
+        // Sessions of the same name in two processes
+        let a = session_dir("main", Some(&dir)).unwrap();
+        let b = session_dir("main", Some(&dir)).unwrap();
+
+        assert_eq!(a, dir.join("rusti-session-main-0"));
+        assert_eq!(b, dir.join("rusti-session-main-1"));
+
+        let x = Artifact::create_in(&a, false).unwrap();
+        let y = Artifact::create_in(&a, false).unwrap();
+
+        assert!(x.path() != y.path());
+
+        drop((x, y));
+        remove_dir_all(&dir).unwrap();
+    }
+}
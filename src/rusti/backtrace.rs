@@ -0,0 +1,199 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Backtraces of panics in compiled input.
+//!
+//! While compiled input runs, a panic hook records the return addresses on
+//! the stack of the panicking thread. Addresses within code compiled by the
+//! execution engine are then named after the function containing them, which
+//! is traced back to the input defining it. Addresses within libraries,
+//! including the standard library and rusti itself, are omitted, so that
+//! a backtrace is the same each time input is run.
+
+use std::cell::RefCell;
+use std::panic;
+use std::sync::Arc;
+
+/// Greatest number of frames recorded
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos"))]
+const MAX_FRAMES: usize = 128;
+
+thread_local!(static FRAMES: RefCell<Option<Vec<usize>>> = RefCell::new(None));
+
+/// Calls a function, returning its result and the return addresses on the
+/// stack when the calling thread first panicked, if it did.
+///
+/// The panic hook in place is still called for each panic.
+pub fn with_backtrace<F, R>(f: F) -> (R, Option<Vec<usize>>)
+        where F: FnOnce() -> R {
+    let prev = Arc::new(panic::take_hook());
+    let hook = prev.clone();
+
+    panic::set_hook(Box::new(move |info| {
+        FRAMES.with(|frames| {
+            let mut frames = frames.borrow_mut();
+
+            if frames.is_none() {
+                *frames = Some(capture());
+            }
+        });
+
+        hook(info);
+    }));
+
+    FRAMES.with(|frames| *frames.borrow_mut() = None);
+
+    let res = f();
+
+    // Dropping the hook releases its reference to the previous hook
+    drop(panic::take_hook());
+
+    match Arc::try_unwrap(prev) {
+        Ok(prev) => panic::set_hook(prev),
+        // Another thread is running the hook; it cannot be restored
+        Err(_) => (),
+    }
+
+    (res, FRAMES.with(|frames| frames.borrow_mut().take()))
+}
+
+/// Returns the return addresses on the stack of the calling thread.
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos"))]
+fn capture() -> Vec<usize> {
+    use std::ptr;
+    use libc::{c_int, c_void};
+
+    extern "C" {
+        fn backtrace(buf: *mut *mut c_void, size: c_int) -> c_int;
+    }
+
+    let mut buf = [ptr::null_mut(); MAX_FRAMES];
+    let n = unsafe { backtrace(buf.as_mut_ptr(), MAX_FRAMES as c_int) };
+
+    buf[..n.max(0) as usize].iter().map(|&p| p as usize).collect()
+}
+
+#[cfg(not(any(all(target_os = "linux", target_env = "gnu"), target_os = "macos")))]
+fn capture() -> Vec<usize> {
+    Vec::new()
+}
+
+/// Returns whether an address lies within a library loaded into the process,
+/// rather than code compiled by the execution engine.
+#[cfg(unix)]
+pub fn in_library(addr: usize) -> bool {
+    use std::mem;
+    use libc::{dladdr, Dl_info};
+
+    let mut info: Dl_info = unsafe { mem::zeroed() };
+
+    unsafe { dladdr(addr as *const _, &mut info) != 0 && !info.dli_fname.is_null() }
+}
+
+#[cfg(not(unix))]
+pub fn in_library(_addr: usize) -> bool {
+    false
+}
+
+/// Demangles a symbol name mangled by the compiler, without its trailing hash.
+/// Returns `None` if the name is not a mangled Rust name.
+pub fn demangle(sym: &str) -> Option<String> {
+    // macOS prefixes symbols with an additional underscore
+    let mut rest = sym.trim_left_matches('_');
+
+    if !rest.starts_with("ZN") || !rest.ends_with('E') {
+        return None;
+    }
+
+    rest = &rest[2..rest.len() - 1];
+
+    let mut segments = Vec::new();
+
+    while !rest.is_empty() {
+        let digits = rest.chars().take_while(|c| c.is_digit(10)).count();
+        let len = rest[..digits].parse::<usize>().ok()?;
+        let end = digits + len;
+
+        if end > rest.len() || !rest.is_char_boundary(end) {
+            return None;
+        }
+
+        segments.push(&rest[digits..end]);
+        rest = &rest[end..];
+    }
+
+    // The hash ends the path
+    if segments.last().map_or(false, |s| is_hash(s)) {
+        segments.pop();
+    }
+
+    Some(segments.iter().map(|s| unescape(s)).collect::<Vec<_>>().join("::"))
+}
+
+fn is_hash(segment: &str) -> bool {
+    segment.len() == 17 && segment.starts_with('h') &&
+        segment[1..].chars().all(|c| c.is_digit(16))
+}
+
+/// Replaces escape sequences within a segment of a mangled name.
+fn unescape(segment: &str) -> String {
+    const ESCAPES: &'static [(&'static str, &'static str)] = &[
+        ("$SP$", "@"), ("$BP$", "*"), ("$RF$", "&"), ("$LT$", "<"), ("$GT$", ">"),
+        ("$LP$", "("), ("$RP$", ")"), ("$C$", ","),
+        ("$u7e$", "~"), ("$u20$", " "), ("$u27$", "'"), ("$u5b$", "["), ("$u5d$", "]"),
+        ("$u7b$", "{"), ("$u7d$", "}"), ("$u3b$", ";"), ("$u2b$", "+"), ("$u22$", "\""),
+    ];
+
+    let mut rest = if segment.starts_with("_$") { &segment[1..] } else { segment };
+    let mut res = String::with_capacity(rest.len());
+
+    'outer: while let Some(ch) = rest.chars().next() {
+        if ch == '$' {
+            for &(esc, s) in ESCAPES {
+                if rest.starts_with(esc) {
+                    res.push_str(s);
+                    rest = &rest[esc.len()..];
+                    continue 'outer;
+                }
+            }
+        } else if rest.starts_with("..") {
+            res.push_str("::");
+            rest = &rest[2..];
+            continue;
+        }
+
+        res.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::{demangle, with_backtrace};
+
+    #[test]
+    fn test_demangle() {
+        assert_eq!(demangle("_ZN4repl3foo17h0123456789abcdefE").unwrap(), "repl::foo");
+        assert_eq!(demangle("_ZN4repl12_rusti_inner28_$u7b$$u7b$closure$u7d$$u7d$17h0123456789abcdefE")
+            .unwrap(), "repl::_rusti_inner::{{closure}}");
+        assert_eq!(demangle("_ZN46_$LT$repl..Foo$u20$as$u20$core..fmt..Debug$GT$3fmt17h0123456789abcdefE")
+            .unwrap(), "<repl::Foo as core::fmt::Debug>::fmt");
+        assert_eq!(demangle("main"), None);
+        assert_eq!(demangle("_ZN4repl9E"), None);
+    }
+
+    #[test]
+    fn test_with_backtrace() {
+        let (res, frames) = with_backtrace(|| 1);
+
+        assert_eq!(res, 1);
+        assert_eq!(frames, None);
+    }
+}
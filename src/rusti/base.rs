@@ -0,0 +1,210 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Base images, built by `--build-base`, which speed up the start of sessions.
+//!
+//! A base image is a dynamic library compiled from the items of the prelude,
+//! together with the dependencies declared by its manifest header (see `deps`),
+//! each re-exported by `pub extern crate`. Sessions then import the library
+//! rather than compiling the prelude's items with every input, and can use
+//! dependencies, which Cargo builds as static libraries, in the execution
+//! engine. Statements of the prelude are still run by each session.
+//!
+//! An image is cached under the hash of the prelude, so that a changed
+//! prelude is no longer served by an image built from an earlier one.
+
+use std::collections::hash_map::DefaultHasher;
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::fs::{create_dir_all, File};
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use artifacts::build_dir;
+use deps;
+use exec::{build_library, ExecOptions};
+use input::{parse_program, InputResult};
+use version;
+
+/// Name of the crate of a base image
+pub const CRATE_NAME: &'static str = "rusti_base";
+
+/// Name of the file, within the directory of an image, listing the
+/// directories containing the libraries of its dependencies
+const DEPS_FILE: &'static str = "deps";
+
+/// Returns the library search paths needed to use the base image built
+/// from a prelude with the given options, if it has been built.
+pub fn find(prelude: &str, opts: &ExecOptions) -> Option<Vec<String>> {
+    let dir = image_dir(prelude, opts)?;
+
+    if !dir.join(library_name()).is_file() {
+        return None;
+    }
+
+    let mut deps = String::new();
+
+    if let Ok(mut f) = File::open(dir.join(DEPS_FILE)) {
+        if f.read_to_string(&mut deps).is_err() {
+            return None;
+        }
+    }
+
+    let mut paths = vec![dir.to_string_lossy().into_owned()];
+    paths.extend(deps.lines().filter(|l| !l.is_empty()).map(|l| l.to_owned()));
+
+    Some(paths)
+}
+
+/// Builds the base image of a prelude, after building its dependencies.
+/// Returns desired process exit status.
+pub fn build(argv0: &str, prelude: &str, mut opts: ExecOptions) -> i32 {
+    let dir = match image_dir(prelude, &opts) {
+        Some(dir) => dir,
+        None => {
+            println!("{}: cannot find a directory in which to build the base image", argv0);
+            return 1;
+        }
+    };
+
+    let mut crates = Vec::new();
+    let mut deps_dirs = String::new();
+
+    if let Some(manifest) = deps::manifest(prelude) {
+        match deps::build(&manifest, &opts, false) {
+            Ok(deps_dir) => {
+                let deps_dir = deps_dir.to_string_lossy().into_owned();
+                deps_dirs.push_str(&deps_dir);
+                deps_dirs.push('\n');
+                opts.lib_paths.push(deps_dir);
+            }
+            Err(e) => {
+                println!("{}: failed to build dependencies: {}", argv0, e);
+                return 1;
+            }
+        }
+
+        crates = deps::crate_names(&manifest);
+    }
+
+    let source = match source(prelude, &crates) {
+        Some(source) => source,
+        None => {
+            println!("{}: failed to parse the prelude", argv0);
+            return 1;
+        }
+    };
+
+    if let Err(e) = create_dir_all(&dir) {
+        println!("{}: failed to create {}: {}", argv0, dir.display(), e);
+        return 1;
+    }
+
+    // Code using the image must find the metadata of its dependencies
+    let written = File::create(dir.join(DEPS_FILE))
+        .and_then(|mut f| f.write_all(deps_dirs.as_bytes()));
+
+    if let Err(e) = written {
+        println!("{}: failed to write {}: {}", argv0, dir.join(DEPS_FILE).display(), e);
+        return 1;
+    }
+
+    if !build_library(source, &dir.join(library_name()), opts) {
+        return 1;
+    }
+
+    println!("built base image in {}", dir.display());
+    0
+}
+
+/// Returns the input run by a session using a base image, in place of the
+/// prelude: an import of the image's crate, followed by the prelude's
+/// `use` declarations and statements.
+pub fn session_input(prelude: &str) -> String {
+    let mut res = format!("extern crate {name};\n#[allow(unused_imports)] use {name}::*;\n",
+        name = CRATE_NAME);
+
+    if let InputResult::Program(input) = parse_program(prelude, false, None) {
+        for line in input.view_items.iter().chain(&input.statements) {
+            res.push_str(line);
+            res.push('\n');
+        }
+    }
+
+    res
+}
+
+/// Returns the source of the crate of a base image, containing the
+/// attributes and items of the prelude, and re-exporting the given crates.
+fn source(prelude: &str, crates: &[String]) -> Option<String> {
+    let input = match parse_program(prelude, false, None) {
+        InputResult::Program(input) => input,
+        _ => return None,
+    };
+
+    let mut res = format!("#![crate_name = \"{}\"]\n", CRATE_NAME);
+
+    for attr in &input.attributes {
+        res.push_str(attr);
+        res.push('\n');
+    }
+
+    for krate in crates {
+        res.push_str(&format!("pub extern crate {};\n", krate));
+    }
+
+    for item in input.view_items.iter().chain(&input.items) {
+        res.push_str(item);
+        res.push('\n');
+    }
+
+    Some(res)
+}
+
+fn image_dir(prelude: &str, opts: &ExecOptions) -> Option<PathBuf> {
+    let dir = build_dir(opts.target_dir.as_ref())?.join("base").join(format!("{:016x}", cache_key(prelude, opts)));
+
+    Some(dir)
+}
+
+fn library_name() -> String {
+    format!("{}{}{}", DLL_PREFIX, CRATE_NAME, DLL_SUFFIX)
+}
+
+/// Returns the key under which the base image of a prelude is cached.
+/// Images are rebuilt by each version of rusti and for each target.
+fn cache_key(prelude: &str, opts: &ExecOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    version().hash(&mut hasher);
+    prelude.hash(&mut hasher);
+    opts.sysroot.hash(&mut hasher);
+    opts.target.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{session_input, source};
+
+    #[test]
+    fn test_source() {
+        assert_eq!(source("#![feature(box_syntax)]\nuse std::fmt;\npub fn one() -> i32 { 1 }\n\
+            let x = one();\n", &["regex".to_owned()]).unwrap(),
+            "#![crate_name = \"rusti_base\"]\n#![feature(box_syntax)]\npub extern crate regex;\n\
+            use std::fmt;\npub fn one() -> i32 { 1 }\n");
+    }
+
+    #[test]
+    fn test_session_input() {
+        assert_eq!(session_input("use std::fmt;\npub fn one() -> i32 { 1 }\nlet x = one();\n"),
+            "extern crate rusti_base;\n#[allow(unused_imports)] use rusti_base::*;\n\
+            use std::fmt;\nlet x = one();\n");
+    }
+}
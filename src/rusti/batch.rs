@@ -0,0 +1,124 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Non-interactive evaluation of a sequence of inputs, as by `--batch`.
+//!
+//! Inputs are separated by lines containing only `DELIMITER`. Each is
+//! evaluated in turn, as if it had been entered in a single round of input,
+//! and its result is printed beneath a line giving its number and status:
+//!
+//! ```text
+//! [1] ok
+//! 42
+//! [2] failed to compile
+//! ```
+//!
+//! Output written by the input, followed by its value, is printed to
+//! `stdout`; diagnostics are printed to `stderr`. Evaluation stops at the
+//! first input which fails to compile or which panics.
+
+use std::io::{self, Write};
+
+use input::is_command;
+use repl::{EvalResult, Repl};
+
+/// Line separating one input from the next
+pub const DELIMITER: &'static str = "---";
+
+/// Evaluates each input within `text`, stopping at the first failure.
+/// Returns desired process exit status.
+pub fn run(repl: &mut Repl, text: &str) -> i32 {
+    for (i, input) in split(text).iter().enumerate() {
+        let (status, res) = evaluate(repl, input);
+
+        println!("[{}] {}", i + 1, status);
+        print!("{}", res.stdout);
+        eprint!("{}", res.stderr);
+
+        if let Some(ref value) = res.value {
+            println!("{}", value);
+        }
+
+        let _ = io::stdout().flush();
+
+        if status != "ok" {
+            return 1;
+        }
+
+        if repl.is_quitting() {
+            break;
+        }
+    }
+
+    0
+}
+
+/// Evaluates an input, returning its status and result.
+/// Commands at the start of the input are run before any code.
+pub fn evaluate(repl: &mut Repl, input: &str) -> (&'static str, EvalResult) {
+    let mut code = input.trim_left();
+
+    while is_command(code) {
+        let end = code.find('\n').map_or(code.len(), |n| n + 1);
+
+        repl.run_command(code[..end].trim_right());
+        code = code[end..].trim_left();
+
+        if repl.is_quitting() {
+            return ("ok", EvalResult::default());
+        }
+    }
+
+    if code.trim().is_empty() {
+        return ("ok", EvalResult::default());
+    }
+
+    let res = repl.evaluate(code);
+
+    let status = if !res.compiled {
+        "failed to compile"
+    } else if res.failed {
+        "failed"
+    } else {
+        "ok"
+    };
+
+    (status, res)
+}
+
+/// Splits text into inputs at each `DELIMITER` line.
+/// Inputs containing only whitespace are omitted.
+fn split(text: &str) -> Vec<String> {
+    let mut inputs = vec![String::new()];
+
+    for line in text.lines() {
+        if line.trim_right() == DELIMITER {
+            inputs.push(String::new());
+        } else {
+            let last = inputs.last_mut().unwrap();
+            last.push_str(line);
+            last.push('\n');
+        }
+    }
+
+    inputs.retain(|input| !input.trim().is_empty());
+    inputs
+}
+
+#[cfg(test)]
+mod test {
+    use super::split;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("let a = 1;\n---\na + 1\n"), ["let a = 1;\n", "a + 1\n"]);
+        assert_eq!(split("---\n\n---  \n.set opt-level 2\nfoo()"),
+            ["\n.set opt-level 2\nfoo()\n"]);
+        assert!(split("").is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Benchmarking of expressions.
+//!
+//! The expression is evaluated repeatedly in batches, each long enough to be
+//! timed accurately. Batches are first run until the warm-up time has passed,
+//! after which the time per iteration of each batch is collected as a sample.
+//! Samples are handed back to rusti as the value of the benchmark program.
+
+use std::fmt;
+
+/// Time spent warming up before samples are collected, in milliseconds
+const WARM_UP_MS: u64 = 200;
+
+/// Minimum time of each sample, in nanoseconds
+const SAMPLE_NS: u64 = 10_000_000;
+
+/// Number of samples collected, unless the time limit is reached
+const SAMPLES: usize = 50;
+
+/// Number of samples collected regardless of the time limit
+const MIN_SAMPLES: usize = 5;
+
+/// Time after which no more than `MIN_SAMPLES` are collected, in milliseconds
+const TIME_LIMIT_MS: u64 = 5_000;
+
+/// Returns the body of an entry point which benchmarks an expression,
+/// returning samples in the form parsed by `Stats::parse`.
+pub fn statements(expr: &str) -> String {
+    format!(r#"
+// Prevents the optimizer from removing the evaluation of `x`
+fn _rusti_black_box<T>(x: T) -> T {{
+    unsafe {{
+        let y = ::std::ptr::read_volatile(&x);
+        ::std::mem::forget(x);
+        y
+    }}
+}}
+
+fn _rusti_ns(d: ::std::time::Duration) -> u64 {{
+    d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64
+}}
+
+#[allow(unused_mut)]
+let mut _rusti_bench = || {{ _rusti_black_box({{ {expr} }}); }};
+let _rusti_start = ::std::time::Instant::now();
+let mut _rusti_batch = 1u64;
+
+loop {{
+    let t = ::std::time::Instant::now();
+    for _ in 0.._rusti_batch {{ _rusti_bench(); }}
+
+    if _rusti_ns(t.elapsed()) < {sample_ns} {{
+        _rusti_batch *= 2;
+    }} else if _rusti_start.elapsed() >= ::std::time::Duration::from_millis({warm_up_ms}) {{
+        break;
+    }}
+}}
+
+let _rusti_start = ::std::time::Instant::now();
+let mut _rusti_samples = String::new();
+let mut _rusti_n = 0;
+
+while _rusti_n < {samples} && (_rusti_n < {min_samples} ||
+        _rusti_start.elapsed() < ::std::time::Duration::from_millis({time_limit_ms})) {{
+    let t = ::std::time::Instant::now();
+    for _ in 0.._rusti_batch {{ _rusti_bench(); }}
+    let ns = _rusti_ns(t.elapsed());
+
+    _rusti_samples.push_str(&format!(" {{}}", ns as f64 / _rusti_batch as f64));
+    _rusti_n += 1;
+}}
+
+Some(format!("{{}}{{}}", _rusti_batch, _rusti_samples))
+"#,
+        expr = expr,
+        sample_ns = SAMPLE_NS,
+        warm_up_ms = WARM_UP_MS,
+        samples = SAMPLES,
+        min_samples = MIN_SAMPLES,
+        time_limit_ms = TIME_LIMIT_MS)
+}
+
+/// Statistics of benchmark samples, in nanoseconds per iteration
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    /// Total number of iterations run for all samples
+    pub iterations: u64,
+    pub mean: f64,
+    pub median: f64,
+    /// Sample standard deviation
+    pub std_dev: f64,
+}
+
+impl Stats {
+    /// Computes statistics from the time per iteration of each sample,
+    /// each of which ran `batch` iterations.
+    /// Returns `None` if there are no samples.
+    pub fn new(batch: u64, samples: &[f64]) -> Option<Stats> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mid = sorted.len() / 2;
+        let median = if sorted.len() % 2 == 0 {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        };
+
+        let std_dev = if samples.len() > 1 {
+            (samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+
+        Some(Stats{
+            iterations: batch * samples.len() as u64,
+            mean: mean,
+            median: median,
+            std_dev: std_dev,
+        })
+    }
+
+    /// Parses the value returned by an entry point generated by `statements`.
+    pub fn parse(value: &str) -> Option<Stats> {
+        let mut words = value.split_whitespace();
+        let batch = words.next()?.parse().ok()?;
+        let samples = words.map(|w| w.parse()).collect::<Result<Vec<f64>, _>>().ok()?;
+
+        Stats::new(batch, &samples)
+    }
+
+    /// Returns the number of iterations run per second, on average.
+    pub fn per_second(&self) -> f64 {
+        1e9 / self.mean
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "mean:    {}", format_time(self.mean))?;
+        writeln!(f, "median:  {}", format_time(self.median))?;
+        writeln!(f, "std dev: {}", format_time(self.std_dev))?;
+        write!(f, "{:.0} iterations/s ({} iterations)", self.per_second(), self.iterations)
+    }
+}
+
+/// Formats a number of nanoseconds in the largest fitting unit.
+fn format_time(ns: f64) -> String {
+    if ns < 1e3 {
+        format!("{:.2} ns", ns)
+    } else if ns < 1e6 {
+        format!("{:.2} µs", ns / 1e3)
+    } else if ns < 1e9 {
+        format!("{:.2} ms", ns / 1e6)
+    } else {
+        format!("{:.2} s", ns / 1e9)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_time, Stats};
+
+    #[test]
+    fn test_stats() {
+        let stats = Stats::parse("4 1 2 3 6").unwrap();
+
+        assert_eq!(stats.iterations, 16);
+        assert_eq!(stats.mean, 3.0);
+        assert_eq!(stats.median, 2.5);
+        assert!((stats.std_dev - 2.160247).abs() < 1e-6);
+        assert_eq!(stats.per_second(), 1e9 / 3.0);
+
+        assert_eq!(Stats::parse("4"), None);
+        assert_eq!(Stats::parse("x 1"), None);
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(12.3456), "12.35 ns");
+        assert_eq!(format_time(1500.0), "1.50 µs");
+        assert_eq!(format_time(2.5e9), "2.50 s");
+    }
+}
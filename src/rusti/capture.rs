@@ -0,0 +1,454 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Captures output written to the standard output and error streams,
+//! and supplies input to the standard input stream.
+
+use std::io;
+
+/// Text captured from `stdout` and `stderr`
+#[derive(Clone, Debug, Default)]
+pub struct Captured {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Calls the given closure, capturing anything written to the `stdout` and
+/// `stderr` file descriptors of this process while it runs.
+///
+/// Compiled input does not share rusti's `std` streams, so output is
+/// captured at the file descriptor level rather than with `io::set_print`.
+///
+/// On platforms where output cannot be captured, the closure is called
+/// and output is written as usual.
+pub fn capture<F, R>(f: F) -> io::Result<(R, Captured)> where F: FnOnce() -> R {
+    imp::capture(f)
+}
+
+/// Calls the given closure, capturing output as `capture` does, except that
+/// `stdout` and `stderr` are each connected to a pseudo-terminal.
+///
+/// Code which checks whether its output is a terminal, e.g. to color it or
+/// to show progress bars, behaves as it would in a terminal.
+///
+/// On platforms without pseudo-terminals, output is captured as by `capture`.
+pub fn capture_tty<F, R>(f: F) -> io::Result<(R, Captured)> where F: FnOnce() -> R {
+    imp::capture_tty(f)
+}
+
+/// Calls the given closure, capturing output while also writing it to `stdout`
+/// and `stderr` as it is written. Each stream is connected to a
+/// pseudo-terminal if, and only if, it was a terminal, so output is written
+/// as it would be without being captured.
+///
+/// Output written after the closure returns, such as by a process it started,
+/// continues to be written, but is not captured.
+///
+/// On platforms where output cannot be captured, the closure is called
+/// and output is written as usual.
+pub fn tee<F, R>(f: F) -> io::Result<(R, Captured)> where F: FnOnce() -> R {
+    imp::tee(f)
+}
+
+/// Calls the given closure with the `stdin` file descriptor of this process
+/// reading the given text, rather than the terminal or rusti's own input.
+///
+/// On platforms where input cannot be redirected, the closure is called
+/// with `stdin` unchanged.
+pub fn with_stdin<F, R>(input: &str, f: F) -> io::Result<R> where F: FnOnce() -> R {
+    imp::with_stdin(input, f)
+}
+
+/// Calls the given closure with the `stdin` file descriptor of this process
+/// reading the terminal, in its usual line-by-line mode, rather than rusti's
+/// own input. Lines typed but not read by the time the closure returns are
+/// discarded, rather than being read by rusti.
+///
+/// On platforms where input cannot be redirected, the closure is called
+/// with `stdin` unchanged.
+pub fn with_terminal_stdin<F, R>(f: F) -> io::Result<R> where F: FnOnce() -> R {
+    imp::with_terminal_stdin(f)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, stderr, stdout, Read, Seek, SeekFrom, Write};
+    use std::mem::replace;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+    use std::time::Duration;
+
+    use libc::{self, c_int};
+    use tempfile::NamedTempFile;
+
+    use pager::terminal_size;
+
+    use super::Captured;
+
+    /// Terminal controlling this process
+    const TTY: &'static str = "/dev/tty";
+
+    pub fn capture<F, R>(f: F) -> io::Result<(R, Captured)> where F: FnOnce() -> R {
+        let (_out_tmp, mut out_file) = temp_file()?;
+        let (_err_tmp, mut err_file) = temp_file()?;
+
+        let out = Redirect::new(libc::STDOUT_FILENO, out_file.as_raw_fd())?;
+        let err = Redirect::new(libc::STDERR_FILENO, err_file.as_raw_fd())?;
+
+        let r = f();
+
+        drop(err);
+        drop(out);
+
+        Ok((r, Captured{
+            stdout: read_all(&mut out_file)?,
+            stderr: read_all(&mut err_file)?,
+        }))
+    }
+
+    pub fn capture_tty<F, R>(f: F) -> io::Result<(R, Captured)> where F: FnOnce() -> R {
+        let (out_master, out_slave) = open_pty()?;
+        let (err_master, err_slave) = open_pty()?;
+
+        let out = Stream::new(out_master, out_slave, None)?;
+        let err = Stream::new(err_master, err_slave, None)?;
+
+        let r = {
+            let _out = Redirect::new(libc::STDOUT_FILENO, out.input.as_raw_fd())?;
+            let _err = Redirect::new(libc::STDERR_FILENO, err.input.as_raw_fd())?;
+
+            f()
+        };
+
+        Ok((r, Captured{
+            stdout: out.finish(None),
+            stderr: err.finish(None),
+        }))
+    }
+
+    pub fn tee<F, R>(f: F) -> io::Result<(R, Captured)> where F: FnOnce() -> R {
+        let out = Stream::forwarding(libc::STDOUT_FILENO)?;
+        let err = Stream::forwarding(libc::STDERR_FILENO)?;
+
+        let r = {
+            let _out = Redirect::new(libc::STDOUT_FILENO, out.input.as_raw_fd())?;
+            let _err = Redirect::new(libc::STDERR_FILENO, err.input.as_raw_fd())?;
+
+            f()
+        };
+
+        // Output of processes which outlive the closure is not waited for
+        let wait = Some(Duration::from_millis(100));
+
+        Ok((r, Captured{
+            stdout: out.finish(wait),
+            stderr: err.finish(wait),
+        }))
+    }
+
+    pub fn with_stdin<F, R>(input: &str, f: F) -> io::Result<R> where F: FnOnce() -> R {
+        let (_tmp, mut file) = temp_file()?;
+
+        file.write_all(input.as_bytes())?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let _redirect = Redirect::new(libc::STDIN_FILENO, file.as_raw_fd())?;
+
+        Ok(f())
+    }
+
+    pub fn with_terminal_stdin<F, R>(f: F) -> io::Result<R> where F: FnOnce() -> R {
+        let tty = OpenOptions::new().read(true).write(true).open(TTY)?;
+        let fd = tty.as_raw_fd();
+
+        let mut saved = unsafe { ::std::mem::zeroed::<libc::termios>() };
+        let has_attrs = unsafe { libc::tcgetattr(fd, &mut saved) } == 0;
+
+        // Input is read a line at a time and echoed, as in a shell
+        if has_attrs {
+            let mut attrs = saved;
+            attrs.c_iflag |= libc::ICRNL;
+            attrs.c_lflag |= libc::ICANON | libc::ECHO | libc::ISIG;
+            unsafe { libc::tcsetattr(fd, libc::TCSANOW, &attrs) };
+        }
+
+        let r = {
+            let _redirect = Redirect::new(libc::STDIN_FILENO, fd)?;
+
+            f()
+        };
+
+        unsafe {
+            libc::tcflush(fd, libc::TCIFLUSH);
+
+            if has_attrs {
+                libc::tcsetattr(fd, libc::TCSANOW, &saved);
+            }
+        }
+
+        Ok(r)
+    }
+
+    /// Creates a temporary file, opened for reading and writing.
+    fn temp_file() -> io::Result<(NamedTempFile, File)> {
+        let tmp = NamedTempFile::new()?;
+        let file = OpenOptions::new().read(true).write(true).open(tmp.path())?;
+
+        Ok((tmp, file))
+    }
+
+    /// Reads the text written to a file by a redirected file descriptor.
+    fn read_all(file: &mut File) -> io::Result<String> {
+        let mut buf = Vec::new();
+
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut buf)?;
+
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    /// Opens a pseudo-terminal the size of the terminal, if any,
+    /// returning its master and slave.
+    fn open_pty() -> io::Result<(File, File)> {
+        let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+
+        if master == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let master = unsafe { File::from_raw_fd(master) };
+
+        let slave = unsafe {
+            let fd = master.as_raw_fd();
+
+            if libc::grantpt(fd) == -1 || libc::unlockpt(fd) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let name = libc::ptsname(fd);
+
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+
+            let slave = libc::open(name, libc::O_RDWR | libc::O_NOCTTY);
+
+            if slave == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            File::from_raw_fd(slave)
+        };
+
+        unsafe {
+            let fd = slave.as_raw_fd();
+
+            // Keep newlines as they are written
+            let mut attrs = ::std::mem::zeroed::<libc::termios>();
+
+            if libc::tcgetattr(fd, &mut attrs) == 0 {
+                attrs.c_oflag &= !libc::OPOST;
+                libc::tcsetattr(fd, libc::TCSANOW, &attrs);
+            }
+
+            let (rows, cols) = terminal_size().unwrap_or((24, 80));
+            let size = libc::winsize{
+                ws_row: rows as _,
+                ws_col: cols as _,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+
+            libc::ioctl(fd, libc::TIOCSWINSZ, &size);
+        }
+
+        Ok((master, slave))
+    }
+
+    /// Opens a pipe, returning its read and write ends.
+    fn open_pipe() -> io::Result<(File, File)> {
+        let mut fds = [0; 2];
+
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Processes started by input inherit the pipe as `stdout` or
+        // `stderr`, but need not hold it open otherwise
+        for &fd in &fds {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+
+        unsafe { Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]))) }
+    }
+
+    /// A pseudo-terminal or pipe, whose output is read by a thread until
+    /// it is closed
+    struct Stream {
+        /// File to which output is written
+        input: File,
+        /// Output read so far
+        buf: Arc<Mutex<Vec<u8>>>,
+        /// Receives once all output has been read
+        done: Receiver<()>,
+    }
+
+    impl Stream {
+        /// Reads the output of `input` from `output`, writing it to `forward`,
+        /// if given, as it is read.
+        fn new(mut output: File, input: File, mut forward: Option<File>) -> io::Result<Stream> {
+            let buf = Arc::new(Mutex::new(Vec::new()));
+            let (tx, rx) = channel();
+            let thread_buf = buf.clone();
+
+            // Output must be read as it is written, as the terminal's
+            // buffer is small and writes would block once it is full.
+            thread::Builder::new()
+                .name("capture".to_owned())
+                .spawn(move || {
+                    let mut chunk = [0; 4096];
+
+                    loop {
+                        // Once a pseudo-terminal's slave is closed, reads fail with `EIO`
+                        let n = match output.read(&mut chunk) {
+                            Ok(0) => break,
+                            Ok(n) => n,
+                            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                            Err(_) => break,
+                        };
+
+                        thread_buf.lock().unwrap().extend_from_slice(&chunk[..n]);
+
+                        if let Some(ref mut f) = forward {
+                            let _ = f.write_all(&chunk[..n]);
+                        }
+                    }
+
+                    let _ = tx.send(());
+                })?;
+
+            Ok(Stream{
+                input: input,
+                buf: buf,
+                done: rx,
+            })
+        }
+
+        /// Captures output which would be written to the file descriptor `fd`,
+        /// forwarding it to `fd`. Output is read from a pseudo-terminal if `fd`
+        /// is a terminal, or otherwise from a pipe.
+        fn forwarding(fd: c_int) -> io::Result<Stream> {
+            let forward = unsafe { libc::dup(fd) };
+
+            if forward == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let forward = unsafe { File::from_raw_fd(forward) };
+
+            let (output, input) = if unsafe { libc::isatty(fd) } != 0 {
+                open_pty()?
+            } else {
+                open_pipe()?
+            };
+
+            Stream::new(output, input, Some(forward))
+        }
+
+        /// Closes the stream and returns the text written to it, waiting at
+        /// most `timeout`, if given, for output of other processes holding
+        /// the stream open. Any redirected file descriptors must be restored first.
+        fn finish(self, timeout: Option<Duration>) -> String {
+            drop(self.input);
+
+            match timeout {
+                Some(timeout) => { let _ = self.done.recv_timeout(timeout); }
+                None => { let _ = self.done.recv(); }
+            }
+
+            let buf = replace(&mut *self.buf.lock().unwrap(), Vec::new());
+
+            String::from_utf8_lossy(&buf).into_owned()
+        }
+    }
+
+    /// Redirects a file descriptor to another until the `Redirect` is dropped.
+    struct Redirect {
+        fd: c_int,
+        saved: c_int,
+    }
+
+    impl Redirect {
+        fn new(fd: c_int, target: c_int) -> io::Result<Redirect> {
+            flush_std();
+
+            let saved = unsafe { libc::dup(fd) };
+
+            if saved == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            if unsafe { libc::dup2(target, fd) } == -1 {
+                let e = io::Error::last_os_error();
+                unsafe { libc::close(saved) };
+                return Err(e);
+            }
+
+            Ok(Redirect{
+                fd: fd,
+                saved: saved,
+            })
+        }
+    }
+
+    impl Drop for Redirect {
+        fn drop(&mut self) {
+            flush_std();
+
+            unsafe {
+                libc::dup2(self.saved, self.fd);
+                libc::close(self.saved);
+            }
+        }
+    }
+
+    fn flush_std() {
+        let _ = stdout().flush();
+        let _ = stderr().flush();
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+
+    use super::Captured;
+
+    pub fn capture<F, R>(f: F) -> io::Result<(R, Captured)> where F: FnOnce() -> R {
+        Ok((f(), Captured::default()))
+    }
+
+    pub fn capture_tty<F, R>(f: F) -> io::Result<(R, Captured)> where F: FnOnce() -> R {
+        capture(f)
+    }
+
+    pub fn tee<F, R>(f: F) -> io::Result<(R, Captured)> where F: FnOnce() -> R {
+        capture(f)
+    }
+
+    pub fn with_stdin<F, R>(_input: &str, f: F) -> io::Result<R> where F: FnOnce() -> R {
+        Ok(f())
+    }
+
+    pub fn with_terminal_stdin<F, R>(f: F) -> io::Result<R> where F: FnOnce() -> R {
+        Ok(f())
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Access to the system clipboard, as by `.copy` and `.paste-eval`.
+//!
+//! The clipboard is accessed by the programs of the platform: `pbcopy` and
+//! `pbpaste` on macOS; otherwise, `wl-copy` and `wl-paste` under Wayland,
+//! then `xclip` or `xsel`. The first which can be run is used.
+
+use std::env::var_os;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Commands which access the clipboard
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Tool {
+    /// Command writing standard input to the clipboard
+    copy: &'static [&'static str],
+    /// Command writing the clipboard to standard output
+    paste: &'static [&'static str],
+}
+
+const PBCOPY: Tool = Tool{
+    copy: &["pbcopy"],
+    paste: &["pbpaste"],
+};
+
+const WL_CLIPBOARD: Tool = Tool{
+    copy: &["wl-copy"],
+    paste: &["wl-paste", "--no-newline"],
+};
+
+const XCLIP: Tool = Tool{
+    copy: &["xclip", "-selection", "clipboard", "-in"],
+    paste: &["xclip", "-selection", "clipboard", "-out"],
+};
+
+const XSEL: Tool = Tool{
+    copy: &["xsel", "--clipboard", "--input"],
+    paste: &["xsel", "--clipboard", "--output"],
+};
+
+/// Returns the tools which may access the clipboard, in order of preference.
+fn tools(macos: bool, wayland: bool) -> Vec<Tool> {
+    if macos {
+        vec![PBCOPY]
+    } else if wayland {
+        vec![WL_CLIPBOARD, XCLIP, XSEL]
+    } else {
+        vec![XCLIP, XSEL]
+    }
+}
+
+/// Returns the tools which may access the clipboard of this system.
+fn system_tools() -> Vec<Tool> {
+    tools(cfg!(target_os = "macos"), var_os("WAYLAND_DISPLAY").is_some())
+}
+
+/// Returns a command running the program of `args`.
+fn command(args: &[&str]) -> Command {
+    let mut cmd = Command::new(args[0]);
+    cmd.args(&args[1..]);
+    cmd
+}
+
+/// Returns the error reported when no tool could be run.
+fn no_tool() -> String {
+    let names = system_tools().iter()
+        .map(|t| t.copy[0])
+        .collect::<Vec<_>>();
+
+    format!("cannot access the clipboard: install one of {}", names.join(", "))
+}
+
+/// Writes text to the clipboard.
+pub fn copy(text: &str) -> Result<(), String> {
+    for tool in system_tools() {
+        let mut child = match command(tool.copy).stdin(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("failed to run {}: {}", tool.copy[0], e)),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())
+                .map_err(|e| format!("failed to write to {}: {}", tool.copy[0], e))?;
+        }
+
+        return match child.wait() {
+            Ok(ref status) if status.success() => Ok(()),
+            Ok(_) => Err(format!("{} failed to write to the clipboard", tool.copy[0])),
+            Err(e) => Err(format!("failed to run {}: {}", tool.copy[0], e)),
+        };
+    }
+
+    Err(no_tool())
+}
+
+/// Returns the text of the clipboard.
+pub fn paste() -> Result<String, String> {
+    for tool in system_tools() {
+        let out = match command(tool.paste).stdin(Stdio::null()).output() {
+            Ok(out) => out,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("failed to run {}: {}", tool.paste[0], e)),
+        };
+
+        if !out.status.success() {
+            return Err(format!("{} failed to read the clipboard: {}", tool.paste[0],
+                String::from_utf8_lossy(&out.stderr).trim()));
+        }
+
+        return String::from_utf8(out.stdout)
+            .map_err(|_| "the clipboard does not contain text".to_owned());
+    }
+
+    Err(no_tool())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tools, PBCOPY, WL_CLIPBOARD, XCLIP, XSEL};
+
+    #[test]
+    fn test_tools() {
+        assert_eq!(tools(true, false), [PBCOPY]);
+        assert_eq!(tools(false, true), [WL_CLIPBOARD, XCLIP, XSEL]);
+        assert_eq!(tools(false, false), [XCLIP, XSEL]);
+    }
+}
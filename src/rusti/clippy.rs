@@ -0,0 +1,145 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Runs clippy lints over session code, using `clippy-driver`.
+//!
+//! The driver is run on a program written to a temporary file, with the
+//! options used to compile input. Its diagnostics are read as JSON, so that
+//! each can be traced back to the input which defined the code it refers to.
+
+use std::fs::create_dir_all;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+use serialize::json::Json;
+
+use tempfile::NamedTempFileOptions;
+
+use artifacts::{artifact_dir, PREFIX};
+use exec::ExecOptions;
+
+/// Name of the clippy driver executable
+const CLIPPY_DRIVER: &'static str = "clippy-driver";
+
+/// Name given to the program in diagnostics, in place of its temporary file
+pub const PROGRAM_NAME: &'static str = "<session>";
+
+/// A diagnostic emitted by clippy
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// Byte offset of the primary span within the program, if there is one
+    pub offset: Option<usize>,
+    /// Diagnostic, rendered as it is normally shown
+    pub rendered: String,
+}
+
+/// Returns the path of the clippy driver: the one installed in the sysroot,
+/// if any, as it is built for the same compiler; otherwise, the one in `PATH`.
+fn driver_path(opts: &ExecOptions) -> PathBuf {
+    let path = opts.sysroot.join("bin").join(CLIPPY_DRIVER);
+
+    if path.exists() {
+        path
+    } else {
+        PathBuf::from(CLIPPY_DRIVER)
+    }
+}
+
+/// Runs clippy over a program, returning its diagnostics.
+pub fn lint(prog: &str, opts: &ExecOptions) -> io::Result<Vec<Diagnostic>> {
+    let dir = artifact_dir(opts.target_dir.as_ref());
+    create_dir_all(&dir)?;
+
+    let mut src = NamedTempFileOptions::new().prefix(PREFIX).create_in(&dir)?;
+
+    src.write_all(prog.as_bytes())?;
+    src.flush()?;
+
+    let out = NamedTempFileOptions::new().prefix(PREFIX).create_in(&dir)?;
+
+    let mut cmd = Command::new(driver_path(opts));
+
+    cmd.arg(src.path())
+        .args(&["--crate-type", "lib", "--emit", "metadata", "--error-format", "json"])
+        .arg("-o").arg(out.path())
+        .arg("--sysroot").arg(&opts.sysroot);
+
+    for path in &opts.lib_paths {
+        cmd.arg("-L").arg(path);
+    }
+
+    if let Some(ref target) = opts.target {
+        cmd.arg("--target").arg(target);
+    }
+
+    cmd.args(&opts.lint_args());
+
+    let output = cmd.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let path = src.path().to_string_lossy();
+
+    Ok(parse_diagnostics(&stderr).into_iter()
+        .map(|d| Diagnostic{
+            offset: d.offset,
+            rendered: d.rendered.replace(&path[..], PROGRAM_NAME),
+        })
+        .collect())
+}
+
+/// Parses diagnostics emitted as JSON, one per line.
+/// Other lines, and diagnostics without a rendered message, are ignored.
+fn parse_diagnostics(stderr: &str) -> Vec<Diagnostic> {
+    let mut res = Vec::new();
+
+    for line in stderr.lines() {
+        let diag = match Json::from_str(line) {
+            Ok(diag) => diag,
+            Err(_) => continue,
+        };
+
+        let rendered = match diag.find("rendered").and_then(|r| r.as_string()) {
+            Some(r) => r.to_owned(),
+            None => continue,
+        };
+
+        let offset = diag.find("spans")
+            .and_then(|s| s.as_array())
+            .and_then(|spans| spans.iter()
+                .find(|s| s.find("is_primary").and_then(|p| p.as_boolean()) == Some(true)))
+            .and_then(|s| s.find("byte_start"))
+            .and_then(|b| b.as_u64())
+            .map(|b| b as usize);
+
+        res.push(Diagnostic{
+            offset: offset,
+            rendered: rendered,
+        });
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_diagnostics, Diagnostic};
+
+    #[test]
+    fn test_parse_diagnostics() {
+        let stderr = r#"{"message":"x","level":"warning","spans":[{"byte_start":3,"is_primary":false},{"byte_start":7,"is_primary":true}],"rendered":"warning: x\n"}
+not json
+{"message":"y","level":"warning","spans":[],"rendered":"warning: y\n"}
+{"message":"z","level":"note","spans":[],"rendered":null}
+"#;
+
+        assert_eq!(parse_diagnostics(stderr), vec![
+            Diagnostic{offset: Some(7), rendered: "warning: x\n".to_owned()},
+            Diagnostic{offset: None, rendered: "warning: y\n".to_owned()},
+        ]);
+    }
+}
@@ -11,25 +11,36 @@
 use std::io::Write;
 use std::iter::repeat;
 use std::process::Command;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
 
 use linefeed::{self, Completion, Reader, Suffix, Terminal};
 use linefeed::complete::complete_path;
 
 use tempfile::NamedTempFile;
 
+use exec::lint_names;
 use input::is_command;
 use repl::{lookup_command, search_command, CmdArgs};
 
-pub struct Completer;
+pub struct Completer {
+    /// Whether text is being pasted, in which case tabs are inserted as typed
+    pub pasting: Arc<AtomicBool>,
+}
 
 impl<Term: Terminal> linefeed::Completer<Term> for Completer {
     fn complete(
         &self,
-        _word: &str,
+        word: &str,
         reader: &Reader<Term>,
         start: usize,
         end: usize,
     ) -> Option<Vec<Completion>> {
+        if self.pasting.load(SeqCst) {
+            return Some(vec![Completion::simple(format!("{}\t", word))]);
+        }
+
         let is_whitespace = reader.buffer()[..start]
             .chars()
             .all(|ch| ch.is_whitespace());
@@ -79,31 +90,61 @@ pub fn complete(text: &str, end: usize) -> Option<Vec<Completion>> {
             match accepts {
                 CmdArgs::Expr => args.and_then(|arg| complete_code(arg, arg.len())),
                 CmdArgs::Filename => Some(complete_path(args.unwrap_or(""))),
+                CmdArgs::Lints => complete_lints(args.unwrap_or("")),
+                CmdArgs::Command => complete_command(args.unwrap_or("")),
                 _ => None,
             }
         } else {
-            // Complete command name
-            let mut names = Vec::new();
-
-            search_command(&line[1..], |cmd| {
-                names.push(Completion {
-                    completion: cmd.name.to_owned(),
-                    display: None,
-                    suffix: Suffix::Some(' '),
-                })
-            });
-
-            if names.is_empty() {
-                None
-            } else {
-                Some(names)
-            }
+            complete_command(&line[1..])
         }
     } else {
         complete_code(text, end)
     }
 }
 
+/// Completes the name of a command.
+fn complete_command(prefix: &str) -> Option<Vec<Completion>> {
+    let mut names = Vec::new();
+
+    search_command(prefix, |cmd| {
+        names.push(Completion {
+            completion: cmd.name.to_owned(),
+            display: None,
+            suffix: Suffix::Some(' '),
+        })
+    });
+
+    if names.is_empty() {
+        None
+    } else {
+        Some(names)
+    }
+}
+
+/// Completes the last of a list of lint names, separated by commas or spaces.
+fn complete_lints(args: &str) -> Option<Vec<Completion>> {
+    // The word being completed ends at the last space; names before
+    // the last comma within it are kept.
+    let word = args.rsplit(char::is_whitespace).next().unwrap_or("");
+    let (list, prefix) = word.split_at(word.rfind(',').map_or(0, |i| i + 1));
+    let prefix = prefix.replace('-', "_");
+
+    let names = lint_names().into_iter()
+        .filter(|name| name.starts_with(&prefix))
+        .map(|name| Completion {
+            completion: format!("{}{}", list, name),
+            display: Some(name),
+            suffix: Suffix::Default,
+        })
+        .collect::<Vec<_>>();
+
+    if names.is_empty() {
+        None
+    } else {
+        Some(names)
+    }
+}
+
 /// Performs completion for Rust code.
 fn complete_code(text: &str, end: usize) -> Option<Vec<Completion>> {
     let mut file = NamedTempFile::new().unwrap();
@@ -189,3 +230,28 @@ fn complete_code(text: &str, end: usize) -> Option<Vec<Completion>> {
         Some(completions)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::complete;
+
+    fn completions(text: &str) -> Vec<String> {
+        complete(text, text.len()).unwrap_or_default()
+            .into_iter().map(|c| c.completion).collect()
+    }
+
+    #[test]
+    fn test_complete_command() {
+        assert_eq!(completions(".exp"), ["explain", "export-ipynb", "export-md"]);
+        assert_eq!(completions(".help wat"), ["watch"]);
+        assert!(completions(".frob").is_empty());
+    }
+
+    #[test]
+    fn test_complete_lints() {
+        assert_eq!(completions(".allow dead_c"), ["dead_code"]);
+        assert_eq!(completions(".deny warnings,dead-c"), ["warnings,dead_code"]);
+        assert!(completions(".warn unused_").contains(&"unused_variables".to_owned()));
+        assert!(completions(".warn no_such_lint").is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! User configuration, read from a TOML file at startup.
+//!
+//! The file is read from `$XDG_CONFIG_HOME/rusti/config.toml`, or
+//! `~/.config/rusti/config.toml` if `XDG_CONFIG_HOME` is not set.
+
+use std::env::{home_dir, var_os};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use syntax::errors::ColorConfig;
+
+use toml::Value;
+
+/// Path of the configuration file, within the configuration directory
+const CONFIG_FILE: &'static str = "rusti/config.toml";
+
+/// Configuration read from a file
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Template of the prompt; see `prompt`
+    pub prompt: Option<String>,
+    /// Whether diagnostics and values are colored
+    pub color: Option<ColorConfig>,
+    /// Additional search paths for libraries, as `-L`
+    pub lib_paths: Vec<String>,
+    /// Code run at the start of each session, before the rc file
+    pub prelude: Option<String>,
+    /// Values of settings, as given to `.set`
+    pub settings: Vec<(String, String)>,
+    /// Key sequences bound to line editor commands
+    pub key_bindings: Vec<(String, String)>,
+    /// Age, in hours, beyond which unused artifacts are removed; see `artifacts`
+    pub artifact_max_age: Option<u64>,
+    /// Paths which code run in a sandbox may read; see `sandbox`
+    pub sandbox_read: Vec<String>,
+    /// Paths which code run in a sandbox may write
+    pub sandbox_write: Vec<String>,
+    /// Directory in which artifacts are written; see `artifacts`
+    pub target_dir: Option<String>,
+}
+
+impl Config {
+    /// Returns the default path of the configuration file.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match var_os("XDG_CONFIG_HOME") {
+            Some(ref d) if !d.is_empty() => PathBuf::from(d),
+            _ => home_dir()?.join(".config"),
+        };
+
+        Some(dir.join(CONFIG_FILE))
+    }
+
+    /// Reads configuration from the given file.
+    /// A file which does not exist yields the default configuration.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let mut s = String::new();
+
+        match File::open(path) {
+            Ok(mut f) => {
+                f.read_to_string(&mut s).map_err(|e| e.to_string())?;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e.to_string()),
+        }
+
+        Config::parse(&s)
+    }
+
+    /// Parses configuration from TOML source.
+    pub fn parse(s: &str) -> Result<Config, String> {
+        let value = s.parse::<Value>().map_err(|e| e.to_string())?;
+        let table = match value.as_table() {
+            Some(t) => t,
+            None => return Err("expected a table".to_owned()),
+        };
+
+        let mut config = Config::default();
+
+        for (key, value) in table {
+            match &key[..] {
+                "prompt" => config.prompt = Some(string(key, value)?),
+                "color" => {
+                    config.color = Some(match &string(key, value)?[..] {
+                        "auto" => ColorConfig::Auto,
+                        "always" => ColorConfig::Always,
+                        "never" => ColorConfig::Never,
+                        _ => return Err("`color` must be one of auto, always, never".to_owned()),
+                    });
+                }
+                "lib-paths" => config.lib_paths = strings(key, value)?,
+                "sandbox-read" => config.sandbox_read = strings(key, value)?,
+                "sandbox-write" => config.sandbox_write = strings(key, value)?,
+                "prelude" => config.prelude = Some(string(key, value)?),
+                "target-dir" => config.target_dir = Some(string(key, value)?),
+                "artifact-max-age" => {
+                    config.artifact_max_age = match value.as_integer() {
+                        Some(n) if n >= 0 => Some(n as u64),
+                        _ => return Err("`artifact-max-age` must be a number of hours".to_owned()),
+                    };
+                }
+                "settings" => config.settings = pairs(key, value)?,
+                "keybindings" => config.key_bindings = pairs(key, value)?,
+                _ => return Err(format!("unknown key `{}`", key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Returns the value of a key which must be a string.
+fn string(key: &str, value: &Value) -> Result<String, String> {
+    match value.as_str() {
+        Some(s) => Ok(s.to_owned()),
+        None => Err(format!("`{}` must be a string", key)),
+    }
+}
+
+/// Returns the elements of a value which must be an array of strings.
+fn strings(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    let values = match value.as_array() {
+        Some(a) => a,
+        None => return Err(format!("`{}` must be an array of strings", key)),
+    };
+
+    values.iter().map(|v| string(key, v)).collect()
+}
+
+/// Returns the entries of a table, with each value written as it would be
+/// given to `.set`.
+fn pairs(key: &str, value: &Value) -> Result<Vec<(String, String)>, String> {
+    let table = match value.as_table() {
+        Some(t) => t,
+        None => return Err(format!("`{}` must be a table", key)),
+    };
+
+    let mut res = Vec::new();
+
+    for (name, value) in table {
+        let value = match *value {
+            Value::String(ref s) => s.clone(),
+            Value::Integer(n) => n.to_string(),
+            Value::Boolean(b) => (if b { "on" } else { "off" }).to_owned(),
+            _ => return Err(format!("`{}.{}` must be a string, integer or boolean", key, name)),
+        };
+
+        res.push((name.clone(), value));
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(r#"
+prompt = "rust> "
+lib-paths = ["a", "b"]
+artifact-max-age = 48
+sandbox-write = ["/srv/out"]
+target-dir = "/scratch/rusti"
+
+[settings]
+opt-level = 2
+unload = true
+"#).unwrap();
+
+        assert_eq!(config.prompt, Some("rust> ".to_owned()));
+        assert_eq!(config.lib_paths, vec!["a", "b"]);
+        assert_eq!(config.artifact_max_age, Some(48));
+        assert_eq!(config.sandbox_write, vec!["/srv/out"]);
+        assert_eq!(config.target_dir, Some("/scratch/rusti".to_owned()));
+        assert_eq!(config.settings, vec![
+            ("opt-level".to_owned(), "2".to_owned()),
+            ("unload".to_owned(), "on".to_owned()),
+        ]);
+        assert!(Config::parse("colour = \"never\"").is_err());
+    }
+}
@@ -0,0 +1,367 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Dependencies declared by scripts and loaded files.
+//!
+//! The inner doc comments beginning a file may declare crates it uses,
+//! either on a single line:
+//!
+//! ```text
+//! //! cargo-deps: regex = "0.2", itertools = "0.7"
+//! ```
+//!
+//! or as part of a Cargo manifest, within a `cargo` code block:
+//!
+//! ```text
+//! //! ```cargo
+//! //! [dependencies]
+//! //! regex = { version = "0.2", default-features = false }
+//! //! ```
+//! ```
+//!
+//! Dependencies are built by Cargo, using the compiler of rusti's sysroot,
+//! in a package of their own within the cache directory. Each manifest has
+//! its own package, so dependencies are only built once. In sandbox mode,
+//! crates are fetched first, and then built without network access in a
+//! sandbox which may only write to the package (see `sandbox`).
+
+use std::collections::hash_map::DefaultHasher;
+use std::env::consts::EXE_SUFFIX;
+use std::env::{home_dir, var_os};
+use std::ffi::OsString;
+use std::fs::{create_dir_all, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use tempfile::NamedTempFile;
+use toml::Value;
+
+use artifacts::build_dir;
+use exec::ExecOptions;
+use sandbox::Policy;
+use version;
+
+/// Prefix of the line declaring dependencies
+const DEPS_PREFIX: &'static str = "cargo-deps:";
+
+/// Line beginning a code block containing a Cargo manifest
+const MANIFEST_BLOCK: &'static str = "```cargo";
+
+/// Crates known to expose foreign functions or raw system interfaces
+const FFI_CRATES: &'static [&'static str] = &["libc", "libloading", "memmap", "nix",
+    "winapi", "kernel32", "mach", "region"];
+
+/// Returns the Cargo manifest declared by the leading comments of a file,
+/// or `None` if it declares no dependencies.
+pub fn manifest(text: &str) -> Option<String> {
+    let mut deps = Vec::new();
+    let mut block = Vec::new();
+    let mut in_block = false;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        // A shebang line is not a comment, but may precede one
+        if (i == 0 && line.starts_with("#!") && !line.starts_with("#![")) || line.is_empty() {
+            continue;
+        }
+
+        if !line.starts_with("//") {
+            break;
+        }
+
+        if !line.starts_with("//!") {
+            continue;
+        }
+
+        let doc = line[3..].trim();
+
+        if in_block {
+            if doc == "```" {
+                in_block = false;
+            } else {
+                block.push(doc);
+            }
+        } else if doc == MANIFEST_BLOCK {
+            in_block = true;
+        } else if doc.starts_with(DEPS_PREFIX) {
+            deps.extend(split_deps(&doc[DEPS_PREFIX.len()..]));
+        }
+    }
+
+    if deps.is_empty() && block.is_empty() {
+        return None;
+    }
+
+    let mut res = String::new();
+
+    for line in block {
+        res.push_str(line);
+        res.push('\n');
+    }
+
+    if !deps.is_empty() {
+        res.push_str("[dependencies]\n");
+
+        for dep in deps {
+            res.push_str(dep);
+            res.push('\n');
+        }
+    }
+
+    Some(res)
+}
+
+/// Returns the names of the crates declared as dependencies by a manifest,
+/// as they are named in code, with `_` in place of `-`.
+pub fn crate_names(manifest: &str) -> Vec<String> {
+    let value = match manifest.parse::<Value>() {
+        Ok(value) => value,
+        Err(_) => return Vec::new(),
+    };
+
+    match value.get("dependencies").and_then(|deps| deps.as_table()) {
+        Some(deps) => deps.keys().map(|name| name.replace('-', "_")).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Returns whether a crate, named as in code, exposes foreign functions or
+/// raw interfaces of the system, through which safe code could corrupt
+/// memory. By convention, crates binding native libraries end in `_sys`.
+pub fn is_ffi_crate(name: &str) -> bool {
+    FFI_CRATES.contains(&name) || name.ends_with("_sys")
+}
+
+/// Reads the named file and returns the Cargo manifest it declares, if any.
+pub fn read_manifest(path: &Path) -> io::Result<Option<String>> {
+    let mut text = String::new();
+
+    File::open(path)?.read_to_string(&mut text)?;
+
+    Ok(manifest(&text))
+}
+
+/// Builds the dependencies of a Cargo manifest, using the compiler in the
+/// sysroot of `opts` and running at most its number of jobs, within a sandbox
+/// if `sandboxed` is `true`. Returns the directory containing the compiled
+/// crates, to be added to the library search path, or the errors reported by Cargo.
+pub fn build(manifest: &str, opts: &ExecOptions, sandboxed: bool) -> Result<PathBuf, String> {
+    let dir = match build_dir(opts.target_dir.as_ref()) {
+        Some(dir) => dir.join("deps").join(format!("{:016x}", cache_key(manifest))),
+        None => return Err("cannot find a directory in which to build dependencies".to_owned()),
+    };
+
+    create_package(&dir, manifest).map_err(|e|
+        format!("failed to create package in {}: {}", dir.display(), e))?;
+
+    let mut cmd = cargo_command(&dir, "build", opts, sandboxed)?;
+    cmd.arg("--quiet");
+
+    check_output(cmd.output())?;
+
+    Ok(dir.join("target").join("debug").join("deps"))
+}
+
+/// Documents the dependencies built by `build` in the given directory,
+/// as `build` builds them. Returns the directory containing the
+/// documentation, or the errors reported by Cargo.
+pub fn document(lib_dir: &Path, opts: &ExecOptions, sandboxed: bool) -> Result<PathBuf, String> {
+    // Libraries are built in `target/debug/deps` of the package
+    let dir = match lib_dir.parent().and_then(|d| d.parent()).and_then(|d| d.parent()) {
+        Some(dir) if dir.join("Cargo.toml").is_file() => dir,
+        _ => return Err(format!("{} was not built by rusti", lib_dir.display())),
+    };
+
+    let mut cmd = cargo_command(dir, "doc", opts, sandboxed)?;
+    cmd.arg("--quiet");
+
+    let rustdoc = opts.sysroot.join("bin").join(format!("rustdoc{}", EXE_SUFFIX));
+
+    if rustdoc.is_file() {
+        cmd.env("RUSTDOC", rustdoc);
+    }
+
+    check_output(cmd.output())?;
+
+    Ok(dir.join("target").join("doc"))
+}
+
+/// Returns a command running Cargo's `subcommand` on the package in `dir`,
+/// using the compiler in the sysroot of `opts` and running at most its
+/// number of jobs. If `sandboxed` is `true`, crates are fetched first,
+/// and the command is run in a sandbox.
+fn cargo_command(dir: &Path, subcommand: &str, opts: &ExecOptions, sandboxed: bool)
+        -> Result<Command, String> {
+    let sysroot = &opts.sysroot;
+    let cargo = var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+    let rustc = sysroot.join("bin").join(format!("rustc{}", EXE_SUFFIX));
+
+    let manifest_path = dir.join("Cargo.toml");
+
+    let mut cmd = if sandboxed {
+        // Build scripts and procedural macros of crates are run by the build
+        let fetched = Command::new(&cargo)
+            .arg("fetch").arg("--manifest-path").arg(&manifest_path)
+            .output();
+        check_output(fetched)?;
+
+        let mut readable = cargo_dirs();
+        readable.push(sysroot.to_path_buf());
+
+        let mut cmd = Policy::new(dir.to_path_buf()).command(&cargo,
+            &readable.iter().map(|p| p.as_path()).collect::<Vec<_>>());
+        cmd.arg(subcommand).arg("--frozen");
+        cmd
+    } else {
+        let mut cmd = Command::new(&cargo);
+        cmd.arg(subcommand);
+        cmd
+    };
+
+    cmd.arg("--manifest-path").arg(&manifest_path);
+
+    if let Some(jobs) = opts.jobs {
+        cmd.arg("--jobs").arg(jobs.to_string());
+    }
+
+    // Crates must be built by the compiler which rusti is built against
+    if rustc.is_file() {
+        cmd.env("RUSTC", rustc);
+    }
+
+    // A wrapper such as `sccache` may need a server or files outside the sandbox
+    match opts.rustc_wrapper {
+        Some(ref wrapper) if !sandboxed => cmd.env("RUSTC_WRAPPER", wrapper),
+        _ => cmd.env_remove("RUSTC_WRAPPER"),
+    };
+
+    Ok(cmd)
+}
+
+/// Returns the errors reported by Cargo if it failed.
+fn check_output(out: io::Result<Output>) -> Result<(), String> {
+    let out = out.map_err(|e| format!("failed to run cargo: {}", e))?;
+
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&out.stderr).trim_right().to_owned())
+    }
+}
+
+/// Returns the directories of Cargo and rustup, which contain the crates
+/// fetched by Cargo and, commonly, the `cargo` program itself.
+fn cargo_dirs() -> Vec<PathBuf> {
+    let dir = |var: &str, name: &str| match var_os(var) {
+        Some(ref d) if !d.is_empty() => Some(PathBuf::from(d)),
+        _ => home_dir().map(|h| h.join(name)),
+    };
+
+    dir("CARGO_HOME", ".cargo").into_iter()
+        .chain(dir("RUSTUP_HOME", ".rustup"))
+        .filter(|d| d.is_dir())
+        .collect()
+}
+
+/// Creates an empty library package with the given manifest, unless it exists.
+fn create_package(dir: &Path, manifest: &str) -> io::Result<()> {
+    let path = dir.join("Cargo.toml");
+
+    if path.is_file() {
+        return Ok(());
+    }
+
+    create_dir_all(dir.join("src"))?;
+    File::create(dir.join("src").join("lib.rs"))?;
+
+    // Sessions in other processes may create the same package at once.
+    // Replacing the manifest whole keeps Cargo from reading it half-written.
+    let mut f = NamedTempFile::new_in(dir)?;
+
+    write!(f, "[package]\nname = \"rusti-deps\"\nversion = \"0.0.0\"\nauthors = []\n\n{}",
+        manifest)?;
+
+    f.persist(&path).map(|_| ()).map_err(|e| e.error)
+}
+
+/// Returns the key under which the dependencies of a manifest are built.
+fn cache_key(manifest: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    version().hash(&mut hasher);
+    manifest.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+/// Splits a `cargo-deps` line into dependencies, at commas which are not
+/// within quotes, brackets or braces.
+fn split_deps(s: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (i, ch) in s.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            '[' | '{' if !quoted => depth += 1,
+            ']' | '}' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                res.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    res.push(s[start..].trim());
+    res.retain(|dep| !dep.is_empty());
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crate_names, is_ffi_crate, manifest, split_deps};
+
+    #[test]
+    fn test_crate_names() {
+        assert_eq!(crate_names("[dependencies]\nregex = \"0.2\"\n\
+            serde-json = { version = \"1\" }\n"), ["regex", "serde_json"]);
+        assert!(crate_names("[package]\nname = \"x\"\n").is_empty());
+    }
+
+    #[test]
+    fn test_is_ffi_crate() {
+        assert!(is_ffi_crate("libc"));
+        assert!(is_ffi_crate("openssl_sys"));
+        assert!(!is_ffi_crate("regex"));
+    }
+
+    #[test]
+    fn test_split_deps() {
+        assert_eq!(split_deps(r#" a = "1", b = { version = "2", features = ["x", "y"] },"#),
+            [r#"a = "1""#, r#"b = { version = "2", features = ["x", "y"] }"#]);
+        assert!(split_deps("").is_empty());
+    }
+
+    #[test]
+    fn test_manifest() {
+        assert_eq!(manifest("fn main() {}\n"), None);
+        assert_eq!(manifest("#!/usr/bin/env rusti\n// rusti: -L .\n\
+            //! cargo-deps: a = \"1\", b = \"2\"\nfn main() {}\n"),
+            Some("[dependencies]\na = \"1\"\nb = \"2\"\n".to_owned()));
+        assert_eq!(manifest("//! A file\n//!\n//! ```cargo\n//! [dependencies]\n\
+            //! a = \"1\"\n//! ```\n\nlet x = 1;\n//! cargo-deps: b = \"2\"\n"),
+            Some("[dependencies]\na = \"1\"\n".to_owned()));
+        assert_eq!(manifest("let x = 1;\n//! cargo-deps: b = \"2\"\n"), None);
+    }
+}
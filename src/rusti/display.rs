@@ -0,0 +1,120 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rich display data written by evaluated code.
+//!
+//! Code may emit display data, such as HTML or an image, by writing it to
+//! `stdout` between marker lines:
+//!
+//! ```text
+//! RUSTI_BEGIN_CONTENT text/html
+//! <b>bold</b>
+//! RUSTI_END_CONTENT
+//! ```
+//!
+//! The marker line names the MIME type of the data. Data of a binary type,
+//! such as `image/png`, is encoded using base64. When output is captured,
+//! display data is removed from the text of `stdout` and passed along
+//! separately, so that frontends can render it.
+
+/// Marker beginning display data, followed by a space and its MIME type
+pub const BEGIN_CONTENT: &'static str = "RUSTI_BEGIN_CONTENT";
+
+/// Marker ending display data
+pub const END_CONTENT: &'static str = "RUSTI_END_CONTENT";
+
+/// Display data written by evaluated code
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DisplayData {
+    /// MIME type of the data
+    pub mime: String,
+    /// Data, encoded using base64 if the MIME type is not textual
+    pub data: String,
+}
+
+/// Separates display data from other text written to `stdout`.
+///
+/// Returns the remaining text and the display data, in the order written.
+/// Display data missing its end marker is left in the text.
+pub fn extract(output: &str) -> (String, Vec<DisplayData>) {
+    let mut text = String::new();
+    let mut display = Vec::new();
+    let mut current: Option<(String, String)> = None;
+    let mut pending = String::new();
+    // Whether the last line was left in the text
+    let mut last_in_text = false;
+
+    for line in output.split_terminator('\n') {
+        let line_nl = format!("{}\n", line);
+
+        last_in_text = false;
+
+        if current.is_some() {
+            pending.push_str(&line_nl);
+
+            if line.trim_right() == END_CONTENT {
+                let (mime, mut data) = current.take().unwrap();
+
+                if data.ends_with('\n') {
+                    data.pop();
+                }
+                display.push(DisplayData{
+                    mime: mime,
+                    data: data,
+                });
+                pending.clear();
+            } else {
+                current.as_mut().unwrap().1.push_str(&line_nl);
+            }
+        } else if line.starts_with(BEGIN_CONTENT) &&
+                line[BEGIN_CONTENT.len()..].starts_with(' ') {
+            let mime = line[BEGIN_CONTENT.len()..].trim().to_owned();
+
+            pending.push_str(&line_nl);
+            current = Some((mime, String::new()));
+        } else {
+            text.push_str(&line_nl);
+            last_in_text = true;
+        }
+    }
+
+    text.push_str(&pending);
+
+    // Preserve text which did not end in a newline
+    if (last_in_text || current.is_some()) && !output.ends_with('\n') {
+        text.pop();
+    }
+
+    (text, display)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{extract, DisplayData};
+
+    #[test]
+    fn test_extract() {
+        let (text, display) = extract(
+            "a\nRUSTI_BEGIN_CONTENT text/html\n<b>b</b>\nRUSTI_END_CONTENT\nc\n");
+
+        assert_eq!(text, "a\nc\n");
+        assert_eq!(display, vec![DisplayData{
+            mime: "text/html".to_owned(),
+            data: "<b>b</b>".to_owned(),
+        }]);
+    }
+
+    #[test]
+    fn test_extract_unterminated() {
+        let output = "a\nRUSTI_BEGIN_CONTENT text/html\n<b>";
+        let (text, display) = extract(output);
+
+        assert_eq!(text, output);
+        assert!(display.is_empty());
+    }
+}
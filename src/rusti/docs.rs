@@ -0,0 +1,90 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Local HTML documentation, as opened by `.doc --open`.
+//!
+//! Documentation of the standard library is that installed in the sysroot,
+//! as by the `rust-docs` component of rustup. Documentation of dependencies
+//! is built by `cargo doc` in the package the dependencies were built in
+//! (see `deps`).
+
+use std::env::var_os;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory of the sysroot in which HTML documentation is installed
+const SYSROOT_DOC_DIR: &'static str = "share/doc/rust/html";
+
+/// Crates whose documentation is installed in the sysroot
+const STD_CRATES: &'static [&'static str] = &["alloc", "core", "proc_macro", "std", "test"];
+
+/// Program which opens a URL in the default browser, unless `BROWSER` is set
+#[cfg(target_os = "macos")]
+const OPEN: &'static str = "open";
+#[cfg(not(target_os = "macos"))]
+const OPEN: &'static str = "xdg-open";
+
+/// Returns the name of the crate documented by a page, as returned in
+/// `ItemDoc::file`: the first component of its path.
+pub fn page_crate(file: &str) -> &str {
+    file.split('/').next().unwrap_or("")
+}
+
+/// Returns the directory of the sysroot documenting the given crate,
+/// if it is a crate of the standard library and its documentation is installed.
+pub fn std_doc_dir(sysroot: &Path, krate: &str) -> Option<PathBuf> {
+    if !STD_CRATES.contains(&krate) {
+        return None;
+    }
+
+    let dir = sysroot.join(SYSROOT_DOC_DIR);
+
+    if dir.join(krate).is_dir() { Some(dir) } else { None }
+}
+
+/// Returns whether `dir`, the root of rustdoc's output, contains the given
+/// page, ignoring any anchor.
+pub fn has_page(dir: &Path, file: &str) -> bool {
+    dir.join(file.split('#').next().unwrap_or("")).is_file()
+}
+
+/// Returns the `file:` URL of a page within `dir`, the root of rustdoc's output.
+pub fn page_url(dir: &Path, file: &str) -> String {
+    format!("file://{}/{}", dir.display(), file)
+}
+
+/// Opens a URL in the browser named by `BROWSER`, or else the default browser.
+pub fn open(url: &str) -> Result<(), String> {
+    let browser = var_os("BROWSER").unwrap_or_else(|| OsString::from(OPEN));
+
+    let status = Command::new(&browser).arg(url).status()
+        .map_err(|e| format!("failed to run {}: {}", browser.to_string_lossy(), e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} failed to open {}", browser.to_string_lossy(), url))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{page_crate, page_url};
+
+    #[test]
+    fn test_page() {
+        let file = "std/vec/struct.Vec.html#method.push";
+
+        assert_eq!(page_crate(file), "std");
+        assert_eq!(page_url(Path::new("/doc"), file),
+            "file:///doc/std/vec/struct.Vec.html#method.push");
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Edits input in an external editor.
+
+use std::env::var;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::Command;
+
+use tempfile::NamedTempFile;
+
+/// Editor used when neither `VISUAL` nor `EDITOR` is set
+const DEFAULT_EDITOR: &'static str = "vi";
+
+/// Returns the editor command named by the `VISUAL` or `EDITOR`
+/// environment variables, or the default editor.
+pub fn default_editor() -> String {
+    for name in &["VISUAL", "EDITOR"] {
+        match var(name) {
+            Ok(ref e) if !e.trim().is_empty() => return e.clone(),
+            _ => (),
+        }
+    }
+
+    DEFAULT_EDITOR.to_owned()
+}
+
+/// Opens text in the given editor command and returns the text
+/// as it was saved when the editor exited.
+pub fn edit(text: &str, editor: &str) -> io::Result<String> {
+    let mut args = editor.split_whitespace();
+    let program = match args.next() {
+        Some(p) => p,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty editor")),
+    };
+
+    let mut file = NamedTempFile::new()?;
+
+    file.write_all(text.as_bytes())?;
+    file.flush()?;
+
+    let status = Command::new(program)
+        .args(args)
+        .arg(file.path())
+        .status()?;
+
+    if !status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other,
+            format!("`{}` exited with {}", editor, status)));
+    }
+
+    // The editor may have replaced the file, rather than writing to it
+    let mut res = String::new();
+    File::open(file.path())?.read_to_string(&mut res)?;
+
+    Ok(res)
+}
@@ -0,0 +1,190 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Entry points through which compiled input is run.
+//!
+//! Input is wrapped in a function generated by rusti, which is then looked up
+//! by name and called. The generated function is `#[no_mangle]` and
+//! `extern "C"`, so that its symbol name and calling convention are fixed
+//! regardless of the compiler version or the options used to compile it.
+//!
+//! If input ends with an expression, its formatted value is handed back
+//! through a callback supplied by the caller of the entry point, rather than
+//! being printed by the compiled code itself. The value itself may be kept
+//! alive for later input through another callback; see the `results` module.
+
+use std::slice;
+use std::str::from_utf8;
+
+use results::{DropFn, Kept};
+
+/// Signature of the callback through which an entry point hands back the
+/// formatted value of input, as UTF-8 bytes.
+/// The first argument is the context pointer passed to the entry point.
+pub type EmitFn = extern "C" fn(*mut u8, *const u8, usize);
+
+/// Signature of the callback through which an entry point hands over the
+/// value of input, moved to the heap, with a function to drop it and
+/// the name of its type, as UTF-8 bytes.
+/// The first argument is the context pointer passed to the entry point.
+pub type KeepFn = extern "C" fn(*mut u8, *mut u8, DropFn, *const u8, usize);
+
+/// Signature of every generated entry point.
+///
+/// The arguments are a context pointer and callbacks, each of which is
+/// called with that pointer at most once, if the input has a value.
+/// The returned value is `EXIT_SUCCESS` if the input ran to completion,
+/// or `EXIT_PANIC` if it panicked.
+pub type EntryFn = unsafe extern "C" fn(*mut u8, EmitFn, KeepFn) -> i32;
+
+/// Value returned by an entry point when input runs to completion
+pub const EXIT_SUCCESS: i32 = 0;
+
+/// Value returned by an entry point when input panics
+pub const EXIT_PANIC: i32 = 101;
+
+/// Describes a generated entry point
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntryPoint {
+    /// Unmangled symbol name of the entry point
+    pub name: String,
+}
+
+impl EntryPoint {
+    /// Returns an entry point with a name unique to the given input number.
+    ///
+    /// Each input is given its own entry point so that looking up the
+    /// function can never find one defined by previous input.
+    pub fn new(id: usize) -> EntryPoint {
+        EntryPoint{
+            name: format!("_rusti_run_{}", id),
+        }
+    }
+
+    /// Returns source defining the entry point, which runs the function
+    /// `inner`, catching any panic.
+    ///
+    /// `inner` is called with the context pointer and the keep callback,
+    /// of type `_RustiKeepFn`, and must return `Option<String>`, the formatted
+    /// value of input. It may keep the value by passing both to the generated
+    /// function `_rusti_keep`, which requires the feature `core_intrinsics`.
+    /// Only values of types which are `'static` can be kept.
+    pub fn define(&self, inner: &str) -> String {
+        format!(r#"
+type _RustiKeepFn = extern "C" fn(*mut u8, *mut u8,
+    unsafe extern "C" fn(*mut u8), *const u8, usize);
+
+#[no_mangle]
+pub extern "C" fn {name}(ctx: *mut u8,
+        emit: extern "C" fn(*mut u8, *const u8, usize),
+        keep: _RustiKeepFn) -> i32 {{
+    let inner = std::panic::AssertUnwindSafe(|| {inner}(ctx, keep));
+
+    match std::panic::catch_unwind(inner) {{
+        Ok(Some(v)) => {{
+            emit(ctx, v.as_ptr(), v.len());
+            {success}
+        }}
+        Ok(None) => {success},
+        Err(_) => {panic},
+    }}
+}}
+
+fn _rusti_keep<T: 'static>(ctx: *mut u8, keep: _RustiKeepFn, value: T) {{
+    // A panic in the value's destructor must not unwind into rusti
+    unsafe extern "C" fn drop_value<T>(p: *mut u8) {{
+        let value = std::panic::AssertUnwindSafe(Box::from_raw(p as *mut T));
+        let _ = std::panic::catch_unwind(move || drop(value));
+    }}
+
+    let ty = unsafe {{ std::intrinsics::type_name::<T>() }};
+    let p = Box::into_raw(Box::new(value)) as *mut u8;
+
+    keep(ctx, p, drop_value::<T>, ty.as_ptr(), ty.len());
+}}
+"#,
+            name = self.name,
+            inner = inner,
+            success = EXIT_SUCCESS,
+            panic = EXIT_PANIC)
+    }
+
+    /// Returns source defining a `main` function which runs the entry point
+    /// and exits with its returned value, for use in compiled executables.
+    ///
+    /// An executable cannot hand a value back to rusti, so the value of input
+    /// is printed to `stdout` instead, and is not kept.
+    pub fn define_main(&self) -> String {
+        format!(r#"
+extern "C" fn _rusti_emit(_: *mut u8, ptr: *const u8, len: usize) {{
+    let v = unsafe {{ std::slice::from_raw_parts(ptr, len) }};
+    println!("{{}}", String::from_utf8_lossy(v));
+}}
+
+extern "C" fn _rusti_discard(_: *mut u8, p: *mut u8,
+        drop: unsafe extern "C" fn(*mut u8), _: *const u8, _: usize) {{
+    unsafe {{ drop(p) }}
+}}
+
+fn main() {{
+    std::process::exit({name}(std::ptr::null_mut(), _rusti_emit, _rusti_discard));
+}}
+"#,
+            name = self.name)
+    }
+}
+
+/// Describes the result of calling an entry point
+#[derive(Debug, Default)]
+pub struct EntryResult {
+    /// Value returned by the entry point
+    pub status: i32,
+    /// Formatted value of input, if it had one
+    pub value: Option<String>,
+    /// Value of input, if it was kept
+    pub kept: Option<Kept>,
+}
+
+/// Calls an entry point, collecting the values it hands back.
+///
+/// The function pointer must point to a function generated by
+/// `EntryPoint::define`.
+pub unsafe fn call(f: EntryFn) -> EntryResult {
+    let mut res = EntryResult::default();
+    let ctx = &mut res as *mut EntryResult as *mut u8;
+
+    res.status = f(ctx, emit_value, keep_value);
+    res
+}
+
+extern "C" fn emit_value(ctx: *mut u8, ptr: *const u8, len: usize) {
+    if ctx.is_null() || ptr.is_null() {
+        return;
+    }
+
+    let res = unsafe { &mut *(ctx as *mut EntryResult) };
+    let bytes = unsafe { slice::from_raw_parts(ptr, len) };
+
+    res.value = Some(String::from_utf8_lossy(bytes).into_owned());
+}
+
+extern "C" fn keep_value(ctx: *mut u8, ptr: *mut u8, drop: DropFn,
+        ty: *const u8, ty_len: usize) {
+    if ctx.is_null() || ptr.is_null() {
+        return;
+    }
+
+    let res = unsafe { &mut *(ctx as *mut EntryResult) };
+    let ty = unsafe { slice::from_raw_parts(ty, ty_len) };
+
+    res.kept = Some(Kept{
+        ptr: ptr,
+        drop: drop,
+        type_name: from_utf8(ty).unwrap_or("").to_owned(),
+    });
+}
@@ -9,234 +9,2514 @@
 //! Rust code parsing and compilation.
 
 use std::any::Any;
+use std::boxed::FnBox;
+use std::collections::HashSet;
+use std::env::var_os;
 use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::c_void;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::ptr;
 use std::process::Command;
 use std::rc::Rc;
+use std::slice;
 use std::str::from_utf8;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::mpsc::{channel, Receiver};
 use std::thread::Builder;
+use std::time::Instant;
 
-use rustc;
 use rustc_lint;
 
 use rustc::dep_graph::DepGraph;
+use rustc::hir;
+use rustc::hir::def::Def;
+use rustc::hir::def_id::DefId;
+use rustc::hir::intravisit::{self, NestedVisitorMap, Visitor};
 use rustc::hir::map as ast_map;
+use rustc::hir::map::DefPathData;
+use rustc::lint;
 use rustc_llvm as llvm;
 use rustc::middle::cstore::LinkagePreference::RequireDynamic;
+use rustc::mir::mono::MonoItem;
+use rustc::traits;
 use rustc::ty;
-use rustc::session::build_session;
+use rustc::ty::adjustment::{Adjust, Adjustment, AutoBorrow};
+use rustc::ty::layout::{LayoutOf, TyLayout, Variants};
+use rustc::ty::subst::Subst;
+use rustc::session::{build_session, build_session_with_codemap};
 use rustc::session::config::{self, basic_options, build_configuration,
-    ErrorOutputType, Input, Options, OptLevel, OutputFilenames};
+    ErrorOutputType, Input, Options, OptLevel, OutputFilenames, OutputType,
+    OutputTypes, PrintRequest, Sanitizer};
+use rustc_back::target::{get_targets, Target};
 use rustc_driver::driver;
 use rustc_metadata::cstore::CStore;
+use rustc_mir::monomorphize::collector::{collect_crate_mono_items, MonoItemCollectionMode};
 use rustc_resolve::MakeGlobMap;
-use rustc_trans::ModuleSource;
+use rustc_trans;
 use rustc_plugin::registry::Registry as PluginRegistry;
 
-use syntax::ast::Crate;
-use syntax::codemap::{MultiSpan, FileName};
+use artifacts::env_target_dir;
+use backtrace;
+use entry::{self, EntryFn, EntryPoint, EntryResult};
+use heap::{self, HeapStats};
+use hole::{self, Hole};
+use layout::{Discriminant, FieldLayout, TypeLayout, VariantLayout};
+use phases::PhaseTimes;
+use selection::Selection;
+
+use syntax::ast::{self, Crate};
+use syntax::codemap::{BytePos, CodeMap, MultiSpan, FileName, Span, DUMMY_SP};
 use syntax::errors;
+use syntax::errors::ColorConfig;
 use syntax::errors::emitter::EmitterWriter;
 use syntax::errors::registry::Registry;
+use syntax::ext::hygiene::SyntaxContext;
 use syntax::feature_gate::UnstableFeatures;
 
 /// Compiles input code into an execution environment.
 pub struct ExecutionEngine {
     ee: llvm::ExecutionEngineRef,
-    modules: Vec<llvm::ModuleRef>,
+    /// Modules added to the execution engine, each with the LLVM context
+    /// which owns it and is disposed of along with it
+    modules: Vec<(llvm::ContextRef, llvm::ModuleRef)>,
+    libraries: LoadedLibraries,
+    opts: ExecOptions,
+    /// Number of inputs compiled; used to give each a unique crate disambiguator
+    compiled: usize,
+    /// Time spent in each phase for the most recently added module
+    last_phases: PhaseTimes,
+    /// Time spent in each phase for all modules added since construction
+    total_phases: PhaseTimes,
+    /// Linker which linked the most recent input, if it was built into
+    /// an executable rather than added to the execution engine
+    last_linker: Option<String>,
+    /// Names of allocator functions mapped to the hooks in `heap`
+    heap_hooks: HashSet<&'static str>,
+    /// Heap allocations made by the most recently called entry point
+    last_heap: HeapStats,
+    /// Heap allocations made by all entry points called since construction
+    total_heap: HeapStats,
+    /// Set to cancel compilation of input in progress
+    cancel: Arc<AtomicBool>,
+}
+
+/// Records the dynamic libraries loaded for crates used by compiled input
+///
+/// Libraries remain loaded for the lifetime of the process, as the
+/// execution engine resolves symbols from them. Each is loaded only once.
+#[derive(Debug, Default)]
+pub struct LoadedLibraries {
+    paths: Vec<PathBuf>,
+}
+
+impl LoadedLibraries {
+    /// Returns whether the library at the given path has been loaded.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.iter().any(|p| p == path)
+    }
+
+    /// Returns an iterator over the paths of loaded libraries,
+    /// in the order they were loaded.
+    pub fn iter(&self) -> slice::Iter<PathBuf> {
+        self.paths.iter()
+    }
+
+    /// Returns the number of loaded libraries.
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    fn insert(&mut self, path: PathBuf) {
+        self.paths.push(path);
+    }
+}
+
+/// Lints allowed unless their levels are set otherwise. Every input is
+/// compiled with all items defined earlier, which would be warned of again.
+const ALLOWED_LINTS: &'static [&'static str] = &["dead_code", "unused_imports", "unused_features"];
+
+/// Prefixes of symbols which the compiler defines in every crate, such as
+/// the allocator shim, rather than for items of the crate's code.
+/// These are also defined by the standard library, which is always loaded.
+const GENERATED_SYMBOL_PREFIXES: &'static [&'static str] = &["__rust_", "__rustc_",
+    "rust_eh_"];
+
+/// Options used to compile input for an `ExecutionEngine`
+#[derive(Clone, Debug)]
+pub struct ExecOptions {
+    /// Rust sysroot used to locate the standard library
+    pub sysroot: PathBuf,
     /// Additional search paths for libraries
-    lib_paths: Vec<String>,
-    sysroot: PathBuf,
+    pub lib_paths: Vec<String>,
+    /// Linker to use in place of the target's default, as `-C linker`.
+    /// Only executables are linked by the linker; code run within rusti
+    /// is linked in memory by the JIT.
+    pub linker: Option<PathBuf>,
+    /// Additional linker arguments, as `-C link-arg`, likewise only passed
+    /// when linking executables
+    pub link_args: Vec<String>,
+    /// Optimization level, as `-C opt-level`
+    pub opt_level: OptLevel,
+    /// Number of codegen units into which input is split, as `-C codegen-units`.
+    /// Units are optimized in parallel, then linked into a single module
+    /// for the execution engine.
+    pub codegen_units: usize,
+    /// Whether codegen units are optimized with ThinLTO before they are
+    /// linked, as `-Z thinlto`, so that functions of one unit are inlined
+    /// into the others. This applies only to optimized input of several units.
+    pub thin_lto: bool,
+    /// Maximum number of jobs run in parallel, by codegen and by Cargo when
+    /// building dependencies, as `-j`. If `None`, there is no limit.
+    pub jobs: Option<usize>,
+    /// Program wrapping `rustc` in builds of dependencies by Cargo, such as
+    /// `sccache`, as `RUSTC_WRAPPER`. Input is compiled within rusti's own
+    /// process, and is not wrapped.
+    pub rustc_wrapper: Option<PathBuf>,
+    /// Directory in which artifacts are written and dependencies are built,
+    /// in place of the temporary and cache directories; see `artifacts`
+    pub target_dir: Option<PathBuf>,
+    /// Whether compiler diagnostics are emitted as JSON, as `--error-format json`
+    pub json_diagnostics: bool,
+    /// Whether compiler diagnostics are colored, as `--color`
+    pub color: ColorConfig,
+    /// Target triple to compile for, as `--target`.
+    /// If `None`, code is compiled for the host.
+    pub target: Option<String>,
+    /// Sanitizer to instrument executables with, as `-Z sanitizer`.
+    /// Code loaded into the execution engine is never instrumented,
+    /// as the sanitizer runtime cannot be loaded into a running process.
+    pub sanitizer: Option<Sanitizer>,
+    /// Whether intermediate files written while building executables are
+    /// kept, as `-C save-temps`
+    pub save_temps: bool,
+    /// Amount of diagnostic output written while compiling input
+    pub verbosity: Verbosity,
+    /// How warnings emitted while compiling input are treated
+    pub warnings: Warnings,
+    /// Levels of lints, as `-A`, `-W` and `-D`, in the order they were set.
+    /// Later levels take precedence.
+    pub lints: Vec<(String, lint::Level)>,
+}
+
+/// How warnings emitted while compiling input are treated, as `.set warnings`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Warnings {
+    /// Warnings are errors, as `-D warnings`
+    Deny,
+    /// Warnings are emitted
+    Show,
+    /// Warnings are not emitted, as `--cap-lints allow`
+    Hide,
+}
+
+/// Reason for which input could not be analyzed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AnalysisError {
+    /// Input failed to compile; its errors have been emitted
+    Failed,
+    /// The compiler panicked; the panic has been reported to `stderr`
+    Panicked,
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AnalysisError::Failed => f.write_str("input failed to compile"),
+            AnalysisError::Panicked => f.write_str("the compiler panicked"),
+        }
+    }
+}
+
+/// Callbacks run between phases of compiling input added to an
+/// `ExecutionEngine`, so that input can be inspected at each phase
+/// by the same compilation which runs it.
+///
+/// Hooks run on the compiler's thread, which is joined before compilation
+/// returns, so they may borrow from the caller. A hook is not called if
+/// compilation fails or is cancelled before its phase.
+#[derive(Default)]
+pub struct CompileHooks<'a> {
+    after_parse: Option<Box<FnMut(&Crate) + Send + 'a>>,
+    after_hir_lowering: Option<Box<for<'hir> FnMut(&ast_map::Map<'hir>) + Send + 'a>>,
+    after_analysis: Option<Box<for<'b, 'gcx, 'tcx> FnMut(&ty::TyCtxt<'b, 'gcx, 'tcx>) + Send + 'a>>,
+    after_llvm: Option<Box<FnMut(llvm::ModuleRef) + Send + 'a>>,
+}
+
+impl<'a> CompileHooks<'a> {
+    /// Returns a set of hooks, none of which do anything.
+    pub fn new() -> CompileHooks<'a> {
+        CompileHooks::default()
+    }
+
+    /// Calls `f` with the crate as parsed, before macro expansion.
+    pub fn after_parse<F>(mut self, f: F) -> CompileHooks<'a>
+            where F: FnMut(&Crate) + Send + 'a {
+        self.after_parse = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the HIR map of the crate, once it has been expanded
+    /// and lowered, before analysis.
+    pub fn after_hir_lowering<F>(mut self, f: F) -> CompileHooks<'a>
+            where F: for<'hir> FnMut(&ast_map::Map<'hir>) + Send + 'a {
+        self.after_hir_lowering = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the type context, once analysis has emitted no errors,
+    /// before translation.
+    pub fn after_analysis<F>(mut self, f: F) -> CompileHooks<'a>
+            where F: for<'b, 'gcx, 'tcx> FnMut(&ty::TyCtxt<'b, 'gcx, 'tcx>) + Send + 'a {
+        self.after_analysis = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the translated LLVM module, before it is added
+    /// to the execution engine.
+    pub fn after_llvm<F>(mut self, f: F) -> CompileHooks<'a>
+            where F: FnMut(llvm::ModuleRef) + Send + 'a {
+        self.after_llvm = Some(Box::new(f));
+        self
+    }
+}
+
+/// Amount of diagnostic output written while compiling input, as `.set verbosity`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verbosity {
+    /// Warnings are not emitted, as `--cap-lints allow`
+    Quiet,
+    /// Warnings and errors are emitted
+    Normal,
+    /// The equivalent `rustc` command line and the source of each program
+    /// are also written to `stderr`, followed by the time spent compiling it
+    Debug,
+}
+
+impl ExecOptions {
+    /// Constructs a new `ExecOptions` with the given library search paths.
+    /// If `sysroot` is `None`, `rustc` is asked for its sysroot.
+    pub fn new(libs: Vec<String>, sysroot: Option<PathBuf>) -> ExecOptions {
+        ExecOptions{
+            sysroot: sysroot.unwrap_or_else(get_sysroot),
+            lib_paths: libs,
+            linker: None,
+            link_args: Vec::new(),
+            // Prefer faster build times
+            opt_level: OptLevel::No,
+            codegen_units: 1,
+            thin_lto: true,
+            jobs: None,
+            rustc_wrapper: match var_os("RUSTC_WRAPPER") {
+                Some(ref w) if !w.is_empty() => Some(PathBuf::from(w)),
+                _ => None,
+            },
+            target_dir: env_target_dir(),
+            json_diagnostics: false,
+            color: ColorConfig::Auto,
+            target: None,
+            sanitizer: None,
+            save_temps: false,
+            verbosity: Verbosity::Normal,
+            warnings: Warnings::Show,
+            lints: Vec::new(),
+        }
+    }
+
+    /// Returns the arguments of a `rustc` command line, beginning with `rustc`,
+    /// which compiles input as the execution engine does.
+    pub fn rustc_args(&self) -> Vec<String> {
+        let mut args = vec!["rustc".to_owned(), "--crate-type".to_owned(), "dylib".to_owned(),
+            "--sysroot".to_owned(), self.sysroot.display().to_string()];
+
+        for path in &self.lib_paths {
+            args.push("-L".to_owned());
+            args.push(path.clone());
+        }
+
+        args.push("-C".to_owned());
+        args.push(format!("opt-level={}", opt_level_name(self.opt_level)));
+        args.push("-C".to_owned());
+        args.push(format!("codegen-units={}", self.codegen_units));
+        args.push("-Z".to_owned());
+        args.push(format!("thinlto={}", if self.thin_lto { "yes" } else { "no" }));
+
+        if let Some(ref linker) = self.linker {
+            args.push("-C".to_owned());
+            args.push(format!("linker={}", linker.display()));
+        }
+
+        for arg in &self.link_args {
+            args.push("-C".to_owned());
+            args.push(format!("link-arg={}", arg));
+        }
+
+        if let Some(ref target) = self.target {
+            args.push("--target".to_owned());
+            args.push(target.clone());
+        }
+
+        if self.json_diagnostics {
+            args.push("--error-format".to_owned());
+            args.push("json".to_owned());
+        }
+
+        args.push("--color".to_owned());
+        args.push(match self.color {
+            ColorConfig::Auto => "auto",
+            ColorConfig::Always => "always",
+            ColorConfig::Never => "never",
+        }.to_owned());
+
+        args.extend(self.lint_args());
+
+        args
+    }
+
+    /// Returns the `rustc` arguments setting the levels of lints.
+    pub fn lint_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        for (name, level) in self.lint_levels() {
+            args.push(match level {
+                lint::Allow => "-A",
+                lint::Warn => "-W",
+                lint::Deny => "-D",
+                lint::Forbid => "-F",
+            }.to_owned());
+            args.push(name);
+        }
+
+        if self.caps_lints() {
+            args.push("--cap-lints".to_owned());
+            args.push("allow".to_owned());
+        }
+
+        args
+    }
+
+    /// Returns the level of each lint set, in order of increasing precedence.
+    fn lint_levels(&self) -> Vec<(String, lint::Level)> {
+        let mut levels = ALLOWED_LINTS.iter()
+            .map(|&name| (name.to_owned(), lint::Allow))
+            .collect::<Vec<_>>();
+
+        if self.warnings == Warnings::Deny {
+            levels.push(("warnings".to_owned(), lint::Deny));
+        }
+
+        levels.extend(self.lints.iter().cloned());
+        levels
+    }
+
+    /// Returns whether all lints are allowed, whatever their levels.
+    fn caps_lints(&self) -> bool {
+        self.warnings == Warnings::Hide || self.verbosity == Verbosity::Quiet
+    }
+
+    /// Returns whether the target supports dynamic libraries, which are
+    /// required to load compiled input into the execution engine.
+    pub fn supports_dylib(&self) -> bool {
+        match Target::search(self.target_triple()) {
+            Ok(target) => target.options.dynamic_linking &&
+                (!target.options.crt_static_default ||
+                    target.options.crt_static_allows_dylibs),
+            // Let the compiler report the unknown target
+            Err(_) => true,
+        }
+    }
+
+    /// Returns the triple of the target code is compiled for.
+    pub fn target_triple(&self) -> &str {
+        self.target.as_ref().map(|s| &s[..]).unwrap_or(config::host_triple())
+    }
+
+    /// Returns whether code is compiled for a target other than the host,
+    /// which cannot be run within the `rusti` process.
+    pub fn is_cross_compiling(&self) -> bool {
+        match self.target {
+            Some(ref t) => t != config::host_triple(),
+            None => false,
+        }
+    }
+
+    /// Returns a description of the linker in use.
+    pub fn linker_name(&self) -> String {
+        let name = match self.linker {
+            Some(ref p) => p.display().to_string(),
+            None => "default".to_owned(),
+        };
+
+        if self.link_args.is_empty() {
+            name
+        } else {
+            format!("{} {}", name, self.link_args.join(" "))
+        }
+    }
+}
+
+/// A value that can be translated into `ExecutionEngine` input
+pub trait IntoInput {
+    fn into_input(self) -> Input;
+}
+
+impl<'a> IntoInput for &'a str {
+    fn into_input(self) -> Input {
+        self.to_owned().into_input()
+    }
+}
+
+impl IntoInput for String {
+    fn into_input(self) -> Input {
+        Input::Str{
+            name: FileName::Custom("<input>".to_owned()),
+            input: self,
+        }
+    }
+}
+
+impl IntoInput for PathBuf {
+    fn into_input(self) -> Input {
+        Input::File(self)
+    }
+}
+
+type Deps = Vec<PathBuf>;
+
+impl ExecutionEngine {
+    /// Constructs a new `ExecutionEngine` with the given library search paths.
+    pub fn new(libs: Vec<String>, sysroot: Option<PathBuf>) -> ExecutionEngine {
+        ExecutionEngine::new_with_input(String::new(), libs, sysroot)
+    }
+
+    /// Constructs a new `ExecutionEngine` with the given starting input
+    /// and library search paths.
+    pub fn new_with_input<T>(input: T, libs: Vec<String>, sysroot: Option<PathBuf>) -> ExecutionEngine
+            where T: IntoInput {
+        ExecutionEngine::with_options(input, ExecOptions::new(libs, sysroot))
+    }
+
+    /// Constructs a new `ExecutionEngine` with the given starting input
+    /// and compile options.
+    ///
+    /// If the target does not support dynamic libraries, no input can be
+    /// loaded into the execution engine and the starting input is ignored.
+    pub fn with_options<T>(input: T, opts: ExecOptions) -> ExecutionEngine
+            where T: IntoInput {
+        let (llcx, llmod, deps) = if opts.supports_dylib() {
+            let cancel = Arc::new(AtomicBool::new(false));
+            let (llcx, llmod, deps, _) = compile_input(input.into_input(), opts.clone(), 0,
+                cancel, CompileHooks::new())
+                .expect("ExecutionEngine init input failed to compile");
+            (llcx, llmod, deps)
+        } else {
+            let (llcx, llmod) = empty_module();
+            (llcx, llmod, Vec::new())
+        };
+
+        let ee = unsafe { llvm::LLVMBuildExecutionEngine(llmod) };
+
+        if ee.is_null() {
+            panic!("Failed to create ExecutionEngine: {}", llvm_error());
+        }
+
+        let mut ee = ExecutionEngine{
+            ee: ee,
+            modules: vec![(llcx, llmod)],
+            libraries: LoadedLibraries::default(),
+            opts: opts,
+            compiled: 1,
+            last_phases: PhaseTimes::default(),
+            total_phases: PhaseTimes::default(),
+            last_linker: None,
+            heap_hooks: HashSet::new(),
+            last_heap: HeapStats::default(),
+            total_heap: HeapStats::default(),
+            cancel: Arc::new(AtomicBool::new(false)),
+        };
+
+        ee.load_deps(&deps);
+
+        ee
+    }
+
+    /// Compile a module and add it to the execution engine.
+    /// If the module fails to compile, errors will be printed to `stderr`
+    /// and `None` will be returned. Otherwise, the module is returned.
+    ///
+    /// A warning is printed to `stderr` for each symbol defined by the module
+    /// which is also defined by a library loaded into the process, as the
+    /// reference may be resolved to either definition.
+    pub fn add_module<T>(&mut self, input: T) -> Option<llvm::ModuleRef>
+            where T: IntoInput {
+        self.add_module_with_hooks(input, CompileHooks::new())
+    }
+
+    /// Compiles a module and adds it to the execution engine as `add_module`
+    /// does, calling the given hooks as each phase of compilation completes.
+    pub fn add_module_with_hooks<T>(&mut self, input: T, hooks: CompileHooks)
+            -> Option<llvm::ModuleRef> where T: IntoInput {
+        debug!("compiling module");
+
+        let id = self.compiled;
+        self.compiled += 1;
+        self.last_linker = None;
+
+        let input = input.into_input();
+
+        if self.opts.verbosity == Verbosity::Debug {
+            print_debug_input(&self.opts, &input);
+        }
+
+        let (llcx, llmod, deps, mut phases) = match compile_input(input,
+                self.opts.clone(), id, self.cancel.clone(), hooks) {
+            Some(r) => r,
+            None => return None,
+        };
+
+        let start = Instant::now();
+
+        self.load_deps(&deps);
+
+        for name in library_collisions(llmod) {
+            if self.opts.verbosity == Verbosity::Quiet {
+                break;
+            }
+
+            eprintln!("warning: symbol `{}` is also defined by a loaded library; \
+                references to it may not resolve to the definition in this input", name);
+        }
+
+        self.modules.push((llcx, llmod));
+
+        self.map_heap_hooks(llmod);
+        externalize_functions(llmod);
+
+        unsafe { llvm::LLVMExecutionEngineAddModule(self.ee, llmod); }
+
+        phases.link = start.elapsed();
+        self.last_phases = phases;
+        self.total_phases += phases;
+
+        Some(llmod)
+    }
+
+    /// Remove the given module from the execution engine.
+    /// The module and its context are destroyed after it is removed.
+    ///
+    /// # Panics
+    ///
+    /// If the Module does not exist within this `ExecutionEngine`.
+    pub fn remove_module(&mut self, llmod: llvm::ModuleRef) {
+        match self.modules.iter().position(|&(_, m)| m == llmod) {
+            Some(i) => {
+                let (llcx, _) = self.modules.remove(i);
+                let res = unsafe {
+                    llvm::LLVMExecutionEngineRemoveModule(self.ee, llmod)
+                };
+
+                assert_eq!(res, 1);
+
+                unsafe {
+                    llvm::LLVMDisposeModule(llmod);
+                    llvm::LLVMContextDispose(llcx);
+                }
+            },
+            None => panic!("Module not contained in ExecutionEngine"),
+        }
+    }
+
+    /// Removes and destroys all modules added since the `ExecutionEngine`
+    /// was constructed, releasing the memory used by their code and data.
+    ///
+    /// This is only safe if no code or data from these modules remains in use;
+    /// e.g. by running threads, function pointers, or `'static` references.
+    /// Libraries loaded for crates used by these modules remain loaded.
+    pub fn unload_modules(&mut self) {
+        while self.modules.len() > 1 {
+            let (_, llmod) = *self.modules.last().unwrap();
+            self.remove_module(llmod);
+        }
+    }
+
+    /// Returns the dynamic libraries loaded for crates used by compiled input.
+    pub fn loaded_libraries(&self) -> &LoadedLibraries {
+        &self.libraries
+    }
+
+    /// Compiles the given input only up to the analysis phase, calling the
+    /// given closure with a borrowed reference to the type context and
+    /// the produced analysis, if the input is free of errors.
+    ///
+    /// The closure runs on the compiler's thread, which is joined before
+    /// returning, so it may borrow from the caller.
+    pub fn with_analysis<F, R, T>(&self, input: T, f: F) -> Result<R, AnalysisError>
+            where F: Send, R: Send, T: IntoInput,
+            F: for<'a, 'gcx, 'tcx> FnOnce(&Crate, &ty::TyCtxt<'a, 'gcx, 'tcx>, ty::CrateAnalysis) -> R {
+        with_analysis(f, input.into_input(), self.opts.clone(), None, false)
+    }
+
+    /// Compiles the given input up to the analysis phase as `with_analysis`
+    /// does, but calls the closure even if analysis emitted errors, as long
+    /// as the input could be parsed and expanded.
+    pub fn with_partial_analysis<F, R, T>(&self, input: T, f: F) -> Result<R, AnalysisError>
+            where F: Send, R: Send, T: IntoInput,
+            F: for<'a, 'gcx, 'tcx> FnOnce(&Crate, &ty::TyCtxt<'a, 'gcx, 'tcx>, ty::CrateAnalysis) -> R {
+        with_analysis(f, input.into_input(), self.opts.clone(), None, true)
+    }
+
+    /// Compiles the given input only up to the analysis phase, emitting any
+    /// errors without translating or running it.
+    /// Returns `true` if the input is free of errors.
+    pub fn check<T>(&self, input: T) -> bool where T: IntoInput {
+        self.with_analysis(input, |_krate, _tcx, _analysis| ()).is_ok()
+    }
+
+    /// Type-checks input as `check` does, but in a thread of its own,
+    /// returning at once. Once checking finishes, the diagnostics emitted,
+    /// as JSON objects on separate lines, are sent through the returned
+    /// channel, rather than being written to `stderr`.
+    pub fn check_in_background<T>(&self, input: T) -> Receiver<String> where T: IntoInput {
+        let (tx, rx) = channel();
+        let input = input.into_input();
+        let mut opts = self.opts.clone();
+
+        opts.json_diagnostics = true;
+
+        let thread = Builder::new().name("check_in_background".to_owned());
+
+        let res = thread.spawn(move || {
+            let data = Arc::new(Mutex::new(Vec::new()));
+
+            let _ = with_analysis(|_krate, _tcx, _analysis| (), input, opts,
+                Some(Box::new(SyncBuf(data.clone()))), true);
+
+            let diagnostics = String::from_utf8_lossy(&data.lock().unwrap()).into_owned();
+            let _ = tx.send(diagnostics);
+        });
+
+        if let Err(e) = res {
+            debug!("failed to spawn check thread: {}", e);
+        }
+
+        rx
+    }
+
+    /// Resolves paths and method calls in the given input.
+    /// Returns, for each one which refers to an item defined in the input,
+    /// the byte offsets within the input source of the reference and of
+    /// the definition.
+    pub fn resolve_paths<T>(&self, input: T) -> Option<Vec<(usize, usize)>>
+            where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let mut v = PathResolver{
+                tcx: *tcx,
+                tables: None,
+                paths: Vec::new(),
+            };
+
+            tcx.hir.krate().visit_all_item_likes(&mut v.as_deep_visitor());
+            v.paths
+        }).ok()
+    }
+
+    /// Infers the types of variables bound by `let` statements in the given
+    /// input which begin at or after the byte offset `start`.
+    /// Returns the name and type of each, in order of their offsets.
+    pub fn let_types<T>(&self, input: T, start: usize) -> Option<Vec<(String, String)>>
+            where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let mut v = LetTypes{
+                tcx: *tcx,
+                tables: None,
+                start: start,
+                types: Vec::new(),
+            };
+
+            tcx.hir.krate().visit_all_item_likes(&mut v.as_deep_visitor());
+
+            v.types.sort_by_key(|&(offset, _, _)| offset);
+            v.types.into_iter().map(|(_, name, ty)| (name, ty)).collect()
+        }).ok()
+    }
+
+    /// Finds the holes in the given input, calls to `hole::FUNCTION`, with the
+    /// type expected in place of each and the values and functions in scope
+    /// which fit it. Holes are returned in order of their offsets.
+    ///
+    /// Holes whose type can't be inferred cause errors, but are still returned.
+    pub fn holes<T>(&self, input: T) -> Option<Vec<Hole>> where T: IntoInput {
+        self.with_partial_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+
+            let mut v = HoleFinder{
+                tcx: tcx,
+                tables: None,
+                locals: Vec::new(),
+                items: hole_candidates(tcx),
+                holes: Vec::new(),
+            };
+
+            tcx.hir.krate().visit_all_item_likes(&mut v.as_deep_visitor());
+
+            v.holes.sort_by_key(|h| h.offset);
+            v.holes
+        }).ok()
+    }
+
+    /// Explains the method selected for the outermost method call or
+    /// overloaded operator in the given input which begins at or after
+    /// the byte offset `start`.
+    ///
+    /// Returns `Some(None)` if there is no such call, or `None` if the input
+    /// fails to compile.
+    pub fn explain_selection<T>(&self, input: T, start: usize) -> Option<Option<Selection>>
+            where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+
+            let mut v = CallFinder{
+                tcx: tcx,
+                tables: None,
+                start: start,
+                found: None,
+            };
+
+            tcx.hir.krate().visit_all_item_likes(&mut v.as_deep_visitor());
+            v.found.map(|(_, sel)| sel)
+        }).ok()
+    }
+
+    /// Infers the type of the innermost expression or pattern of the given
+    /// input containing the byte offset `offset`.
+    /// Returns `Some(None)` if there is no expression at the offset.
+    pub fn type_at<T>(&self, input: T, offset: usize) -> Option<Option<String>>
+            where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let mut v = TypeAt{
+                tcx: *tcx,
+                tables: None,
+                offset: offset,
+                found: None,
+            };
+
+            tcx.hir.krate().visit_all_item_likes(&mut v.as_deep_visitor());
+            v.found.map(|(_, ty)| ty)
+        }).ok()
+    }
+
+    /// Collects the instantiations of generic functions which translating
+    /// the given input would require, with the estimated size of each.
+    /// Every function of the input is translated, whether or not it is used.
+    pub fn instantiations<T>(&self, input: T) -> Option<Vec<Instantiation>>
+            where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+            let (items, _) = collect_crate_mono_items(tcx, MonoItemCollectionMode::Eager);
+
+            items.into_iter()
+                .filter_map(|item| match item {
+                    MonoItem::Fn(instance) if instance.substs.types().next().is_some() => {
+                        Some(Instantiation{
+                            name: instance.to_string(),
+                            size: item.size_estimate(tcx),
+                        })
+                    }
+                    _ => None,
+                })
+                .collect()
+        }).ok()
+    }
+
+    /// Computes the memory layout of the type aliased by the type alias
+    /// named `name` in the given input.
+    ///
+    /// Returns `None` if the input fails to compile or defines no such alias,
+    /// or an error message if the type has no layout, e.g. if it is too large.
+    pub fn type_layout<T>(&self, input: T, name: &str) -> Option<Result<TypeLayout, String>>
+            where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+            aliased_type(tcx, name).map(|ty| compute_layout(tcx, ty))
+        }).ok().and_then(|r| r)
+    }
+
+    /// Lists the impls which apply to the type aliased by the type alias
+    /// named `name` in the given input: its inherent impls, and an impl of
+    /// each trait it implements, including blanket and auto trait impls.
+    ///
+    /// Returns `None` if the input fails to compile or defines no such alias.
+    pub fn type_impls<T>(&self, input: T, name: &str) -> Option<Vec<TypeImpl>>
+            where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+            aliased_type(tcx, name).map(|ty| collect_impls(tcx, ty))
+        }).ok().and_then(|r| r)
+    }
+
+    /// Lists the methods which may be called on the type aliased by the type
+    /// alias named `name` in the given input: its inherent methods, and the
+    /// methods of traits it implements which are in scope at the crate root.
+    ///
+    /// Returns `None` if the input fails to compile or defines no such alias.
+    pub fn type_methods<T>(&self, input: T, name: &str) -> Option<Vec<TypeMethod>>
+            where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+            aliased_type(tcx, name).map(|ty| collect_methods(tcx, ty))
+        }).ok().and_then(|r| r)
+    }
+
+    /// Computes the memory layout, impls and methods of the type aliased by
+    /// the type alias named `name` in the given input, as `type_layout`,
+    /// `type_impls` and `type_methods` do, analyzing the input only once.
+    ///
+    /// Returns `None` if the input fails to compile or defines no such alias.
+    pub fn type_info<T>(&self, input: T, name: &str) -> Option<TypeInfo>
+            where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+            aliased_type(tcx, name).map(|ty| TypeInfo{
+                layout: compute_layout(tcx, ty),
+                impls: collect_impls(tcx, ty),
+                methods: collect_methods(tcx, ty),
+            })
+        }).ok().and_then(|r| r)
+    }
+
+    /// Returns the documentation of the item imported by the first of the
+    /// named `use` items in the given input which resolves; or, where a name
+    /// is given with that of an associated item, the documentation of the
+    /// associated item of the imported type or trait.
+    ///
+    /// Input is analyzed even if it has errors, so that imports which do not
+    /// resolve are skipped. Returns `None` if the input fails to compile,
+    /// or `Some(None)` if no import resolves.
+    pub fn item_doc<T>(&self, input: T, imports: &[(String, Option<String>)])
+            -> Option<Option<ItemDoc>> where T: IntoInput {
+        self.with_partial_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+
+            resolve_imports(tcx, imports).map(|(page, def_id)| ItemDoc{
+                path: tcx.item_path_str(def_id),
+                page: tcx.item_path_str(page),
+                module: tcx.item_path_str(enclosing_module(tcx, page)),
+                text: doc_text(tcx, def_id),
+                file: doc_file(tcx, page, def_id),
+            })
+        }).ok()
+    }
+
+    /// Returns the location of the source of the item named as by `item_doc`.
+    ///
+    /// Returns `None` if the input fails to compile, or `Some(None)`
+    /// if no import resolves.
+    pub fn item_source<T>(&self, input: T, imports: &[(String, Option<String>)])
+            -> Option<Option<ItemSource>> where T: IntoInput {
+        self.with_partial_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+            let (_, def_id) = resolve_imports(tcx, imports)?;
+
+            let span = match tcx.hir.span_if_local(def_id) {
+                Some(span) => span,
+                None => tcx.def_span(def_id),
+            };
+
+            let cm = tcx.sess.codemap();
+            let lo = cm.lookup_char_pos(span.lo());
+            let hi = cm.lookup_char_pos(span.hi());
+
+            let code = if def_id.is_local() { cm.span_to_snippet(span).ok() } else { None };
+
+            Some(ItemSource{
+                path: tcx.item_path_str(def_id),
+                file: lo.file.name.to_string(),
+                lines: (lo.line, hi.line),
+                code: code,
+            })
+        }).ok()
+    }
+
+    /// Compiles the given input into an executable at the path `output`.
+    /// Unlike other compiled input, the executable is linked by the linker
+    /// and may be built for a target other than the host.
+    ///
+    /// If the input fails to compile, errors will be printed to `stderr`
+    /// and `false` will be returned.
+    pub fn build_executable<T>(&mut self, input: T, output: &Path) -> bool
+            where T: IntoInput {
+        let built = build_executable(input.into_input(), output.to_path_buf(),
+            self.opts.clone(), false);
+
+        self.last_linker = if built { Some(self.opts.linker_name()) } else { None };
+        built
+    }
+
+    /// Compiles the given input into a test executable at the path `output`,
+    /// as `--test`. The executable runs the `#[test]` functions defined by
+    /// the input, rather than a `main` function.
+    ///
+    /// If the input fails to compile, errors will be printed to `stderr`
+    /// and `false` will be returned.
+    pub fn build_test_executable<T>(&mut self, input: T, output: &Path) -> bool
+            where T: IntoInput {
+        let built = build_executable(input.into_input(), output.to_path_buf(),
+            self.opts.clone(), true);
+
+        self.last_linker = if built { Some(self.opts.linker_name()) } else { None };
+        built
+    }
+
+    /// Returns the options used to compile input.
+    pub fn options(&self) -> &ExecOptions {
+        &self.opts
+    }
+
+    /// Returns a mutable reference to the options used to compile input.
+    /// Changes take effect for the next compiled input.
+    pub fn options_mut(&mut self) -> &mut ExecOptions {
+        &mut self.opts
+    }
+
+    /// Returns the number of modules loaded into the execution engine.
+    pub fn module_count(&self) -> usize {
+        self.modules.len()
+    }
+
+    /// Returns the linker which linked the most recent input, or `None` if it
+    /// was linked in memory by the execution engine.
+    /// Input is only linked by the linker when it is built into an executable.
+    pub fn last_linker(&self) -> Option<&str> {
+        self.last_linker.as_ref().map(|s| &s[..])
+    }
+
+    /// Returns the time spent in each phase for the most recently added module.
+    /// Its `jit` time is recorded once an entry point is called.
+    pub fn last_phases(&self) -> &PhaseTimes {
+        &self.last_phases
+    }
+
+    /// Returns the time spent in each phase for all modules added
+    /// to the execution engine.
+    pub fn total_phases(&self) -> &PhaseTimes {
+        &self.total_phases
+    }
+
+    /// Returns the heap allocations made by the most recently called entry point.
+    pub fn last_heap(&self) -> &HeapStats {
+        &self.last_heap
+    }
+
+    /// Returns the heap allocations made by all entry points called.
+    pub fn total_heap(&self) -> &HeapStats {
+        &self.total_heap
+    }
+
+    /// Returns a flag which, while it is set, cancels compilation of input by
+    /// `add_module`, so that `add_module` returns `None` without compiling
+    /// further. The flag may be set from another thread.
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancel.clone()
+    }
+
+    /// Returns whether compilation of input is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(SeqCst)
+    }
+
+    /// Searches for the named function in the set of loaded modules,
+    /// beginning with the most recently added module.
+    /// If the function is found, a raw pointer is returned.
+    /// If the function is not found, `None` is returned.
+    pub fn get_function(&mut self, name: &str) -> Option<*const ()> {
+        let s = CString::new(name.as_bytes()).unwrap();
+
+        for &(_, m) in self.modules.iter().rev() {
+            let fv = unsafe { llvm::LLVMGetNamedFunction(m, s.as_ptr()) };
+
+            if !fv.is_null() {
+                let fp = unsafe { llvm::LLVMGetPointerToGlobal(self.ee, fv) };
+
+                assert!(!fp.is_null());
+
+                return Some(fp as *const ());
+            }
+        }
+
+        None
+    }
+
+    /// Looks up the given entry point in the set of loaded modules and calls it.
+    /// Returns the result of the call, or `None` if the entry point is
+    /// not defined by any loaded module.
+    ///
+    /// Time spent compiling modules to machine code is recorded as
+    /// the `jit` phase of the most recently added module, and heap
+    /// allocations made by the call as `last_heap`.
+    pub fn call_entry(&mut self, entry: &EntryPoint) -> Option<EntryResult> {
+        let start = Instant::now();
+        let fp = self.get_function(&entry.name);
+        let jit = start.elapsed();
+
+        self.last_phases.jit += jit;
+        self.total_phases.jit += jit;
+
+        fp.map(|fp| {
+            // The entry point is generated with exactly this signature
+            let f: EntryFn = unsafe { mem::transmute(fp) };
+
+            heap::reset();
+            let res = unsafe { entry::call(f) };
+
+            self.last_heap = heap::current();
+            self.total_heap.add(&self.last_heap);
+
+            res
+        })
+    }
+
+    /// Returns the symbol name of the function containing each address, among
+    /// those defined by the most recently added module, which defines all items
+    /// of the session along with the input. Addresses within libraries loaded
+    /// into the process, or outside of any such function, are given `None`.
+    ///
+    /// Addresses are expected to be return addresses, as found in a backtrace.
+    pub fn function_names(&self, addrs: &[usize]) -> Vec<Option<String>> {
+        let mut funcs = Vec::new();
+
+        if let Some(&(_, llmod)) = self.modules.last() {
+            unsafe {
+                let mut f = llvm::LLVMGetFirstFunction(llmod);
+
+                while !f.is_null() {
+                    if llvm::LLVMIsDeclaration(f) == 0 {
+                        let fp = llvm::LLVMGetPointerToGlobal(self.ee, f);
+                        let name = CStr::from_ptr(llvm::LLVMGetValueName(f));
+
+                        if !fp.is_null() {
+                            funcs.push((fp as usize, name.to_string_lossy().into_owned()));
+                        }
+                    }
+
+                    f = llvm::LLVMGetNextFunction(f);
+                }
+            }
+        }
+
+        funcs.sort();
+
+        addrs.iter().map(|&addr| {
+            if funcs.is_empty() || backtrace::in_library(addr) {
+                return None;
+            }
+
+            // A return address may follow a call ending its function
+            let addr = addr - 1;
+
+            funcs.iter().rev()
+                .find(|&&(start, _)| start <= addr)
+                .map(|&(_, ref name)| name.clone())
+        }).collect()
+    }
+
+    /// Finds the definition of each function named by `paths` in the given input,
+    /// returning its byte offset within the input. Paths are absolute, as in
+    /// demangled symbol names, e.g. `repl::foo` or `repl::main::{{closure}}`.
+    ///
+    /// Returns `None` if the input fails to compile.
+    pub fn definition_offsets<T>(&self, input: T, paths: &[String])
+            -> Option<Vec<Option<usize>>> where T: IntoInput {
+        self.with_analysis(input, |_krate, tcx, _analysis| {
+            let tcx = tcx.global_tcx();
+            let mut offsets = vec![None; paths.len()];
+
+            for &body_id in &tcx.hir.krate().body_ids {
+                let path = tcx.absolute_item_path_str(tcx.hir.body_owner_def_id(body_id));
+                let span = tcx.hir.span(tcx.hir.body_owner(body_id));
+
+                for (i, p) in paths.iter().enumerate() {
+                    if *p == path {
+                        let pos = tcx.sess.codemap().lookup_byte_offset(span.lo()).pos;
+                        offsets[i] = Some(pos.0 as usize);
+                    }
+                }
+            }
+
+            offsets
+        }).ok()
+    }
+
+    /// Searches for the named global in the set of loaded modules,
+    /// beginning with the most recently added module.
+    /// If the global is found, a raw pointer is returned.
+    /// If the global is not found, `None` is returned.
+    pub fn get_global(&mut self, name: &str) -> Option<*const ()> {
+        let s = CString::new(name.as_bytes()).unwrap();
+
+        for &(_, m) in self.modules.iter().rev() {
+            let gv = unsafe { llvm::LLVMGetNamedGlobal(m, s.as_ptr()) };
+
+            if !gv.is_null() {
+                let gp = unsafe { llvm::LLVMGetPointerToGlobal(self.ee, gv) };
+
+                assert!(!gp.is_null());
+
+                return Some(gp as *const ());
+            }
+        }
+
+        None
+    }
+
+    /// Maps calls to the global allocator made by the given module to the
+    /// hooks in `heap`, which count allocations.
+    ///
+    /// Mappings apply by name to all modules in the execution engine,
+    /// so each function is mapped only once.
+    fn map_heap_hooks(&mut self, llmod: llvm::ModuleRef) {
+        for &(name, hook) in heap::hooks().iter() {
+            if self.heap_hooks.contains(name) {
+                continue;
+            }
+
+            let s = CString::new(name).unwrap();
+            let fv = unsafe { llvm::LLVMGetNamedFunction(llmod, s.as_ptr()) };
+
+            if !fv.is_null() {
+                unsafe { LLVMAddGlobalMapping(self.ee, fv, hook as *mut c_void); }
+                self.heap_hooks.insert(name);
+            }
+        }
+    }
+
+    /// Loads all dependencies of compiled code which are not already loaded.
+    /// Expects a series of paths to dynamic library files.
+    fn load_deps(&mut self, deps: &Deps) {
+        for path in deps.iter() {
+            if self.libraries.contains(path) {
+                continue;
+            }
+
+            debug!("loading crate {}", path.display());
+
+            let s = match path.as_os_str().to_str() {
+                Some(s) => s,
+                None => panic!(
+                    "Could not convert crate path to UTF-8 string: {:?}", path)
+            };
+            let cs = CString::new(s).unwrap();
+
+            let res = unsafe { llvm::LLVMRustLoadDynamicLibrary(cs.as_ptr()) };
+
+            if res == 0 {
+                panic!("Failed to load crate {:?}: {}",
+                    path.display(), llvm_error());
+            }
+
+            self.libraries.insert(path.clone());
+        }
+    }
+}
+
+impl Drop for ExecutionEngine {
+    fn drop(&mut self) {
+        unsafe {
+            // Modules are disposed of by the execution engine which owns them,
+            // before the contexts which own their types and constants
+            llvm::LLVMDisposeExecutionEngine(self.ee);
+
+            for &(llcx, _) in &self.modules {
+                llvm::LLVMContextDispose(llcx);
+            }
+        }
+    }
+}
+
+/// Parses an optimization level as accepted by `-C opt-level`.
+pub fn parse_opt_level(s: &str) -> Option<OptLevel> {
+    match s {
+        "0" => Some(OptLevel::No),
+        "1" => Some(OptLevel::Less),
+        "2" => Some(OptLevel::Default),
+        "3" => Some(OptLevel::Aggressive),
+        "s" => Some(OptLevel::Size),
+        "z" => Some(OptLevel::SizeMin),
+        _ => None,
+    }
+}
+
+/// Parses a sanitizer as accepted by `-Z sanitizer`.
+/// The memory sanitizer is not accepted, as it requires the standard
+/// library to be instrumented as well.
+pub fn parse_sanitizer(s: &str) -> Option<Sanitizer> {
+    match s {
+        "address" => Some(Sanitizer::Address),
+        "leak" => Some(Sanitizer::Leak),
+        "thread" => Some(Sanitizer::Thread),
+        _ => None,
+    }
+}
+
+/// Parses a verbosity as accepted by `.set verbosity`.
+pub fn parse_verbosity(s: &str) -> Option<Verbosity> {
+    match s {
+        "quiet" => Some(Verbosity::Quiet),
+        "normal" => Some(Verbosity::Normal),
+        "debug" => Some(Verbosity::Debug),
+        _ => None,
+    }
+}
+
+/// Returns the `.set verbosity` name of a verbosity.
+pub fn verbosity_name(verbosity: Verbosity) -> &'static str {
+    match verbosity {
+        Verbosity::Quiet => "quiet",
+        Verbosity::Normal => "normal",
+        Verbosity::Debug => "debug",
+    }
+}
+
+/// Writes the `rustc` command line and the source with which input
+/// is compiled to `stderr`.
+fn print_debug_input(opts: &ExecOptions, input: &Input) {
+    eprintln!("rustc args: {}", opts.rustc_args().join(" "));
+
+    match *input {
+        Input::Str{ref input, ..} => eprintln!("source:\n{}", input.trim_right()),
+        Input::File(ref path) => eprintln!("source: {}", path.display()),
+    }
+}
+
+/// Parses a treatment of warnings as accepted by `.set warnings`.
+pub fn parse_warnings(s: &str) -> Option<Warnings> {
+    match s {
+        "deny" => Some(Warnings::Deny),
+        "show" => Some(Warnings::Show),
+        "hide" => Some(Warnings::Hide),
+        _ => None,
+    }
+}
+
+/// Returns the `.set warnings` name of a treatment of warnings.
+pub fn warnings_name(warnings: Warnings) -> &'static str {
+    match warnings {
+        Warnings::Deny => "deny",
+        Warnings::Show => "show",
+        Warnings::Hide => "hide",
+    }
+}
+
+/// Returns the `-Z sanitizer` name of a sanitizer.
+pub fn sanitizer_name(sanitizer: &Sanitizer) -> &'static str {
+    match *sanitizer {
+        Sanitizer::Address => "address",
+        Sanitizer::Leak => "leak",
+        Sanitizer::Memory => "memory",
+        Sanitizer::Thread => "thread",
+    }
+}
+
+/// Returns the registry of error codes known to the compiler,
+/// with their explanations.
+fn diagnostics_registry() -> Registry {
+    ::rustc_driver::diagnostics_registry()
+}
+
+/// Returns the names of lints and lint groups known to the compiler, sorted.
+pub fn lint_names() -> Vec<String> {
+    let mut store = lint::LintStore::new();
+    rustc_lint::register_builtins(&mut store, None);
+
+    let mut names = store.get_lints().iter()
+        .map(|&(lint, _)| lint.name_lower())
+        .chain(store.get_lint_groups().into_iter().map(|(name, _, _)| name.to_owned()))
+        .collect::<Vec<_>>();
+
+    names.sort();
+    names
+}
+
+/// Returns the explanation of an error code, such as `E0308`,
+/// or `None` if the code is unknown or has no explanation.
+pub fn explain_error(code: &str) -> Option<&'static str> {
+    // The driver's registry omits codes emitted during translation
+    diagnostics_registry().find_description(code)
+        .or_else(|| rustc_trans::DIAGNOSTICS.iter()
+            .find(|&&(c, _)| c == code)
+            .map(|&(_, desc)| desc))
+}
+
+/// Returns the `-C opt-level` name of an optimization level.
+pub fn opt_level_name(level: OptLevel) -> &'static str {
+    match level {
+        OptLevel::No => "0",
+        OptLevel::Less => "1",
+        OptLevel::Default => "2",
+        OptLevel::Aggressive => "3",
+        OptLevel::Size => "s",
+        OptLevel::SizeMin => "z",
+    }
+}
+
+/// An impl which applies to a type, as listed by `type_impls`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeImpl {
+    /// Self type of an inherent impl, or the implemented trait
+    pub name: String,
+    /// Whether the impl is inherent
+    pub inherent: bool,
+    /// Name of the crate defining the impl, or the trait if it is
+    /// implemented by the compiler
+    pub krate: String,
+    /// Whether the trait is an auto trait, implemented by the compiler
+    /// as it is implemented for all fields of the type
+    pub auto: bool,
+}
+
+/// An instantiation of a generic function, as listed by `instantiations`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instantiation {
+    /// Path of the function, with its generic arguments
+    pub name: String,
+    /// Estimated size of the translated function, in MIR statements
+    pub size: usize,
+}
+
+/// A method which may be called on a type, as listed by `type_methods`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeMethod {
+    /// Signature of the method, such as `fn len(&self) -> usize`
+    pub signature: String,
+    /// Trait declaring the method, or `None` for an inherent method
+    pub trait_name: Option<String>,
+}
+
+/// Documentation of an item, as returned by `item_doc`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemDoc {
+    /// Path of the item, such as `std::vec::Vec::push`
+    pub path: String,
+    /// Path of the item on whose page the item is documented: the item
+    /// itself, or the type or trait of an associated item
+    pub page: String,
+    /// Path of the module to which links in the documentation are relative
+    pub module: String,
+    /// Text of the documentation, as Markdown
+    pub text: String,
+    /// Path of the page on which rustdoc documents the item, relative to the
+    /// root of its output, followed by the anchor of an associated item or
+    /// variant; `None` for items of the session
+    pub file: Option<String>,
+}
+
+/// Location of the source of an item, as returned by `item_source`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemSource {
+    /// Path of the item, such as `std::vec::Vec::push`
+    pub path: String,
+    /// Name of the file defining the item, as recorded when it was compiled
+    pub file: String,
+    /// First and last lines of the file spanned by the item, counted from 1
+    pub lines: (usize, usize),
+    /// Code of an item defined in the session, whose file is not on disk
+    pub code: Option<String>,
+}
+
+/// Layout, impls and methods of a type, as computed by `type_info`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeInfo {
+    /// Memory layout of the type, or the reason it has none
+    pub layout: Result<TypeLayout, String>,
+    /// Impls which apply to the type
+    pub impls: Vec<TypeImpl>,
+    /// Methods which may be called on the type
+    pub methods: Vec<TypeMethod>,
+}
+
+/// Returns the item imported by the first of the named `use` items which
+/// resolves, or the associated item of the imported type or trait where one
+/// is named, together with the imported item
+fn resolve_imports<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>,
+        imports: &[(String, Option<String>)]) -> Option<(DefId, DefId)> {
+    imports.iter()
+        .filter_map(|&(ref name, ref assoc)| {
+            let page = imported_def(tcx, name)?;
+
+            match *assoc {
+                Some(ref assoc) => associated_item(tcx, page, assoc).map(|def_id| (page, def_id)),
+                None => Some((page, page)),
+            }
+        })
+        .next()
+}
+
+/// Returns the item imported by the `use` item named `name`, defined in the crate,
+/// if the import resolves
+fn imported_def<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, name: &str) -> Option<DefId> {
+    tcx.hir.krate().items.values()
+        .filter(|item| item.name == name)
+        .filter_map(|item| match item.node {
+            hir::ItemUse(ref path, _) => match path.def {
+                Def::Mod(def_id) | Def::Struct(def_id) | Def::Union(def_id) |
+                Def::Enum(def_id) | Def::Variant(def_id) | Def::Trait(def_id) |
+                Def::TyAlias(def_id) | Def::Fn(def_id) | Def::Const(def_id) |
+                Def::Static(def_id, _) | Def::Macro(def_id, _) => Some(def_id),
+                _ => None,
+            },
+            _ => None,
+        })
+        .next()
+}
+
+/// Returns the associated item named `name` of a trait, or of an inherent
+/// impl of a type
+fn associated_item<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, def_id: DefId, name: &str)
+        -> Option<DefId> {
+    let containers = match tcx.describe_def(def_id) {
+        Some(Def::Trait(_)) => vec![def_id],
+        Some(Def::Struct(_)) | Some(Def::Union(_)) | Some(Def::Enum(_)) =>
+            tcx.inherent_impls(def_id).iter().cloned().collect(),
+        _ => Vec::new(),
+    };
+
+    containers.into_iter()
+        .flat_map(|id| tcx.associated_items(id))
+        .find(|item| item.name == name)
+        .map(|item| item.def_id)
+}
+
+/// Returns the module which is, or which contains, the given item
+fn enclosing_module<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, mut def_id: DefId) -> DefId {
+    loop {
+        match tcx.def_key(def_id).disambiguated_data.data {
+            DefPathData::CrateRoot | DefPathData::Module(_) => return def_id,
+            _ => (),
+        }
+
+        match tcx.parent_def_id(def_id) {
+            Some(parent) => def_id = parent,
+            None => return def_id,
+        }
+    }
+}
+
+/// Returns the path of the page on which rustdoc documents an item of another
+/// crate, such as `std/vec/struct.Vec.html#method.push`, given the item on
+/// whose page it is documented
+fn doc_file<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, page: DefId, def_id: DefId)
+        -> Option<String> {
+    if page.is_local() {
+        return None;
+    }
+
+    let name = |id| tcx.item_path_str(id).rsplit("::").next().unwrap_or("").to_owned();
+
+    // Variants are documented on the page of their enum
+    let (page, mut anchor) = match tcx.describe_def(page)? {
+        Def::Variant(_) => (tcx.parent_def_id(page)?, Some(format!("variant.{}", name(page)))),
+        _ => (page, None),
+    };
+
+    let dir = tcx.item_path_str(enclosing_module(tcx, page)).replace("::", "/");
+
+    let kind = match tcx.describe_def(page)? {
+        Def::Mod(_) => return Some(format!("{}/index.html", dir)),
+        // Exported macros are documented at the root of their crate
+        Def::Macro(..) => {
+            let krate = dir.split('/').next().unwrap_or("");
+            return Some(format!("{}/macro.{}.html", krate, name(page)));
+        }
+        Def::Struct(_) => "struct",
+        Def::Union(_) => "union",
+        Def::Enum(_) => "enum",
+        Def::Trait(_) => "trait",
+        Def::TyAlias(_) => "type",
+        Def::Fn(_) => "fn",
+        Def::Const(_) => "constant",
+        Def::Static(..) => "static",
+        _ => return None,
+    };
+
+    if def_id != page && anchor.is_none() {
+        let item = tcx.associated_item(def_id);
+
+        let kind = match item.kind {
+            // Required methods of traits are anchored apart from provided ones
+            ty::AssociatedKind::Method => match item.container {
+                ty::TraitContainer(_) if !item.defaultness.has_value() => "tymethod",
+                _ => "method",
+            },
+            ty::AssociatedKind::Type => "associatedtype",
+            ty::AssociatedKind::Const => "associatedconstant",
+        };
+
+        anchor = Some(format!("{}.{}", kind, item.name));
+    }
+
+    let file = format!("{}/{}.{}.html", dir, kind, name(page));
+
+    Some(match anchor {
+        Some(anchor) => format!("{}#{}", file, anchor),
+        None => file,
+    })
+}
+
+/// Returns the documentation of an item, with the decoration of its doc
+/// comments removed and their common indentation stripped
+fn doc_text<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, def_id: DefId) -> String {
+    let text = tcx.get_attrs(def_id).iter()
+        .filter(|attr| attr.check_name("doc"))
+        .filter_map(|attr| attr.with_desugared_doc(|a| a.value_str()))
+        .map(|s| s.as_str().to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let indent = text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_left().len())
+        .min()
+        .unwrap_or(0);
+
+    text.lines()
+        .map(|line| if line.len() >= indent { &line[indent..] } else { line.trim_left() })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the type aliased by the type alias named `name`, defined in the crate
+fn aliased_type<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, name: &str) -> Option<ty::Ty<'tcx>> {
+    tcx.hir.krate().items.values()
+        .find(|item| match item.node {
+            hir::ItemTy(..) => item.name == name,
+            _ => false,
+        })
+        .map(|item| tcx.type_of(tcx.hir.local_def_id(item.id)))
+}
+
+/// Lists the impls which apply to a type, for `type_impls`.
+/// Inherent impls come first, followed by trait impls in order of name.
+fn collect_impls<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Vec<TypeImpl> {
+    let crate_name = |def_id: DefId| tcx.crate_name(def_id.krate).to_string();
+
+    let mut res = inherent_impls(tcx, ty).into_iter()
+        .map(|impl_id| TypeImpl{
+            name: tcx.type_of(impl_id).to_string(),
+            inherent: true,
+            krate: crate_name(impl_id),
+            auto: false,
+        })
+        .collect::<Vec<_>>();
+
+    let mut trait_impls = implemented_traits(tcx, ty).into_iter()
+        .map(|(trait_id, impl_id)| match impl_id {
+            Some(impl_id) if tcx.generics_of(trait_id).count() != 1 => TypeImpl{
+                name: tcx.impl_trait_ref(impl_id).unwrap().to_string(),
+                inherent: false,
+                krate: crate_name(impl_id),
+                auto: false,
+            },
+            _ => TypeImpl{
+                name: tcx.item_path_str(trait_id),
+                inherent: false,
+                krate: crate_name(impl_id.unwrap_or(trait_id)),
+                auto: impl_id.is_none() && tcx.trait_is_auto(trait_id),
+            },
+        })
+        .collect::<Vec<_>>();
+
+    trait_impls.sort_by(|a, b| a.name.cmp(&b.name));
+    res.extend(trait_impls);
+    res
+}
+
+/// Returns the inherent impls which apply to a type, in order of definition.
+fn inherent_impls<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Vec<DefId> {
+    match ty.sty {
+        ty::TyAdt(def, _) => tcx.inherent_impls(def.did).iter()
+            .cloned()
+            .filter(|&impl_id| impl_applies(tcx, impl_id, ty))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the traits implemented by a type, each with the impl implementing
+/// it, if any; auto traits and traits implemented by the compiler have none.
+///
+/// Only `Self` is known for a trait without parameters, so whether it is
+/// implemented is known even when implemented by a blanket impl. Otherwise,
+/// the trait is listed once for each impl for the type itself.
+fn implemented_traits<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>)
+        -> Vec<(DefId, Option<DefId>)> {
+    // Every trait with an impl in some crate, including negative impls
+    // of auto traits
+    let local_impls = tcx.hir.krate().items.values()
+        .filter(|item| match item.node {
+            hir::ItemImpl(..) => true,
+            _ => false,
+        })
+        .map(|item| tcx.hir.local_def_id(item.id))
+        .collect::<Vec<_>>();
+
+    let mut trait_ids = HashSet::new();
+
+    for &cnum in tcx.crates().iter() {
+        for &impl_id in tcx.all_trait_implementations(cnum).iter() {
+            if let Some(trait_ref) = tcx.impl_trait_ref(impl_id) {
+                trait_ids.insert(trait_ref.def_id);
+            }
+        }
+    }
+
+    for impl_id in local_impls {
+        if let Some(trait_ref) = tcx.impl_trait_ref(impl_id) {
+            trait_ids.insert(trait_ref.def_id);
+        }
+    }
+
+    let mut res = Vec::new();
+
+    for trait_id in trait_ids {
+        let mut impls = Vec::new();
+
+        tcx.for_each_relevant_impl(trait_id, ty, |impl_id| {
+            if tcx.impl_polarity(impl_id) == hir::ImplPolarity::Positive &&
+                    impl_applies(tcx, impl_id, ty) {
+                impls.push(impl_id);
+            }
+        });
+
+        if tcx.generics_of(trait_id).count() == 1 {
+            let implemented = tcx.infer_ctxt().enter(|infcx| {
+                traits::type_known_to_meet_bound(&infcx,
+                    ty::ParamEnv::reveal_all(), ty, trait_id, DUMMY_SP)
+            });
+
+            if implemented {
+                // Prefer an impl for the type itself over a blanket impl
+                let impl_id = impls.iter().find(|&&i| !is_blanket_impl(tcx, i))
+                    .or(impls.first()).cloned();
+
+                res.push((trait_id, impl_id));
+            }
+        } else {
+            for impl_id in impls {
+                if !is_blanket_impl(tcx, impl_id) {
+                    res.push((trait_id, Some(impl_id)));
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Lists the methods which may be called on a type, for `type_methods`.
+/// Inherent methods come first, followed by methods of traits in scope
+/// in order of trait name.
+fn collect_methods<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Vec<TypeMethod> {
+    let mut res = Vec::new();
+
+    for impl_id in inherent_impls(tcx, ty) {
+        let self_ty = tcx.type_of(impl_id);
+
+        for item in tcx.associated_items(impl_id) {
+            if item.kind == ty::AssociatedKind::Method {
+                res.push(TypeMethod{
+                    signature: fn_signature(tcx, item.def_id, self_ty),
+                    trait_name: None,
+                });
+            }
+        }
+    }
+
+    let in_scope = traits_in_scope(tcx);
+    let mut trait_ids = implemented_traits(tcx, ty).into_iter()
+        .map(|(trait_id, _)| trait_id)
+        .filter(|trait_id| in_scope.contains(trait_id))
+        .collect::<Vec<_>>();
+
+    trait_ids.sort_by_key(|&trait_id| tcx.item_path_str(trait_id));
+    trait_ids.dedup();
+
+    for trait_id in trait_ids {
+        let trait_name = tcx.item_path_str(trait_id);
+
+        for item in tcx.associated_items(trait_id) {
+            if item.kind == ty::AssociatedKind::Method {
+                res.push(TypeMethod{
+                    signature: fn_signature(tcx, item.def_id, tcx.mk_self_type()),
+                    trait_name: Some(trait_name.clone()),
+                });
+            }
+        }
+    }
+
+    res
+}
+
+/// Returns the traits in scope at the crate root: those defined there,
+/// those imported by name, and those exported by glob imported modules
+/// of other crates, such as the prelude.
+fn traits_in_scope<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>) -> HashSet<DefId> {
+    let mut res = HashSet::new();
+
+    for id in &tcx.hir.krate().module.item_ids {
+        let item = tcx.hir.expect_item(id.id);
+
+        match item.node {
+            hir::ItemTrait(..) => {
+                res.insert(tcx.hir.local_def_id(item.id));
+            }
+            hir::ItemUse(ref path, kind) => match (path.def, kind) {
+                (Def::Trait(trait_id), hir::UseKind::Single) => {
+                    res.insert(trait_id);
+                }
+                (Def::Mod(mod_id), hir::UseKind::Glob) if !mod_id.is_local() => {
+                    for child in tcx.item_children(mod_id).iter() {
+                        if let Def::Trait(trait_id) = child.def {
+                            res.insert(trait_id);
+                        }
+                    }
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+
+    res
+}
+
+/// Formats the signature of a function or method, such as `fn len(&self) -> usize`.
+/// Parameters of type `self_ty`, or references to it, named `self`
+/// are shown as `self`, `&self` or `&mut self`.
+fn fn_signature<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, def_id: DefId,
+        self_ty: ty::Ty<'tcx>) -> String {
+    let sig = tcx.fn_sig(def_id);
+    let sig = sig.skip_binder();
+    let names = param_names(tcx, def_id);
+
+    let params = sig.inputs().iter().enumerate()
+        .map(|(i, &input)| {
+            let name = names.get(i).cloned().unwrap_or_else(|| "_".to_owned());
+
+            if name == "self" {
+                if input == self_ty {
+                    return "self".to_owned();
+                }
+
+                if let ty::TyRef(_, ty::TypeAndMut{ty, mutbl}) = input.sty {
+                    if ty == self_ty {
+                        return match mutbl {
+                            hir::MutMutable => "&mut self".to_owned(),
+                            hir::MutImmutable => "&self".to_owned(),
+                        };
+                    }
+                }
+            }
+
+            format!("{}: {}", name, input)
+        })
+        .collect::<Vec<_>>();
+
+    let mut res = format!("{}fn {}({})",
+        if sig.unsafety == hir::Unsafety::Unsafe { "unsafe " } else { "" },
+        tcx.item_name(def_id), params.join(", "));
+
+    if !sig.output().is_nil() {
+        res.push_str(&format!(" -> {}", sig.output()));
+    }
+
+    res
+}
+
+/// Returns the names of a function's parameters; patterns for those
+/// of local functions with a body.
+fn param_names(tcx: ty::TyCtxt, def_id: DefId) -> Vec<String> {
+    let node_id = match tcx.hir.as_local_node_id(def_id) {
+        Some(node_id) => node_id,
+        None => return tcx.fn_arg_names(def_id).iter().map(|n| n.to_string()).collect(),
+    };
+
+    if let Some(body_id) = tcx.hir.maybe_body_owned_by(node_id) {
+        return tcx.hir.body(body_id).arguments.iter()
+            .map(|arg| tcx.hir.node_to_pretty_string(arg.pat.id))
+            .collect();
+    }
+
+    // Trait methods without a default have no body
+    match tcx.hir.get(node_id) {
+        ast_map::NodeTraitItem(&hir::TraitItem{
+                node: hir::TraitItemKind::Method(_, hir::TraitMethod::Required(ref names)),
+                ..}) => {
+            names.iter().map(|n| n.node.to_string()).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Returns whether the self type of an impl can be unified with a type.
+/// Where clauses of the impl are not considered.
+fn impl_applies<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, impl_id: DefId,
+        ty: ty::Ty<'tcx>) -> bool {
+    tcx.infer_ctxt().enter(|infcx| {
+        let substs = infcx.fresh_substs_for_item(DUMMY_SP, impl_id);
+        let impl_ty = infcx.tcx.type_of(impl_id).subst(infcx.tcx, substs);
+
+        infcx.can_eq(ty::ParamEnv::reveal_all(), impl_ty, ty).is_ok()
+    })
+}
+
+/// Returns whether an impl is for any type, such as `impl<T: Display> ToString for T`.
+fn is_blanket_impl(tcx: ty::TyCtxt, impl_id: DefId) -> bool {
+    match tcx.type_of(impl_id).sty {
+        ty::TyParam(..) => true,
+        _ => false,
+    }
+}
+
+/// Describes the layout of a type, for `type_layout`
+fn compute_layout<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>)
+        -> Result<TypeLayout, String> {
+    let cx = (tcx, ty::ParamEnv::reveal_all());
+    let layout = cx.layout_of(ty).map_err(|e| e.to_string())?;
+
+    let mut res = TypeLayout{
+        name: ty.to_string(),
+        size: layout.size.bytes(),
+        align: layout.align.abi(),
+        ..TypeLayout::default()
+    };
+
+    match ty.sty {
+        ty::TyAdt(def, _) if def.is_enum() => {
+            for (i, variant) in def.variants.iter().enumerate() {
+                let variant_layout = layout.for_variant(cx, i);
+                let names = variant.fields.iter().map(|f| f.name.to_string()).collect();
+
+                res.variants.push(VariantLayout{
+                    name: variant.name.to_string(),
+                    size: variant_layout.size.bytes(),
+                    fields: field_layouts(cx, variant_layout, names)?,
+                });
+            }
+        }
+        ty::TyAdt(def, _) => {
+            let names = def.non_enum_variant().fields.iter()
+                .map(|f| f.name.to_string()).collect();
+            res.fields = field_layouts(cx, layout, names)?;
+        }
+        ty::TyTuple(..) => {
+            let names = (0..layout.fields.count()).map(|i| i.to_string()).collect();
+            res.fields = field_layouts(cx, layout, names)?;
+        }
+        _ => ()
+    }
+
+    res.discriminant = match layout.variants {
+        Variants::Single{..} => None,
+        Variants::Tagged{ref discr, ..} => Some(Discriminant::Tag{
+            offset: layout.fields.offset(0).bytes(),
+            size: discr.value.size(tcx).bytes(),
+        }),
+        Variants::NicheFilling{dataful_variant, ref niche, niche_start, ..} => {
+            let variant = match ty.sty {
+                ty::TyAdt(def, _) => def.variants[dataful_variant].name.to_string(),
+                _ => dataful_variant.to_string(),
+            };
+
+            Some(Discriminant::Niche{
+                variant: variant,
+                size: niche.value.size(tcx).bytes(),
+                start: niche_start,
+            })
+        }
+    };
+
+    Ok(res)
+}
+
+/// Describes the fields of a layout, given their names in order of declaration
+fn field_layouts<'a, 'tcx>(cx: (ty::TyCtxt<'a, 'tcx, 'tcx>, ty::ParamEnv<'tcx>),
+        layout: TyLayout<'tcx>, names: Vec<String>) -> Result<Vec<FieldLayout>, String> {
+    names.into_iter().enumerate().map(|(i, name)| {
+        let field = layout.field(cx, i).map_err(|e| e.to_string())?;
+
+        Ok(FieldLayout{
+            name: name,
+            ty: field.ty.to_string(),
+            offset: layout.fields.offset(i).bytes(),
+            size: field.size.bytes(),
+            align: field.align.abi(),
+        })
+    }).collect()
+}
+
+/// Collects references to local definitions, for `resolve_paths`
+struct PathResolver<'a, 'gcx: 'a + 'tcx, 'tcx: 'a> {
+    tcx: ty::TyCtxt<'a, 'gcx, 'tcx>,
+    /// Type-check results of the body being visited, used to resolve
+    /// method calls and associated items
+    tables: Option<&'gcx ty::TypeckTables<'gcx>>,
+    paths: Vec<(usize, usize)>,
+}
+
+impl<'a, 'gcx, 'tcx> PathResolver<'a, 'gcx, 'tcx> {
+    fn record(&mut self, span: Span, def: Def) {
+        let def_id = match def {
+            Def::Local(..) | Def::Upvar(..) | Def::Label(..) |
+                Def::PrimTy(..) | Def::SelfTy(..) | Def::Err => return,
+            def => def.def_id(),
+        };
+
+        if let Some(def_span) = self.tcx.hir.span_if_local(def_id) {
+            let cm = self.tcx.sess.codemap();
+
+            self.paths.push((
+                cm.lookup_byte_offset(span.lo()).pos.0 as usize,
+                cm.lookup_byte_offset(def_span.lo()).pos.0 as usize,
+            ));
+        }
+    }
+}
+
+impl<'a, 'gcx, 'tcx> Visitor<'gcx> for PathResolver<'a, 'gcx, 'tcx> {
+    fn nested_visit_map<'this>(&'this mut self) -> NestedVisitorMap<'this, 'gcx> {
+        NestedVisitorMap::All(&self.tcx.hir)
+    }
+
+    fn visit_nested_body(&mut self, id: hir::BodyId) {
+        let prev = mem::replace(&mut self.tables, Some(self.tcx.body_tables(id)));
+        let body = self.tcx.hir.body(id);
+
+        self.visit_body(body);
+        self.tables = prev;
+    }
+
+    fn visit_path(&mut self, path: &'gcx hir::Path, _id: ast::NodeId) {
+        self.record(path.span, path.def);
+        intravisit::walk_path(self, path);
+    }
+
+    fn visit_expr(&mut self, expr: &'gcx hir::Expr) {
+        let def = self.tables.and_then(|t| t.type_dependent_defs().get(expr.hir_id).cloned());
+
+        if let Some(def) = def {
+            self.record(expr.span, def);
+        }
+
+        intravisit::walk_expr(self, expr);
+    }
+}
+
+/// Collects the types of variables bound by `let` statements, for `let_types`
+struct LetTypes<'a, 'gcx: 'a + 'tcx, 'tcx: 'a> {
+    tcx: ty::TyCtxt<'a, 'gcx, 'tcx>,
+    /// Type-check results of the body being visited
+    tables: Option<&'gcx ty::TypeckTables<'gcx>>,
+    /// Byte offset before which statements are ignored
+    start: usize,
+    /// Byte offset, name and type of each variable
+    types: Vec<(usize, String, String)>,
+}
+
+impl<'a, 'gcx, 'tcx> Visitor<'gcx> for LetTypes<'a, 'gcx, 'tcx> {
+    fn nested_visit_map<'this>(&'this mut self) -> NestedVisitorMap<'this, 'gcx> {
+        NestedVisitorMap::All(&self.tcx.hir)
+    }
+
+    fn visit_nested_body(&mut self, id: hir::BodyId) {
+        let prev = mem::replace(&mut self.tables, Some(self.tcx.body_tables(id)));
+        let body = self.tcx.hir.body(id);
+
+        self.visit_body(body);
+        self.tables = prev;
+    }
+
+    fn visit_local(&mut self, local: &'gcx hir::Local) {
+        let cm = self.tcx.sess.codemap();
+        let offset = cm.lookup_byte_offset(local.span.lo()).pos.0 as usize;
+
+        if let (true, Some(tables)) = (offset >= self.start, self.tables) {
+            let tcx = self.tcx;
+            let types = &mut self.types;
+
+            local.pat.each_binding(|_, id, span, name| {
+                let name = name.node.to_string();
+
+                // Names of rusti's own variables
+                if name.starts_with("_rusti") {
+                    return;
+                }
+
+                if let Some(ty) = tables.node_id_to_type_opt(tcx.hir.node_to_hir_id(id)) {
+                    let offset = cm.lookup_byte_offset(span.lo()).pos.0 as usize;
+                    types.push((offset, name, ty.to_string()));
+                }
+            });
+        }
+
+        intravisit::walk_local(self, local);
+    }
 }
 
-/// A value that can be translated into `ExecutionEngine` input
-pub trait IntoInput {
-    fn into_input(self) -> Input;
+/// Finds the innermost expression or pattern containing an offset, for `type_at`
+struct TypeAt<'a, 'gcx: 'a + 'tcx, 'tcx: 'a> {
+    tcx: ty::TyCtxt<'a, 'gcx, 'tcx>,
+    /// Type-check results of the body being visited
+    tables: Option<&'gcx ty::TypeckTables<'gcx>>,
+    /// Byte offset of interest
+    offset: usize,
+    /// Length of the innermost expression or pattern found, and its type
+    found: Option<(usize, String)>,
 }
 
-impl<'a> IntoInput for &'a str {
-    fn into_input(self) -> Input {
-        self.to_owned().into_input()
-    }
-}
+impl<'a, 'gcx, 'tcx> TypeAt<'a, 'gcx, 'tcx> {
+    fn record(&mut self, span: Span, hir_id: hir::HirId) {
+        let cm = self.tcx.sess.codemap();
+        let start = cm.lookup_byte_offset(span.lo()).pos.0 as usize;
+        let len = (span.hi().0 - span.lo().0) as usize;
 
-impl IntoInput for String {
-    fn into_input(self) -> Input {
-        Input::Str{
-            name: FileName::Custom("<input>".to_owned()),
-            input: self,
+        // Expressions expanded from macros may not contain the offset in the source
+        if span.ctxt() != SyntaxContext::empty() ||
+                self.offset < start || self.offset > start + len {
+            return;
+        }
+
+        let innermost = self.found.as_ref().map_or(true, |&(found, _)| len <= found);
+
+        if let (Some(tables), true) = (self.tables, innermost) {
+            if let Some(ty) = tables.node_id_to_type_opt(hir_id) {
+                self.found = Some((len, ty.to_string()));
+            }
         }
     }
 }
 
-impl IntoInput for PathBuf {
-    fn into_input(self) -> Input {
-        Input::File(self)
+impl<'a, 'gcx, 'tcx> Visitor<'gcx> for TypeAt<'a, 'gcx, 'tcx> {
+    fn nested_visit_map<'this>(&'this mut self) -> NestedVisitorMap<'this, 'gcx> {
+        NestedVisitorMap::All(&self.tcx.hir)
+    }
+
+    fn visit_nested_body(&mut self, id: hir::BodyId) {
+        let prev = mem::replace(&mut self.tables, Some(self.tcx.body_tables(id)));
+        let body = self.tcx.hir.body(id);
+
+        self.visit_body(body);
+        self.tables = prev;
+    }
+
+    fn visit_expr(&mut self, expr: &'gcx hir::Expr) {
+        self.record(expr.span, expr.hir_id);
+        intravisit::walk_expr(self, expr);
+    }
+
+    fn visit_pat(&mut self, pat: &'gcx hir::Pat) {
+        self.record(pat.span, pat.hir_id);
+        intravisit::walk_pat(self, pat);
     }
 }
 
-type Deps = Vec<PathBuf>;
+/// Finds the outermost method call or overloaded operator,
+/// for `explain_selection`
+struct CallFinder<'a, 'tcx: 'a> {
+    tcx: ty::TyCtxt<'a, 'tcx, 'tcx>,
+    /// Type-check results of the body being visited
+    tables: Option<&'tcx ty::TypeckTables<'tcx>>,
+    /// Byte offset before which expressions are ignored
+    start: usize,
+    /// Length of the outermost call found, and its explanation
+    found: Option<(usize, Selection)>,
+}
 
-impl ExecutionEngine {
-    /// Constructs a new `ExecutionEngine` with the given library search paths.
-    pub fn new(libs: Vec<String>, sysroot: Option<PathBuf>) -> ExecutionEngine {
-        ExecutionEngine::new_with_input(String::new(), libs, sysroot)
+impl<'a, 'tcx> Visitor<'tcx> for CallFinder<'a, 'tcx> {
+    fn nested_visit_map<'this>(&'this mut self) -> NestedVisitorMap<'this, 'tcx> {
+        NestedVisitorMap::All(&self.tcx.hir)
     }
 
-    /// Constructs a new `ExecutionEngine` with the given starting input
-    /// and library search paths.
-    pub fn new_with_input<T>(input: T, libs: Vec<String>, sysroot: Option<PathBuf>) -> ExecutionEngine
-            where T: IntoInput {
-        let sysroot = sysroot.unwrap_or_else(get_sysroot);
+    fn visit_nested_body(&mut self, id: hir::BodyId) {
+        let prev = mem::replace(&mut self.tables, Some(self.tcx.body_tables(id)));
+        let body = self.tcx.hir.body(id);
 
-        let (llmod, deps) = compile_input(input.into_input(),
-            sysroot.clone(), libs.clone())
-            .expect("ExecutionEngine init input failed to compile");
+        self.visit_body(body);
+        self.tables = prev;
+    }
 
-        let ee = unsafe { llvm::LLVMBuildExecutionEngine(llmod) };
+    fn visit_expr(&mut self, expr: &'tcx hir::Expr) {
+        let cm = self.tcx.sess.codemap();
+        let offset = cm.lookup_byte_offset(expr.span.lo()).pos.0 as usize;
+        let len = (expr.span.hi().0 - expr.span.lo().0) as usize;
+
+        let receiver = match expr.node {
+            hir::ExprMethodCall(_, _, ref args) => args.first(),
+            hir::ExprBinary(_, ref lhs, _) |
+            hir::ExprAssignOp(_, ref lhs, _) |
+            hir::ExprIndex(ref lhs, _) |
+            hir::ExprUnary(_, ref lhs) => Some(&**lhs),
+            _ => None,
+        };
 
-        if ee.is_null() {
-            panic!("Failed to create ExecutionEngine: {}", llvm_error());
+        let outermost = self.found.as_ref().map_or(true, |&(found, _)| len > found);
+
+        if let (Some(receiver), Some(tables), true) = (receiver, self.tables, outermost) {
+            if let Some(Def::Method(def_id)) = tables.type_dependent_defs().get(expr.hir_id).cloned() {
+                if offset >= self.start {
+                    let substs = tables.node_substs(expr.hir_id);
+                    let mut sel = select_method(self.tcx, def_id, substs);
+
+                    sel.receiver = tables.expr_ty(receiver).to_string();
+                    sel.adjustments = tables.expr_adjustments(receiver).iter()
+                        .map(|adj| (describe_adjustment(adj), adj.target.to_string()))
+                        .collect();
+
+                    self.found = Some((len, sel));
+                }
+            }
         }
 
-        let ee = ExecutionEngine{
-            ee: ee,
-            modules: vec![llmod],
-            lib_paths: libs,
-            sysroot: sysroot,
-        };
+        intravisit::walk_expr(self, expr);
+    }
+}
 
-        ee.load_deps(&deps);
+/// Describes the method selected for a call to the method `def_id`
+/// with generic arguments `substs`, for `explain_selection`.
+fn select_method<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, def_id: DefId,
+        substs: &'tcx ty::subst::Substs<'tcx>) -> Selection {
+    let substs = tcx.erase_regions(&substs);
+    let item = tcx.associated_item(def_id);
+
+    let (method, selected, selected_substs) = match item.container {
+        ty::TraitContainer(trait_id) => {
+            let trait_ref = ty::TraitRef::from_method(tcx, trait_id, substs);
+            let method = format!("<{} as {}>::{}", trait_ref.self_ty(), trait_ref, item.name);
+
+            match ty::Instance::resolve(tcx, ty::ParamEnv::reveal_all(), def_id, substs) {
+                Some(ty::Instance{def: ty::InstanceDef::Item(id), substs}) => (method, Some(id), substs),
+                _ => (method, None, substs),
+            }
+        }
+        ty::ImplContainer(impl_id) => {
+            let self_ty = tcx.type_of(impl_id).subst(tcx, substs);
+            (format!("{}::{}", self_ty, item.name), Some(def_id), substs)
+        }
+    };
 
-        ee
+    let (impl_header, krate) = match selected.map(|id| (id, tcx.associated_item(id).container)) {
+        Some((_, ty::ImplContainer(impl_id))) => (Some(impl_header(tcx, impl_id)), impl_id.krate),
+        Some((id, ty::TraitContainer(trait_id))) => {
+            (Some(format!("default method of {}", tcx.item_path_str(trait_id))), id.krate)
+        }
+        None => (None, def_id.krate),
+    };
+
+    Selection{
+        method: method,
+        impl_header: impl_header,
+        krate: tcx.crate_name(krate).to_string(),
+        generics: generic_args(tcx, selected.unwrap_or(def_id), selected_substs),
+        ..Selection::default()
     }
+}
 
-    /// Compile a module and add it to the execution engine.
-    /// If the module fails to compile, errors will be printed to `stderr`
-    /// and `None` will be returned. Otherwise, the module is returned.
-    pub fn add_module<T>(&mut self, input: T) -> Option<llvm::ModuleRef>
-            where T: IntoInput {
-        debug!("compiling module");
+/// Returns the header of an impl, such as `impl<T> Clone for Vec<T>`.
+fn impl_header(tcx: ty::TyCtxt, impl_id: DefId) -> String {
+    let params = tcx.generics_of(impl_id).types.iter()
+        .map(|t| t.name.to_string())
+        .collect::<Vec<_>>();
 
-        let (llmod, deps) = match compile_input(input.into_input(),
-                self.sysroot.clone(), self.lib_paths.clone()) {
-            Some(r) => r,
-            None => return None,
-        };
+    let params = if params.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", params.join(", "))
+    };
 
-        self.load_deps(&deps);
+    match tcx.impl_trait_ref(impl_id) {
+        Some(trait_ref) => format!("impl{} {} for {}", params, trait_ref, trait_ref.self_ty()),
+        None => format!("impl{} {}", params, tcx.type_of(impl_id)),
+    }
+}
 
-        self.modules.push(llmod);
+/// Returns the name of each generic type parameter of an item and those
+/// enclosing it, outermost first, with its argument in `substs`.
+fn generic_args<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>, def_id: DefId,
+        substs: &'tcx ty::subst::Substs<'tcx>) -> Vec<(String, String)> {
+    let mut all = Vec::new();
+    let mut generics = Some(tcx.generics_of(def_id));
 
-        unsafe { llvm::LLVMExecutionEngineAddModule(self.ee, llmod); }
+    while let Some(g) = generics {
+        all.push(g);
+        generics = g.parent.map(|parent| tcx.generics_of(parent));
+    }
 
-        Some(llmod)
+    all.iter().rev()
+        .flat_map(|g| g.types.iter())
+        .filter(|t| t.name != "Self")
+        .map(|t| (t.name.to_string(), substs.type_at(t.index as usize).to_string()))
+        .collect()
+}
+
+/// Describes an adjustment of a method receiver or operand.
+fn describe_adjustment(adj: &Adjustment) -> String {
+    match adj.kind {
+        Adjust::Deref(None) => "deref".to_owned(),
+        Adjust::Deref(Some(ref overloaded)) => match overloaded.mutbl {
+            hir::MutImmutable => "deref via Deref::deref".to_owned(),
+            hir::MutMutable => "deref via DerefMut::deref_mut".to_owned(),
+        },
+        Adjust::Borrow(AutoBorrow::Ref(_, hir::MutImmutable)) => "autoref".to_owned(),
+        Adjust::Borrow(AutoBorrow::Ref(_, hir::MutMutable)) => "autoref mut".to_owned(),
+        Adjust::Borrow(AutoBorrow::RawPtr(_)) => "coerce to raw pointer".to_owned(),
+        Adjust::Unsize => "unsize".to_owned(),
+        ref kind => format!("{:?}", kind),
+    }
+}
+
+/// Collects holes and the variables in scope at each, for `holes`
+struct HoleFinder<'a, 'tcx: 'a> {
+    tcx: ty::TyCtxt<'a, 'tcx, 'tcx>,
+    /// Type-check results of the body being visited
+    tables: Option<&'tcx ty::TypeckTables<'tcx>>,
+    /// Variables bound in the body being visited, with the byte offset
+    /// following the statement binding each
+    locals: Vec<(usize, String, ast::NodeId)>,
+    /// Functions, constants and statics which may fill holes, each with
+    /// the type of its value and a description
+    items: Vec<(ty::Ty<'tcx>, String)>,
+    holes: Vec<Hole>,
+}
+
+impl<'a, 'tcx> HoleFinder<'a, 'tcx> {
+    fn offset(&self, pos: BytePos) -> usize {
+        self.tcx.sess.codemap().lookup_byte_offset(pos).pos.0 as usize
     }
 
-    /// Remove the given module from the execution engine.
-    /// The module is destroyed after it is removed.
-    ///
-    /// # Panics
-    ///
-    /// If the Module does not exist within this `ExecutionEngine`.
-    pub fn remove_module(&mut self, llmod: llvm::ModuleRef) {
-        match self.modules.iter().position(|p| *p == llmod) {
-            Some(i) => {
-                self.modules.remove(i);
-                let res = unsafe {
-                    llvm::LLVMExecutionEngineRemoveModule(self.ee, llmod)
+    fn bind(&mut self, pat: &hir::Pat, offset: usize) {
+        let locals = &mut self.locals;
+
+        pat.each_binding(|_, id, _, name| {
+            locals.push((offset, name.node.to_string(), id));
+        });
+    }
+
+    /// Returns the values and functions in scope at `offset` which fit
+    /// a hole of type `expected`.
+    fn fits(&self, expected: ty::Ty<'tcx>, offset: usize) -> Vec<String> {
+        let tcx = self.tcx;
+        let expected = tcx.erase_regions(&expected);
+        let mut res = Vec::new();
+
+        if let Some(tables) = self.tables {
+            for &(bound, ref name, id) in &self.locals {
+                if bound > offset || name.starts_with("_rusti") {
+                    continue;
+                }
+
+                let ty = match tables.node_id_to_type_opt(tcx.hir.node_to_hir_id(id)) {
+                    Some(ty) => tcx.erase_regions(&ty),
+                    None => continue,
                 };
 
-                assert_eq!(res, 1);
+                if ty == expected {
+                    res.push(format!("{}: {}", name, ty));
+                } else if let ty::TyRef(_, ty::TypeAndMut{ty: referent, ..}) = ty.sty {
+                    // A reference to a `Copy` value may be dereferenced
+                    if referent == expected &&
+                            !referent.moves_by_default(tcx, ty::ParamEnv::reveal_all(), DUMMY_SP) {
+                        res.push(format!("*{}: {}", name, ty));
+                    }
+                }
+            }
+        }
 
-                unsafe { llvm::LLVMDisposeModule(llmod) };
-            },
-            None => panic!("Module not contained in ExecutionEngine"),
+        for &(ty, ref desc) in &self.items {
+            if ty == expected {
+                res.push(desc.clone());
+            }
         }
+
+        res
     }
+}
 
-    /// Compiles the given input only up to the analysis phase, calling the
-    /// given closure with a borrowed reference to the type context and
-    /// the produced analysis.
-    pub fn with_analysis<F, R, T>(&self, input: T, f: F) -> Option<R>
-            where F: Send + 'static, R: Send + 'static, T: IntoInput,
-            F: for<'a, 'gcx, 'tcx> FnOnce(&Crate, &ty::TyCtxt<'a, 'gcx, 'tcx>, ty::CrateAnalysis) -> R {
-        with_analysis(f, input.into_input(),
-            self.sysroot.clone(), self.lib_paths.clone())
+impl<'a, 'tcx> Visitor<'tcx> for HoleFinder<'a, 'tcx> {
+    fn nested_visit_map<'this>(&'this mut self) -> NestedVisitorMap<'this, 'tcx> {
+        NestedVisitorMap::All(&self.tcx.hir)
     }
 
-    /// Searches for the named function in the set of loaded modules,
-    /// beginning with the most recently added module.
-    /// If the function is found, a raw pointer is returned.
-    /// If the function is not found, `None` is returned.
-    pub fn get_function(&mut self, name: &str) -> Option<*const ()> {
-        let s = CString::new(name.as_bytes()).unwrap();
+    fn visit_nested_body(&mut self, id: hir::BodyId) {
+        let prev_tables = mem::replace(&mut self.tables, Some(self.tcx.body_tables(id)));
+        let prev_locals = self.locals.clone();
+        let body = self.tcx.hir.body(id);
 
-        for m in self.modules.iter().rev() {
-            let fv = unsafe { llvm::LLVMGetNamedFunction(*m, s.as_ptr()) };
+        // Closures see the variables of the body enclosing them
+        for arg in &body.arguments {
+            self.bind(&arg.pat, 0);
+        }
 
-            if !fv.is_null() {
-                let fp = unsafe { llvm::LLVMGetPointerToGlobal(self.ee, fv) };
+        self.visit_body(body);
+        self.tables = prev_tables;
+        self.locals = prev_locals;
+    }
 
-                assert!(!fp.is_null());
+    fn visit_local(&mut self, local: &'tcx hir::Local) {
+        intravisit::walk_local(self, local);
 
-                return Some(fp as *const ());
-            }
+        let offset = self.offset(local.span.hi());
+        self.bind(&local.pat, offset);
+    }
+
+    fn visit_expr(&mut self, expr: &'tcx hir::Expr) {
+        let is_hole = match expr.node {
+            hir::ExprCall(ref callee, ref args) if args.is_empty() => match callee.node {
+                hir::ExprPath(hir::QPath::Resolved(None, ref path)) =>
+                    path.segments.last().map_or(false, |s| s.name == hole::FUNCTION),
+                _ => false,
+            },
+            _ => false,
+        };
+
+        if is_hole {
+            let offset = self.offset(expr.span.lo());
+            let expected = match self.tables.and_then(|t| t.expr_ty_opt(expr)) {
+                Some(ty) if !ty.references_error() => Some(ty),
+                _ => None,
+            };
+
+            let fits = expected.map(|ty| self.fits(ty, offset)).unwrap_or_default();
+
+            self.holes.push(Hole{
+                offset: offset,
+                expected: expected.map(|ty| ty.to_string()),
+                fits: fits,
+            });
         }
 
-        None
+        intravisit::walk_expr(self, expr);
     }
+}
 
-    /// Searches for the named global in the set of loaded modules,
-    /// beginning with the most recently added module.
-    /// If the global is found, a raw pointer is returned.
-    /// If the global is not found, `None` is returned.
-    pub fn get_global(&mut self, name: &str) -> Option<*const ()> {
-        let s = CString::new(name.as_bytes()).unwrap();
+/// Returns the functions, constants and statics defined at the crate root
+/// which may fill holes, each with the type of its value and a description.
+fn hole_candidates<'a, 'tcx>(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>) -> Vec<(ty::Ty<'tcx>, String)> {
+    let mut res = Vec::new();
 
-        for m in self.modules.iter().rev() {
-            let gv = unsafe { llvm::LLVMGetNamedGlobal(*m, s.as_ptr()) };
+    for id in &tcx.hir.krate().module.item_ids {
+        let item = tcx.hir.expect_item(id.id);
+        let name = item.name.to_string();
 
-            if !gv.is_null() {
-                let gp = unsafe { llvm::LLVMGetPointerToGlobal(self.ee, gv) };
+        if name.starts_with("_rusti") || name.starts_with("_Rusti") {
+            continue;
+        }
 
-                assert!(!gp.is_null());
+        let def_id = tcx.hir.local_def_id(item.id);
 
-                return Some(gp as *const ());
+        match item.node {
+            hir::ItemFn(..) => {
+                let sig = tcx.erase_late_bound_regions(&tcx.fn_sig(def_id));
+                res.push((tcx.erase_regions(&sig.output()),
+                    fn_signature(tcx, def_id, tcx.mk_self_type())));
+            }
+            hir::ItemConst(..) | hir::ItemStatic(..) => {
+                let ty = tcx.type_of(def_id);
+                res.push((tcx.erase_regions(&ty), format!("{}: {}", name, ty)));
             }
+            _ => (),
         }
+    }
 
-        None
+    res
+}
+
+/// Returns the names of functions and globals defined
+/// with external linkage in the given module.
+fn defined_symbols(llmod: llvm::ModuleRef) -> Vec<String> {
+    unsafe fn push_defined(syms: &mut Vec<String>, v: llvm::ValueRef) {
+        if llvm::LLVMIsDeclaration(v) == 0 &&
+                llvm::LLVMRustGetLinkage(v) == llvm::Linkage::ExternalLinkage {
+            let name = CStr::from_ptr(llvm::LLVMGetValueName(v));
+            syms.push(name.to_string_lossy().into_owned());
+        }
     }
 
-    /// Loads all dependencies of compiled code.
-    /// Expects a series of paths to dynamic library files.
-    fn load_deps(&self, deps: &Deps) {
-        for path in deps.iter() {
-            debug!("loading crate {}", path.display());
+    let mut syms = Vec::new();
 
-            let s = match path.as_os_str().to_str() {
-                Some(s) => s,
-                None => panic!(
-                    "Could not convert crate path to UTF-8 string: {:?}", path)
-            };
-            let cs = CString::new(s).unwrap();
+    unsafe {
+        let mut f = llvm::LLVMGetFirstFunction(llmod);
 
-            let res = unsafe { llvm::LLVMRustLoadDynamicLibrary(cs.as_ptr()) };
+        while !f.is_null() {
+            push_defined(&mut syms, f);
+            f = llvm::LLVMGetNextFunction(f);
+        }
 
-            if res == 0 {
-                panic!("Failed to load crate {:?}: {}",
-                    path.display(), llvm_error());
+        let mut g = llvm::LLVMGetFirstGlobal(llmod);
+
+        while !g.is_null() {
+            push_defined(&mut syms, g);
+            g = llvm::LLVMGetNextGlobal(g);
+        }
+    }
+
+    syms
+}
+
+/// Gives each function defined by the given module external linkage,
+/// so that the execution engine can report its address to `function_names`.
+///
+/// The mangled name of such a function is unique to the module, as each
+/// input is compiled with its own crate disambiguator.
+fn externalize_functions(llmod: llvm::ModuleRef) {
+    unsafe {
+        let mut f = llvm::LLVMGetFirstFunction(llmod);
+
+        while !f.is_null() {
+            if llvm::LLVMIsDeclaration(f) == 0 &&
+                    llvm::LLVMRustGetLinkage(f) == llvm::Linkage::InternalLinkage {
+                llvm::LLVMRustSetLinkage(f, llvm::Linkage::ExternalLinkage);
             }
+
+            f = llvm::LLVMGetNextFunction(f);
         }
     }
 }
 
-impl Drop for ExecutionEngine {
-    fn drop(&mut self) {
-        unsafe { llvm::LLVMDisposeExecutionEngine(self.ee) };
+/// Returns the names of symbols defined by the given module which are
+/// also defined by a library loaded into the process.
+///
+/// Symbols defined by other modules in the execution engine are not reported;
+/// every module redefines all items from previous input, and functions are
+/// looked up in the most recently added module first. Neither are symbols
+/// which the compiler generates in every crate.
+#[cfg(unix)]
+fn library_collisions(llmod: llvm::ModuleRef) -> Vec<String> {
+    use libc::{dlsym, RTLD_DEFAULT};
+
+    defined_symbols(llmod).into_iter().filter(|name| {
+        if GENERATED_SYMBOL_PREFIXES.iter().any(|p| name.starts_with(p)) {
+            return false;
+        }
+
+        let s = CString::new(name.as_bytes()).unwrap();
+        !unsafe { dlsym(RTLD_DEFAULT, s.as_ptr()) }.is_null()
+    }).collect()
+}
+
+#[cfg(not(unix))]
+fn library_collisions(_llmod: llvm::ModuleRef) -> Vec<String> {
+    Vec::new()
+}
+
+/// Creates an empty LLVM module in a new context, returning both.
+fn empty_module() -> (llvm::ContextRef, llvm::ModuleRef) {
+    let name = CString::new("rusti").unwrap();
+
+    unsafe {
+        let llcx = llvm::LLVMRustContextCreate(false);
+        (llcx, llvm::LLVMModuleCreateWithNameInContext(name.as_ptr(), llcx))
     }
 }
 
@@ -265,20 +2545,31 @@ fn get_sysroot() -> PathBuf {
     PathBuf::from(path)
 }
 
-fn build_exec_options(sysroot: PathBuf, libs: Vec<String>) -> Options {
+fn build_exec_options(exec_opts: ExecOptions) -> Options {
     let mut opts = basic_options();
 
+    opts.lint_opts = exec_opts.lint_levels();
+
+    if exec_opts.caps_lints() {
+        opts.lint_cap = Some(lint::Allow);
+    }
+
     // librustc derives sysroot from the executable name.
     // Since we are not rustc, we must specify it.
-    opts.maybe_sysroot = Some(sysroot);
+    opts.maybe_sysroot = Some(exec_opts.sysroot);
 
-    for p in libs.iter() {
+    for p in exec_opts.lib_paths.iter() {
         opts.search_paths.add_path(&p,
             ErrorOutputType::HumanReadable(errors::ColorConfig::Auto));
     }
 
-    // Prefer faster build times
-    opts.optimize = OptLevel::No;
+    // Every input is compiled with all items defined so far,
+    // so optimization also inlines helper functions from earlier inputs.
+    opts.optimize = exec_opts.opt_level;
+
+    // Units are linked into a single LLVM module to add to the execution engine
+    opts.cg.codegen_units = Some(exec_opts.codegen_units);
+    opts.debugging_opts.thinlto = Some(exec_opts.thin_lto);
 
     // Don't require a `main` function
     opts.crate_types = vec![config::CrateTypeDylib];
@@ -286,9 +2577,83 @@ fn build_exec_options(sysroot: PathBuf, libs: Vec<String>) -> Options {
     // Allow use of unstable features
     opts.unstable_features = UnstableFeatures::Allow;
 
+    if exec_opts.json_diagnostics {
+        opts.error_format = ErrorOutputType::Json(false);
+    } else {
+        opts.error_format = ErrorOutputType::HumanReadable(exec_opts.color);
+    }
+
+    if let Some(target) = exec_opts.target {
+        opts.target_triple = target;
+    }
+
+    opts.cg.linker = exec_opts.linker;
+    opts.cg.link_arg = exec_opts.link_args;
+
     opts
 }
 
+/// Compiles a complete program, which defines its own `main` function,
+/// into an executable at the path `output`, without an execution engine.
+///
+/// If the program fails to compile, errors will be printed to `stderr`
+/// and `false` will be returned.
+pub fn build_program<T>(input: T, output: &Path, exec_opts: ExecOptions) -> bool
+        where T: IntoInput {
+    build_executable(input.into_input(), output.to_path_buf(), exec_opts, false)
+}
+
+/// Compiles a library crate into a dynamic library at the path `output`,
+/// which code loaded into an execution engine may link against.
+///
+/// If the crate fails to compile, errors will be printed to `stderr`
+/// and `false` will be returned.
+pub fn build_library<T>(input: T, output: &Path, exec_opts: ExecOptions) -> bool
+        where T: IntoInput {
+    build_output(input.into_input(), output.to_path_buf(), exec_opts,
+        config::CrateTypeDylib, false)
+}
+
+/// Returns the triples of all targets supported by the compiler, sorted.
+pub fn supported_targets() -> Vec<String> {
+    let mut targets = get_targets().collect::<Vec<_>>();
+    targets.sort();
+    targets
+}
+
+/// Returns the target features enabled when compiling with the given options,
+/// or `None` if the compiler could not be set up for the target.
+pub fn target_features(exec_opts: ExecOptions) -> Option<Vec<String>> {
+    monitor(move || {
+        let sess = build_session(build_exec_options(exec_opts), None, diagnostics_registry());
+        let trans = ::rustc_driver::get_trans(&sess);
+
+        trans.target_features(&sess).iter()
+            .map(|feature| feature.as_str().to_string()).collect()
+    }).ok()
+}
+
+/// Prints all features supported by the target of the given options to `stdout`,
+/// with a description of each, as `--print target-features` does.
+/// Returns `false` if the compiler could not be set up for the target.
+pub fn print_target_features(exec_opts: ExecOptions) -> bool {
+    monitor(move || {
+        let sess = build_session(build_exec_options(exec_opts), None, diagnostics_registry());
+        let trans = ::rustc_driver::get_trans(&sess);
+
+        trans.print(PrintRequest::TargetFeatures, &sess);
+
+        // LLVM prints through C stdio, which is buffered separately from `stdout`
+        unsafe { ::libc::fflush(ptr::null_mut()); }
+    }).is_ok()
+}
+
+extern "C" {
+    // Part of LLVM's C API, but not declared by `rustc_llvm`
+    fn LLVMAddGlobalMapping(ee: llvm::ExecutionEngineRef, global: llvm::ValueRef,
+        addr: *mut c_void);
+}
+
 struct SyncBuf(Arc<Mutex<Vec<u8>>>);
 
 impl Write for SyncBuf {
@@ -301,10 +2666,24 @@ impl Write for SyncBuf {
 
 /// Compiles input up to phase 4, translation to LLVM.
 ///
-/// Returns the LLVM `ModuleRef` and a series of paths to dynamic libraries
-/// for crates used in the given input.
-fn compile_input(input: Input, sysroot: PathBuf, libs: Vec<String>)
-        -> Option<(llvm::ModuleRef, Deps)> {
+/// Translation happens entirely in memory; no object files or libraries
+/// are written to disk for the compiled input.
+///
+/// Each compiled input is given a distinct crate disambiguator, derived from
+/// `id`, so that mangled symbol names never collide with those of other input.
+///
+/// Returns the LLVM `ModuleRef` with the `ContextRef` owning it, which the
+/// caller must dispose of, a series of paths to dynamic libraries
+/// for crates used in the given input and the time spent in each phase
+/// of compilation.
+///
+/// Compilation stops between phases, returning `None`, once `cancel` is set.
+/// Each of `hooks` is called as its phase completes.
+fn compile_input(input: Input, exec_opts: ExecOptions, id: usize, cancel: Arc<AtomicBool>,
+        hooks: CompileHooks) -> Option<(llvm::ContextRef, llvm::ModuleRef, Deps, PhaseTimes)> {
+    let CompileHooks{mut after_parse, mut after_hir_lowering, mut after_analysis,
+        mut after_llvm} = hooks;
+
     let r = monitor(move || {
         let compile_controller = ::rustc_driver::driver::CompileController::basic();
         let mut args = Vec::new();
@@ -316,8 +2695,9 @@ fn compile_input(input: Input, sysroot: PathBuf, libs: Vec<String>)
             None => return None,
         };
         let (sopts, cfg) = config::build_session_options_and_crate_config(&matches);
-        let opts = build_exec_options(sysroot, libs);
-        let sess = build_session(opts, None, Registry::new(&rustc::DIAGNOSTICS));
+        let mut opts = build_exec_options(exec_opts);
+        opts.cg.metadata = vec![format!("rusti-{}", id)];
+        let sess = build_session(opts, None, diagnostics_registry());
         let trans = ::rustc_driver::get_trans(&sess);
         let dep_graph = DepGraph::new_disabled();
         let cstore = Rc::new(CStore::new(trans.metadata_loader()));
@@ -326,6 +2706,9 @@ fn compile_input(input: Input, sysroot: PathBuf, libs: Vec<String>)
         let cfg = build_configuration(&sess, cfg);
 
         let id = "repl";
+        let mut phases = PhaseTimes::default();
+
+        let start = Instant::now();
 
         let krate = match driver::phase_1_parse_input(&compile_controller, &sess, &input) {
             Ok(krate) => krate,
@@ -335,6 +2718,18 @@ fn compile_input(input: Input, sysroot: PathBuf, libs: Vec<String>)
             }
         };
 
+        phases.parse = start.elapsed();
+
+        if let Some(ref mut f) = after_parse {
+            f(&krate);
+        }
+
+        if cancel.load(SeqCst) {
+            return None;
+        }
+
+        let start = Instant::now();
+
         let driver::ExpansionResult{defs, analysis, resolutions, mut hir_forest,
                 expanded_crate: krate} =
             match driver::phase_2_configure_and_expand(
@@ -343,6 +2738,14 @@ fn compile_input(input: Input, sysroot: PathBuf, libs: Vec<String>)
                 Err(_) => return None,
             };
 
+        phases.expand = start.elapsed();
+
+        if cancel.load(SeqCst) {
+            return None;
+        }
+
+        let start = Instant::now();
+
         let arenas = ty::AllArenas::new();
         let outputs = OutputFilenames {
             out_directory: PathBuf::new(),
@@ -353,19 +2756,37 @@ fn compile_input(input: Input, sysroot: PathBuf, libs: Vec<String>)
         };
         let ast_map = ast_map::map_crate(&sess, &*cstore, &mut hir_forest, &defs);
 
+        if let Some(ref mut f) = after_hir_lowering {
+            f(&ast_map);
+        }
+
         driver::phase_3_run_analysis_passes(
             &*trans, &compile_controller,
             &sess, &*cstore, ast_map, analysis, resolutions, &arenas, id, &outputs,
-                |tcx, analysis, rx, _| {
+                |tcx, analysis, _, _| {
                     tcx.sess.abort_if_errors();
 
-                    let ongoing_trans = driver::phase_4_translate_to_llvm(
-                        &*trans,
-                        tcx,
-                        rx);
+                    // Analysis passes have run by the time this is called
+                    phases.typeck = start.elapsed();
+
+                    if let Some(ref mut f) = after_analysis {
+                        f(&tcx);
+                    }
+
+                    if cancel.load(SeqCst) {
+                        return None;
+                    }
+
+                    let start = Instant::now();
+
+                    // Translate straight into memory; the module is handed to
+                    // the execution engine without emitting or linking objects.
+                    let module = rustc_trans::trans_crate_in_memory(tcx);
 
                     tcx.sess.abort_if_errors();
 
+                    phases.trans = start.elapsed();
+
                     let crates = tcx.sess.cstore.used_crates(RequireDynamic);
 
                     // Collect crates used in the session.
@@ -373,27 +2794,66 @@ fn compile_input(input: Input, sysroot: PathBuf, libs: Vec<String>)
                     let deps = crates.into_iter().rev()
                         .filter_map(|(_, p)| p).collect();
 
-                    assert_eq!(ongoing_trans.modules.len(), 1);
-                    let llmod = match ongoing_trans.modules[0].source {
-                        ModuleSource::Translated(ref ll) => ll.llmod,
-                        _ => panic!("translation contains no LLVM module")
-                    };
+                    let (llcx, llmod) = module.into_raw();
+
+                    if let Some(ref mut f) = after_llvm {
+                        f(llmod);
+                    }
 
                     // Workaround because raw pointers do not impl Send
-                    let modp = llmod as usize;
+                    let (cxp, modp) = (llcx as usize, llmod as usize);
 
-                    (modp, deps)
-                }).ok()
+                    Some((cxp, modp, deps, phases))
+                }).ok().and_then(|r| r)
     });
 
-    let r: Option<_> = r;
-    r.and_then(|r| r).map(|(modp, deps)| (modp as llvm::ModuleRef, deps))
+    let r: Option<_> = r.ok();
+    r.and_then(|r| r).map(|(cxp, modp, deps, phases)|
+        (cxp as llvm::ContextRef, modp as llvm::ModuleRef, deps, phases))
+}
+
+/// Compiles input into an executable, running all phases through linking.
+/// If `test` is `true`, the executable is a test harness.
+fn build_executable(input: Input, output: PathBuf, exec_opts: ExecOptions, test: bool) -> bool {
+    build_output(input, output, exec_opts, config::CrateTypeExecutable, test)
+}
+
+/// Compiles input into a crate of the given type, running all phases through linking.
+fn build_output(input: Input, output: PathBuf, exec_opts: ExecOptions,
+        crate_type: config::CrateType, test: bool) -> bool {
+    monitor(move || {
+        let sanitizer = exec_opts.sanitizer.clone();
+        let save_temps = exec_opts.save_temps;
+        let mut opts = build_exec_options(exec_opts);
+
+        opts.crate_types = vec![crate_type];
+        // The sanitizer runtime is linked into the executable
+        opts.debugging_opts.sanitizer = sanitizer;
+        opts.cg.save_temps = save_temps;
+        opts.output_types = OutputTypes::new(&[(OutputType::Exe, None)]);
+        opts.test = test;
+
+        let sess = build_session(opts, None, diagnostics_registry());
+        let trans = ::rustc_driver::get_trans(&sess);
+        let cstore = Rc::new(CStore::new(trans.metadata_loader()));
+        rustc_lint::register_builtins(&mut sess.lint_store.borrow_mut(), Some(&sess));
+
+        let compile_controller = driver::CompileController::basic();
+        let out_dir = output.parent().map(|p| p.to_path_buf());
+
+        driver::compile_input(trans, &sess, &cstore, &None, &input,
+            &out_dir, &Some(output), None, &compile_controller).is_ok()
+    }).unwrap_or(false)
 }
 
 /// Compiles input up to phase 3, type/region check analysis, and calls
 /// the given closure with the borrowed type context and resulting `CrateAnalysis`.
-fn with_analysis<F, R>(f: F, input: Input, sysroot: PathBuf, libs: Vec<String>) -> Option<R>
-        where F: Send + 'static, R: Send + 'static,
+/// Unless `partial` is `true`, the closure is only called if analysis
+/// emitted no errors. Diagnostics are written to `dest`, if given, rather
+/// than `stderr`.
+fn with_analysis<F, R>(f: F, input: Input, exec_opts: ExecOptions,
+        dest: Option<Box<Write + Send>>, partial: bool) -> Result<R, AnalysisError>
+        where F: Send, R: Send,
         F: for<'a, 'gcx, 'tcx> FnOnce(&Crate, &ty::TyCtxt<'a, 'gcx, 'tcx>, ty::CrateAnalysis) -> R {
     monitor(move || {
         let compile_controller = ::rustc_driver::driver::CompileController::basic();
@@ -403,11 +2863,17 @@ fn with_analysis<F, R>(f: F, input: Input, sysroot: PathBuf, libs: Vec<String>)
         }
         let matches = match ::rustc_driver::handle_options(&args) {
             Some(matches) => matches,
-            None => return None,
+            None => return Err(AnalysisError::Failed),
         };
         let (sopts, cfg) = config::build_session_options_and_crate_config(&matches);
-        let opts = build_exec_options(sysroot, libs);
-        let sess = build_session(opts, None, Registry::new(&rustc::DIAGNOSTICS));
+        let opts = build_exec_options(exec_opts);
+        let sess = match dest {
+            Some(dest) => {
+                let codemap = Rc::new(CodeMap::new(opts.file_path_mapping()));
+                build_session_with_codemap(opts, None, diagnostics_registry(), codemap, Some(dest))
+            }
+            None => build_session(opts, None, diagnostics_registry()),
+        };
         let trans = ::rustc_driver::get_trans(&sess);
         let dep_graph = DepGraph::new_disabled();
         let cstore = Rc::new(CStore::new(trans.metadata_loader()));
@@ -421,7 +2887,7 @@ fn with_analysis<F, R>(f: F, input: Input, sysroot: PathBuf, libs: Vec<String>)
             Ok(krate) => krate,
             Err(mut e) => {
                 e.emit();
-                return None;
+                return Err(AnalysisError::Failed);
             }
         };
 
@@ -430,7 +2896,7 @@ fn with_analysis<F, R>(f: F, input: Input, sysroot: PathBuf, libs: Vec<String>)
             match driver::phase_2_configure_and_expand(
                 &sess, &cstore, krate, Some(<PluginRegistry>::new(&sess, krate.span)), id, None, MakeGlobMap::No, |_| Ok(())) {
                 Ok(res) => res,
-                Err(_) => return None,
+                Err(_) => return Err(AnalysisError::Failed),
             };
 
         let arenas = ty::AllArenas::new();
@@ -447,10 +2913,14 @@ fn with_analysis<F, R>(f: F, input: Input, sysroot: PathBuf, libs: Vec<String>)
             &*trans, &compile_controller,
             &sess, &*cstore, ast_map, analysis, resolutions, &arenas, id, &outputs,
                 |tcx, analysis, _, _| {
-                    tcx.dep_graph.with_ignore(|| {
+                    if !partial && tcx.sess.has_errors() {
+                        return Err(AnalysisError::Failed);
+                    }
+
+                    Ok(tcx.dep_graph.with_ignore(|| {
                         f(&krate, &tcx, analysis)
-                    })
-                }).ok()
+                    }))
+                }).unwrap_or(Err(AnalysisError::Failed))
     }).and_then(|r| r)
 }
 
@@ -458,26 +2928,41 @@ fn check_compile<F, R>(f: F) -> Option<R> where F: FnOnce() -> Result<R, usize>
     f().ok()
 }
 
-fn monitor<F, R>(f: F) -> Option<R>
-        where F: Send + 'static + FnOnce() -> R, R: Send + 'static {
+/// Runs the compiler on a thread of its own, which may
+/// borrow from the caller as the thread is joined before returning.
+/// Panics with `FatalError`, by which the compiler aborts after emitting
+/// errors, are reported as `Failed`; other panics are reported to `stderr`.
+fn monitor<'a, F, R>(f: F) -> Result<R, AnalysisError>
+        where F: Send + 'a + FnOnce() -> R, R: Send + 'a {
     let thread = Builder::new().name("compile_input".to_owned());
     let data = Arc::new(Mutex::new(Vec::new()));
     let sink = SyncBuf(data.clone());
+    let mut res = None;
 
-    let handle = thread.spawn(move || {
-        if !log_enabled!(::log::LogLevel::Debug) {
-            io::set_panic(Some(Box::new(sink)));
-        }
-        f()
-    }).unwrap();
+    {
+        let res = &mut res;
+        let run: Box<FnBox() + Send + 'a> = Box::new(move || *res = Some(f()));
+
+        // The thread is joined below, before anything `run` borrows goes
+        // out of scope, even if the thread panics
+        let run: Box<FnBox() + Send + 'static> = unsafe { mem::transmute(run) };
 
-    match handle.join() {
-        Ok(r) => Some(r),
-        Err(e) => {
+        let handle = thread.spawn(move || {
+            if !log_enabled!(::log::LogLevel::Debug) {
+                io::set_panic(Some(Box::new(sink)));
+            }
+            run()
+        }).unwrap();
+
+        if let Err(e) = handle.join() {
+            let fatal = e.is::<errors::FatalError>();
             handle_compiler_panic(e, data);
-            None
+
+            return Err(if fatal { AnalysisError::Failed } else { AnalysisError::Panicked });
         }
     }
+
+    Ok(res.unwrap())
 }
 
 fn handle_compiler_panic(e: Box<Any + Send + 'static>, data: Arc<Mutex<Vec<u8>>>) {
@@ -0,0 +1,147 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Exercises, as worked through with `--exercise`.
+//!
+//! Each exercise is a Markdown file, and exercises are taken in the order of
+//! their file names. Text outside of code blocks is the prompt, and a `rust`
+//! block contains starter code, which is shown with the prompt. A `check`
+//! block contains code which `.submit` runs in the session; the exercise
+//! is passed if the code compiles and does not panic, such that hidden tests
+//! can be written as assertions. If the exercise has an `output` block, the
+//! output and value of the check must also match it.
+
+use std::fs::{read_dir, File};
+use std::io::Read;
+use std::path::Path;
+
+use transcript::{parse_fence, read_block};
+
+/// An exercise read from a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exercise {
+    /// Name of the exercise: the name of its file, without extension
+    pub name: String,
+    /// Text describing the task
+    pub prompt: String,
+    /// Code which the solution completes
+    pub starter: Option<String>,
+    /// Code run in the session to check the solution
+    pub check: String,
+    /// Expected output and value of the check
+    pub output: Option<String>,
+}
+
+impl Exercise {
+    /// Prints the prompt and starter code of the exercise.
+    pub fn print(&self) {
+        println!("exercise `{}`\n", self.name);
+        print!("{}", self.prompt);
+
+        if let Some(ref starter) = self.starter {
+            println!("");
+
+            for line in starter.lines() {
+                println!("    {}", line);
+            }
+        }
+    }
+}
+
+/// Reads the exercises of a directory, in the order of their file names.
+pub fn load(dir: &Path) -> Result<Vec<Exercise>, String> {
+    let entries = read_dir(dir).map_err(|e| format!("failed to read {}: {}", dir.display(), e))?;
+    let mut paths = Vec::new();
+
+    for entry in entries {
+        let path = entry.map_err(|e| format!("failed to read {}: {}", dir.display(), e))?.path();
+
+        if path.extension().map_or(false, |ext| ext == "md") {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+
+    if paths.is_empty() {
+        return Err(format!("no exercises in {}", dir.display()));
+    }
+
+    let mut res = Vec::new();
+
+    for path in paths {
+        let mut text = String::new();
+
+        if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_string(&mut text)) {
+            return Err(format!("failed to read {}: {}", path.display(), e));
+        }
+
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+
+        match parse(name, &text) {
+            Some(exercise) => res.push(exercise),
+            None => return Err(format!("{}: exercise has no `check` block", path.display())),
+        }
+    }
+
+    Ok(res)
+}
+
+/// Parses an exercise, returning `None` if it has no `check` block.
+fn parse(name: String, text: &str) -> Option<Exercise> {
+    let mut prompt = String::new();
+    let mut starter = None;
+    let mut check = None;
+    let mut output = None;
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        if let Some((fence, lang)) = parse_fence(line, "") {
+            let block = read_block(&mut lines, fence, "");
+
+            match lang.trim() {
+                "check" => check = Some(block),
+                "output" => output = Some(block),
+                "rust" | "" => starter = Some(block),
+                _ => (),
+            }
+        } else {
+            prompt.push_str(line);
+            prompt.push('\n');
+        }
+    }
+
+    Some(Exercise{
+        name: name,
+        prompt: prompt.trim().to_owned() + "\n",
+        starter: starter,
+        check: check?,
+        output: output,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Exercise};
+
+    #[test]
+    fn test_parse() {
+        let text = "Write `double`.\n\n```rust\nfn double(x: i32) -> i32 {\n}\n```\n\n\
+            ```check\ndouble(21)\n```\n\n```output\n42\n```\n";
+
+        assert_eq!(parse("double".to_owned(), text), Some(Exercise{
+            name: "double".to_owned(),
+            prompt: "Write `double`.\n".to_owned(),
+            starter: Some("fn double(x: i32) -> i32 {\n}\n".to_owned()),
+            check: "double(21)\n".to_owned(),
+            output: Some("42\n".to_owned()),
+        }));
+
+        assert_eq!(parse("none".to_owned(), "Write something.\n"), None);
+    }
+}
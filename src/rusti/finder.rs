@@ -0,0 +1,116 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fuzzy selection of past input, items defined in the session and
+//! crates in use, as bound to Ctrl-T.
+//!
+//! Selection is made with `fzf`, which draws beneath the prompt;
+//! the selected line is inserted at the cursor.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Program which selects among candidates
+pub const FZF: &'static str = "fzf";
+
+/// Name of the line editor command which runs the finder
+pub const FIND_COMMAND: &'static str = "fuzzy-find";
+
+/// Key sequence bound to the finder by default: Ctrl-T
+pub const FIND_SEQUENCE: &'static str = "\x14";
+
+/// Keywords which introduce an item, followed by its name
+const ITEM_KEYWORDS: &'static [&'static str] = &["const", "enum", "fn", "macro_rules!", "mod",
+    "static", "struct", "trait", "type", "union"];
+
+/// Words which may precede or follow the keyword of an item
+const QUALIFIERS: &'static [&'static str] = &["extern", "mut", "pub", "unsafe"];
+
+/// Lets the user select one of `candidates`, returning the selection,
+/// or `None` if the selection is cancelled or `fzf` cannot be run.
+pub fn select(candidates: &[String]) -> Option<String> {
+    let mut child = Command::new(FZF)
+        .args(&["--height=40%", "--reverse", "--no-multi"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        for c in candidates {
+            // fzf stops reading once a selection is made
+            if writeln!(stdin, "{}", c).is_err() {
+                break;
+            }
+        }
+    }
+
+    let output = child.wait_with_output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let selection = String::from_utf8(output.stdout).ok()?;
+    let selection = selection.trim_right_matches('\n');
+
+    if selection.is_empty() {
+        None
+    } else {
+        Some(selection.to_owned())
+    }
+}
+
+/// Returns the name of the item defined by `item`, or `None` if the item
+/// is unnamed, such as an `impl`.
+pub fn item_name(item: &str) -> Option<&str> {
+    let words = item.lines()
+        .map(|line| line.trim_left())
+        .filter(|line| !line.starts_with("#") && !line.starts_with("//"))
+        .flat_map(|line| line.split_whitespace());
+
+    let mut keyword = false;
+
+    for word in words {
+        if QUALIFIERS.contains(&word) || word.starts_with("pub(") || word.starts_with('"') {
+            continue;
+        }
+
+        if ITEM_KEYWORDS.contains(&word) {
+            keyword = true;
+            continue;
+        }
+
+        if !keyword {
+            return None;
+        }
+
+        let end = word.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(word.len());
+
+        return if end == 0 { None } else { Some(&word[..end]) };
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::item_name;
+
+    #[test]
+    fn test_item_name() {
+        assert_eq!(item_name("fn double(x: i32) -> i32 { x * 2 }"), Some("double"));
+        assert_eq!(item_name("#[derive(Debug)]\npub struct Point<T> { x: T }"), Some("Point"));
+        assert_eq!(item_name("pub(crate) const fn one() -> u8 { 1 }"), Some("one"));
+        assert_eq!(item_name("static mut COUNT: u32 = 0;"), Some("COUNT"));
+        assert_eq!(item_name("extern \"C\" fn cb() {}"), Some("cb"));
+        assert_eq!(item_name("macro_rules! square { ($x:expr) => ($x * $x) }"), Some("square"));
+        assert_eq!(item_name("impl Point<i32> { fn new() {} }"), None);
+        assert_eq!(item_name("extern crate rand;"), None);
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Application of suggestions made by compiler diagnostics.
+
+use std::ops::Range;
+
+use serialize::json::Json;
+
+/// Applicability of suggestions which may be applied without review
+const MACHINE_APPLICABLE: &'static str = "MachineApplicable";
+
+/// Replacement of a range of source text
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Replacement {
+    /// Byte range of the replaced text
+    pub range: Range<usize>,
+    /// Text replacing the range
+    pub text: String,
+}
+
+/// Returns the replacements suggested by diagnostics emitted as JSON,
+/// one per line, with byte ranges within the compiled program.
+///
+/// Only suggestions which the compiler considers applicable without review
+/// are returned. Compilers which don't state the applicability of
+/// suggestions, such as the one rusti is built with, suggest nothing which
+/// is returned.
+pub fn suggestions(stderr: &str) -> Vec<Replacement> {
+    let mut res = Vec::new();
+
+    for line in stderr.lines() {
+        if let Ok(diag) = Json::from_str(line) {
+            collect_suggestions(&diag, &mut res);
+        }
+    }
+
+    res
+}
+
+/// Collects suggestions from a diagnostic and its children.
+fn collect_suggestions(diag: &Json, res: &mut Vec<Replacement>) {
+    let spans = diag.find("spans").and_then(|s| s.as_array()).map_or(&[][..], |s| &s[..]);
+
+    for span in spans {
+        let text = match span.find("suggested_replacement").and_then(|r| r.as_string()) {
+            Some(text) => text,
+            None => continue,
+        };
+
+        // Imports are inserted before all items; see `imports`
+        if text.trim_left().starts_with("use ") {
+            continue;
+        }
+
+        if span.find("suggestion_applicability").and_then(|a| a.as_string()) !=
+                Some(MACHINE_APPLICABLE) {
+            continue;
+        }
+
+        let start = span.find("byte_start").and_then(|b| b.as_u64());
+        let end = span.find("byte_end").and_then(|b| b.as_u64());
+
+        if let (Some(start), Some(end)) = (start, end) {
+            res.push(Replacement{
+                range: start as usize..end as usize,
+                text: text.to_owned(),
+            });
+        }
+    }
+
+    let children = diag.find("children").and_then(|c| c.as_array()).map_or(&[][..], |c| &c[..]);
+
+    for child in children {
+        collect_suggestions(child, res);
+    }
+}
+
+/// Returns the `use` declarations suggested by diagnostics emitted as JSON,
+/// one per line, to import unresolved names. Each entry holds the candidates
+/// suggested for one name.
+pub fn imports(stderr: &str) -> Vec<Vec<String>> {
+    let mut res = Vec::new();
+
+    for line in stderr.lines() {
+        if let Ok(diag) = Json::from_str(line) {
+            collect_imports(&diag, &mut res);
+        }
+    }
+
+    res
+}
+
+/// Collects suggested imports from a diagnostic and its children.
+fn collect_imports(diag: &Json, res: &mut Vec<Vec<String>>) {
+    let spans = diag.find("spans").and_then(|s| s.as_array()).map_or(&[][..], |s| &s[..]);
+
+    // Each candidate is suggested as a separate replacement
+    let mut candidates = Vec::new();
+
+    for span in spans {
+        if let Some(text) = span.find("suggested_replacement").and_then(|r| r.as_string()) {
+            let text = text.trim();
+
+            if text.starts_with("use ") && text.ends_with(';') &&
+                    !candidates.iter().any(|c| c == text) {
+                candidates.push(text.to_owned());
+            }
+        }
+    }
+
+    if !candidates.is_empty() {
+        res.push(candidates);
+    }
+
+    let children = diag.find("children").and_then(|c| c.as_array()).map_or(&[][..], |c| &c[..]);
+
+    for child in children {
+        collect_imports(child, res);
+    }
+}
+
+/// Applies replacements to text. Replacements which overlap an earlier
+/// replacement, or which lie outside of the text, are ignored.
+pub fn apply(text: &str, replacements: &[Replacement]) -> String {
+    let mut sorted = replacements.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|r| (r.range.start, r.range.end));
+
+    let mut res = String::with_capacity(text.len());
+    let mut pos = 0;
+
+    for r in sorted {
+        if r.range.start < pos || r.range.end > text.len() || r.range.start > r.range.end ||
+                !text.is_char_boundary(r.range.start) || !text.is_char_boundary(r.range.end) {
+            continue;
+        }
+
+        res.push_str(&text[pos..r.range.start]);
+        res.push_str(&r.text);
+        pos = r.range.end;
+    }
+
+    res.push_str(&text[pos..]);
+    res
+}
+
+/// Returns a line-by-line diff of two texts, with removed lines prefixed by
+/// `-`, added lines by `+` and unchanged lines by a space.
+pub fn diff(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // Length of the longest common subsequence of the remaining lines
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut res = String::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            res.push_str(&format!(" {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            res.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            res.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply, diff, imports, suggestions, Replacement};
+
+    #[test]
+    fn test_suggestions() {
+        let stderr = r#"{"message":"a","spans":[],"children":[{"message":"b","spans":[{"byte_start":4,"byte_end":5,"suggested_replacement":"mut x","suggestion_applicability":"MachineApplicable"}],"children":[]},{"message":"c","spans":[{"byte_start":1,"byte_end":2,"suggested_replacement":"y","suggestion_applicability":"MaybeIncorrect"}],"children":[]}]}
+{"message":"d","spans":[{"byte_start":8,"byte_end":8,"suggested_replacement":"&"}],"children":[]}
+"#;
+
+        assert_eq!(suggestions(stderr), vec![
+            Replacement{range: 4..5, text: "mut x".to_owned()},
+        ]);
+    }
+
+    #[test]
+    fn test_imports() {
+        let stderr = r#"{"message":"a","spans":[],"children":[{"message":"b","spans":[{"byte_start":0,"byte_end":0,"suggested_replacement":"use std::collections::HashMap;\n\n"}],"children":[]}]}
+{"message":"c","spans":[],"children":[{"message":"d","spans":[{"byte_start":0,"byte_end":0,"suggested_replacement":"use std::fmt::Result;\n\n"},{"byte_start":0,"byte_end":0,"suggested_replacement":"use std::io::Result;\n\n"}],"children":[]}]}
+"#;
+
+        assert_eq!(imports(stderr), vec![
+            vec!["use std::collections::HashMap;".to_owned()],
+            vec!["use std::fmt::Result;".to_owned(), "use std::io::Result;".to_owned()],
+        ]);
+    }
+
+    #[test]
+    fn test_apply() {
+        let replacements = [
+            Replacement{range: 8..8, text: "&".to_owned()},
+            Replacement{range: 4..5, text: "mut x".to_owned()},
+            Replacement{range: 4..6, text: "z".to_owned()},
+            Replacement{range: 20..21, text: "z".to_owned()},
+        ];
+
+        assert_eq!(apply("let x = y;", &replacements), "let mut x = &y;");
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("a\nb\nc", "a\nB\nc\nd"), " a\n-b\n+B\n c\n+d\n");
+        assert_eq!(diff("", "a"), "+a\n");
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Formatting of the values of input expressions.
+//!
+//! By default, values are formatted using `Debug`. A formatter may be
+//! registered for a type, either naming a built-in formatter or a function
+//! defined in the session which accepts a reference to a value of the type
+//! and returns a value implementing `Display`.
+//!
+//! Formatters are selected at compile time, through a trait generated into
+//! each program. The trait is implemented for all `Debug` types and its
+//! implementation is specialized for each type with a registered formatter.
+
+/// Built-in formatters, with the format string each one uses
+const BUILTINS: &'static [(&'static str, &'static str)] = &[
+    ("debug", "{:?}"),
+    ("display", "{}"),
+    ("pretty", "{:#?}"),
+];
+
+/// Suffix appended to truncated values
+const ELLIPSIS: &'static str = "...";
+
+/// Formatter registered for a type
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Formatter {
+    /// Type, as written in source
+    pub type_name: String,
+    /// Name of a built-in formatter or a path to a function
+    pub function: String,
+}
+
+impl Formatter {
+    /// Returns whether the formatter is built in to rusti,
+    /// rather than a function defined in the session.
+    pub fn is_builtin(&self) -> bool {
+        builtin_format(&self.function).is_some()
+    }
+}
+
+/// Set of formatters registered in a session
+#[derive(Clone, Debug, Default)]
+pub struct Formatters {
+    formatters: Vec<Formatter>,
+}
+
+impl Formatters {
+    /// Returns an empty set of formatters.
+    pub fn new() -> Formatters {
+        Formatters::default()
+    }
+
+    /// Returns whether no formatters are registered.
+    pub fn is_empty(&self) -> bool {
+        self.formatters.is_empty()
+    }
+
+    /// Returns an iterator over registered formatters.
+    pub fn iter(&self) -> ::std::slice::Iter<Formatter> {
+        self.formatters.iter()
+    }
+
+    /// Registers a formatter for a type,
+    /// replacing any formatter previously registered for it.
+    pub fn insert(&mut self, type_name: &str, function: &str) {
+        self.remove(type_name);
+        self.formatters.push(Formatter{
+            type_name: type_name.to_owned(),
+            function: function.to_owned(),
+        });
+    }
+
+    /// Removes the formatter registered for a type.
+    /// Returns whether a formatter was registered.
+    pub fn remove(&mut self, type_name: &str) -> bool {
+        let n = self.formatters.len();
+        self.formatters.retain(|f| f.type_name != type_name);
+        self.formatters.len() != n
+    }
+
+    /// Removes formatters which name functions defined in the session,
+    /// keeping those which use built-in formatters.
+    pub fn clear_functions(&mut self) {
+        self.formatters.retain(|f| f.is_builtin());
+    }
+
+    /// Returns crate attributes required by `items`.
+    pub fn attributes(&self) -> &'static str {
+        if self.is_empty() {
+            ""
+        } else {
+            "#![feature(specialization)]"
+        }
+    }
+
+    /// Returns source defining the formatting trait and its implementations.
+    pub fn items(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+
+        let mut items = String::from(r#"
+trait _RustiFormat {
+    fn _rusti_format(&self) -> String;
+}
+
+impl<T: ?Sized + std::fmt::Debug> _RustiFormat for T {
+    default fn _rusti_format(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+"#);
+
+        for f in &self.formatters {
+            let body = match builtin_format(&f.function) {
+                Some(fmt) => format!(r#"format!("{}", self)"#, fmt),
+                None => format!(r#"format!("{{}}", {}(self))"#, f.function),
+            };
+
+            items.push_str(&format!(r#"
+impl _RustiFormat for {ty} {{
+    fn _rusti_format(&self) -> String {{
+        {body}
+    }}
+}}
+"#,
+                ty = f.type_name,
+                body = body));
+        }
+
+        items
+    }
+
+    /// Returns an expression formatting the value of the variable `var`
+    /// as a `String`, without moving it.
+    pub fn format_expr(&self, var: &str) -> String {
+        if self.is_empty() {
+            format!(r#"format!("{{:?}}", {})"#, var)
+        } else {
+            format!("_RustiFormat::_rusti_format(&{})", var)
+        }
+    }
+}
+
+/// Returns the format string used by the named built-in formatter.
+pub fn builtin_format(name: &str) -> Option<&'static str> {
+    BUILTINS.iter().find(|&&(n, _)| n == name).map(|&(_, fmt)| fmt)
+}
+
+/// Truncates a formatted value to at most `limit` characters,
+/// marking the value as truncated if any characters were removed.
+pub fn truncate(value: &str, limit: usize) -> String {
+    match value.char_indices().nth(limit) {
+        Some((end, _)) => format!("{}{}", &value[..end], ELLIPSIS),
+        None => value.to_owned(),
+    }
+}
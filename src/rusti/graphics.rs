@@ -0,0 +1,145 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Renders image display data inline in capable terminals.
+
+use std::env::var;
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use serialize::base64::FromBase64;
+
+use display::DisplayData;
+
+/// Maximum size of a chunk of image data sent using the kitty protocol
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// Program used to convert images into sixel graphics
+const SIXEL_CONVERTER: &'static str = "img2sixel";
+
+/// Terminal graphics protocols
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Protocol {
+    /// Inline images protocol of iTerm2, also supported by WezTerm
+    Iterm,
+    /// Graphics protocol of the kitty terminal
+    Kitty,
+    /// DEC sixel graphics; images are converted using `img2sixel`
+    Sixel,
+}
+
+impl Protocol {
+    /// Detects the protocol supported by the terminal from the environment.
+    /// Returns `None` if no supported protocol is detected.
+    pub fn detect() -> Option<Protocol> {
+        let term = var("TERM").unwrap_or_default();
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+
+        if var("KITTY_WINDOW_ID").is_ok() || term.contains("kitty") {
+            Some(Protocol::Kitty)
+        } else if program == "iTerm.app" || program == "WezTerm" {
+            Some(Protocol::Iterm)
+        } else if term.contains("mlterm") || term.contains("sixel") {
+            Some(Protocol::Sixel)
+        } else {
+            None
+        }
+    }
+
+    /// Parses the name of a protocol.
+    pub fn parse(name: &str) -> Option<Protocol> {
+        match name {
+            "iterm" => Some(Protocol::Iterm),
+            "kitty" => Some(Protocol::Kitty),
+            "sixel" => Some(Protocol::Sixel),
+            _ => None,
+        }
+    }
+
+    /// Writes display data to the terminal using this protocol.
+    /// Returns `Ok(false)` if the data is not an image which can be rendered.
+    pub fn render<W: Write>(&self, w: &mut W, data: &DisplayData) -> io::Result<bool> {
+        if !data.mime.starts_with("image/") || data.mime == "image/svg+xml" {
+            return Ok(false);
+        }
+
+        // Terminals accept base64 data, but it should not contain line breaks
+        let b64 = data.data.split_whitespace().collect::<String>();
+
+        match *self {
+            Protocol::Iterm => {
+                let size = b64.from_base64().map(|b| b.len()).unwrap_or(0);
+
+                write!(w, "\x1b]1337;File=inline=1;size={}:{}\x07\n", size, b64)?;
+            }
+            Protocol::Kitty => {
+                // Only PNG data can be sent without being decoded first
+                if data.mime != "image/png" {
+                    return Ok(false);
+                }
+
+                let chunks = b64.as_bytes().chunks(KITTY_CHUNK_SIZE).collect::<Vec<_>>();
+
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = if i + 1 < chunks.len() { 1 } else { 0 };
+
+                    if i == 0 {
+                        write!(w, "\x1b_Gf=100,a=T,m={};", more)?;
+                    } else {
+                        write!(w, "\x1b_Gm={};", more)?;
+                    }
+                    w.write_all(chunk)?;
+                    write!(w, "\x1b\\")?;
+                }
+
+                writeln!(w, "")?;
+            }
+            Protocol::Sixel => {
+                let bytes = match b64.from_base64() {
+                    Ok(b) => b,
+                    Err(_) => return Ok(false),
+                };
+
+                w.write_all(&sixel(&bytes)?)?;
+            }
+        }
+
+        w.flush()?;
+        Ok(true)
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Protocol::Iterm => "iterm",
+            Protocol::Kitty => "kitty",
+            Protocol::Sixel => "sixel",
+        })
+    }
+}
+
+/// Converts an image into sixel graphics.
+fn sixel(image: &[u8]) -> io::Result<Vec<u8>> {
+    let mut child = Command::new(SIXEL_CONVERTER)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    child.stdin.take().unwrap().write_all(image)?;
+
+    let output = child.wait_with_output()?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other,
+            format!("{} exited with {}", SIXEL_CONVERTER, output.status)))
+    }
+}
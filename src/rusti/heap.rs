@@ -0,0 +1,169 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Counting of heap allocations made by compiled input.
+//!
+//! Compiled input calls the global allocator through the `__rust_alloc`
+//! family of functions. The execution engine maps these to the hooks defined
+//! here, which count each allocation before calling the global allocator.
+//! Allocations made by code compiled into the standard library itself,
+//! rather than instantiated in compiled input, are not counted. Nor are those
+//! of input which defines its own `#[global_allocator]`, as its module then
+//! defines these functions itself.
+
+use std::cmp::max;
+use std::sync::atomic::{AtomicIsize, AtomicUsize, ATOMIC_ISIZE_INIT, ATOMIC_USIZE_INIT};
+use std::sync::atomic::Ordering::SeqCst;
+
+/// Heap allocations made while running input
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeapStats {
+    /// Number of allocations, including reallocations
+    pub allocations: u64,
+    /// Total number of bytes allocated
+    pub bytes: u64,
+    /// Greatest number of bytes in use at once, beyond those in use
+    /// before input began to run
+    pub peak: u64,
+}
+
+impl HeapStats {
+    /// Adds the allocations of another run, keeping the greater peak.
+    pub fn add(&mut self, other: &HeapStats) {
+        self.allocations += other.allocations;
+        self.bytes += other.bytes;
+        self.peak = max(self.peak, other.peak);
+    }
+}
+
+static ALLOCATIONS: AtomicUsize = ATOMIC_USIZE_INIT;
+static BYTES: AtomicUsize = ATOMIC_USIZE_INIT;
+static LIVE: AtomicIsize = ATOMIC_ISIZE_INIT;
+static PEAK: AtomicIsize = ATOMIC_ISIZE_INIT;
+
+/// Resets counts, so that they describe only allocations made hereafter.
+pub fn reset() {
+    ALLOCATIONS.store(0, SeqCst);
+    BYTES.store(0, SeqCst);
+    LIVE.store(0, SeqCst);
+    PEAK.store(0, SeqCst);
+}
+
+/// Returns allocations counted since the last call to `reset`.
+pub fn current() -> HeapStats {
+    HeapStats{
+        allocations: ALLOCATIONS.load(SeqCst) as u64,
+        bytes: BYTES.load(SeqCst) as u64,
+        peak: max(PEAK.load(SeqCst), 0) as u64,
+    }
+}
+
+/// Returns the names of allocator functions with the hooks replacing them.
+pub fn hooks() -> [(&'static str, usize); 4] {
+    [
+        ("__rust_alloc", alloc_hook as usize),
+        ("__rust_alloc_zeroed", alloc_zeroed_hook as usize),
+        ("__rust_dealloc", dealloc_hook as usize),
+        ("__rust_realloc", realloc_hook as usize),
+    ]
+}
+
+extern "Rust" {
+    fn __rust_alloc(size: usize, align: usize, err: *mut u8) -> *mut u8;
+    fn __rust_alloc_zeroed(size: usize, align: usize, err: *mut u8) -> *mut u8;
+    fn __rust_dealloc(ptr: *mut u8, size: usize, align: usize);
+    fn __rust_realloc(ptr: *mut u8, old_size: usize, old_align: usize,
+        new_size: usize, new_align: usize, err: *mut u8) -> *mut u8;
+}
+
+fn record_alloc(size: usize) {
+    ALLOCATIONS.fetch_add(1, SeqCst);
+    BYTES.fetch_add(size, SeqCst);
+    record_live(size as isize);
+}
+
+fn record_live(change: isize) {
+    let live = LIVE.fetch_add(change, SeqCst) + change;
+    let mut peak = PEAK.load(SeqCst);
+
+    while live > peak {
+        match PEAK.compare_exchange_weak(peak, live, SeqCst, SeqCst) {
+            Ok(_) => break,
+            Err(p) => peak = p,
+        }
+    }
+}
+
+unsafe extern "C" fn alloc_hook(size: usize, align: usize, err: *mut u8) -> *mut u8 {
+    let ptr = __rust_alloc(size, align, err);
+
+    if !ptr.is_null() {
+        record_alloc(size);
+    }
+
+    ptr
+}
+
+unsafe extern "C" fn alloc_zeroed_hook(size: usize, align: usize, err: *mut u8) -> *mut u8 {
+    let ptr = __rust_alloc_zeroed(size, align, err);
+
+    if !ptr.is_null() {
+        record_alloc(size);
+    }
+
+    ptr
+}
+
+unsafe extern "C" fn dealloc_hook(ptr: *mut u8, size: usize, align: usize) {
+    __rust_dealloc(ptr, size, align);
+    record_live(-(size as isize));
+}
+
+unsafe extern "C" fn realloc_hook(ptr: *mut u8, old_size: usize, old_align: usize,
+        new_size: usize, new_align: usize, err: *mut u8) -> *mut u8 {
+    let new_ptr = __rust_realloc(ptr, old_size, old_align, new_size, new_align, err);
+
+    if !new_ptr.is_null() {
+        ALLOCATIONS.fetch_add(1, SeqCst);
+        BYTES.fetch_add(new_size, SeqCst);
+        record_live(new_size as isize - old_size as isize);
+    }
+
+    new_ptr
+}
+
+#[cfg(test)]
+mod test {
+    use super::{alloc_hook, current, dealloc_hook, realloc_hook, reset, HeapStats};
+
+    #[test]
+    fn test_hooks() {
+        let mut err = [0u8; 64];
+
+        reset();
+
+        unsafe {
+            let a = alloc_hook(16, 8, err.as_mut_ptr());
+            let a = realloc_hook(a, 16, 8, 64, 8, err.as_mut_ptr());
+            let b = alloc_hook(8, 8, err.as_mut_ptr());
+            dealloc_hook(a, 64, 8);
+            dealloc_hook(b, 8, 8);
+        }
+
+        assert_eq!(current(), HeapStats{allocations: 3, bytes: 88, peak: 72});
+    }
+
+    #[test]
+    fn test_add() {
+        let mut total = HeapStats{allocations: 2, bytes: 10, peak: 8};
+
+        total.add(&HeapStats{allocations: 1, bytes: 4, peak: 4});
+
+        assert_eq!(total, HeapStats{allocations: 3, bytes: 14, peak: 8});
+    }
+}
@@ -0,0 +1,409 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Syntax highlighting of Rust code and printed values using ANSI colors.
+//!
+//! Highlighting uses a simple lexer, rather than the compiler's own, so that
+//! incomplete or invalid code, such as partial input, can be highlighted.
+
+use syntax::errors::ColorConfig;
+
+/// Resets all colors and attributes
+const RESET: &'static str = "\x1b[0m";
+
+/// Rust keywords, including values which are highlighted as keywords
+const KEYWORDS: &'static [&'static str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+    "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct",
+    "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+];
+
+/// Style of a highlighted token
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Style {
+    /// Line or block comment
+    Comment,
+    /// Unmatched or mismatched bracket
+    Error,
+    /// Keyword
+    Keyword,
+    /// Lifetime or loop label
+    Lifetime,
+    /// Bracket at the cursor, and the bracket matching it
+    Match,
+    /// Numeric literal
+    Number,
+    /// String or character literal
+    String,
+}
+
+impl Style {
+    /// Returns the ANSI escape sequence which begins text in this style.
+    pub fn escape(&self) -> &'static str {
+        match *self {
+            Style::Comment => "\x1b[90m",
+            Style::Error => "\x1b[1;31m",
+            Style::Keyword => "\x1b[1;35m",
+            Style::Lifetime => "\x1b[33m",
+            Style::Match => "\x1b[1;4m",
+            Style::Number => "\x1b[36m",
+            Style::String => "\x1b[32m",
+        }
+    }
+}
+
+/// A highlighted range of code
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Token {
+    /// Byte offset of the start of the token
+    pub start: usize,
+    /// Byte offset of the end of the token
+    pub end: usize,
+    /// Style of the token
+    pub style: Style,
+}
+
+/// Returns whether colors should be used for output to `stdout`,
+/// given the color configuration.
+pub fn use_color(config: ColorConfig) -> bool {
+    match config {
+        ColorConfig::Always => true,
+        ColorConfig::Never => false,
+        ColorConfig::Auto => stdout_is_terminal(),
+    }
+}
+
+#[cfg(unix)]
+fn stdout_is_terminal() -> bool {
+    unsafe { ::libc::isatty(::libc::STDOUT_FILENO) != 0 }
+}
+
+#[cfg(not(unix))]
+fn stdout_is_terminal() -> bool {
+    false
+}
+
+/// Returns the code with ANSI escape sequences highlighting its tokens.
+pub fn highlight(code: &str) -> String {
+    render(code, &tokens(code))
+}
+
+/// Returns the code with ANSI escape sequences applied to the given tokens,
+/// which must be sorted and must not overlap.
+pub fn render(code: &str, tokens: &[Token]) -> String {
+    let mut res = String::with_capacity(code.len());
+    let mut pos = 0;
+
+    for tok in tokens {
+        res.push_str(&code[pos..tok.start]);
+        res.push_str(tok.style.escape());
+        res.push_str(&code[tok.start..tok.end]);
+        res.push_str(RESET);
+        pos = tok.end;
+    }
+
+    res.push_str(&code[pos..]);
+    res
+}
+
+/// Returns a line of input with ANSI escape sequences highlighting its tokens
+/// and any brackets which do not match.
+///
+/// `prev` is input preceding the line, such as earlier lines of a multi-line
+/// item, which may contain brackets closed in this line. If `cursor` is the
+/// byte offset in the line of the cursor of a line being edited, a bracket
+/// just before or at the cursor is highlighted along with the bracket
+/// matching it, if that is within the line.
+pub fn highlight_input(prev: &str, line: &str, cursor: Option<usize>) -> String {
+    let mut toks = tokens(line);
+
+    let code = format!("{}{}", prev, line);
+
+    if let Some(pos) = cursor.and_then(|c| bracket_at_cursor(line, c)) {
+        let pos = prev.len() + pos;
+
+        if let Some(other) = matching_bracket(&code, pos) {
+            for &p in &[pos, other] {
+                if p >= prev.len() {
+                    toks.push(Token{
+                        start: p - prev.len(),
+                        end: p - prev.len() + 1,
+                        style: Style::Match,
+                    });
+                }
+            }
+        }
+    }
+
+    for pos in unmatched_brackets(&code) {
+        if pos < prev.len() {
+            continue;
+        }
+
+        let pos = pos - prev.len();
+
+        if toks.iter().any(|t| t.start <= pos && pos < t.end) {
+            continue;
+        }
+
+        toks.push(Token{
+            start: pos,
+            end: pos + 1,
+            style: Style::Error,
+        });
+    }
+
+    toks.sort_by_key(|t| t.start);
+
+    render(line, &toks)
+}
+
+/// Returns the byte offsets of closing brackets which do not match
+/// an opening bracket. Brackets within comments and literals are ignored.
+pub fn unmatched_brackets(code: &str) -> Vec<usize> {
+    bracket_pairs(code).1
+}
+
+/// Returns the byte offset of the bracket matching the one at `pos`,
+/// if it is a bracket which is matched.
+pub fn matching_bracket(code: &str, pos: usize) -> Option<usize> {
+    bracket_pairs(code).0.into_iter()
+        .filter_map(|(open, close)| if open == pos {
+            Some(close)
+        } else if close == pos {
+            Some(open)
+        } else {
+            None
+        })
+        .next()
+}
+
+/// Returns the byte offsets of each pair of matching brackets, opening
+/// bracket first, and of closing brackets which do not match.
+/// Brackets within comments and literals are ignored.
+fn bracket_pairs(code: &str) -> (Vec<(usize, usize)>, Vec<usize>) {
+    let toks = tokens(code);
+    let mut open = Vec::new();
+    let mut pairs = Vec::new();
+    let mut unmatched = Vec::new();
+    let mut tok = toks.iter().peekable();
+
+    for (i, ch) in code.char_indices() {
+        while tok.peek().map_or(false, |t| t.end <= i) {
+            tok.next();
+        }
+
+        if tok.peek().map_or(false, |t| t.start <= i) {
+            continue;
+        }
+
+        match ch {
+            '(' | '[' | '{' => open.push((i, ch)),
+            ')' | ']' | '}' => {
+                let expected = match ch {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+
+                match open.last().cloned() {
+                    Some((start, c)) if c == expected => {
+                        open.pop();
+                        pairs.push((start, i));
+                    }
+                    _ => unmatched.push(i),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    (pairs, unmatched)
+}
+
+/// Returns the byte offset of a bracket just before the cursor at `cursor`,
+/// or else at the cursor.
+fn bracket_at_cursor(line: &str, cursor: usize) -> Option<usize> {
+    let is_bracket = |c: char| "()[]{}".contains(c);
+
+    match line[..cursor].chars().next_back() {
+        Some(c) if is_bracket(c) => return Some(cursor - 1),
+        _ => (),
+    }
+
+    match line[cursor..].chars().next() {
+        Some(c) if is_bracket(c) => Some(cursor),
+        _ => None,
+    }
+}
+
+/// Splits code into highlighted tokens.
+/// Text which is not highlighted is not included.
+pub fn tokens(code: &str) -> Vec<Token> {
+    let mut toks = Vec::new();
+    let mut chars = code.char_indices().peekable();
+
+    while let Some((start, ch)) = chars.next() {
+        let style = match ch {
+            '/' if code[start..].starts_with("//") => {
+                while let Some(&(_, c)) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+                Style::Comment
+            }
+            '/' if code[start..].starts_with("/*") => {
+                chars.next();
+                let mut prev = ' ';
+                while let Some((_, c)) = chars.next() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                Style::Comment
+            }
+            '"' => {
+                let mut escaped = false;
+                while let Some((_, c)) = chars.next() {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if c == '"' {
+                        break;
+                    }
+                }
+                Style::String
+            }
+            '\'' => {
+                let rest = &code[start + 1..];
+                let mut it = rest.chars();
+
+                match (it.next(), it.next()) {
+                    (Some('\\'), _) | (Some(_), Some('\'')) => {
+                        let mut escaped = false;
+                        while let Some((_, c)) = chars.next() {
+                            if escaped {
+                                escaped = false;
+                            } else if c == '\\' {
+                                escaped = true;
+                            } else if c == '\'' {
+                                break;
+                            }
+                        }
+                        Style::String
+                    }
+                    (Some(c), _) if is_ident_start(c) => {
+                        while let Some(&(_, c)) = chars.peek() {
+                            if !is_ident_char(c) {
+                                break;
+                            }
+                            chars.next();
+                        }
+                        Style::Lifetime
+                    }
+                    _ => continue,
+                }
+            }
+            c if c.is_digit(10) => {
+                while let Some(&(i, c)) = chars.peek() {
+                    let is_fraction = c == '.' &&
+                        code[i + 1..].chars().next().map_or(false, |c| c.is_digit(10));
+
+                    if !is_ident_char(c) && !is_fraction {
+                        break;
+                    }
+                    chars.next();
+                }
+                Style::Number
+            }
+            c if is_ident_start(c) => {
+                while let Some(&(_, c)) = chars.peek() {
+                    if !is_ident_char(c) {
+                        break;
+                    }
+                    chars.next();
+                }
+
+                let end = chars.peek().map_or(code.len(), |&(i, _)| i);
+
+                if KEYWORDS.contains(&&code[start..end]) {
+                    Style::Keyword
+                } else {
+                    continue;
+                }
+            }
+            _ => continue,
+        };
+
+        let end = chars.peek().map_or(code.len(), |&(i, _)| i);
+
+        toks.push(Token{
+            start: start,
+            end: end,
+            style: style,
+        });
+    }
+
+    toks
+}
+
+fn is_ident_start(c: char) -> bool {
+    c == '_' || c.is_alphabetic()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{highlight_input, matching_bracket, tokens, unmatched_brackets, Style};
+
+    fn styles(code: &str) -> Vec<(&str, Style)> {
+        tokens(code).into_iter().map(|t| (&code[t.start..t.end], t.style)).collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(styles(r#"let s: &'a str = "x\"y"; // hi"#), vec![
+            ("let", Style::Keyword),
+            ("'a", Style::Lifetime),
+            (r#""x\"y""#, Style::String),
+            ("// hi", Style::Comment),
+        ]);
+        assert_eq!(styles("Some('x', 1.5f32)"), vec![
+            ("'x'", Style::String),
+            ("1.5f32", Style::Number),
+        ]);
+    }
+
+    #[test]
+    fn test_unmatched_brackets() {
+        assert_eq!(unmatched_brackets("foo(a[0])"), vec![]);
+        assert_eq!(unmatched_brackets("foo(a[0)]"), vec![7]);
+        assert_eq!(unmatched_brackets("\")\" }"), vec![4]);
+    }
+
+    #[test]
+    fn test_matching_bracket() {
+        assert_eq!(matching_bracket("foo(a[0])", 3), Some(8));
+        assert_eq!(matching_bracket("foo(a[0])", 7), Some(5));
+        assert_eq!(matching_bracket("foo(a[0]", 3), None);
+        assert_eq!(matching_bracket("f(\")\")", 1), Some(5));
+
+        assert_eq!(highlight_input("", "f(x)", Some(4)),
+            "f\x1b[1;4m(\x1b[0mx\x1b[1;4m)\x1b[0m");
+        assert_eq!(highlight_input("fn f() {\n", "}", Some(1)), "\x1b[1;4m}\x1b[0m");
+        assert_eq!(highlight_input("", "f(x, y)", Some(3)), "f(x, y)");
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Plain-language hints for errors which commonly trouble newcomers,
+//! as shown by `.set hints on`.
+//!
+//! Each hint is selected by the code of an error and by fragments of its
+//! rendered message, and comes with a short example of code which compiles.
+
+use serialize::json::Json;
+
+/// An error, and the hint shown for it
+struct Pattern {
+    /// Code of the error
+    code: &'static str,
+    /// Fragments which the rendered error must contain
+    contains: &'static [&'static str],
+    /// Explanation of the error
+    hint: &'static str,
+    /// Code avoiding the error
+    example: &'static str,
+}
+
+const PATTERNS: &'static [Pattern] = &[
+    Pattern{
+        code: "E0382",
+        contains: &[],
+        hint: "a value can only have one owner. Assigning it, or passing it to a \
+            function, moves it, and the old name can no longer be used. Borrow the \
+            value with `&` instead, or `.clone()` it to keep a copy.",
+        example: "let a = String::from(\"hi\");\nlet b = a.clone();\nprintln!(\"{} {}\", a, b);",
+    },
+    Pattern{
+        code: "E0507",
+        contains: &[],
+        hint: "a value behind a reference belongs to someone else, so it cannot be \
+            moved out. Borrow it, or `.clone()` it.",
+        example: "let v = vec![String::from(\"hi\")];\nlet s = &v[0];\nlet t = v[0].clone();",
+    },
+    Pattern{
+        code: "E0308",
+        contains: &["expected type `std::string::String`", "found type `&"],
+        hint: "a string literal is a `&str`, borrowed text, but a `String`, which owns \
+            its text, is expected. Make one with `.to_string()` or `String::from`.",
+        example: "let s: String = \"hi\".to_string();",
+    },
+    Pattern{
+        code: "E0308",
+        contains: &["expected type `&", "found type `std::string::String`"],
+        hint: "a `String` was given where borrowed text, `&str`, is expected. \
+            Borrow it with `&`.",
+        example: "fn greet(name: &str) {}\nlet s = String::from(\"hi\");\ngreet(&s);",
+    },
+    Pattern{
+        code: "E0384",
+        contains: &[],
+        hint: "variables cannot be changed unless they are declared with `let mut`.",
+        example: "let mut n = 1;\nn += 1;",
+    },
+    Pattern{
+        code: "E0596",
+        contains: &[],
+        hint: "a variable can only be borrowed with `&mut` if it is declared with \
+            `let mut`.",
+        example: "let mut v = Vec::new();\nv.push(1);",
+    },
+];
+
+/// Returns the hints for errors among diagnostics emitted as JSON,
+/// one per line, in order and without repetition.
+pub fn hints(stderr: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut seen = Vec::new();
+
+    for line in stderr.lines() {
+        let diag = match Json::from_str(line) {
+            Ok(diag) => diag,
+            Err(_) => continue,
+        };
+
+        let code = diag.find_path(&["code", "code"]).and_then(|c| c.as_string());
+        let rendered = diag.find("rendered").and_then(|r| r.as_string()).unwrap_or("");
+
+        let found = PATTERNS.iter().enumerate().find(|&(_, p)| Some(p.code) == code &&
+            p.contains.iter().all(|s| rendered.contains(s)));
+
+        if let Some((i, pattern)) = found {
+            if !seen.contains(&i) {
+                seen.push(i);
+                res.push(format_hint(pattern));
+            }
+        }
+    }
+
+    res
+}
+
+fn format_hint(pattern: &Pattern) -> String {
+    let mut res = format!("hint: {}\nfor example:\n", pattern.hint);
+
+    for line in pattern.example.lines() {
+        res.push_str("    ");
+        res.push_str(line);
+        res.push('\n');
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::hints;
+
+    #[test]
+    fn test_hints() {
+        let stderr = r#"{"message":"use of moved value: `a`","code":{"code":"E0382","explanation":null},"level":"error","spans":[],"rendered":"error[E0382]: use of moved value: `a`\n"}
+{"message":"use of moved value: `b`","code":{"code":"E0382","explanation":null},"level":"error","spans":[],"rendered":"error[E0382]: use of moved value: `b`\n"}
+{"message":"mismatched types","code":{"code":"E0308","explanation":null},"level":"error","spans":[],"rendered":"error[E0308]: mismatched types\n  = note: expected type `std::string::String`\n             found type `&'static str`\n"}
+{"message":"mismatched types","code":{"code":"E0308","explanation":null},"level":"error","spans":[],"rendered":"error[E0308]: mismatched types\n  = note: expected type `i32`\n             found type `bool`\n"}
+not json"#;
+
+        let res = hints(stderr);
+
+        assert_eq!(res.len(), 2);
+        assert!(res[0].starts_with("hint: a value can only have one owner."));
+        assert!(res[0].ends_with("for example:\n    let a = String::from(\"hi\");\n    \
+            let b = a.clone();\n    println!(\"{} {}\", a, b);\n"));
+        assert!(res[1].contains("`.to_string()`"));
+    }
+}
@@ -0,0 +1,263 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! History of complete inputs, optionally persisted to a file.
+//!
+//! Each entry is a complete input, such as a command or a multi-line item.
+//! In the history file, each entry is written on a single line, with newlines
+//! and backslashes escaped.
+
+use std::env::home_dir;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// Name of the history file, within the home directory
+const HISTORY_FILE: &'static str = ".rusti_history";
+
+/// Maximum number of entries loaded from the history file
+const MAX_ENTRIES: usize = 1000;
+
+/// History of complete inputs
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Returns an empty history which is not persisted.
+    pub fn new() -> History {
+        History::default()
+    }
+
+    /// Returns the default path of the history file, within the home directory.
+    pub fn default_path() -> Option<PathBuf> {
+        home_dir().map(|p| p.join(HISTORY_FILE))
+    }
+
+    /// Loads history from the given file, to which new entries are appended.
+    /// A file which does not exist yields an empty history.
+    pub fn load(path: PathBuf) -> io::Result<History> {
+        let mut entries = Vec::new();
+
+        match File::open(&path) {
+            Ok(f) => {
+                for line in BufReader::new(f).lines() {
+                    entries.push(unescape(&line?));
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+
+        if entries.len() > MAX_ENTRIES {
+            let n = entries.len() - MAX_ENTRIES;
+            entries.drain(..n);
+        }
+
+        Ok(History{
+            entries: entries,
+            path: Some(path),
+        })
+    }
+
+    /// Adds an entry to the history, appending it to the history file, if any.
+    /// Empty entries and entries repeating the last entry are ignored.
+    pub fn push(&mut self, entry: &str) -> io::Result<()> {
+        let entry = entry.trim_right();
+
+        if entry.is_empty() || self.last() == Some(entry) {
+            return Ok(());
+        }
+
+        self.entries.push(entry.to_owned());
+
+        if let Some(ref path) = self.path {
+            let mut f = OpenOptions::new().append(true).create(true).open(path)?;
+
+            // Other sessions may be appending to the same file. Writing each
+            // line at once keeps their lines from being interleaved.
+            let line = format!("{}\n", escape(entry));
+            f.write_all(line.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the entry with the given number, beginning with 1.
+    pub fn get(&self, n: usize) -> Option<&str> {
+        if n == 0 {
+            None
+        } else {
+            self.entries.get(n - 1).map(|s| &s[..])
+        }
+    }
+
+    /// Returns the most recent entry.
+    pub fn last(&self) -> Option<&str> {
+        self.entries.last().map(|s| &s[..])
+    }
+
+    /// Returns an iterator over entries, with their numbers.
+    pub fn iter<'a>(&'a self) -> Box<Iterator<Item=(usize, &'a str)> + 'a> {
+        Box::new(self.entries.iter().enumerate().map(|(i, s)| (i + 1, &s[..])))
+    }
+
+    /// Resolves a history reference: `!!` for the most recent entry,
+    /// `!N` for entry number `N`, or `!-N` for the `N`th most recent entry.
+    ///
+    /// Returns `None` if the input is not a history reference and
+    /// `Some(Err(..))` if it refers to an entry which does not exist.
+    pub fn expand(&self, input: &str) -> Option<Result<&str, String>> {
+        let input = input.trim();
+
+        if !input.starts_with('!') {
+            return None;
+        }
+
+        let spec = &input[1..];
+
+        let n = if spec == "!" {
+            self.len()
+        } else if spec.starts_with('-') {
+            match spec[1..].parse::<usize>() {
+                Ok(n) if n <= self.len() => self.len() + 1 - n,
+                Ok(_) => 0,
+                Err(_) => return None,
+            }
+        } else {
+            match spec.parse::<usize>() {
+                Ok(n) => n,
+                Err(_) => return None,
+            }
+        };
+
+        Some(self.get(n).ok_or_else(|| format!("{}: event not found", input)))
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            match chars.next() {
+                Some('n') => res.push('\n'),
+                Some(c) => res.push(c),
+                None => res.push('\\'),
+            }
+        } else {
+            res.push(ch);
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    use tempfile::NamedTempFile;
+
+    use super::{escape, unescape, History};
+
+    #[test]
+    fn test_escape() {
+        let s = "fn foo() {\n    \"\\n\"\n}";
+        assert_eq!(unescape(&escape(s)), s);
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut h = History::new();
+
+        h.push("1").unwrap();
+        h.push("2").unwrap();
+
+        assert_eq!(h.expand("!!"), Some(Ok("2")));
+        assert_eq!(h.expand("!1"), Some(Ok("1")));
+        assert_eq!(h.expand("!-2"), Some(Ok("1")));
+        assert!(h.expand("!3").unwrap().is_err());
+        assert_eq!(h.expand("!x"), None);
+        assert_eq!(h.expand("x"), None);
+    }
+
+    #[test]
+    fn test_shared_file() {
+        let file = NamedTempFile::new().unwrap();
+
+        let mut a = History::load(file.path().to_owned()).unwrap();
+        let mut b = History::load(file.path().to_owned()).unwrap();
+
+        a.push("1").unwrap();
+        b.push("fn foo() {\n}").unwrap();
+        a.push("3").unwrap();
+
+        let h = History::load(file.path().to_owned()).unwrap();
+
+        assert_eq!(h.iter().map(|(_, e)| e).collect::<Vec<_>>(), ["1", "fn foo() {\n}", "3"]);
+    }
+
+    #[test]
+    fn test_concurrent_writes() {
+        const WRITERS: usize = 8;
+        const ENTRIES: usize = 100;
+
+        fn entry(w: usize, i: usize) -> String {
+            format!("fn f_{}_{}() {{\n    \"{}\"\n}}", w, i, "x".repeat(i * 10))
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        let barrier = Arc::new(Barrier::new(WRITERS));
+
+        // Each writer opens the file for each entry, as does a session
+        // in another process sharing it
+        let writers = (0..WRITERS).map(|w| {
+            let path = file.path().to_owned();
+            let barrier = barrier.clone();
+
+            thread::spawn(move || {
+                let mut h = History::load(path).unwrap();
+
+                barrier.wait();
+
+                for i in 0..ENTRIES {
+                    h.push(&entry(w, i)).unwrap();
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        for w in writers {
+            w.join().unwrap();
+        }
+
+        let h = History::load(file.path().to_owned()).unwrap();
+        let mut entries = h.iter().map(|(_, e)| e.to_owned()).collect::<Vec<_>>();
+        let mut expected = (0..WRITERS)
+            .flat_map(|w| (0..ENTRIES).map(move |i| entry(w, i)))
+            .collect::<Vec<_>>();
+
+        entries.sort();
+        expected.sort();
+
+        assert_eq!(entries, expected);
+    }
+}
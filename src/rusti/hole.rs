@@ -0,0 +1,145 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Typed holes in input.
+//!
+//! A hole, written `__?`, stands for code which is yet to be written.
+//! Input containing holes is not run; instead, each hole is replaced by a
+//! call to a function returning a value of any type, so that the type
+//! expected at the hole is inferred by the compiler.
+
+use std::fmt;
+
+/// Marker of a hole in input
+pub const MARKER: &'static str = "__?";
+
+/// Name of the function called in place of a hole
+pub const FUNCTION: &'static str = "_rusti_hole";
+
+/// A hole, with the type expected in its place
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hole {
+    /// Byte offset of the hole within the program
+    pub offset: usize,
+    /// Expected type, or `None` if it could not be inferred
+    pub expected: Option<String>,
+    /// Values and functions in scope whose type fits the hole
+    pub fits: Vec<String>,
+}
+
+impl fmt::Display for Hole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.expected {
+            Some(ref ty) => write!(f, "expected `{}`", ty)?,
+            None => write!(f, "expected type is unknown")?,
+        }
+
+        for fit in &self.fits {
+            write!(f, "\n  {}", fit)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the definition of the function called in place of holes.
+pub fn definition() -> String {
+    format!("#[allow(dead_code)] fn {}<T>() -> T {{ unreachable!() }}", FUNCTION)
+}
+
+/// Returns whether code contains a hole, outside of comments
+/// and string and character literals.
+pub fn contains(code: &str) -> bool {
+    replace(code) != code
+}
+
+/// Replaces each hole in code with a call to `FUNCTION`.
+/// Markers within comments and string and character literals are kept.
+pub fn replace(code: &str) -> String {
+    let mut res = String::with_capacity(code.len());
+    let mut rest = code;
+    // Whether the previous character may be part of an identifier
+    let mut in_ident = false;
+
+    while let Some(ch) = rest.chars().next() {
+        let len = if rest.starts_with(MARKER) && !in_ident {
+            res.push_str(FUNCTION);
+            res.push_str("()");
+            rest = &rest[MARKER.len()..];
+            continue;
+        } else if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            rest.find("*/").map_or(rest.len(), |n| n + 2)
+        } else if ch == '"' {
+            literal_len(rest, '"').unwrap_or(rest.len())
+        } else if ch == '\'' {
+            char_literal_len(rest).unwrap_or(1)
+        } else {
+            ch.len_utf8()
+        };
+
+        in_ident = len == ch.len_utf8() && (ch.is_alphanumeric() || ch == '_');
+        res.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+
+    res
+}
+
+/// Returns the length of a string or character literal at the start of text,
+/// including its quotes, or `None` if it is not terminated.
+fn literal_len(text: &str, quote: char) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1);
+
+    while let Some((i, ch)) = chars.next() {
+        if ch == '\\' {
+            chars.next();
+        } else if ch == quote {
+            return Some(i + 1);
+        }
+    }
+
+    None
+}
+
+/// Returns the length of a character literal at the start of text,
+/// or `None` if it is a lifetime.
+fn char_literal_len(text: &str) -> Option<usize> {
+    let len = literal_len(text, '\'')?;
+    let body = &text[1..len - 1];
+
+    // A lifetime may be followed by a quote further on, as in `&'a str, 'b'`
+    if body.starts_with('\\') || body.chars().count() == 1 {
+        Some(len)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{contains, replace};
+
+    #[test]
+    fn test_replace() {
+        assert_eq!(replace("let x: u32 = __?;"), "let x: u32 = _rusti_hole();");
+        assert_eq!(replace("f(__?, \"__?\") // __?"), "f(_rusti_hole(), \"__?\") // __?");
+        assert_eq!(replace("fn f<'a>(x: &'a str) -> char { '\\'' } __?"),
+            "fn f<'a>(x: &'a str) -> char { '\\'' } _rusti_hole()");
+        assert_eq!(replace("/* __? */ '?'"), "/* __? */ '?'");
+    }
+
+    #[test]
+    fn test_contains() {
+        assert!(contains("1 + __?"));
+        assert!(!contains("\"__?\""));
+        assert!(!contains("x?"));
+        assert!(!contains("x__?"));
+    }
+}
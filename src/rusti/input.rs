@@ -11,11 +11,13 @@
 use std::borrow::Cow;
 use std::borrow::Cow::*;
 use std::fs::File;
-use std::io::{self, stdin, BufRead, BufReader};
+use std::io::{self, stdin, stdout, BufRead, BufReader, Write};
 use std::mem::swap;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
 use std::sync::mpsc::channel;
 use std::thread::Builder;
 
@@ -28,14 +30,91 @@ use syntax::errors::Level::*;
 use syntax::parse::{classify, token};
 use syntax::parse::{filemap_to_parser, ParseSess};
 
-use linefeed::{ReadResult, Reader};
+use linefeed::{Command, ReadResult, Reader};
 use linefeed::terminal::DefaultTerminal;
 
 use completion::Completer;
+use finder::{self, FIND_COMMAND, FIND_SEQUENCE};
+use highlight::highlight_input;
+use history::History;
+use pager::terminal_size;
 use repl::{lookup_command, CmdArgs};
+use rustfmt;
 
 use self::InputResult::*;
 
+/// Enables bracketed paste, by which the terminal marks text which is pasted
+const ENABLE_PASTE: &'static str = "\x1b[?2004h";
+/// Disables bracketed paste
+const DISABLE_PASTE: &'static str = "\x1b[?2004l";
+/// Sequence preceding pasted text, while bracketed paste is enabled
+const PASTE_START: &'static str = "\x1b[200~";
+/// Sequence following pasted text
+const PASTE_END: &'static str = "\x1b[201~";
+
+/// Name of the line editor function inserting a typed character,
+/// then highlighting the line being edited
+const HIGHLIGHT_INSERT: &'static str = "highlight-insert";
+
+/// Name of the line editor function entering vi command mode
+const VI_COMMAND_MODE: &'static str = "vi-command-mode";
+/// Name of the line editor function entering vi insert mode
+const VI_INSERT_MODE: &'static str = "vi-insert-mode";
+
+/// Indicator preceding the prompt in vi insert mode
+const VI_INSERT_INDICATOR: &'static str = "(ins) ";
+/// Indicator preceding the prompt in vi command mode
+const VI_COMMAND_INDICATOR: &'static str = "(cmd) ";
+
+/// Keys of vi command mode and the line editor commands they are bound to
+const VI_COMMANDS: &'static [(&'static str, &'static str)] = &[
+    ("h", "backward-char"),
+    ("l", "forward-char"),
+    (" ", "forward-char"),
+    ("0", "beginning-of-line"),
+    ("^", "beginning-of-line"),
+    ("$", "end-of-line"),
+    ("w", "forward-word"),
+    ("b", "backward-word"),
+    ("x", "delete-char"),
+    ("X", "backward-delete-char"),
+    ("D", "kill-line"),
+    ("k", "previous-history"),
+    ("j", "next-history"),
+];
+
+/// Keys of vi command mode entering insert mode: at the cursor, after it,
+/// at the end of the line and at its beginning
+const VI_INSERT_KEYS: &'static [char] = &['i', 'a', 'A', 'I'];
+
+/// Key bindings of the line editor, as set by `.set keybindings`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KeyBindings {
+    /// Emacs-style bindings, which the line editor provides
+    Emacs,
+    /// Vi-style bindings, each line beginning in insert mode
+    Vi,
+}
+
+impl KeyBindings {
+    /// Parses the name of key bindings, as given to `.set keybindings`.
+    pub fn parse(s: &str) -> Option<KeyBindings> {
+        match s {
+            "emacs" => Some(KeyBindings::Emacs),
+            "vi" => Some(KeyBindings::Vi),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the key bindings.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            KeyBindings::Emacs => "emacs",
+            KeyBindings::Vi => "vi",
+        }
+    }
+}
+
 pub struct FileReader {
     reader: BufReader<File>,
     path: PathBuf,
@@ -96,16 +175,189 @@ impl FileReader {
 pub struct InputReader {
     buffer: String,
     reader: Option<Reader<DefaultTerminal>>,
+    /// Whether lines are highlighted as they are typed, and redrawn with
+    /// syntax highlighting once entered
+    highlight: bool,
+    /// Line being edited, as highlighted while it is typed
+    editing: Arc<Mutex<Editing>>,
+    /// Key bindings of the line editor
+    key_bindings: KeyBindings,
+    /// Text of the last complete input
+    last: String,
+    /// Lines among which the finder selects
+    candidates: Arc<Mutex<Vec<String>>>,
+    /// Whether pasted text is being read
+    pasting: Arc<AtomicBool>,
+    /// Whether a line of the text being pasted has been read, such that
+    /// the end of the paste also ends its last line
+    multiline: Arc<AtomicBool>,
+}
+
+/// Line being edited, shared with the line editor function highlighting it
+#[derive(Debug, Default)]
+struct Editing {
+    /// Whether the line is code, which is highlighted
+    highlight: bool,
+    /// Prompt preceding the line
+    prompt: String,
+    /// Input preceding the line, which may contain brackets closed in it
+    prev: String,
+    /// Whether vi command mode, rather than insert mode, is active,
+    /// if vi key bindings are used
+    vi_command: Option<bool>,
+}
+
+impl Editing {
+    /// Returns the prompt preceded by the indicator of the vi mode, if any.
+    fn mode_prompt(&self) -> String {
+        let indicator = match self.vi_command {
+            Some(true) => VI_COMMAND_INDICATOR,
+            Some(false) => VI_INSERT_INDICATOR,
+            None => "",
+        };
+
+        format!("{}{}", indicator, self.prompt)
+    }
 }
 
 impl InputReader {
     /// Constructs a new `InputReader` reading from `stdin`.
     pub fn new() -> InputReader {
+        let candidates = Arc::new(Mutex::new(Vec::new()));
+        let pasting = Arc::new(AtomicBool::new(false));
+        let multiline = Arc::new(AtomicBool::new(false));
+        let editing = Arc::new(Mutex::new(Editing::default()));
+
         let r = match Reader::new("rusti") {
             Ok(mut r) => {
-                r.set_completer(Rc::new(Completer));
+                r.set_completer(Rc::new(Completer{pasting: pasting.clone()}));
                 r.set_completion_append_character(None);
                 r.set_word_break_chars(" \t\n!\"#$%&'()*+,-./:;<=>?@[\\]^`");
+
+                let shared = candidates.clone();
+
+                r.define_function(FIND_COMMAND.to_owned(), Rc::new(
+                    move |r: &mut Reader<DefaultTerminal>, _count: i32, _ch: char| {
+                        let candidates = shared.lock().unwrap().clone();
+
+                        match finder::select(&candidates) {
+                            Some(selection) => r.insert_str(&selection),
+                            None => Ok(()),
+                        }
+                    }));
+                r.bind_sequence(FIND_SEQUENCE.to_owned(), Command::from_str(FIND_COMMAND));
+
+                let start = pasting.clone();
+
+                r.define_function("paste-start".to_owned(), Rc::new(
+                    move |_r: &mut Reader<DefaultTerminal>, _count: i32, _ch: char| {
+                        start.store(true, SeqCst);
+                        Ok(())
+                    }));
+
+                let (end, lines) = (pasting.clone(), multiline.clone());
+
+                r.define_function("paste-end".to_owned(), Rc::new(
+                    move |r: &mut Reader<DefaultTerminal>, _count: i32, _ch: char| {
+                        end.store(false, SeqCst);
+
+                        if lines.load(SeqCst) {
+                            r.accept_input()
+                        } else {
+                            Ok(())
+                        }
+                    }));
+
+                r.bind_sequence(PASTE_START.to_owned(), Command::from_str("paste-start"));
+                r.bind_sequence(PASTE_END.to_owned(), Command::from_str("paste-end"));
+
+                let (line, pasted) = (editing.clone(), pasting.clone());
+
+                r.define_function(HIGHLIGHT_INSERT.to_owned(), Rc::new(
+                    move |r: &mut Reader<DefaultTerminal>, count: i32, ch: char| {
+                        let editing = line.lock().unwrap();
+
+                        // Keys of vi command mode insert nothing
+                        if editing.vi_command == Some(true) {
+                            return Ok(());
+                        }
+
+                        if count > 0 {
+                            r.insert(count as usize, ch)?;
+                        }
+
+                        // Pasted text is highlighted once it has been read
+                        if editing.highlight && !pasted.load(SeqCst) {
+                            redraw_editing(&editing, r.buffer(), r.cursor());
+                        }
+
+                        Ok(())
+                    }));
+
+                // Typed characters are inserted by the function above,
+                // rather than by the line editor itself
+                for ch in (0x20u8..0x7f).map(char::from) {
+                    r.bind_sequence(ch.to_string(), Command::from_str(HIGHLIGHT_INSERT));
+                }
+
+                let command = editing.clone();
+
+                r.define_function(VI_COMMAND_MODE.to_owned(), Rc::new(
+                    move |r: &mut Reader<DefaultTerminal>, _count: i32, _ch: char| {
+                        let mut editing = command.lock().unwrap();
+
+                        if editing.vi_command != Some(false) {
+                            return Ok(());
+                        }
+
+                        editing.vi_command = Some(true);
+
+                        for &(key, name) in VI_COMMANDS {
+                            r.bind_sequence(key.to_owned(), Command::from_str(name));
+                        }
+                        for key in VI_INSERT_KEYS {
+                            r.bind_sequence(key.to_string(), Command::from_str(VI_INSERT_MODE));
+                        }
+
+                        // As in vi, the cursor moves back onto the last character inserted
+                        let cursor = r.cursor();
+                        let prev = r.buffer()[..cursor].chars().next_back()
+                            .map(|c| cursor - c.len_utf8());
+
+                        if let Some(pos) = prev {
+                            r.set_cursor(pos)?;
+                        }
+
+                        r.set_prompt(&editing.mode_prompt());
+                        redraw_editing(&editing, r.buffer(), r.cursor());
+                        Ok(())
+                    }));
+
+                let insert = editing.clone();
+
+                r.define_function(VI_INSERT_MODE.to_owned(), Rc::new(
+                    move |r: &mut Reader<DefaultTerminal>, _count: i32, ch: char| {
+                        let mut editing = insert.lock().unwrap();
+
+                        let cursor = r.cursor();
+                        let pos = match ch {
+                            'a' => r.buffer()[cursor..].chars().next()
+                                .map_or(cursor, |c| cursor + c.len_utf8()),
+                            'A' => r.buffer().len(),
+                            'I' => 0,
+                            _ => cursor,
+                        };
+
+                        r.set_cursor(pos)?;
+
+                        editing.vi_command = Some(false);
+                        bind_vi_insert(r);
+
+                        r.set_prompt(&editing.mode_prompt());
+                        redraw_editing(&editing, r.buffer(), r.cursor());
+                        Ok(())
+                    }));
+
                 Some(r)
             }
             Err(_) => None,
@@ -114,14 +366,89 @@ impl InputReader {
         InputReader {
             buffer: String::new(),
             reader: r,
+            highlight: false,
+            editing: editing,
+            key_bindings: KeyBindings::Emacs,
+            last: String::new(),
+            candidates: candidates,
+            pasting: pasting,
+            multiline: multiline,
+        }
+    }
+
+    /// Sets the lines among which the finder, bound to Ctrl-T, selects.
+    pub fn set_candidates(&mut self, candidates: Vec<String>) {
+        *self.candidates.lock().unwrap() = candidates;
+    }
+
+    /// Binds a key sequence to the named line editor command,
+    /// such as `backward-word`.
+    pub fn bind_sequence(&mut self, seq: &str, command: &str) {
+        if let Some(ref mut r) = self.reader {
+            r.bind_sequence(seq.to_owned(), Command::from_str(command));
+        }
+    }
+
+    /// Adds entries from a persisted history to the line editor's history.
+    /// Entries spanning multiple lines are not added, as the line editor
+    /// cannot edit them.
+    pub fn load_history(&mut self, history: &History) {
+        for (_, entry) in history.iter() {
+            if !entry.contains('\n') {
+                self.add_history(entry);
+            }
+        }
+    }
+
+    /// Returns the text of the last complete input read,
+    /// with any history reference expanded.
+    pub fn last_input(&self) -> &str {
+        &self.last
+    }
+
+    /// Sets whether lines are highlighted as they are typed, along with the
+    /// bracket matching one at the cursor, and redrawn with syntax
+    /// highlighting once entered.
+    /// Highlighting only takes effect when reading from a TTY.
+    pub fn set_highlight(&mut self, highlight: bool) {
+        self.highlight = highlight;
+    }
+
+    /// Sets the key bindings of the line editor.
+    /// With vi key bindings, Escape enters command mode and the prompt is
+    /// preceded by the mode in which the line is being edited.
+    pub fn set_key_bindings(&mut self, key_bindings: KeyBindings) {
+        if key_bindings == self.key_bindings {
+            return;
+        }
+
+        self.key_bindings = key_bindings;
+
+        if let Some(ref mut r) = self.reader {
+            match key_bindings {
+                KeyBindings::Vi => {
+                    r.bind_sequence("\x1b".to_owned(), Command::from_str(VI_COMMAND_MODE));
+                }
+                KeyBindings::Emacs => {
+                    r.unbind_sequence("\x1b");
+                    bind_vi_insert(r);
+                }
+            }
         }
     }
 
     /// Reads a single command, item, or statement from `stdin`.
     /// Returns `More` if further input is required for a complete result.
     /// In this case, the input received so far is buffered internally.
-    pub fn read_input(&mut self, prompt: &str) -> InputResult {
-        let line = match self.read_line(prompt) {
+    ///
+    /// A line such as `!N` or `!!`, at the beginning of input, is replaced
+    /// by the referenced entry of `history`; see `History::expand`.
+    pub fn read_input(&mut self, prompt: &str, history: &History) -> InputResult {
+        let indented = format!("{}{}", prompt, " ".repeat(continuation_indent(&self.buffer)));
+
+        let prev = self.buffer.clone();
+
+        let line = match self.read_code_line(&indented, &prev) {
             ReadResult::Eof => return Eof,
             ReadResult::Input(s) => s,
             ReadResult::Signal(_) => {
@@ -130,6 +457,31 @@ impl InputReader {
             }
         };
 
+        // A line ended within pasted text begins a paste of several lines
+        if self.pasting.load(SeqCst) {
+            return self.read_paste(line);
+        }
+
+        self.redraw_highlighted(&prev, &line);
+
+        if self.buffer.is_empty() {
+            match history.expand(&line) {
+                Some(Ok(entry)) => {
+                    println!("{}", entry);
+                    self.add_history(entry);
+                    self.last = entry.to_owned();
+
+                    return if is_command(entry) {
+                        parse_command(entry, false)
+                    } else {
+                        parse_program(&format!("{}\n", entry), false, None)
+                    };
+                }
+                Some(Err(e)) => return InputError(Some(Owned(e))),
+                None => (),
+            }
+        }
+
         self.buffer.push_str(&line);
 
         if self.buffer.is_empty() {
@@ -147,7 +499,59 @@ impl InputReader {
 
         match res {
             More => (),
-            _ => self.buffer.clear(),
+            _ => {
+                self.last = self.buffer.trim_right().to_owned();
+                self.buffer.clear();
+            }
+        };
+
+        res
+    }
+
+    /// Reads the remaining lines of text pasted at once, of which `line` is
+    /// the first, parsing all of it as a single input.
+    fn read_paste(&mut self, line: String) -> InputResult {
+        let mut text = line + "\n";
+
+        self.multiline.store(true, SeqCst);
+
+        loop {
+            let line = match self.read_line("") {
+                ReadResult::Input(s) => s,
+                _ => {
+                    self.pasting.store(false, SeqCst);
+                    self.multiline.store(false, SeqCst);
+                    self.buffer.clear();
+                    return Empty;
+                }
+            };
+
+            let done = !self.pasting.load(SeqCst);
+
+            if !done || !line.is_empty() {
+                text.push_str(&line);
+                text.push('\n');
+            }
+
+            if done {
+                break;
+            }
+        }
+
+        self.multiline.store(false, SeqCst);
+
+        println!("pasted {} lines", text.lines().count());
+
+        self.buffer.push_str(&text);
+
+        let res = parse_program(&self.buffer, true, None);
+
+        match res {
+            More => (),
+            _ => {
+                self.last = self.buffer.trim_right().to_owned();
+                self.buffer.clear();
+            }
         };
 
         res
@@ -160,18 +564,23 @@ impl InputReader {
 
     /// Reads a block of input until receiving a line consisting only of `.`,
     /// which will return input, or `.q`, which will cancel and return `Empty`.
+    /// A line consisting of `.fmt` formats the lines read so far.
+    /// `on_line` is called with the text of the block after each line of it
+    /// is read.
     ///
     /// # Panics
     ///
     /// If the internal buffer contains any data; i.e. if the last
     /// result from a call to `read_input` returned `More`.
-    pub fn read_block_input(&mut self, prompt: &str) -> InputResult {
+    pub fn read_block_input(&mut self, prompt: &str, on_line: &mut FnMut(&str)) -> InputResult {
         assert!(self.buffer.is_empty());
 
         let mut buf = String::new();
 
         loop {
-            let line = match self.read_line(prompt) {
+            let indented = format!("{}{}", prompt, " ".repeat(continuation_indent(&buf)));
+
+            let line = match self.read_code_line(&indented, &buf) {
                 ReadResult::Eof => return Eof,
                 ReadResult::Input(s) => s,
                 ReadResult::Signal(_) => {
@@ -180,6 +589,8 @@ impl InputReader {
                 }
             };
 
+            self.redraw_highlighted(&buf, &line);
+
             if !line.is_empty() {
                 self.add_history(&line);
             }
@@ -187,26 +598,124 @@ impl InputReader {
             if line == ".q" || line == ":q" {
                 self.buffer.clear();
                 return Empty;
+            } else if line == ".fmt" || line == ":fmt" {
+                match rustfmt::format(&buf) {
+                    Ok(code) => {
+                        println!("{}", code);
+                        buf = code;
+                        buf.push('\n');
+                    }
+                    Err(e) => println!("failed to format input: {}", e),
+                }
+                continue;
             } else if line == "." {
+                self.last = buf.trim_right().to_owned();
                 return parse_program(&buf, true, None);
             }
 
             buf.push_str(&line);
             buf.push('\n');
+
+            on_line(&buf);
+        }
+    }
+
+    /// Reads lines of text until a line consisting of `terminator`.
+    /// Returns `None` if input ends or is interrupted first.
+    pub fn read_heredoc(&mut self, prompt: &str, terminator: &str) -> Option<String> {
+        let mut buf = String::new();
+
+        loop {
+            let line = match self.read_line(prompt) {
+                ReadResult::Input(s) => s,
+                _ => return None,
+            };
+
+            if line.trim_right() == terminator {
+                return Some(buf);
+            }
+
+            buf.push_str(line.trim_right_matches('\n'));
+            buf.push('\n');
         }
     }
 
+    /// Reads a line of code, following `prev`, which is highlighted as it
+    /// is typed if highlighting is enabled.
+    fn read_code_line(&mut self, prompt: &str, prev: &str) -> ReadResult {
+        {
+            let mut editing = self.editing.lock().unwrap();
+            editing.highlight = self.highlight;
+            editing.prev = prev.to_owned();
+        }
+
+        let res = self.read_line(prompt);
+
+        self.editing.lock().unwrap().highlight = false;
+        res
+    }
+
     fn read_line(&mut self, prompt: &str) -> ReadResult {
         match self.reader {
             Some(ref mut r) => {
-                r.set_prompt(prompt);
+                let prompt = {
+                    let mut editing = self.editing.lock().unwrap();
+                    editing.prompt = prompt.to_owned();
+
+                    // Each line begins in vi insert mode
+                    editing.vi_command = match self.key_bindings {
+                        KeyBindings::Vi => {
+                            bind_vi_insert(r);
+                            Some(false)
+                        }
+                        KeyBindings::Emacs => None,
+                    };
+
+                    editing.mode_prompt()
+                };
 
-                r.read_line().ok().unwrap_or(ReadResult::Eof)
+                r.set_prompt(&prompt);
+
+                // Pasted text is only marked while the line editor reads it,
+                // not when it is read by programs which are run
+                print!("{}", ENABLE_PASTE);
+                let _ = stdout().flush();
+
+                let res = r.read_line().ok().unwrap_or(ReadResult::Eof);
+
+                print!("{}", DISABLE_PASTE);
+                let _ = stdout().flush();
+
+                res
             }
             None => self.read_stdin(),
         }
     }
 
+    /// Redraws a line just entered with syntax highlighting, at the prompt
+    /// at which it was edited. `prev` is the input preceding the line.
+    ///
+    /// The line is highlighted as characters are typed, but not as it is
+    /// otherwise edited, so it is redrawn in full once it has been entered.
+    fn redraw_highlighted(&self, prev: &str, line: &str) {
+        if !self.highlight || !self.is_tty() || line.is_empty() {
+            return;
+        }
+
+        let prompt = self.editing.lock().unwrap().mode_prompt();
+
+        // A line which wrapped would occupy more than one row
+        let width = prompt.chars().count() + line.chars().count();
+
+        match terminal_size() {
+            Some((_, cols)) if width < cols => (),
+            _ => return,
+        }
+
+        print!("\x1b[1A\r{}{}\x1b[K\n", prompt, highlight_input(prev, line, None));
+        let _ = stdout().flush();
+    }
+
     fn read_stdin(&self) -> ReadResult {
         let mut s = String::new();
 
@@ -223,6 +732,45 @@ impl InputReader {
     }
 }
 
+/// Binds the keys of vi command mode to insert their characters,
+/// as they do in insert mode.
+fn bind_vi_insert(r: &mut Reader<DefaultTerminal>) {
+    let keys = VI_COMMANDS.iter().map(|&(key, _)| key.to_owned())
+        .chain(VI_INSERT_KEYS.iter().map(|key| key.to_string()));
+
+    for key in keys {
+        r.bind_sequence(key, Command::from_str(HIGHLIGHT_INSERT));
+    }
+}
+
+/// Redraws the line being edited, highlighted if it is code, along with the
+/// bracket matching one at the cursor, leaving the cursor where it was.
+fn redraw_editing(editing: &Editing, line: &str, cursor: usize) {
+    let prompt = editing.mode_prompt();
+
+    // A line which wrapped would occupy more than one row
+    let width = prompt.chars().count() + line.chars().count();
+
+    match terminal_size() {
+        Some((_, cols)) if width < cols => (),
+        _ => return,
+    }
+
+    if editing.highlight {
+        print!("\r{}{}\x1b[K", prompt, highlight_input(&editing.prev, line, Some(cursor)));
+    } else {
+        print!("\r{}{}\x1b[K", prompt, line);
+    }
+
+    let after = line[cursor..].chars().count();
+
+    if after > 0 {
+        print!("\x1b[{}D", after);
+    }
+
+    let _ = stdout().flush();
+}
+
 /// Possible results from reading input from `InputReader`
 #[derive(Clone, Debug)]
 pub enum InputResult {
@@ -290,11 +838,18 @@ pub fn parse_command(line: &str, filter: bool) -> InputResult {
     }
 
     let line = &line[1..];
-    let mut words = line.trim_right().splitn(2, ' ');
 
-    let name = match words.next() {
-        Some(name) if !name.is_empty() => name,
-        _ => return InputError(Some(Borrowed("expected command name"))),
+    // A shell command need not be separated from `!`
+    let (name, args) = if line.starts_with('!') {
+        let args = line[1..].trim();
+        ("!", if args.is_empty() { None } else { Some(args) })
+    } else {
+        let mut words = line.trim_right().splitn(2, ' ');
+
+        match words.next() {
+            Some(name) if !name.is_empty() => (name, words.next()),
+            _ => return InputError(Some(Borrowed("expected command name"))),
+        }
     };
 
     let cmd = match lookup_command(name) {
@@ -302,8 +857,6 @@ pub fn parse_command(line: &str, filter: bool) -> InputResult {
         None => return InputError(Some(Owned(format!("unrecognized command: {}", name)))),
     };
 
-    let args = words.next();
-
     match cmd.accepts {
         CmdArgs::Nothing if args.is_some() => InputError(Some(Owned(format!(
             "command `{}` takes no arguments",
@@ -515,6 +1068,138 @@ impl Emitter for ErrorEmitter {
     }
 }
 
+/// Lexical context carried from one line of code to the next
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Lex {
+    Code,
+    /// Within a string literal
+    Str,
+    /// Within a raw string literal, closed by `"` and this many `#`
+    RawStr(usize),
+    /// Within block comments, nested this deep
+    Comment(usize),
+}
+
+/// Returns the number of columns by which to indent the next line of
+/// incomplete input, as shown by the continuation prompt.
+///
+/// Each delimiter left open indents four columns more than the line which
+/// opened it. The body of a match arm, and a line continuing a statement,
+/// are indented four columns further, and a chain of method calls stays
+/// aligned with its previous line.
+pub fn continuation_indent(code: &str) -> usize {
+    // Indentation of the line which opened each delimiter left open
+    let mut open = Vec::new();
+    // Indentation and text of the last line of code, and the delimiters open before it
+    let mut last = None;
+    let mut lex = Lex::Code;
+    let mut next = 0;
+
+    for line in code.lines() {
+        let indent = next + line.len() - line.trim_left().len();
+        let depth = open.len();
+        let starts_in_code = lex == Lex::Code;
+        let bytes = line.as_bytes();
+        let mut end = line.len();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let b = bytes[i];
+            i += 1;
+
+            match lex {
+                Lex::Str => match b {
+                    b'\\' => i += 1,
+                    b'"' => lex = Lex::Code,
+                    _ => (),
+                },
+                Lex::RawStr(n) => {
+                    if b == b'"' && bytes[i..].iter().take(n).filter(|&&c| c == b'#').count() == n {
+                        i += n;
+                        lex = Lex::Code;
+                    }
+                }
+                Lex::Comment(n) => {
+                    if b == b'*' && bytes.get(i) == Some(&b'/') {
+                        i += 1;
+                        lex = if n == 1 { Lex::Code } else { Lex::Comment(n - 1) };
+                    } else if b == b'/' && bytes.get(i) == Some(&b'*') {
+                        i += 1;
+                        lex = Lex::Comment(n + 1);
+                    }
+                }
+                Lex::Code => match b {
+                    b'/' if bytes.get(i) == Some(&b'/') => {
+                        end = i - 1;
+                        break;
+                    }
+                    b'/' if bytes.get(i) == Some(&b'*') => {
+                        i += 1;
+                        lex = Lex::Comment(1);
+                    }
+                    b'"' => lex = Lex::Str,
+                    b'r' if i == 1 || !is_ident_byte(bytes[i - 2]) => {
+                        let hashes = bytes[i..].iter().take_while(|&&c| c == b'#').count();
+
+                        if bytes.get(i + hashes) == Some(&b'"') {
+                            i += hashes + 1;
+                            lex = Lex::RawStr(hashes);
+                        }
+                    }
+                    b'\'' => {
+                        // A character literal, rather than a lifetime
+                        if bytes.get(i) == Some(&b'\\') {
+                            i += 2;
+                            i += bytes[i.min(bytes.len())..].iter()
+                                .position(|&c| c == b'\'').map_or(0, |n| n + 1);
+                        } else if let Some(c) = line[i..].chars().next() {
+                            if bytes.get(i + c.len_utf8()) == Some(&b'\'') {
+                                i += c.len_utf8() + 1;
+                            }
+                        }
+                    }
+                    b'(' | b'[' | b'{' => open.push(indent),
+                    b')' | b']' | b'}' => {
+                        open.pop();
+                    }
+                    _ => (),
+                },
+            }
+        }
+
+        let text = line[..end].trim();
+
+        if starts_in_code && !text.is_empty() && !text.starts_with("/*") {
+            last = Some((indent, text, depth));
+        }
+
+        next = open.last().map_or(0, |&i| i + 4);
+
+        if let Some((indent, text, depth)) = last {
+            // Only a line which leaves as many delimiters open as before it
+            // can continue an arm or statement
+            if open.len() == depth && lex == Lex::Code {
+                if text.ends_with("=>") {
+                    next = indent + 4;
+                } else if text.starts_with('.') {
+                    if !text.ends_with(';') && !text.ends_with(',') {
+                        next = indent;
+                    }
+                } else if !text.starts_with('#') &&
+                        !text.ends_with(|c: char| c == ';' || c == ',' || c == '{' || c == '}') {
+                    next += 4;
+                }
+            }
+        }
+    }
+
+    next
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b == b'_' || (b as char).is_alphanumeric()
+}
+
 fn is_non_fatal(msg: &str) -> bool {
     msg.contains("un-closed delimiter") || msg.contains("expected item after attributes")
         || msg.contains("unterminated block comment")
@@ -526,7 +1211,7 @@ fn is_non_fatal(msg: &str) -> bool {
 
 #[cfg(test)]
 mod test {
-    use super::{parse_program, InputResult};
+    use super::{continuation_indent, parse_program, InputResult};
 
     fn parse(s: &str) -> InputResult {
         parse_program(s, true, None)
@@ -542,4 +1227,20 @@ mod test {
         assert_matches!(parse("let a = foo("), InputResult::More);
         assert_matches!(parse("let a = \""), InputResult::More);
     }
+
+    #[test]
+    fn test_continuation_indent() {
+        assert_eq!(continuation_indent("fn foo() {"), 4);
+        assert_eq!(continuation_indent("fn foo() {\nlet v = vec![(1, {"), 8);
+        assert_eq!(continuation_indent("fn foo() {\nmatch x {\nSome(y) =>"), 12);
+        assert_eq!(continuation_indent("fn foo() {\nmatch x {\nSome(y) => y,"), 8);
+        assert_eq!(continuation_indent("fn foo() {\nmatch x {\n}"), 4);
+        assert_eq!(continuation_indent("fn foo() {\nlet v = xs.iter()"), 8);
+        assert_eq!(continuation_indent("fn foo() {\nlet v = xs.iter()\n.map(f)"), 8);
+        assert_eq!(continuation_indent("fn foo() {\nlet v = xs.iter()\n.map(f)\n.count();"), 4);
+        assert_eq!(continuation_indent("fn foo() {\n#[allow(unused)]"), 4);
+        assert_eq!(continuation_indent("fn foo() { // {\nlet s = \"{\"; let c = '{';"), 4);
+        assert_eq!(continuation_indent("fn foo<'a>(s: &'a str) {\nlet r = r#\"(\"#;"), 4);
+        assert_eq!(continuation_indent("fn foo() {\n/* { */"), 4);
+    }
 }
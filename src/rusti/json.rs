@@ -0,0 +1,366 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Machine-readable evaluation protocol using JSON.
+//!
+//! Each line of input is a request; either a JSON object or a JSON string
+//! containing code to evaluate. A request object has an `"op"` member
+//! naming the operation, which defaults to `"eval"`. Optional `"id"` and
+//! `"block"` members of a request object are copied into the response;
+//! `"block"` names the source block of a document which the code came from,
+//! as sent by editors.
+//!
+//! Operations are:
+//!
+//! * `"eval"` evaluates `"code"`. The response has members `"compiled"`,
+//!   `"executed"`, `"cancelled"`, `"value"`, `"display"`, `"stdout"`,
+//!   `"stderr"`, `"diagnostics"`, and `"timing"`. `"value"` is the formatted
+//!   value of the code, or `null` if it has none. `"display"` is an array of
+//!   display data written by the code, each an object with members `"mime"`
+//!   and `"data"`; see the `display` module. `"cancelled"` is `true` if an
+//!   `"interrupt"` request cancelled compilation or, in server mode, a later
+//!   `"eval"` request superseded this one before it was compiled; see `serve`.
+//! * `"complete"` completes `"code"` at byte offset `"pos"`, which defaults to
+//!   the end of the code. The response has a `"completions"` array.
+//! * `"interrupt"` cancels compilation of the code of an `"eval"` request
+//!   being handled, whose response then has `"cancelled"` set to `true`.
+//!   Code which has begun running runs to completion. The response member
+//!   `"interrupted"` is `true` if compilation was cancelled.
+//! * `"reset"` forgets all items defined in the session.
+//! * `"command"` runs the rusti command `"command"`, such as `".set opt-level 2"`.
+//!   The response has members `"stdout"` and `"stderr"`, the output of the command.
+//!
+//! Requests are read while others are handled, so that `"interrupt"` takes
+//! effect at once. Each response is written as a JSON object on a single line.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, stdin, stdout, BufRead, BufReader, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use serialize::json::Json;
+
+use capture::capture;
+use completion::complete;
+use display::DisplayData;
+use repl::{EvalResult, Repl};
+
+/// Reads evaluation requests from `stdin` until end of file, writing a
+/// response to `stdout` for each one.
+/// Returns desired process exit status.
+pub fn run(repl: &mut Repl) -> i32 {
+    repl.exec_options_mut().json_diagnostics = true;
+
+    match serve(repl, stdin(), stdout(), false) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Reads requests until end of file, or a `.quit` command, writing a
+/// response for each one.
+///
+/// Requests are read while others are handled. If `supersede` is `true`,
+/// an `"eval"` request read while the code of another is compiled
+/// supersedes it: compilation is cancelled and the response has member
+/// `"cancelled"` set to `true`. Likewise, an `"eval"` request waiting to be
+/// handled behind a later `"eval"` request is cancelled rather than
+/// compiled. Other requests, such as commands loading files, are not
+/// cancelled.
+pub fn serve<R, W>(repl: &mut Repl, reader: R, mut writer: W, supersede: bool) -> io::Result<()>
+        where R: Read + Send + 'static, W: Write {
+    let cancel = repl.cancel_flag();
+    // Set while an `"eval"` request is handled, which may then be cancelled
+    let evaluating = Arc::new(AtomicBool::new(false));
+    let requests = read_requests(reader, cancel.clone(), evaluating.clone(), supersede);
+    let mut pending = VecDeque::new();
+
+    loop {
+        let line = match pending.pop_front() {
+            Some(line) => line,
+            None => match requests.recv() {
+                Ok(line) => line,
+                Err(_) => return Ok(()),
+            },
+        };
+        let Request{line, interrupted} = line?;
+
+        // Only requests read hereafter may cancel this one
+        cancel.store(false, SeqCst);
+
+        let eval = is_eval(&line);
+        evaluating.store(eval, SeqCst);
+
+        while let Ok(next) = requests.try_recv() {
+            pending.push_back(next);
+        }
+
+        let superseded = supersede && eval &&
+            pending.iter().any(|r| r.as_ref().map_or(false, |r| is_eval(&r.line)));
+
+        if superseded {
+            cancel.store(true, SeqCst);
+        }
+
+        let response = handle_request(repl, &line, interrupted);
+        evaluating.store(false, SeqCst);
+
+        writeln!(writer, "{}", response)?;
+        writer.flush()?;
+
+        if repl.is_quitting() {
+            return Ok(());
+        }
+    }
+}
+
+/// A request line read by `read_requests`
+struct Request {
+    line: String,
+    /// Whether the request is an `"interrupt"` which cancelled compilation
+    interrupted: bool,
+}
+
+/// Reads requests on a thread of its own. While `evaluating` is set,
+/// `cancel` is set for each `"interrupt"` request read and, if `supersede`
+/// is `true`, each `"eval"` request.
+fn read_requests<R>(reader: R, cancel: Arc<AtomicBool>, evaluating: Arc<AtomicBool>,
+        supersede: bool) -> Receiver<io::Result<Request>>
+        where R: Read + Send + 'static {
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    let _ = tx.send(Err(io::Error::new(e.kind(),
+                        format!("failed to read request: {}", e))));
+                    return;
+                }
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let interrupt = is_op(&line, "interrupt");
+            let cancels = evaluating.load(SeqCst) && (interrupt || (supersede && is_eval(&line)));
+
+            if cancels {
+                cancel.store(true, SeqCst);
+            }
+
+            let req = Request{
+                line: line,
+                interrupted: interrupt && cancels,
+            };
+
+            if tx.send(Ok(req)).is_err() {
+                return;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Returns whether a request line is an `"eval"` request.
+fn is_eval(line: &str) -> bool {
+    match Json::from_str(line) {
+        Ok(Json::String(_)) => true,
+        _ => is_op(line, "eval"),
+    }
+}
+
+/// Returns whether a request line is a request object for the named
+/// operation; `"eval"` if it names none.
+fn is_op(line: &str, name: &str) -> bool {
+    match Json::from_str(line) {
+        Ok(Json::Object(ref obj)) => match obj.get("op") {
+            None => name == "eval",
+            Some(op) => op.as_string() == Some(name),
+        },
+        _ => false,
+    }
+}
+
+/// Handles a single request line, returning the response. `interrupted` is
+/// the result of an `"interrupt"` request, which is read by `serve` while
+/// the request it interrupts is handled.
+pub fn handle_request(repl: &mut Repl, line: &str, interrupted: bool) -> Json {
+    let mut req = match Json::from_str(line) {
+        Ok(Json::Object(obj)) => obj,
+        Ok(Json::String(code)) => return eval_response(repl.evaluate(&code)),
+        Ok(_) => return error_response("invalid request: expected object or string".to_owned()),
+        Err(e) => return error_response(format!("invalid request: {}", e)),
+    };
+
+    let id = req.remove("id");
+    let block = req.remove("block");
+
+    let op = match req.remove("op") {
+        Some(Json::String(op)) => op,
+        None => "eval".to_owned(),
+        Some(_) => "".to_owned(),
+    };
+
+    let mut response = match &op[..] {
+        "eval" => match req.remove("code") {
+            Some(Json::String(code)) => eval_response(repl.evaluate(&code)),
+            _ => error_response("invalid request: expected string member `code`".to_owned()),
+        },
+        "complete" => match req.remove("code") {
+            Some(Json::String(code)) => {
+                match req.get("pos").map(|p| p.as_u64().map(|p| p as usize)) {
+                    None => complete_response(&code, code.len()),
+                    Some(Some(pos)) if code.is_char_boundary(pos) => complete_response(&code, pos),
+                    Some(_) => error_response("invalid request: invalid member `pos`".to_owned()),
+                }
+            }
+            _ => error_response("invalid request: expected string member `code`".to_owned()),
+        },
+        "interrupt" => {
+            let mut obj = BTreeMap::new();
+            obj.insert("interrupted".to_owned(), Json::Boolean(interrupted));
+            Json::Object(obj)
+        }
+        "reset" => {
+            repl.reset();
+
+            let mut obj = BTreeMap::new();
+            obj.insert("reset".to_owned(), Json::Boolean(true));
+            Json::Object(obj)
+        }
+        "command" => match req.remove("command") {
+            Some(Json::String(cmd)) => command_response(repl, &cmd),
+            _ => error_response("invalid request: expected string member `command`".to_owned()),
+        },
+        _ => error_response(format!("invalid request: unknown operation `{}`", op)),
+    };
+
+    if let Json::Object(ref mut obj) = response {
+        if let Some(id) = id {
+            obj.insert("id".to_owned(), id);
+        }
+        if let Some(block) = block {
+            obj.insert("block".to_owned(), block);
+        }
+    }
+
+    response
+}
+
+/// Returns the response to an `"eval"` request with the given result.
+pub fn eval_response(result: EvalResult) -> Json {
+    let mut obj = BTreeMap::new();
+
+    // Diagnostics from the compiler are emitted as JSON objects, one per line.
+    // Any other text written to `stderr` is passed along as it is.
+    let mut diagnostics = Vec::new();
+    let mut stderr = String::new();
+
+    for line in result.stderr.lines() {
+        match Json::from_str(line) {
+            Ok(diag @ Json::Object(_)) => diagnostics.push(diag),
+            _ => {
+                stderr.push_str(line);
+                stderr.push('\n');
+            }
+        }
+    }
+
+    let mut timing = BTreeMap::new();
+
+    timing.insert("compile_ms".to_owned(), Json::F64(millis(result.compile_time)));
+    timing.insert("run_ms".to_owned(), Json::F64(millis(result.run_time)));
+
+    obj.insert("compiled".to_owned(), Json::Boolean(result.compiled));
+    obj.insert("executed".to_owned(), Json::Boolean(result.executed));
+    obj.insert("cancelled".to_owned(), Json::Boolean(result.cancelled));
+    obj.insert("value".to_owned(), result.value.map_or(Json::Null, Json::String));
+    obj.insert("display".to_owned(), Json::Array(result.display.into_iter()
+        .map(display_data).collect()));
+    obj.insert("stdout".to_owned(), Json::String(result.stdout));
+    obj.insert("stderr".to_owned(), Json::String(stderr));
+    obj.insert("diagnostics".to_owned(), Json::Array(diagnostics));
+    obj.insert("timing".to_owned(), Json::Object(timing));
+
+    Json::Object(obj)
+}
+
+fn display_data(data: DisplayData) -> Json {
+    let mut obj = BTreeMap::new();
+
+    obj.insert("mime".to_owned(), Json::String(data.mime));
+    obj.insert("data".to_owned(), Json::String(data.data));
+
+    Json::Object(obj)
+}
+
+/// Returns the response to a `"complete"` request.
+pub fn complete_response(code: &str, pos: usize) -> Json {
+    let completions = complete(code, pos)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|c| Json::String(c.completion))
+        .collect();
+
+    let mut obj = BTreeMap::new();
+
+    obj.insert("completions".to_owned(), Json::Array(completions));
+
+    Json::Object(obj)
+}
+
+fn command_response(repl: &mut Repl, cmd: &str) -> Json {
+    let output = match capture(|| repl.run_command(cmd)) {
+        Ok(((), output)) => output,
+        Err(e) => return error_response(format!("failed to capture output: {}", e)),
+    };
+
+    let mut obj = BTreeMap::new();
+
+    obj.insert("stdout".to_owned(), Json::String(output.stdout));
+    obj.insert("stderr".to_owned(), Json::String(output.stderr));
+
+    Json::Object(obj)
+}
+
+fn error_response(msg: String) -> Json {
+    let mut obj = BTreeMap::new();
+
+    obj.insert("error".to_owned(), Json::String(msg));
+
+    Json::Object(obj)
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1e3 + d.subsec_nanos() as f64 / 1e6
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_eval, is_op};
+
+    #[test]
+    fn test_is_op() {
+        assert!(is_eval(r#""1 + 1""#));
+        assert!(is_eval(r#"{"code": "1 + 1"}"#));
+        assert!(!is_eval(r#"{"op": "interrupt"}"#));
+        assert!(is_op(r#"{"id": 2, "op": "interrupt"}"#, "interrupt"));
+        assert!(!is_op(r#""interrupt""#, "interrupt"));
+    }
+}
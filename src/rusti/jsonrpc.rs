@@ -0,0 +1,240 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! JSON-RPC 2.0 channel for editor plugins, as served by `--jsonrpc`.
+//!
+//! Each line read from `stdin` is a request object, with parameters given
+//! by name, and each response is written to `stdout` as a JSON object on
+//! a single line. Methods are:
+//!
+//! * `eval` evaluates `code`. The result is as the response to an `"eval"`
+//!   request of the `json` module. An optional `block` parameter, naming the
+//!   source block which the code came from, is copied into the result.
+//! * `complete` completes `code` at byte offset `pos`, which defaults to the
+//!   end of the code. The result has a `completions` array.
+//! * `type_at` infers the type of the innermost expression or pattern of
+//!   `code` at byte offset `pos`, as if the code were evaluated. The result
+//!   has member `type`, which is `null` if the type cannot be inferred.
+//! * `interrupt` cancels compilation of the code of an `eval` request being
+//!   handled, whose result then has `cancelled` set to `true`. Code which has
+//!   begun running runs to completion. The result is `null`.
+//!
+//! Requests are read while others are handled, so that `interrupt` takes
+//! effect at once. Requests without an `id` are notifications, to which
+//! no response is written.
+
+use std::collections::BTreeMap;
+use std::io::{stdin, stdout, BufRead, BufReader, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use serialize::json::Json;
+
+use json::{complete_response, eval_response};
+use repl::Repl;
+
+/// Invalid JSON was received
+const PARSE_ERROR: i64 = -32700;
+/// The JSON received is not a valid request object
+const INVALID_REQUEST: i64 = -32600;
+/// The method does not exist
+const METHOD_NOT_FOUND: i64 = -32601;
+/// The method parameters are invalid
+const INVALID_PARAMS: i64 = -32602;
+
+/// Reads requests from `stdin` until end of file, writing a response
+/// to `stdout` for each one which is not a notification.
+/// Returns desired process exit status.
+pub fn run(repl: &mut Repl) -> i32 {
+    repl.exec_options_mut().json_diagnostics = true;
+
+    let cancel = repl.cancel_flag();
+    let requests = read_requests(stdin(), cancel.clone());
+    let stdout = stdout();
+
+    for line in requests {
+        // Only interrupts read hereafter may cancel this request
+        cancel.store(false, SeqCst);
+
+        if let Some(response) = handle_request(repl, &line) {
+            let mut out = stdout.lock();
+
+            if writeln!(out, "{}", response).and_then(|_| out.flush()).is_err() {
+                return 1;
+            }
+        }
+    }
+
+    0
+}
+
+/// Reads request lines on a thread of its own, setting `cancel`
+/// for each `interrupt` request read.
+fn read_requests<R>(reader: R, cancel: Arc<AtomicBool>) -> Receiver<String>
+        where R: Read + Send + 'static {
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("failed to read request: {}", e);
+                    return;
+                }
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if is_interrupt(&line) {
+                cancel.store(true, SeqCst);
+            }
+
+            if tx.send(line).is_err() {
+                return;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Returns whether a request line is an `interrupt` request.
+fn is_interrupt(line: &str) -> bool {
+    match Json::from_str(line) {
+        Ok(req) => req.find("method").and_then(|m| m.as_string()) == Some("interrupt"),
+        Err(_) => false,
+    }
+}
+
+/// Handles a single request line, returning the response,
+/// or `None` if the request is a notification.
+pub fn handle_request(repl: &mut Repl, line: &str) -> Option<Json> {
+    let mut req = match Json::from_str(line) {
+        Ok(Json::Object(obj)) => obj,
+        Ok(_) => return Some(error_response(Json::Null, INVALID_REQUEST,
+            "invalid request: expected object".to_owned())),
+        Err(e) => return Some(error_response(Json::Null, PARSE_ERROR,
+            format!("parse error: {}", e))),
+    };
+
+    let id = req.remove("id");
+
+    let method = match req.remove("method") {
+        Some(Json::String(method)) => method,
+        _ => return Some(error_response(id.unwrap_or(Json::Null), INVALID_REQUEST,
+            "invalid request: expected string member `method`".to_owned())),
+    };
+
+    let result = match req.remove("params") {
+        Some(Json::Object(params)) => call(repl, &method, params),
+        None => call(repl, &method, BTreeMap::new()),
+        Some(_) => Err((INVALID_PARAMS, "invalid params: expected object".to_owned())),
+    };
+
+    let id = match id {
+        Some(id) => id,
+        None => return None,
+    };
+
+    Some(match result {
+        Ok(result) => response(id, "result", result),
+        Err((code, msg)) => error_response(id, code, msg),
+    })
+}
+
+/// Calls a method, returning its result, or an error code and message.
+fn call(repl: &mut Repl, method: &str, mut params: BTreeMap<String, Json>)
+        -> Result<Json, (i64, String)> {
+    match method {
+        "eval" => {
+            let code = string_param(&mut params, "code")?;
+            let block = params.remove("block");
+            let mut result = eval_response(repl.evaluate(&code));
+
+            if let (Some(block), &mut Json::Object(ref mut obj)) = (block, &mut result) {
+                obj.insert("block".to_owned(), block);
+            }
+
+            Ok(result)
+        }
+        "complete" => {
+            let code = string_param(&mut params, "code")?;
+            let pos = pos_param(&params, &code)?.unwrap_or(code.len());
+
+            Ok(complete_response(&code, pos))
+        }
+        "type_at" => {
+            let code = string_param(&mut params, "code")?;
+            let pos = match pos_param(&params, &code)? {
+                Some(pos) => pos,
+                None => return Err((INVALID_PARAMS,
+                    "invalid params: expected number `pos`".to_owned())),
+            };
+
+            let mut obj = BTreeMap::new();
+            obj.insert("type".to_owned(), repl.type_at(&code, pos).map_or(Json::Null, Json::String));
+            Ok(Json::Object(obj))
+        }
+        // Compilation was cancelled as the request was read
+        "interrupt" => Ok(Json::Null),
+        _ => Err((METHOD_NOT_FOUND, format!("method not found: `{}`", method))),
+    }
+}
+
+fn string_param(params: &mut BTreeMap<String, Json>, name: &str) -> Result<String, (i64, String)> {
+    match params.remove(name) {
+        Some(Json::String(s)) => Ok(s),
+        _ => Err((INVALID_PARAMS, format!("invalid params: expected string `{}`", name))),
+    }
+}
+
+/// Returns the byte offset `pos` within `code`, if given.
+fn pos_param(params: &BTreeMap<String, Json>, code: &str) -> Result<Option<usize>, (i64, String)> {
+    match params.get("pos").map(|p| p.as_u64().map(|p| p as usize)) {
+        None => Ok(None),
+        Some(Some(pos)) if pos <= code.len() && code.is_char_boundary(pos) => Ok(Some(pos)),
+        Some(_) => Err((INVALID_PARAMS, "invalid params: invalid `pos`".to_owned())),
+    }
+}
+
+fn response(id: Json, key: &str, value: Json) -> Json {
+    let mut obj = BTreeMap::new();
+
+    obj.insert("jsonrpc".to_owned(), Json::String("2.0".to_owned()));
+    obj.insert("id".to_owned(), id);
+    obj.insert(key.to_owned(), value);
+
+    Json::Object(obj)
+}
+
+fn error_response(id: Json, code: i64, msg: String) -> Json {
+    let mut error = BTreeMap::new();
+
+    error.insert("code".to_owned(), Json::I64(code));
+    error.insert("message".to_owned(), Json::String(msg));
+
+    response(id, "error", Json::Object(error))
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_interrupt;
+
+    #[test]
+    fn test_is_interrupt() {
+        assert!(is_interrupt(r#"{"jsonrpc": "2.0", "method": "interrupt"}"#));
+        assert!(!is_interrupt(r#"{"jsonrpc": "2.0", "id": 1, "method": "eval"}"#));
+        assert!(!is_interrupt("interrupt"));
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Memory layout of types, as computed by the compiler.
+//!
+//! Sizes and offsets are in bytes. Fields are shown in order of their offsets,
+//! which may differ from the order in which they are declared, along with any
+//! padding between them.
+
+use std::fmt;
+
+/// Layout of a type
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeLayout {
+    /// Name of the type
+    pub name: String,
+    pub size: u64,
+    pub align: u64,
+    /// Fields of a struct, union or tuple
+    pub fields: Vec<FieldLayout>,
+    /// How the variant of an enum is stored, if it has more than one
+    pub discriminant: Option<Discriminant>,
+    /// Variants of an enum
+    pub variants: Vec<VariantLayout>,
+}
+
+/// Layout of a field within a type or variant
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldLayout {
+    /// Name of the field, or its index in a tuple
+    pub name: String,
+    /// Name of the field's type
+    pub ty: String,
+    pub offset: u64,
+    pub size: u64,
+    pub align: u64,
+}
+
+/// Layout of a variant of an enum
+#[derive(Clone, Debug, PartialEq)]
+pub struct VariantLayout {
+    pub name: String,
+    pub size: u64,
+    pub fields: Vec<FieldLayout>,
+}
+
+/// Storage of the variant of an enum
+#[derive(Clone, Debug, PartialEq)]
+pub enum Discriminant {
+    /// A tag, stored alongside the fields of each variant
+    Tag {
+        offset: u64,
+        size: u64,
+    },
+    /// Invalid values of a field of one variant, the dataful variant,
+    /// represent the other variants
+    Niche {
+        /// Name of the dataful variant
+        variant: String,
+        /// Size of the field containing the niche
+        size: u64,
+        /// Value representing the first of the other variants
+        start: u128,
+    },
+}
+
+impl fmt::Display for TypeLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: size {}, align {}", self.name, self.size, self.align)?;
+
+        match self.discriminant {
+            Some(Discriminant::Tag{offset, size}) => {
+                write!(f, ", tag of {} at offset {}", bytes(size), offset)?;
+            }
+            Some(Discriminant::Niche{ref variant, size, start}) => {
+                write!(f, ", niche in {} of variant `{}`, from {}", bytes(size), variant, start)?;
+            }
+            None => (),
+        }
+
+        fmt_fields(f, &self.fields, self.size, "  ")?;
+
+        for variant in &self.variants {
+            write!(f, "\n  variant `{}`: size {}", variant.name, variant.size)?;
+            fmt_fields(f, &variant.fields, variant.size, "    ")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes fields in order of their offsets, with padding between them
+/// and following them up to the end of a value of `size` bytes.
+fn fmt_fields(f: &mut fmt::Formatter, fields: &[FieldLayout], size: u64, indent: &str)
+        -> fmt::Result {
+    if fields.is_empty() {
+        return Ok(());
+    }
+
+    let mut sorted = fields.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|field| field.offset);
+
+    let mut end = 0;
+
+    for field in sorted {
+        if field.offset > end {
+            write!(f, "\n{}{:>4}  ({} of padding)", indent, end, bytes(field.offset - end))?;
+        }
+
+        write!(f, "\n{}{:>4}  {}: {} (size {}, align {})",
+            indent, field.offset, field.name, field.ty, field.size, field.align)?;
+
+        end = end.max(field.offset + field.size);
+    }
+
+    if size > end {
+        write!(f, "\n{}{:>4}  ({} of padding)", indent, end, bytes(size - end))?;
+    }
+
+    Ok(())
+}
+
+fn bytes(n: u64) -> String {
+    if n == 1 {
+        "1 byte".to_owned()
+    } else {
+        format!("{} bytes", n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Discriminant, FieldLayout, TypeLayout, VariantLayout};
+
+    fn field(name: &str, ty: &str, offset: u64, size: u64) -> FieldLayout {
+        FieldLayout{
+            name: name.to_owned(),
+            ty: ty.to_owned(),
+            offset: offset,
+            size: size,
+            align: size,
+        }
+    }
+
+    #[test]
+    fn test_struct() {
+        let layout = TypeLayout{
+            name: "Foo".to_owned(),
+            size: 16,
+            align: 8,
+            fields: vec![field("a", "u8", 8, 1), field("b", "u64", 0, 8)],
+            ..TypeLayout::default()
+        };
+
+        assert_eq!(layout.to_string(), "\
+Foo: size 16, align 8
+     0  b: u64 (size 8, align 8)
+     8  a: u8 (size 1, align 1)
+     9  (7 bytes of padding)");
+    }
+
+    #[test]
+    fn test_enum() {
+        let layout = TypeLayout{
+            name: "Bar".to_owned(),
+            size: 8,
+            align: 4,
+            discriminant: Some(Discriminant::Tag{offset: 0, size: 1}),
+            variants: vec![
+                VariantLayout{name: "A".to_owned(), size: 1, fields: Vec::new()},
+                VariantLayout{name: "B".to_owned(), size: 8, fields: vec![field("0", "u32", 4, 4)]},
+            ],
+            ..TypeLayout::default()
+        };
+
+        assert_eq!(layout.to_string(), "\
+Bar: size 8, align 4, tag of 1 byte at offset 0
+  variant `A`: size 1
+  variant `B`: size 8
+       0  (4 bytes of padding)
+       4  0: u32 (size 4, align 4)");
+    }
+}
@@ -9,21 +9,26 @@
 //! A REPL for the Rust programming language.
 
 #![crate_name = "rusti"]
-#![feature(rustc_private, set_stdio)]
+#![feature(rustc_private, set_stdio, fnbox)]
 
 extern crate getopts;
+extern crate libc;
 extern crate linefeed;
 extern crate rustc;
+extern crate rustc_back;
 extern crate rustc_driver;
 extern crate rustc_errors;
 extern crate rustc_lint;
 extern crate rustc_llvm;
 extern crate rustc_metadata;
+extern crate rustc_mir;
 extern crate rustc_plugin;
 extern crate rustc_resolve;
 extern crate rustc_trans;
+extern crate serialize;
 extern crate syntax;
 extern crate tempfile;
+extern crate toml;
 
 #[macro_use] extern crate log;
 extern crate env_logger;
@@ -32,12 +37,66 @@ extern crate env_logger;
 
 use getopts::Options;
 
-use std::path::PathBuf;
+use config::Config;
 
+use syntax::errors::ColorConfig;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+pub mod analysis;
+pub mod artifacts;
+pub mod backtrace;
+pub mod base;
+pub mod batch;
+pub mod bench;
+pub mod capture;
+pub mod clipboard;
+pub mod clippy;
 pub mod completion;
+pub mod config;
+pub mod deps;
+pub mod display;
+pub mod docs;
+pub mod editor;
+pub mod entry;
 pub mod exec;
+pub mod exercise;
+pub mod finder;
+pub mod fix;
+pub mod format;
+pub mod graphics;
+pub mod heap;
+pub mod highlight;
+pub mod hints;
+pub mod history;
+pub mod hole;
 pub mod input;
+pub mod json;
+pub mod jsonrpc;
+pub mod layout;
+pub mod markdown;
+pub mod notebook;
+pub mod pager;
+pub mod phases;
+pub mod profile;
+pub mod prompt;
 pub mod repl;
+pub mod results;
+pub mod runner;
+pub mod rustfmt;
+pub mod sandbox;
+pub mod script;
+pub mod search;
+pub mod selection;
+pub mod server;
+pub mod session;
+pub mod source;
+pub mod transcript;
+pub mod verify;
+pub mod web;
+pub mod worker;
 
 /// Run `rusti` executable using `env::args`.
 /// Returns desired process exit status.
@@ -45,17 +104,66 @@ pub fn run() -> i32 {
     env_logger::init().unwrap();
 
     let args = std::env::args().collect::<Vec<_>>();
+
+    // Arguments following a script are the script's own
+    if let Some(path) = args.get(1).map(Path::new) {
+        if script::is_script(path) {
+            return script::run(&args[0], path, &args[2..]);
+        }
+    }
+
     let mut opts = Options::new();
 
     opts.optopt("c", "", "Execute a rusti command and exit", "COMMAND");
-    opts.optopt("e", "", "Execute a one-line program and exit", "PROGRAM");
+    opts.optopt("e", "", "Evaluate a one-line program, print its value and exit", "PROGRAM");
     opts.optflag("h", "help", "Print this help message and exit");
     opts.optflag("i", "interactive", "Run rusti interactively, \
         even when `-c`, `-e`, or a filename is present");
     opts.optflag("v", "version", "Print version and exit");
+    opts.optflag("", "batch", "Evaluate inputs from a file, or stdin, separated by \
+        `---` lines, stopping at the first failure");
+    opts.optflag("", "json", "Read evaluation requests from stdin \
+        and write results as JSON");
+    opts.optflag("", "jsonrpc", "Serve JSON-RPC requests from editor plugins on stdin");
+    opts.optopt("", "run", "Run the code cells of a Jupyter notebook, \
+        writing their outputs to it", "NOTEBOOK");
+    opts.optopt("", "exercise", "Work through the exercises of a directory, \
+        checking solutions with `.submit`", "DIR");
+    opts.optopt("", "verify", "Run the inputs of a transcript written by `.export-md`, \
+        comparing their output with that recorded", "TRANSCRIPT");
+    opts.optopt("", "server", "Serve the session over a TCP socket address \
+        or `unix:PATH`", "ADDR");
+    opts.optopt("", "session", "Serve the session by name, for editors using `--send`", "NAME");
+    opts.optopt("", "send", "Evaluate code from stdin in the named session \
+        and print its output", "NAME");
+    opts.optopt("", "block", "Name the source block of the code given to `--send`", "ID");
+    opts.optopt("", "web", "Serve a browser playground over HTTP on a TCP socket address",
+        "ADDR");
+    opts.optflag("", "isolate", "Evaluate input in a worker process, \
+        which is restarted if it crashes");
+    opts.optflag("", "build-base", "Build a base image of the configured prelude \
+        and its dependencies, used to start sessions, and exit");
     opts.optmulti("L", "", "Add a directory to the library search path", "PATH");
-    opts.optflag("", "no-rc", "Do not run $HOME/.rustirc.rs");
+    opts.optopt("j", "jobs", "Run at most N jobs in parallel, in codegen and \
+        builds of dependencies", "N");
+    opts.optflag("", "check-only", "Type-check input without running it");
+    opts.optflag("", "deny-unsafe", "Forbid unsafe code in input, and crates \
+        exposing foreign functions");
+    opts.optflag("", "no-color", "Do not color diagnostics or highlight printed values");
+    opts.optflag("", "keep-artifacts", "Keep executables and intermediate files \
+        written while running input");
+    opts.optflag("", "no-rc", "Do not read configuration or run $HOME/.rustirc.rs");
     opts.optopt("", "sysroot", "Use an alternate Rust sysroot", "PATH");
+    opts.optopt("", "target", "Compile input for the given target triple", "TRIPLE");
+    opts.optopt("", "remote", "Run compiled input on a remote host using ssh", "HOST");
+    opts.optopt("", "runner", "Run compiled input using the given command, \
+        such as an emulator", "COMMAND");
+    opts.optflag("", "sandbox", "Run input, and builds of dependencies, without network \
+        access and only able to write to the working directory");
+    opts.optflag("", "offline", "Do not search crates.io with `.search`");
+    opts.optmulti("", "allow-read", "Allow code run by `--sandbox` to read a path", "PATH");
+    opts.optmulti("", "allow-write", "Allow code run by `--sandbox` to read and write a path",
+        "PATH");
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -74,8 +182,30 @@ pub fn run() -> i32 {
         return 0;
     }
 
+    if let Some(name) = matches.opt_str("send") {
+        return session::send(&args[0], &name, matches.opt_str("block"));
+    }
+
+    let batch = matches.opt_present("batch");
+    let notebook = matches.opt_str("run");
+    let transcript = matches.opt_str("verify");
+    let json = matches.opt_present("json");
+    let jsonrpc = matches.opt_present("jsonrpc");
+    let web = matches.opt_str("web");
+    let server = match matches.opt_str("session") {
+        Some(name) => match session::server_addr(&name) {
+            Ok(addr) => Some(addr),
+            Err(e) => {
+                println!("{}: {}", args[0], e);
+                return 1;
+            }
+        },
+        None => matches.opt_str("server"),
+    };
+
     let interactive = matches.opt_present("interactive") ||
-        (matches.free.is_empty() &&
+        (!batch && notebook.is_none() && transcript.is_none() && !json && !jsonrpc &&
+        server.is_none() && web.is_none() && matches.free.is_empty() &&
         !matches.opt_present("c") &&
         !matches.opt_present("e"));
 
@@ -84,7 +214,126 @@ pub fn run() -> i32 {
 
     let mut repl = repl::Repl::new_with_libs(addl_libs, sysroot);
 
-    if !matches.opt_present("no-rc") {
+    let config = if matches.opt_present("no-rc") {
+        Config::default()
+    } else {
+        match Config::default_path().map(|p| (Config::load(&p), p)) {
+            Some((Ok(config), _)) => config,
+            Some((Err(e), p)) => {
+                println!("{}: {}: {}", args[0], p.display(), e);
+                return 1;
+            }
+            None => Config::default(),
+        }
+    };
+
+    if matches.opt_present("isolate") {
+        let color = if matches.opt_present("no-color") {
+            ColorConfig::Never
+        } else {
+            config.color.unwrap_or(ColorConfig::Auto)
+        };
+
+        return worker::run(&args[0], worker_args(&matches),
+            matches.opt_str("target"), &config, color);
+    }
+
+    // Command line options take precedence over configuration
+    repl.configure(&config);
+
+    repl.set_check_only(matches.opt_present("check-only"));
+    repl.set_deny_unsafe(matches.opt_present("deny-unsafe"));
+    repl.set_offline(matches.opt_present("offline"));
+
+    // Kept artifacts of earlier sessions may still be of interest
+    if matches.opt_present("keep-artifacts") {
+        repl.set_keep_artifacts(true);
+    } else if let Err(e) = repl.collect_artifacts() {
+        debug!("failed to remove artifacts: {}", e);
+    }
+
+    if let Some(target) = matches.opt_str("target") {
+        repl.exec_options_mut().target = Some(target);
+    }
+
+    if let Some(jobs) = matches.opt_str("jobs") {
+        match jobs.parse::<usize>() {
+            Ok(n) if n > 0 => repl.exec_options_mut().jobs = Some(n),
+            _ => {
+                println!("{}: invalid value for --jobs: expected a positive number", args[0]);
+                return 1;
+            }
+        }
+    }
+
+    if matches.opt_present("no-color") {
+        repl.exec_options_mut().color = ColorConfig::Never;
+    }
+
+    if let Some(host) = matches.opt_str("remote") {
+        repl.set_runner(Some(runner::Runner::Ssh(host)));
+    } else if let Some(cmd) = matches.opt_str("runner") {
+        repl.set_runner(runner::Runner::parse_command(&cmd));
+    }
+
+    if matches.opt_present("sandbox") {
+        if !cfg!(target_os = "linux") {
+            println!("{}: --sandbox requires `{}`, which is only available on Linux",
+                args[0], sandbox::BWRAP);
+            return 1;
+        }
+
+        if ["remote", "runner"].iter().any(|opt| matches.opt_present(opt)) {
+            println!("{}: --sandbox cannot be used with --remote or --runner", args[0]);
+            return 1;
+        }
+
+        let mut policy = match std::env::current_dir() {
+            Ok(dir) => sandbox::Policy::new(dir),
+            Err(e) => {
+                println!("{}: failed to get working directory: {}", args[0], e);
+                return 1;
+            }
+        };
+
+        let read = config.sandbox_read.iter().cloned().chain(matches.opt_strs("allow-read"));
+        let write = config.sandbox_write.iter().cloned().chain(matches.opt_strs("allow-write"));
+
+        match (allowed_paths(read), allowed_paths(write)) {
+            (Ok(read), Ok(write)) => {
+                policy.read = read;
+                policy.write = write;
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                println!("{}: {}", args[0], e);
+                return 1;
+            }
+        }
+
+        repl.set_sandbox(policy);
+    } else if matches.opt_present("allow-read") || matches.opt_present("allow-write") {
+        println!("{}: --allow-read and --allow-write require --sandbox", args[0]);
+        return 1;
+    }
+
+    if matches.opt_present("build-base") {
+        return match config.prelude {
+            Some(ref prelude) => base::build(&args[0], prelude, repl.exec_options_mut().clone()),
+            None => {
+                println!("{}: no prelude is configured", args[0]);
+                1
+            }
+        };
+    }
+
+    // Output from the prelude or rc file would interfere with JSON responses,
+    // and batch, notebook and transcript results should not depend on them
+    let rc = !batch && notebook.is_none() && transcript.is_none() && !json && !jsonrpc &&
+        server.is_none() && web.is_none();
+
+    if !matches.opt_present("no-rc") && rc {
+        repl.run_prelude();
+
         if let Some(p) = std::env::home_dir() {
             let rc = p.join(".rustirc.rs");
             if rc.is_file() {
@@ -95,10 +344,49 @@ pub fn run() -> i32 {
         }
     }
 
+    if let Some(dir) = matches.opt_str("exercise") {
+        match exercise::load(Path::new(&dir)) {
+            Ok(exercises) => repl.start_exercises(exercises),
+            Err(e) => {
+                println!("{}: {}", args[0], e);
+                return 1;
+            }
+        }
+    }
+
+    if batch {
+        repl.exec_options_mut().color = ColorConfig::Never;
+
+        return match read_batch(matches.free.get(0)) {
+            Ok(text) => batch::run(&mut repl, &text),
+            Err(e) => {
+                println!("{}: {}", args[0], e);
+                1
+            }
+        };
+    }
+
+    if let Some(path) = notebook {
+        repl.exec_options_mut().color = ColorConfig::Never;
+        return notebook::run(&args[0], &mut repl, Path::new(&path));
+    }
+
+    if let Some(path) = transcript {
+        repl.exec_options_mut().color = ColorConfig::Never;
+        return verify::run(&args[0], &mut repl, Path::new(&path));
+    }
+
     if let Some(cmd) = matches.opt_str("c") {
         repl.run_command(&cmd);
     } else if let Some(expr) = matches.opt_str("e") {
-        repl.eval(&expr);
+        let res = repl.eval(&expr);
+
+        // Exit as a Rust program does if the program panics
+        if !res.compiled {
+            return 1;
+        } else if res.failed {
+            return entry::EXIT_PANIC;
+        }
     } else if !matches.free.is_empty() {
         let path = PathBuf::from(&matches.free[0]);
 
@@ -107,6 +395,22 @@ pub fn run() -> i32 {
         }
     }
 
+    if json {
+        return json::run(&mut repl);
+    }
+
+    if jsonrpc {
+        return jsonrpc::run(&mut repl);
+    }
+
+    if let Some(addr) = server {
+        return server::run(&mut repl, &addr);
+    }
+
+    if let Some(addr) = web {
+        return web::run(&args[0], &mut repl, &addr);
+    }
+
     if interactive {
         repl.run();
     }
@@ -114,6 +418,62 @@ pub fn run() -> i32 {
     0
 }
 
+/// Returns the arguments given to a worker process, as used by `--isolate`.
+fn worker_args(matches: &getopts::Matches) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for flag in &["check-only", "deny-unsafe", "keep-artifacts", "no-color", "no-rc", "offline",
+            "sandbox"] {
+        if matches.opt_present(flag) {
+            args.push(format!("--{}", flag));
+        }
+    }
+
+    for opt in &["sysroot", "target", "remote", "runner", "jobs"] {
+        if let Some(value) = matches.opt_str(opt) {
+            args.push(format!("--{}", opt));
+            args.push(value);
+        }
+    }
+
+    for path in matches.opt_strs("L") {
+        args.push("-L".to_owned());
+        args.push(path);
+    }
+
+    for opt in &["allow-read", "allow-write"] {
+        for path in matches.opt_strs(opt) {
+            args.push(format!("--{}", opt));
+            args.push(path);
+        }
+    }
+
+    args
+}
+
+/// Returns the absolute paths, as mounted in a sandbox, of paths which
+/// code run in the sandbox is allowed to access.
+fn allowed_paths<I>(paths: I) -> Result<Vec<PathBuf>, String>
+        where I: Iterator<Item=String> {
+    paths.map(|p| std::fs::canonicalize(&p)
+            .map_err(|e| format!("cannot allow access to {}: {}", p, e)))
+        .collect()
+}
+
+/// Reads the inputs of `--batch` from the named file, or from `stdin`.
+fn read_batch(path: Option<&String>) -> io::Result<String> {
+    let mut text = String::new();
+
+    match path {
+        Some(path) => File::open(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("failed to open {}: {}", path, e)))?
+            .read_to_string(&mut text)?,
+        None => io::stdin().read_to_string(&mut text)?,
+    };
+
+    Ok(text)
+}
+
 /// Returns a version string.
 pub fn version() -> &'static str {
     env!("CARGO_PKG_VERSION")
@@ -0,0 +1,409 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rendering of Markdown documentation for the terminal, as by `.doc`.
+//!
+//! Headings are emphasized, code blocks are indented, with the lines which
+//! rustdoc hides removed, and list items are bulleted. Links to other pages
+//! of rustdoc output are shown with the paths of the items they document.
+
+use std::collections::HashMap;
+
+use highlight::highlight;
+use transcript::{parse_fence, read_block};
+
+const BOLD: &'static str = "\x1b[1m";
+const NOT_BOLD: &'static str = "\x1b[22m";
+const ITALIC: &'static str = "\x1b[3m";
+const NOT_ITALIC: &'static str = "\x1b[23m";
+const UNDERLINE: &'static str = "\x1b[4m";
+const NOT_UNDERLINE: &'static str = "\x1b[24m";
+
+/// Words of the language string of a code block which rustdoc tests as Rust
+const RUST_WORDS: &'static [&'static str] = &["rust", "ignore", "no_run", "should_panic",
+    "compile_fail", "test_harness", "allow_fail"];
+
+/// Renders Markdown for the terminal, with ANSI escape sequences if `color`
+/// is `true`.
+///
+/// `page` is the path of the item on whose page the documentation is shown,
+/// and `module` the path of the module to which relative links are resolved.
+pub fn render(text: &str, page: &str, module: &str, color: bool) -> String {
+    let ctx = Context{
+        refs: text.lines().filter_map(reference).collect(),
+        page: page,
+        module: module,
+        color: color,
+    };
+
+    let mut res = String::new();
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_left();
+        let indent = &line[..line.len() - trimmed.len()];
+
+        if let Some((fence, lang)) = parse_fence(trimmed, "") {
+            let code = read_block(&mut lines, fence, indent);
+            ctx.code_block(&mut res, indent, &code, is_rust(lang));
+            continue;
+        }
+
+        if reference(line).is_some() {
+            continue;
+        }
+
+        let level = trimmed.len() - trimmed.trim_left_matches('#').len();
+
+        if level > 0 && trimmed[level..].starts_with(' ') {
+            ctx.heading(&mut res, level, trimmed[level..].trim().trim_right_matches('#').trim());
+            continue;
+        }
+
+        res.push_str(indent);
+
+        if trimmed.starts_with("* ") || trimmed.starts_with("- ") || trimmed.starts_with("+ ") {
+            res.push_str("• ");
+            res.push_str(&ctx.inline(&trimmed[2..]));
+        } else {
+            res.push_str(&ctx.inline(trimmed));
+        }
+
+        res.push('\n');
+    }
+
+    res
+}
+
+/// Returns the path of the item documented by the page a link refers to,
+/// or the link itself if it refers to another site.
+///
+/// Returns `None` if the link does not refer to an item, such as a link
+/// to a section of a page.
+pub fn resolve_link(target: &str, page: &str, module: &str) -> Option<String> {
+    if target.starts_with("http://") || target.starts_with("https://") {
+        return Some(target.to_owned());
+    }
+
+    let (file, fragment) = match target.find('#') {
+        Some(i) => (&target[..i], Some(&target[i + 1..])),
+        None => (target, None),
+    };
+
+    let mut path = Vec::new();
+    let mut item = false;
+
+    if file.is_empty() {
+        path.extend(page.split("::").map(|s| s.to_owned()));
+    } else {
+        path.extend(module.split("::").filter(|s| !s.is_empty()).map(|s| s.to_owned()));
+
+        for seg in file.split('/') {
+            match seg {
+                "" | "." => (),
+                ".." => {
+                    path.pop();
+                }
+                "index.html" => item = true,
+                _ if seg.ends_with(".html") => {
+                    let mut parts = seg[..seg.len() - ".html".len()].splitn(2, '.');
+
+                    match (parts.next()?, parts.next()?) {
+                        ("primitive", name) => path = vec![name.to_owned()],
+                        ("macro", name) => path.push(format!("{}!", name)),
+                        (_, name) => path.push(name.to_owned()),
+                    }
+
+                    item = true;
+                }
+                _ => path.push(seg.to_owned()),
+            }
+        }
+    }
+
+    // Fragments such as `#method.push` name associated items,
+    // others such as `#examples` sections
+    if let Some(fragment) = fragment {
+        if let Some(i) = fragment.find('.') {
+            path.push(fragment[i + 1..].to_owned());
+            item = true;
+        }
+    }
+
+    if item && !path.is_empty() {
+        Some(path.join("::"))
+    } else {
+        None
+    }
+}
+
+/// Parses a link reference definition, such as `[Vec]: struct.Vec.html`,
+/// returning its lowercase label and its target.
+fn reference(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+
+    if !line.starts_with('[') {
+        return None;
+    }
+
+    let end = line.find("]:")?;
+    let target = line[end + 2..].split_whitespace().next()?;
+
+    if end == 1 {
+        None
+    } else {
+        Some((line[1..end].to_lowercase(), target.to_owned()))
+    }
+}
+
+/// Returns whether a code block with the given language string is Rust code.
+fn is_rust(lang: &str) -> bool {
+    lang.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|w| !w.is_empty())
+        .all(|w| RUST_WORDS.contains(&w) || w.starts_with("edition") || w.starts_with('E'))
+}
+
+/// State shared while rendering a document
+struct Context<'a> {
+    /// Targets of reference links, by lowercase label
+    refs: HashMap<String, String>,
+    page: &'a str,
+    module: &'a str,
+    color: bool,
+}
+
+impl<'a> Context<'a> {
+    fn heading(&self, res: &mut String, level: usize, text: &str) {
+        let text = self.inline(text);
+
+        if self.color {
+            let underline = if level == 1 { UNDERLINE } else { "" };
+            res.push_str(&format!("{}{}{}{}{}\n", BOLD, underline, text, NOT_UNDERLINE, NOT_BOLD));
+        } else {
+            res.push_str(&text);
+            res.push('\n');
+
+            if level <= 2 {
+                let c = if level == 1 { "=" } else { "-" };
+                res.push_str(&c.repeat(text.chars().count()));
+                res.push('\n');
+            }
+        }
+    }
+
+    fn code_block(&self, res: &mut String, indent: &str, code: &str, rust: bool) {
+        let code = if rust {
+            // Lines beginning with `#` are hidden by rustdoc; `##` escapes `#`
+            code.lines()
+                .filter(|line| {
+                    let line = line.trim_left();
+                    line != "#" && !line.starts_with("# ")
+                })
+                .map(|line| if line.trim_left().starts_with("##") {
+                    line.replacen("##", "#", 1) + "\n"
+                } else {
+                    line.to_owned() + "\n"
+                })
+                .collect::<String>()
+        } else {
+            code.to_owned()
+        };
+
+        let code = if rust && self.color { highlight(&code) } else { code };
+
+        for line in code.lines() {
+            res.push_str(indent);
+            res.push_str("    ");
+            res.push_str(line);
+            res.push('\n');
+        }
+    }
+
+    /// Renders the inline elements of a line: code spans, links and emphasis.
+    fn inline(&self, text: &str) -> String {
+        let mut res = String::new();
+        let mut rest = text;
+        let mut bold = false;
+        let mut italic = false;
+
+        while let Some(c) = rest.chars().next() {
+            let prev = text[..text.len() - rest.len()].chars().next_back();
+
+            match c {
+                '`' => {
+                    let n = rest.len() - rest.trim_left_matches('`').len();
+
+                    if let Some(end) = rest[n..].find(&rest[..n]) {
+                        res.push_str(&self.code(rest[n..n + end].trim()));
+                        rest = &rest[n + end + n..];
+                    } else {
+                        res.push_str(&rest[..n]);
+                        rest = &rest[n..];
+                    }
+
+                    continue;
+                }
+                '[' => {
+                    if let Some((link, len)) = self.link(rest) {
+                        res.push_str(&link);
+                        rest = &rest[len..];
+                        continue;
+                    }
+                }
+                '*' => {
+                    let n = if rest.starts_with("**") { 2 } else { 1 };
+                    let next = rest[n..].chars().next();
+                    let open = if n == 2 { &mut bold } else { &mut italic };
+
+                    let closes = *open && prev.map_or(false, |c| !c.is_whitespace());
+                    let opens = !*open && next.map_or(false, |c| !c.is_whitespace()) &&
+                        prev.map_or(true, |c| !c.is_alphanumeric());
+
+                    if closes || opens {
+                        *open = opens;
+
+                        if self.color {
+                            res.push_str(match (n, opens) {
+                                (2, true) => BOLD,
+                                (2, false) => NOT_BOLD,
+                                (_, true) => ITALIC,
+                                (_, false) => NOT_ITALIC,
+                            });
+                        }
+
+                        rest = &rest[n..];
+                        continue;
+                    }
+                }
+                _ => (),
+            }
+
+            res.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        if self.color && (bold || italic) {
+            res.push_str(NOT_BOLD);
+            res.push_str(NOT_ITALIC);
+        }
+
+        res
+    }
+
+    fn code(&self, code: &str) -> String {
+        if self.color {
+            highlight(code)
+        } else {
+            format!("`{}`", code)
+        }
+    }
+
+    /// Renders a link at the start of `text`, returning it with the length
+    /// of its Markdown, or `None` if `text` does not start with a link.
+    fn link(&self, text: &str) -> Option<(String, usize)> {
+        let close = matching_bracket(text)?;
+        let label = &text[1..close];
+        let after = &text[close + 1..];
+        let plain = label.trim_matches('`');
+
+        let (target, len) = if after.starts_with('(') {
+            let end = after.find(')')?;
+            let target = after[1..end].split_whitespace().next().unwrap_or("");
+            (resolve_link(target, self.page, self.module), close + end + 2)
+        } else if after.starts_with('[') {
+            let end = after.find(']')?;
+            let name = if end == 1 { label } else { &after[1..end] };
+            let target = self.refs.get(&name.to_lowercase())?;
+            (resolve_link(target, self.page, self.module), close + end + 2)
+        } else if let Some(target) = self.refs.get(&label.to_lowercase()) {
+            (resolve_link(target, self.page, self.module), close + 1)
+        } else if label.starts_with('`') && is_path(plain) {
+            // An intra-doc link, which names the item itself
+            (None, close + 1)
+        } else {
+            return None;
+        };
+
+        let shown = self.inline(label);
+        let shown = if self.color {
+            format!("{}{}{}", UNDERLINE, shown, NOT_UNDERLINE)
+        } else {
+            shown
+        };
+
+        match target {
+            Some(ref path) if *path == plain || path.ends_with(&format!("::{}", plain)) =>
+                Some((shown, len)),
+            Some(path) => Some((format!("{} ({})", shown, path), len)),
+            None => Some((shown, len)),
+        }
+    }
+}
+
+/// Returns the offset of the `]` closing the `[` at the start of `text`.
+fn matching_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    None
+}
+
+/// Returns whether a string is a path, such as `Vec::push`.
+fn is_path(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_' || c == ':' || c == '!')
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, resolve_link};
+
+    #[test]
+    fn test_render() {
+        let text = "Appends an element.\n\n\
+            # Panics\n\n\
+            Panics if the *new* capacity overflows [`usize`].\n\n\
+            * See [popping](#method.pop) and [`Vec`].\n\n\
+            ```\n# let mut v = Vec::new();\nv.push(3);\n```\n\n\
+            [`usize`]: ../../std/primitive.usize.html";
+
+        assert_eq!(render(text, "std::vec::Vec", "std::vec", false),
+            "Appends an element.\n\n\
+            Panics\n\
+            ======\n\n\
+            Panics if the new capacity overflows `usize`.\n\n\
+            • See popping (std::vec::Vec::pop) and `Vec`.\n\n    \
+            v.push(3);\n\n");
+    }
+
+    #[test]
+    fn test_resolve_link() {
+        let resolve = |target| resolve_link(target, "std::vec::Vec", "std::vec");
+
+        assert_eq!(resolve("struct.Vec.html"), Some("std::vec::Vec".to_owned()));
+        assert_eq!(resolve("../boxed/struct.Box.html#method.new"),
+            Some("std::boxed::Box::new".to_owned()));
+        assert_eq!(resolve("#method.push"), Some("std::vec::Vec::push".to_owned()));
+        assert_eq!(resolve("../../std/macro.vec.html"), Some("std::vec!".to_owned()));
+        assert_eq!(resolve("index.html"), Some("std::vec".to_owned()));
+        assert_eq!(resolve("#examples"), None);
+        assert_eq!(resolve("https://doc.rust-lang.org/"), Some("https://doc.rust-lang.org/".to_owned()));
+    }
+}
@@ -0,0 +1,314 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Jupyter notebooks, in version 4 of the `.ipynb` format.
+//!
+//! `.export-ipynb` writes the input of a session as a notebook, with a code
+//! cell for each input, holding its output and value. `--run` runs each code
+//! cell of a notebook in turn, as `--batch` runs inputs, and writes the
+//! output of each cell back to the notebook. A cell may begin with rusti
+//! commands; see the `batch` module.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serialize::json::Json;
+use tempfile::NamedTempFile;
+
+use batch;
+use capture::capture;
+use display::DisplayData;
+use repl::{EvalResult, Repl};
+use transcript::{strip_escapes, Entry};
+
+/// Returns a notebook containing a code cell for each entry of a transcript.
+pub fn export(entries: &[Entry]) -> Json {
+    let cells = entries.iter().enumerate().map(|(i, entry)| {
+        let mut outputs = Vec::new();
+        let output = strip_escapes(&entry.output);
+
+        if !output.is_empty() {
+            outputs.push(stream("stdout", &output));
+        }
+
+        if let Some(ref value) = entry.value {
+            outputs.push(execute_result(i + 1, value));
+        }
+
+        code_cell(&entry.input, i + 1, outputs)
+    }).collect();
+
+    notebook(cells)
+}
+
+/// Runs the code cells of the notebook at `path` and writes their outputs to it.
+/// Cells are run until one fails to compile or panics; later cells are left
+/// as they were. Returns desired process exit status.
+pub fn run(argv0: &str, repl: &mut Repl, path: &Path) -> i32 {
+    let mut nb = match read(path) {
+        Ok(nb) => nb,
+        Err(e) => {
+            println!("{}: failed to read {}: {}", argv0, path.display(), e);
+            return 1;
+        }
+    };
+
+    let (ran, failed) = match run_cells(argv0, repl, &mut nb) {
+        Some(res) => res,
+        None => {
+            println!("{}: {}: not a notebook", argv0, path.display());
+            return 1;
+        }
+    };
+
+    if let Err(e) = write(path, &nb) {
+        println!("{}: failed to write {}: {}", argv0, path.display(), e);
+        return 1;
+    }
+
+    println!("ran {} cells of {}", ran, path.display());
+
+    if failed { 1 } else { 0 }
+}
+
+/// Runs the code cells of a notebook, replacing their outputs. Returns the
+/// number of cells run and whether the last of them failed, or `None` if
+/// the notebook has no cells.
+fn run_cells(argv0: &str, repl: &mut Repl, nb: &mut Json) -> Option<(usize, bool)> {
+    let cells = match *nb {
+        Json::Object(ref mut obj) => match obj.get_mut("cells") {
+            Some(&mut Json::Array(ref mut cells)) => cells,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let mut ran = 0;
+
+    for cell in cells {
+        let cell = match *cell {
+            Json::Object(ref mut cell) => cell,
+            _ => continue,
+        };
+
+        if cell.get("cell_type").and_then(|t| t.as_string()) != Some("code") {
+            continue;
+        }
+
+        let code = match cell.get("source") {
+            Some(source) => source_text(source),
+            None => continue,
+        };
+
+        // Commands write output of their own
+        let res = capture(|| batch::evaluate(repl, &code));
+
+        let ((status, result), commands) = match res {
+            Ok(res) => res,
+            Err(e) => {
+                println!("{}: failed to capture output: {}", argv0, e);
+                return Some((ran, true));
+            }
+        };
+
+        ran += 1;
+
+        let mut outputs = Vec::new();
+
+        if !commands.stdout.is_empty() {
+            outputs.push(stream("stdout", &commands.stdout));
+        }
+
+        outputs.extend(result_outputs(ran, result));
+
+        cell.insert("execution_count".to_owned(), Json::U64(ran as u64));
+        cell.insert("outputs".to_owned(), Json::Array(outputs));
+
+        if status != "ok" {
+            return Some((ran, true));
+        }
+    }
+
+    Some((ran, false))
+}
+
+/// Returns the outputs of a cell which produced the given result.
+fn result_outputs(n: usize, result: EvalResult) -> Vec<Json> {
+    let mut outputs = Vec::new();
+
+    if !result.stdout.is_empty() {
+        outputs.push(stream("stdout", &result.stdout));
+    }
+
+    if !result.stderr.is_empty() {
+        outputs.push(stream("stderr", &strip_escapes(&result.stderr)));
+    }
+
+    for data in result.display {
+        outputs.push(display_data(data));
+    }
+
+    if let Some(ref value) = result.value {
+        outputs.push(execute_result(n, value));
+    }
+
+    outputs
+}
+
+/// Returns the text of a cell's source, which is either a string or
+/// an array of lines.
+fn source_text(source: &Json) -> String {
+    match *source {
+        Json::String(ref s) => s.clone(),
+        Json::Array(ref lines) => lines.iter().filter_map(|l| l.as_string()).collect(),
+        _ => String::new(),
+    }
+}
+
+/// Splits text into lines, each ending with a newline but the last,
+/// as notebooks store multi-line strings.
+fn lines(text: &str) -> Json {
+    let mut res = Vec::new();
+    let mut rest = text;
+
+    while let Some(n) = rest.find('\n') {
+        res.push(Json::String(rest[..n + 1].to_owned()));
+        rest = &rest[n + 1..];
+    }
+
+    if !rest.is_empty() {
+        res.push(Json::String(rest.to_owned()));
+    }
+
+    Json::Array(res)
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(members.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+}
+
+fn notebook(cells: Vec<Json>) -> Json {
+    object(vec![
+        ("cells", Json::Array(cells)),
+        ("metadata", object(vec![
+            ("kernelspec", object(vec![
+                ("display_name", Json::String("Rust".to_owned())),
+                ("language", Json::String("rust".to_owned())),
+                ("name", Json::String("rust".to_owned())),
+            ])),
+            ("language_info", object(vec![
+                ("file_extension", Json::String(".rs".to_owned())),
+                ("name", Json::String("rust".to_owned())),
+            ])),
+        ])),
+        ("nbformat", Json::U64(4)),
+        ("nbformat_minor", Json::U64(2)),
+    ])
+}
+
+fn code_cell(source: &str, n: usize, outputs: Vec<Json>) -> Json {
+    object(vec![
+        ("cell_type", Json::String("code".to_owned())),
+        ("execution_count", Json::U64(n as u64)),
+        ("metadata", Json::Object(BTreeMap::new())),
+        ("outputs", Json::Array(outputs)),
+        ("source", lines(source.trim_right())),
+    ])
+}
+
+fn stream(name: &str, text: &str) -> Json {
+    object(vec![
+        ("output_type", Json::String("stream".to_owned())),
+        ("name", Json::String(name.to_owned())),
+        ("text", lines(text)),
+    ])
+}
+
+fn execute_result(n: usize, value: &str) -> Json {
+    object(vec![
+        ("output_type", Json::String("execute_result".to_owned())),
+        ("execution_count", Json::U64(n as u64)),
+        ("data", object(vec![("text/plain", lines(value))])),
+        ("metadata", Json::Object(BTreeMap::new())),
+    ])
+}
+
+fn display_data(data: DisplayData) -> Json {
+    object(vec![
+        ("output_type", Json::String("display_data".to_owned())),
+        ("data", object(vec![(&data.mime[..], lines(&data.data))])),
+        ("metadata", Json::Object(BTreeMap::new())),
+    ])
+}
+
+/// Reads a notebook from the named file.
+pub fn read(path: &Path) -> io::Result<Json> {
+    let mut text = String::new();
+
+    File::open(path)?.read_to_string(&mut text)?;
+
+    Json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Writes a notebook to the named file, replacing it once written completely.
+pub fn write(path: &Path, nb: &Json) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+
+    let mut file = NamedTempFile::new_in(dir)?;
+
+    writeln!(file, "{}", nb.pretty())?;
+    file.persist(path).map_err(|e| e.error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::UNIX_EPOCH;
+
+    use serialize::json::Json;
+
+    use transcript::Entry;
+    use super::{export, lines, source_text};
+
+    #[test]
+    fn test_lines() {
+        assert_eq!(lines("a\nb\n").to_string(), r#"["a\n","b\n"]"#);
+        assert_eq!(lines("a\nb").to_string(), r#"["a\n","b"]"#);
+        assert_eq!(lines("").to_string(), "[]");
+    }
+
+    #[test]
+    fn test_source_text() {
+        assert_eq!(source_text(&Json::from_str(r#"["a\n","b"]"#).unwrap()), "a\nb");
+        assert_eq!(source_text(&Json::from_str(r#""a\nb""#).unwrap()), "a\nb");
+    }
+
+    #[test]
+    fn test_export() {
+        let nb = export(&[Entry{
+            time: UNIX_EPOCH,
+            input: "println!(\"hi\");\n1\n".to_owned(),
+            command: false,
+            output: "hi\n".to_owned(),
+            value: Some("1".to_owned()),
+        }]);
+
+        let cell = &nb.find("cells").unwrap().as_array().unwrap()[0];
+
+        assert_eq!(cell.find("source").unwrap().to_string(), r#"["println!(\"hi\");\n","1"]"#);
+        assert_eq!(cell.find("outputs").unwrap().to_string(),
+            r#"[{"name":"stdout","output_type":"stream","text":["hi\n"]},{"data":{"text/plain":["1"]},"execution_count":1,"metadata":{},"output_type":"execute_result"}]"#);
+        assert_eq!(nb.find("nbformat").unwrap().as_u64(), Some(4));
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Shows long output through a pager.
+
+use std::env::var;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Pager used when `PAGER` is not set
+const DEFAULT_PAGER: &'static str = "less -R";
+
+/// Returns the pager command named by the `PAGER` environment variable,
+/// or the default pager.
+pub fn default_pager() -> String {
+    match var("PAGER") {
+        Ok(ref p) if !p.trim().is_empty() => p.clone(),
+        _ => DEFAULT_PAGER.to_owned(),
+    }
+}
+
+/// Prints text to `stdout`, followed by a newline.
+///
+/// If `pager` is given, `stdout` is a terminal, and the text does not fit
+/// on the screen, the text is shown through the pager instead.
+pub fn print(text: &str, pager: Option<&str>) {
+    if let Some(pager) = pager {
+        let fits = terminal_size().map_or(true, |(rows, _)| text.lines().count() < rows);
+
+        if !fits && page(text, pager).is_ok() {
+            return;
+        }
+    }
+
+    println!("{}", text);
+}
+
+/// Shows text through the given pager command.
+fn page(text: &str, pager: &str) -> io::Result<()> {
+    let mut args = pager.split_whitespace();
+    let program = match args.next() {
+        Some(p) => p,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty pager")),
+    };
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    {
+        let mut stdin = child.stdin.take().unwrap();
+
+        // The pager may exit before reading all input
+        let _ = stdin.write_all(text.as_bytes());
+        let _ = stdin.write_all(b"\n");
+    }
+
+    child.wait()?;
+    Ok(())
+}
+
+/// Returns the number of rows and columns of the terminal,
+/// or `None` if `stdout` is not a terminal.
+#[cfg(unix)]
+pub fn terminal_size() -> Option<(usize, usize)> {
+    use libc::{ioctl, isatty, winsize, STDOUT_FILENO, TIOCGWINSZ};
+
+    unsafe {
+        if isatty(STDOUT_FILENO) == 0 {
+            return None;
+        }
+
+        let mut ws: winsize = ::std::mem::zeroed();
+
+        if ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut ws) == -1 || ws.ws_row == 0 {
+            None
+        } else {
+            Some((ws.ws_row as usize, ws.ws_col as usize))
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn terminal_size() -> Option<(usize, usize)> {
+    None
+}
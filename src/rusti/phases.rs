@@ -0,0 +1,95 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Time spent in each phase of compiling input and loading it into
+//! the execution engine.
+
+use std::ops::AddAssign;
+use std::time::Duration;
+
+/// Time spent in each phase of compiling and loading input
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PhaseTimes {
+    /// Parsing source
+    pub parse: Duration,
+    /// Configuration and macro expansion
+    pub expand: Duration,
+    /// Analysis, including type checking and borrow checking
+    pub typeck: Duration,
+    /// Translation into an LLVM module
+    pub trans: Duration,
+    /// Loading libraries and adding the module to the execution engine
+    pub link: Duration,
+    /// Compiling the module to machine code, when it is first called
+    pub jit: Duration,
+}
+
+impl PhaseTimes {
+    /// Returns the name and time of each phase, in the order they run.
+    pub fn phases(&self) -> [(&'static str, Duration); 6] {
+        [
+            ("parse", self.parse),
+            ("expand", self.expand),
+            ("typeck", self.typeck),
+            ("trans", self.trans),
+            ("link", self.link),
+            ("jit", self.jit),
+        ]
+    }
+
+    /// Returns the time spent in all phases.
+    pub fn total(&self) -> Duration {
+        self.phases().iter().fold(Duration::new(0, 0), |sum, &(_, d)| sum + d)
+    }
+}
+
+impl AddAssign for PhaseTimes {
+    fn add_assign(&mut self, other: PhaseTimes) {
+        self.parse += other.parse;
+        self.expand += other.expand;
+        self.typeck += other.typeck;
+        self.trans += other.trans;
+        self.link += other.link;
+        self.jit += other.jit;
+    }
+}
+
+/// Formats a duration in milliseconds.
+pub fn format_millis(d: Duration) -> String {
+    format!("{:.1} ms", d.as_secs() as f64 * 1e3 + d.subsec_nanos() as f64 / 1e6)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{format_millis, PhaseTimes};
+
+    #[test]
+    fn test_total() {
+        let mut times = PhaseTimes{
+            parse: Duration::from_millis(1),
+            trans: Duration::from_millis(5),
+            ..PhaseTimes::default()
+        };
+
+        times += PhaseTimes{
+            jit: Duration::from_millis(4),
+            ..times
+        };
+
+        assert_eq!(times.trans, Duration::from_millis(10));
+        assert_eq!(times.total(), Duration::from_millis(16));
+    }
+
+    #[test]
+    fn test_format_millis() {
+        assert_eq!(format_millis(Duration::new(1, 250_000)), "1000.2 ms");
+        assert_eq!(format_millis(Duration::from_millis(12)), "12.0 ms");
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Profiling of expressions, using `perf`.
+//!
+//! The expression is evaluated repeatedly by an executable run under
+//! `perf record`, which samples its call stacks. Samples are read back through
+//! `perf script`, folded into counts of identical stacks and rendered as a
+//! flame graph: an SVG image in which each function is a bar, as wide as the
+//! share of samples in which it was on the stack, above the function calling it.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use tempfile::NamedTempFile;
+
+/// Name of the `perf` executable
+const PERF: &'static str = "perf";
+
+/// Time for which the expression is evaluated, in milliseconds
+const PROFILE_MS: u64 = 2_000;
+
+/// Frequency at which call stacks are sampled, in hertz
+const FREQUENCY: u32 = 999;
+
+/// Width of a flame graph, in pixels
+const WIDTH: f64 = 1200.0;
+
+/// Height of each frame of a flame graph, in pixels
+const FRAME_HEIGHT: usize = 16;
+
+/// Returns the body of an entry point which evaluates an expression repeatedly,
+/// returning the number of evaluations.
+pub fn statements(expr: &str) -> String {
+    format!(r#"
+// Prevents the optimizer from removing the evaluation of `x`
+fn _rusti_black_box<T>(x: T) -> T {{
+    unsafe {{
+        let y = ::std::ptr::read_volatile(&x);
+        ::std::mem::forget(x);
+        y
+    }}
+}}
+
+#[allow(unused_mut)]
+let mut _rusti_profile = || {{ _rusti_black_box({{ {expr} }}); }};
+let _rusti_start = ::std::time::Instant::now();
+let mut _rusti_n = 0u64;
+
+while _rusti_start.elapsed() < ::std::time::Duration::from_millis({profile_ms}) {{
+    _rusti_profile();
+    _rusti_n += 1;
+}}
+
+Some(format!("{{}}", _rusti_n))
+"#,
+        expr = expr,
+        profile_ms = PROFILE_MS)
+}
+
+/// Runs an executable under `perf`, returning its output and the samples
+/// recorded, as printed by `perf script`.
+pub fn record(exe: &Path) -> io::Result<(String, String)> {
+    let data = NamedTempFile::new()?;
+
+    let output = Command::new(PERF)
+        .args(&["record", "--quiet", "--call-graph", "dwarf"])
+        .arg("-F").arg(FREQUENCY.to_string())
+        .arg("-o").arg(data.path())
+        .arg("--").arg(exe)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other,
+            format!("`{} record` exited with {}: {}", PERF, output.status,
+                String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    let script = Command::new(PERF)
+        .arg("script")
+        .arg("-i").arg(data.path())
+        .output()?;
+
+    if !script.status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other,
+            format!("`{} script` exited with {}", PERF, script.status)));
+    }
+
+    Ok((String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&script.stdout).into_owned()))
+}
+
+/// Folds samples printed by `perf script` into the number of times each
+/// call stack was sampled. Stacks are given outermost frame first, with
+/// frames separated by `;`.
+pub fn fold(script: &str) -> BTreeMap<String, u64> {
+    let mut stacks = BTreeMap::new();
+    let mut frames = Vec::new();
+
+    // Each sample is a header line, followed by an indented line for each
+    // frame, innermost first, and an empty line.
+    for line in script.lines().chain(Some("")) {
+        if line.starts_with(|c: char| c.is_whitespace()) && !line.trim().is_empty() {
+            frames.push(frame_name(line.trim()));
+        } else if !frames.is_empty() {
+            frames.reverse();
+            *stacks.entry(frames.join(";")).or_insert(0) += 1;
+            frames.clear();
+        }
+    }
+
+    stacks
+}
+
+/// Returns the name of the function of a frame line printed by `perf script`,
+/// which gives an address, a symbol with an offset and the object file.
+fn frame_name(line: &str) -> String {
+    let line = match line.rfind(" (") {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+
+    // Skip the address
+    let sym = line.splitn(2, ' ').nth(1).unwrap_or("[unknown]").trim();
+
+    let sym = match sym.rfind("+0x") {
+        Some(pos) => &sym[..pos],
+        None => sym,
+    };
+
+    strip_hash(sym).to_owned()
+}
+
+/// Strips the hash which ends a mangled Rust symbol name, as in `foo::h0123456789abcdef`.
+fn strip_hash(sym: &str) -> &str {
+    let len = sym.len();
+
+    if len > 19 && sym.is_char_boundary(len - 16) && sym[..len - 16].ends_with("::h") &&
+            sym[len - 16..].chars().all(|c| c.is_digit(16)) {
+        &sym[..len - 19]
+    } else {
+        sym
+    }
+}
+
+/// Returns the functions in which samples were taken, with the number
+/// of samples taken in each, most sampled first.
+pub fn hottest(stacks: &BTreeMap<String, u64>) -> Vec<(String, u64)> {
+    let mut counts = BTreeMap::new();
+
+    for (stack, &n) in stacks {
+        let leaf = stack.rsplit(';').next().unwrap_or(stack);
+        *counts.entry(leaf.to_owned()).or_insert(0) += n;
+    }
+
+    let mut res = counts.into_iter().collect::<Vec<_>>();
+    res.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    res
+}
+
+/// A function in a tree of call stacks
+#[derive(Default)]
+struct Node {
+    /// Number of samples in which the function was on the stack
+    samples: u64,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn depth(&self) -> usize {
+        self.children.values().map(|c| c.depth() + 1).max().unwrap_or(0)
+    }
+}
+
+/// Renders folded call stacks as a flame graph, in SVG.
+pub fn flamegraph(stacks: &BTreeMap<String, u64>, title: &str) -> String {
+    let mut root = Node::default();
+
+    for (stack, &n) in stacks {
+        root.samples += n;
+
+        let mut node = &mut root;
+
+        for frame in stack.split(';') {
+            node = node.children.entry(frame.to_owned()).or_insert_with(Node::default);
+            node.samples += n;
+        }
+    }
+
+    let height = (root.depth() + 3) * FRAME_HEIGHT;
+    let mut svg = String::new();
+
+    let _ = write!(svg, "<?xml version=\"1.0\" standalone=\"no\"?>\n\
+        <svg version=\"1.1\" width=\"{width}\" height=\"{height}\" \
+        xmlns=\"http://www.w3.org/2000/svg\" font-family=\"monospace\" font-size=\"12\">\n\
+        <rect x=\"0\" y=\"0\" width=\"{width}\" height=\"{height}\" fill=\"#eeeeee\"/>\n\
+        <text x=\"{center}\" y=\"{title_y}\" text-anchor=\"middle\" font-size=\"16\">{title}</text>\n",
+        width = WIDTH, height = height, center = WIDTH / 2.0,
+        title_y = FRAME_HEIGHT, title = escape(title));
+
+    if root.samples > 0 {
+        let scale = WIDTH / root.samples as f64;
+        let mut x = 0.0;
+
+        for (name, child) in &root.children {
+            render_frame(&mut svg, name, child, x, 0, height, scale);
+            x += child.samples as f64 * scale;
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Renders a frame, at the given horizontal position and depth, and the
+/// frames it called above it.
+/// `scale` is the width of a sample, in pixels.
+fn render_frame(svg: &mut String, name: &str, node: &Node, x: f64, depth: usize,
+        height: usize, scale: f64) {
+    let width = node.samples as f64 * scale;
+    let y = height - (depth + 2) * FRAME_HEIGHT;
+
+    let _ = write!(svg, "<g><title>{name} ({samples} samples, {percent:.2}%)</title>\
+        <rect x=\"{x:.1}\" y=\"{y}\" width=\"{width:.1}\" height=\"{h}\" fill=\"{color}\" \
+        stroke=\"#eeeeee\" stroke-width=\"0.5\"/>",
+        name = escape(name), samples = node.samples,
+        percent = width * 100.0 / WIDTH,
+        x = x, y = y, width = width, h = FRAME_HEIGHT - 1, color = color(name));
+
+    // Show as much of the name as fits, at roughly 7 pixels per character
+    let fits = ((width - 6.0) / 7.0).max(0.0) as usize;
+
+    if fits >= 3 {
+        let label = if name.chars().count() <= fits {
+            name.to_owned()
+        } else {
+            format!("{}..", name.chars().take(fits - 2).collect::<String>())
+        };
+
+        let _ = write!(svg, "<text x=\"{:.1}\" y=\"{}\">{}</text>",
+            x + 3.0, y + FRAME_HEIGHT - 4, escape(&label));
+    }
+
+    svg.push_str("</g>\n");
+
+    let mut child_x = x;
+
+    for (child_name, child) in &node.children {
+        render_frame(svg, child_name, child, child_x, depth + 1, height, scale);
+        child_x += child.samples as f64 * scale;
+    }
+}
+
+/// Returns a warm color for a function, derived from its name,
+/// so that each function keeps its color across flame graphs.
+fn color(name: &str) -> String {
+    let hash = name.bytes().fold(5381u32, |h, b| h.wrapping_mul(33) ^ b as u32);
+
+    format!("rgb({},{},{})", 205 + hash % 50, (hash >> 8) % 230, (hash >> 16) % 55)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{flamegraph, fold, hottest, strip_hash};
+
+    const SCRIPT: &'static str = "\
+exe 123 10.000001:    1001 cycles:u:
+\t    55d4c1 work::h0123456789abcdef+0x12 (/tmp/exe)
+\t    55d400 main+0x4 (/tmp/exe)
+
+exe 123 10.000002:    1001 cycles:u:
+\t    55d4c1 work::h0123456789abcdef+0x16 (/tmp/exe)
+\t    55d400 main+0x4 (/tmp/exe)
+
+exe 123 10.000003:    1001 cycles:u:
+\t    7f0000 [unknown] ([unknown])
+\t    55d400 main+0x8 (/tmp/exe)
+";
+
+    #[test]
+    fn test_fold() {
+        let stacks = fold(SCRIPT);
+
+        assert_eq!(stacks.into_iter().collect::<Vec<_>>(), [
+            ("main;[unknown]".to_owned(), 1),
+            ("main;work".to_owned(), 2),
+        ]);
+    }
+
+    #[test]
+    fn test_strip_hash() {
+        assert_eq!(strip_hash("std::rt::lang_start::h0123456789abcdef"), "std::rt::lang_start");
+        assert_eq!(strip_hash("main"), "main");
+    }
+
+    #[test]
+    fn test_hottest() {
+        assert_eq!(hottest(&fold(SCRIPT)), [("work".to_owned(), 2), ("[unknown]".to_owned(), 1)]);
+    }
+
+    #[test]
+    fn test_flamegraph() {
+        let mut stacks = BTreeMap::new();
+        stacks.insert("main;<T as Foo>::foo".to_owned(), 3);
+
+        let svg = flamegraph(&stacks, "profile");
+
+        assert!(svg.contains("<title>main (3 samples, 100.00%)</title>"));
+        assert!(svg.contains("&lt;T as Foo&gt;::foo"));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Prompt templates.
+//!
+//! A template is shown as written, except for these placeholders:
+//!
+//! * `{n}`, the history number of the input being read
+//! * `{mode}`, which is `=` when reading new input, `.` when reading further
+//!   lines of incomplete input, and `+` within a `.block` command
+//! * `{toolchain}`, the name of the sysroot directory, such as a rustup
+//!   toolchain name
+//! * `{target}`, the target triple input is compiled for, or `host`
+//! * `{session}`, the name of the current session; see `.session`
+
+/// Template of the default prompt
+pub const DEFAULT_PROMPT: &'static str = "rusti{mode}> ";
+
+/// State of input being read
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// New input
+    New,
+    /// Further lines of incomplete input
+    More,
+    /// Lines of a `.block` command
+    Block,
+}
+
+impl Mode {
+    fn indicator(&self) -> &'static str {
+        match *self {
+            Mode::New => "=",
+            Mode::More => ".",
+            Mode::Block => "+",
+        }
+    }
+}
+
+/// Values of placeholders in a prompt template
+#[derive(Clone, Debug)]
+pub struct Context<'a> {
+    /// History number of the input being read
+    pub n: usize,
+    /// State of input being read
+    pub mode: Mode,
+    /// Name of the toolchain
+    pub toolchain: &'a str,
+    /// Target triple, or `host`
+    pub target: &'a str,
+    /// Name of the current session
+    pub session: &'a str,
+}
+
+/// Returns the prompt for a template.
+/// Unknown placeholders are shown as written.
+pub fn render(template: &str, cx: &Context) -> String {
+    let mut res = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        match &rest[1..end] {
+            "n" => res.push_str(&cx.n.to_string()),
+            "mode" => res.push_str(cx.mode.indicator()),
+            "toolchain" => res.push_str(cx.toolchain),
+            "target" => res.push_str(cx.target),
+            "session" => res.push_str(cx.session),
+            _ => res.push_str(&rest[..end + 1]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    res.push_str(rest);
+    res
+}
+
+/// Returns the template set by the value of `.set prompt`, or the default
+/// template if no value is given. Quotes preserve leading or trailing spaces.
+pub fn parse_template(value: Option<&str>) -> String {
+    let value = value.unwrap_or(DEFAULT_PROMPT);
+    let quoted = value.len() >= 2 && value.starts_with('"') && value.ends_with('"');
+
+    if quoted {
+        value[1..value.len() - 1].to_owned()
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_template, render, Context, Mode, DEFAULT_PROMPT};
+
+    #[test]
+    fn test_render() {
+        let mut cx = Context{
+            n: 3,
+            mode: Mode::New,
+            toolchain: "nightly",
+            target: "host",
+            session: "main",
+        };
+
+        assert_eq!(render(DEFAULT_PROMPT, &cx), "rusti=> ");
+        assert_eq!(render("rusti[{n}]{mode} {x} {", &cx), "rusti[3]= {x} {");
+
+        cx.mode = Mode::More;
+        assert_eq!(render("{toolchain}{mode}> ", &cx), "nightly.> ");
+        assert_eq!(render("{session}{mode}> ", &cx), "main.> ");
+    }
+
+    #[test]
+    fn test_parse_template() {
+        assert_eq!(parse_template(None), DEFAULT_PROMPT);
+        assert_eq!(parse_template(Some("[{n}]>")), "[{n}]>");
+        assert_eq!(parse_template(Some("\"{n} \"")), "{n} ");
+        assert_eq!(parse_template(Some("\"")), "\"");
+    }
+}
@@ -8,32 +8,81 @@
 
 //! Runs Rust code in an encapsulated environment
 
-use std::env::args;
-use std::fs::File;
-use std::mem::transmute;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::env::{args, current_dir, home_dir, remove_var, set_current_dir, set_var, var_os,
+    vars_os};
+use std::fs::{metadata, remove_dir, File};
+use std::io::{self, stdin, stdout, Read, Write};
+use std::mem;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
+use rustc::session::config::{host_triple, OptLevel};
+use rustc::lint;
 use rustc::ty;
+use rustc_llvm as llvm;
+
+use serialize::json::Json;
 
 use syntax::{ast, codemap};
 use syntax::ast::StmtKind;
+use syntax::errors::ColorConfig;
 use syntax::visit::{self, FnKind};
 
-use exec::ExecutionEngine;
-use input::{parse_command, parse_program};
-use input::{FileReader, Input, InputReader};
-use input::InputResult::{Command, Empty, Eof, InputError, More, Program};
-
-/// Starting prompt
-const DEFAULT_PROMPT: &'static str = "rusti=> ";
-/// Prompt when further input is being read
-const MORE_PROMPT: &'static str = "rusti.> ";
-/// Prompt when a `.block` command is in effect
-const BLOCK_PROMPT: &'static str = "rusti+> ";
+use tempfile::NamedTempFile;
 
-// TODO: Implement commands:
-//     def <name>; shows the definition of type or fn
-//     doc <name>; links to rustdoc page for name
+use analysis::AnalysisCache;
+use artifacts::{self, Artifact, Collected};
+use base;
+use batch;
+use backtrace;
+use bench::{self, Stats};
+use capture::{self, capture, capture_tty, tee};
+use clipboard;
+use clippy;
+use config::Config;
+use deps;
+use display::{self, DisplayData};
+use docs;
+use editor;
+use entry::{EntryPoint, EXIT_PANIC};
+use exec::{explain_error, opt_level_name, parse_opt_level, parse_sanitizer, parse_verbosity,
+    parse_warnings, print_target_features, sanitizer_name, supported_targets, target_features,
+    verbosity_name, warnings_name, ExecOptions, ExecutionEngine, ItemDoc, TypeInfo, Verbosity,
+    Warnings};
+use exercise::Exercise;
+use finder;
+use fix::{self, Replacement};
+use format::{truncate, Formatters};
+use graphics::Protocol;
+use highlight::{highlight, use_color};
+use hints;
+use history::History;
+use hole;
+use input::{is_command, parse_command, parse_program};
+use input::{FileReader, Input, InputReader, KeyBindings};
+use input::InputResult::{Command, Empty, Eof, InputError, More, Program};
+use markdown;
+use notebook;
+use pager;
+use phases::format_millis;
+use profile;
+use prompt::{self, Mode};
+use results::{self, Results};
+use runner::Runner;
+use sandbox::Policy;
+use rustfmt;
+use search::{self, Crate};
+use source;
+use transcript;
+use verify;
 
 /// Describes what type of arguments, if any, a command may accept.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -46,6 +95,10 @@ pub enum CmdArgs {
     Text,
     /// A Rust expression is required
     Expr,
+    /// Optional names of lints, separated by commas or spaces
+    Lints,
+    /// Optional name of a command
+    Command,
 }
 
 /// Represents a REPL command definition
@@ -59,53 +112,639 @@ pub struct CommandDef {
 
 /// List of commands
 static COMMANDS: &'static [CommandDef] = &[
+    CommandDef {
+        name: "!",
+        args: Some("<command>"),
+        accepts: CmdArgs::Text,
+        help: "Run a shell command",
+    },
+    CommandDef {
+        name: "args",
+        args: Some("[arguments]"),
+        accepts: CmdArgs::Text,
+        help: "Set the arguments seen by code in `std::env::args`",
+    },
+    CommandDef {
+        name: "allow",
+        args: Some("[lints]"),
+        accepts: CmdArgs::Lints,
+        help: "Allow lints, as `-A`, or list those allowed",
+    },
     CommandDef {
         name: "block",
         args: None,
         accepts: CmdArgs::Nothing,
         help: "Run a multi-line block of code, terminated by `.`",
     },
+    CommandDef {
+        name: "bench",
+        args: Some("<expr>"),
+        accepts: CmdArgs::Expr,
+        help: "Measure the time taken to evaluate expr, with optimizations",
+    },
+    CommandDef {
+        name: "check",
+        args: Some("<code>"),
+        accepts: CmdArgs::Expr,
+        help: "Type-check code without running it",
+    },
+    CommandDef {
+        name: "checkpoint",
+        args: Some("[name]"),
+        accepts: CmdArgs::Text,
+        help: "Save the items of the session as a checkpoint, or list checkpoints",
+    },
+    CommandDef {
+        name: "cd",
+        args: Some("[directory]"),
+        accepts: CmdArgs::Filename,
+        help: "Change the working directory of the session",
+    },
+    CommandDef {
+        name: "clippy",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Run clippy lints over items defined in the session",
+    },
+    CommandDef {
+        name: "copy",
+        args: Some("[input | output | <n>]"),
+        accepts: CmdArgs::Text,
+        help: "Copy the last input, its output, or input number n to the clipboard",
+    },
+    CommandDef {
+        name: "deny",
+        args: Some("[lints]"),
+        accepts: CmdArgs::Lints,
+        help: "Make lints errors, as `-D`, or list those denied",
+    },
+    CommandDef {
+        name: "doc",
+        args: Some("[--open] <path>"),
+        accepts: CmdArgs::Text,
+        help: "Show the documentation of an item, or open it in the browser",
+    },
+    CommandDef {
+        name: "edit",
+        args: Some("[n]"),
+        accepts: CmdArgs::Text,
+        help: "Edit new input, or input n from history, in $EDITOR and run it",
+    },
+    CommandDef {
+        name: "env",
+        args: Some("[name[=value] | -u name]"),
+        accepts: CmdArgs::Text,
+        help: "Show, set or unset environment variables of the session",
+    },
+    CommandDef {
+        name: "explain",
+        args: Some("<code>"),
+        accepts: CmdArgs::Text,
+        help: "Show the explanation of a compiler error code, such as E0308",
+    },
+    CommandDef {
+        name: "export-ipynb",
+        args: Some("<filename>"),
+        accepts: CmdArgs::Filename,
+        help: "Write the session's input and output to a Jupyter notebook",
+    },
+    CommandDef {
+        name: "export-md",
+        args: Some("<filename>"),
+        accepts: CmdArgs::Filename,
+        help: "Write the session's input and output to a Markdown file",
+    },
+    CommandDef {
+        name: "exercise",
+        args: Some("[name]"),
+        accepts: CmdArgs::Text,
+        help: "Show the current exercise, or select one by name",
+    },
+    CommandDef {
+        name: "format",
+        args: Some("[type [formatter]]"),
+        accepts: CmdArgs::Text,
+        help: "Show formatters or set the formatter used to print values of type",
+    },
+    CommandDef {
+        name: "fix",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Apply compiler suggestions to the last input which failed to compile",
+    },
+    CommandDef {
+        name: "fmt",
+        args: Some("[n]"),
+        accepts: CmdArgs::Text,
+        help: "Format the last input, or input n from history, using rustfmt",
+    },
+    CommandDef {
+        name: "fork",
+        args: Some("<name>"),
+        accepts: CmdArgs::Text,
+        help: "Create a session with the items of this one and switch to it",
+    },
+    CommandDef {
+        name: "gc",
+        args: Some("[hours]"),
+        accepts: CmdArgs::Text,
+        help: "Remove artifacts of past sessions unused for the given number of hours",
+    },
     CommandDef {
         name: "help",
         args: Some("[command]"),
-        accepts: CmdArgs::Text,
+        accepts: CmdArgs::Command,
         help: "Show help for commands",
     },
+    CommandDef {
+        name: "history",
+        args: Some("[--values] [pattern]"),
+        accepts: CmdArgs::Text,
+        help: "Show previous input, optionally only that containing pattern or with values",
+    },
+    CommandDef {
+        name: "impls",
+        args: Some("<type>"),
+        accepts: CmdArgs::Text,
+        help: "Show the inherent and trait impls which apply to a type",
+    },
+    CommandDef {
+        name: "import",
+        args: Some("<session>"),
+        accepts: CmdArgs::Text,
+        help: "Add the items and dependencies of another session to this one",
+    },
+    CommandDef {
+        name: "jobs",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Show code running in the background",
+    },
+    CommandDef {
+        name: "kill",
+        args: Some("<n>"),
+        accepts: CmdArgs::Text,
+        help: "Stop background job n",
+    },
     CommandDef {
         name: "load",
         args: Some("<filename>"),
         accepts: CmdArgs::Filename,
         help: "Evaluate a file's contents as input",
     },
+    CommandDef {
+        name: "layout",
+        args: Some("<type>"),
+        accepts: CmdArgs::Text,
+        help: "Show the size, alignment and field offsets of a type",
+    },
+    CommandDef {
+        name: "methods",
+        args: Some("<type | binding>"),
+        accepts: CmdArgs::Text,
+        help: "Show the methods which may be called on a type or binding",
+    },
+    CommandDef {
+        name: "mono",
+        args: Some("[n]"),
+        accepts: CmdArgs::Text,
+        help: "Show generic functions instantiated by the last input, or input n",
+    },
     CommandDef {
         name: "print",
         args: Some("<expr>"),
         accepts: CmdArgs::Expr,
         help: "Print expression using fmt::Display",
     },
+    CommandDef {
+        name: "paste-eval",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Evaluate code from the clipboard",
+    },
+    CommandDef {
+        name: "profile",
+        args: Some("<expr>"),
+        accepts: CmdArgs::Expr,
+        help: "Profile repeated evaluation of expr, writing a flame graph",
+    },
+    CommandDef {
+        name: "pwd",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Show the working directory of the session",
+    },
+    CommandDef {
+        name: "quit",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Exit rusti, dropping the values of previous input expressions",
+    },
+    CommandDef {
+        name: "reset",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Forget all items defined in the session",
+    },
+    CommandDef {
+        name: "rollback",
+        args: Some("<name>"),
+        accepts: CmdArgs::Text,
+        help: "Restore the items of the session saved by a checkpoint",
+    },
+    CommandDef {
+        name: "set",
+        args: Some("[name [value]]"),
+        accepts: CmdArgs::Text,
+        help: "Show settings or change the value of a setting",
+    },
+    CommandDef {
+        name: "search",
+        args: Some("<query> | <n>"),
+        accepts: CmdArgs::Text,
+        help: "Search crates.io, or add a result of the last search as a dependency",
+    },
+    CommandDef {
+        name: "session",
+        args: Some("[list | new <name> | switch <name>]"),
+        accepts: CmdArgs::Text,
+        help: "Show, create or switch between named sessions",
+    },
+    CommandDef {
+        name: "show-full",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Show the last value without truncation",
+    },
+    CommandDef {
+        name: "source",
+        args: Some("[n]"),
+        accepts: CmdArgs::Text,
+        help: "Show the program compiled for input n, or the last input",
+    },
+    CommandDef {
+        name: "spawn",
+        args: Some("<code>"),
+        accepts: CmdArgs::Expr,
+        help: "Run code in the background, as a job",
+    },
+    CommandDef {
+        name: "src",
+        args: Some("<path>"),
+        accepts: CmdArgs::Text,
+        help: "Show the source of an item",
+    },
+    CommandDef {
+        name: "stats",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Show statistics about the session",
+    },
+    CommandDef {
+        name: "stdin",
+        args: Some("<text> | <<terminator | --tty"),
+        accepts: CmdArgs::Text,
+        help: "Supply text, the following lines up to terminator, or the terminal alone, \
+            as stdin of the next input",
+    },
+    CommandDef {
+        name: "submit",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Check the solution to the current exercise",
+    },
     CommandDef {
         name: "type",
         args: Some("<expr>"),
         accepts: CmdArgs::Expr,
         help: "Show the type of expr",
     },
+    CommandDef {
+        name: "test",
+        args: Some("[filter]"),
+        accepts: CmdArgs::Text,
+        help: "Run #[test] functions, optionally only those whose names contain filter",
+    },
+    CommandDef {
+        name: "targets",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "List target triples supported by the compiler",
+    },
+    CommandDef {
+        name: "target-features",
+        args: None,
+        accepts: CmdArgs::Nothing,
+        help: "Show features of the current target, and which are enabled",
+    },
+    CommandDef {
+        name: "watch",
+        args: Some("<filename> [function]"),
+        accepts: CmdArgs::Filename,
+        help: "Load a file, and call function, again each time the file changes",
+    },
+    CommandDef {
+        name: "warn",
+        args: Some("[lints]"),
+        accepts: CmdArgs::Lints,
+        help: "Make lints warnings, as `-W`, or list those warned of",
+    },
+    CommandDef {
+        name: "why-impl",
+        args: Some("<expr>"),
+        accepts: CmdArgs::Expr,
+        help: "Explain which impl is selected for a method call or operator",
+    },
 ];
 
+/// Interval, in milliseconds, at which a watched file is checked for changes
+const WATCH_INTERVAL_MS: u64 = 250;
+
+/// Prefix of the paths of items defined by the session, as named in symbols
+const CRATE_PATH: &'static str = "repl::";
+
+/// Attribute preceding code of input when unsafe code is forbidden
+const FORBID_UNSAFE: &'static str = "#[forbid(unsafe_code)] ";
+
+/// File to which `.profile` writes its flame graph
+const PROFILE_FILE: &'static str = "rusti-profile.svg";
+
+/// Number of most sampled functions listed by `.profile`
+const PROFILE_HOTTEST: usize = 5;
+
+/// Number of codegen units into which the input of a file run by `.load`
+/// is split, when optimized
+const LOAD_CODEGEN_UNITS: usize = 16;
+
+/// Describes the result of evaluating a single round of input
+#[derive(Clone, Debug, Default)]
+pub struct EvalResult {
+    /// Whether the input compiled successfully
+    pub compiled: bool,
+    /// Whether the compiled input was run
+    pub executed: bool,
+    /// Output written to `stdout`, if it was captured
+    pub stdout: String,
+    /// Output written to `stderr`, including diagnostics, if it was captured
+    pub stderr: String,
+    /// Time spent compiling input
+    pub compile_time: Duration,
+    /// Time spent running compiled input
+    pub run_time: Duration,
+    /// Formatted value of the input expression, if it had one and it was
+    /// handed back from compiled code
+    pub value: Option<String>,
+    /// Display data written to `stdout`, if it was captured
+    pub display: Vec<DisplayData>,
+    /// Whether compilation was cancelled before it finished
+    pub cancelled: bool,
+    /// Whether the compiled input panicked or, if it was run in a process of
+    /// its own, exited unsuccessfully
+    pub failed: bool,
+}
+
+/// Attributes and items defined by a single input
+#[derive(Clone, Debug, Default)]
+struct Snippet {
+    /// History entry of the input, if it was entered interactively
+    entry: Option<usize>,
+    /// Module-level attributes
+    attributes: Vec<String>,
+    /// Module-level view items
+    view_items: Vec<String>,
+    /// Module-level items
+    items: Vec<String>,
+}
+
+impl Snippet {
+    /// Returns input defining the attributes and items of the snippet,
+    /// without any statements.
+    fn to_input(&self) -> Input {
+        let mut input = Input::new();
+
+        input.attributes = self.attributes.clone();
+        input.view_items = self.view_items.clone();
+        input.items = self.items.clone();
+        input
+    }
+
+    /// Returns the names of the items defined by the snippet.
+    fn item_names(&self) -> Vec<&str> {
+        self.items.iter().filter_map(|item| finder::item_name(item)).collect()
+    }
+
+    /// Returns a short description of the snippet, for messages.
+    fn describe(&self) -> String {
+        match self.entry {
+            Some(n) => format!("input {}", n),
+            None => {
+                let first = self.items.iter()
+                    .chain(self.view_items.iter())
+                    .chain(self.attributes.iter())
+                    .next()
+                    .and_then(|s| s.lines().next())
+                    .unwrap_or("");
+
+                format!("`{}`", first.trim())
+            }
+        }
+    }
+}
+
+/// State of a named session, other than the current one; see `.session`
+#[derive(Default)]
+struct Session {
+    snippets: Vec<Snippet>,
+    results: Results,
+    formatters: Formatters,
+    failed: Option<Input>,
+    /// Directories containing dependencies built for the session
+    deps: Vec<String>,
+    /// Directory in which artifacts are written, if not the temporary directory
+    artifact_dir: Option<PathBuf>,
+    checkpoints: BTreeMap<String, Checkpoint>,
+}
+
+/// State of a session saved by `.checkpoint`
+#[derive(Clone)]
+struct Checkpoint {
+    snippets: Vec<Snippet>,
+    formatters: Formatters,
+    /// Addresses of the values kept at the time
+    results: Vec<usize>,
+    /// Directories containing dependencies built for the session
+    deps: Vec<String>,
+}
+
+/// Name of the session in which rusti starts
+const MAIN_SESSION: &'static str = "main";
+
+/// Code running in the background, in a process of its own
+struct Job {
+    /// Number of the job, shown to the user
+    id: usize,
+    /// First line of the job's code
+    code: String,
+    child: process::Child,
+    /// Executable run by the job, removed when the job is dropped
+    _exe: Artifact,
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        // Jobs don't outlive the session
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Standard input of the next input, as set by `.stdin`
+#[derive(Clone, Debug)]
+enum Stdin {
+    /// Text, after which `stdin` reaches its end
+    Text(String),
+    /// The terminal, which only the input reads until it returns
+    Terminal,
+}
+
 /// Executes input code and maintains state of persistent items.
 pub struct Repl {
     /// First entry of `env::args`
     argv0: String,
     engine: ExecutionEngine,
-    /// Module-level attributes applied to every program
-    attributes: Vec<String>,
-    /// View items compiled into every program
-    view_items: Vec<String>,
-    /// Items compiled into every program
+    /// Results of analysis of the current state of the session
+    analysis: AnalysisCache,
+    /// Attributes and items compiled into every program, grouped by the
+    /// input which defined them
     /// TODO: When type/def-injection is implemented,
     /// it will not be necessary to re-compile all functions on every input.
-    items: Vec<String>,
+    snippets: Vec<Snippet>,
+    /// History entry of the input being run, if it was entered interactively
+    entry: Option<usize>,
     /// true if the next input should be a block
     read_block: bool,
+    /// true if input should be type-checked, but not run
+    check_only: bool,
+    /// true if unsafe code in input, and crates exposing foreign functions,
+    /// are forbidden
+    deny_unsafe: bool,
+    /// If set, input is compiled into an executable and run by this runner
+    runner: Option<Runner>,
+    /// true if input, and builds of dependencies, are run in a sandbox
+    sandboxed: bool,
+    /// true if crates.io may not be searched by `.search`
+    offline: bool,
+    /// Crates found by the last `.search`
+    search_results: Vec<Crate>,
+    /// true if compiled code should be unloaded on `.reset`
+    unload_on_reset: bool,
+    /// Number of inputs run; used to give each a unique entry point
+    inputs: usize,
+    /// Formatters used to print the values of input expressions
+    formatters: Formatters,
+    /// Maximum number of characters printed for a value
+    max_output: Option<usize>,
+    /// Untruncated value of the last input expression
+    full_value: Option<String>,
+    /// true if long values are shown through a pager
+    paging: bool,
+    /// Pager command; if `None`, `PAGER` or a default is used
+    pager: Option<String>,
+    /// If set, images in display data are rendered using this protocol
+    graphics: Option<Protocol>,
+    /// Previous complete inputs
+    history: History,
+    /// Values of previous input expressions
+    results: Results,
+    /// Template of the prompt shown when reading input
+    prompt: String,
+    /// Key sequences bound to line editor commands
+    key_bindings: Vec<(String, String)>,
+    /// Key bindings of the line editor, Emacs-style or vi-style
+    line_editing: KeyBindings,
+    /// Arguments passed to input run as an executable
+    program_args: Vec<String>,
+    /// true if captured output is written to pseudo-terminals
+    pty: bool,
+    /// Standard input of the next input, if not shared with rusti
+    stdin: Option<Stdin>,
+    /// If set, the following lines up to this terminator are read as
+    /// the `stdin` of the next input
+    read_heredoc: Option<String>,
+    /// Last input which failed to compile
+    failed: Option<Input>,
+    /// Exercises worked through, as by `--exercise`
+    exercises: Vec<Exercise>,
+    /// Index of the current exercise
+    exercise: usize,
+    /// true if imports suggested for unresolved names are added automatically
+    auto_import: bool,
+    /// true if hints are shown for errors which commonly trouble newcomers
+    show_hints: bool,
+    /// true if the inferred types of variables bound by input are shown
+    show_types: bool,
+    /// Code running in the background
+    jobs: Vec<Job>,
+    /// Number of jobs started
+    started_jobs: usize,
+    /// true if input of `.block` commands is checked as it is entered
+    speculate: bool,
+    /// Diagnostics of the check of an incomplete `.block` in progress
+    speculation: Option<Receiver<String>>,
+    /// Last error reported while checking an incomplete `.block`
+    speculated_error: Option<String>,
+    /// true if executables and intermediate files are kept for debugging
+    keep_artifacts: bool,
+    /// Age, in hours, beyond which unused artifacts are removed by `.gc`
+    artifact_max_age: u64,
+    /// true if the session should end after the current input
+    quit: bool,
+    /// Input entered interactively, and its output, for `.export-md`
+    transcript: Vec<transcript::Entry>,
+    /// true if output of input is captured for the transcript
+    recording: bool,
+    /// Output of the current input, captured for the transcript
+    recorded_output: String,
+    /// Name of the current session
+    session: String,
+    /// Sessions other than the current one, by name
+    sessions: BTreeMap<String, Session>,
+    /// Directories containing dependencies built for the current session
+    deps: Vec<String>,
+    /// Directory in which the current session writes artifacts,
+    /// if not the temporary directory
+    artifact_dir: Option<PathBuf>,
+    /// Code run at the start of each session created by `.session new`
+    prelude: Option<String>,
+    /// Checkpoints of the current session, by name
+    checkpoints: BTreeMap<String, Checkpoint>,
+    /// Program last compiled for each input entered interactively,
+    /// by history entry
+    sources: BTreeMap<usize, String>,
+}
+
+impl Drop for Repl {
+    fn drop(&mut self) {
+        // Values are dropped before the execution engine unloads the code
+        // of their destructors, those of suspended sessions as well as the
+        // current one, as all sessions share the execution engine.
+        unsafe { self.results.clear() };
+
+        for session in self.sessions.values_mut() {
+            unsafe { session.results.clear() };
+        }
+
+        // Running jobs are killed
+        self.jobs.clear();
+
+        // Directories of sessions are removed, unless artifacts are kept in them
+        let dirs = self.sessions.values().filter_map(|s| s.artifact_dir.as_ref())
+            .chain(self.artifact_dir.as_ref());
+
+        for dir in dirs {
+            let _ = remove_dir(dir);
+        }
+    }
 }
 
 /// Looks up a command name by what may be an abbreviated prefix.
@@ -131,58 +770,562 @@ where
     }
 }
 
-impl Repl {
-    /// Constructs a new `Repl`.
-    pub fn new(sysroot: Option<PathBuf>) -> Repl {
-        Repl::new_with_libs(Vec::new(), sysroot)
+/// Returns the message of the first error among diagnostics emitted as JSON,
+/// one per line.
+fn first_error(diagnostics: &str) -> Option<String> {
+    diagnostics.lines()
+        .filter_map(|line| Json::from_str(line).ok())
+        .find(|diag| diag.find("level").and_then(|l| l.as_string()) == Some("error"))
+        .and_then(|diag| diag.find("message").and_then(|m| m.as_string())
+            .map(|m| format!("error: {}", m)))
+}
+
+/// Parses the value of an on/off setting.
+fn parse_bool(s: &str) -> Option<bool> {
+    match s {
+        "on" | "true" | "yes" => Some(true),
+        "off" | "false" | "no" => Some(false),
+        _ => None,
     }
+}
 
-    /// Constructs a new `Repl` with additional library lookup paths.
-    pub fn new_with_libs(libs: Vec<String>, sysroot: Option<PathBuf>) -> Repl {
-        let argv0 = args().next().unwrap_or_else(|| "rusti".to_owned());
+/// Returns the name of the crate imported by an `extern crate` item.
+fn extern_crate_name(item: &str) -> Option<&str> {
+    let rest = &item[item.find("extern crate ")? + "extern crate ".len()..];
 
-        Repl {
-            argv0: argv0,
-            engine: ExecutionEngine::new(libs, sysroot),
-            attributes: Vec::new(),
-            view_items: Vec::new(),
-            items: Vec::new(),
-            read_block: false,
-        }
-    }
+    rest.split(|c: char| c.is_whitespace() || c == ';').find(|s| !s.is_empty())
+}
 
-    /// Evaluates a single round of input, printing the result to `stdout`.
-    pub fn eval(&mut self, input: &str) {
-        if let Program(i) = parse_program(input, false, None) {
-            self.handle_input(i, false);
+/// Returns whether a string is a path, such as the name of a function.
+fn is_path(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Appends one section of a program, consisting of the attributes or items
+/// selected from each snippet, each preceded by `prefix`, recording the range
+/// of each in `layout`.
+fn push_section<'a, F>(prog: &mut String, layout: &mut Vec<(Range<usize>, usize)>,
+        snippets: &[&'a Snippet], prefix: &str, f: F)
+        where F: Fn(&'a Snippet) -> &'a Vec<String> {
+    let mut first = true;
+
+    for (i, snippet) in snippets.iter().enumerate() {
+        for text in f(*snippet) {
+            if !first {
+                prog.push('\n');
+            }
+            first = false;
+
+            let start = prog.len();
+            prog.push_str(prefix);
+            prog.push_str(text);
+            layout.push((start..prog.len(), i));
         }
     }
 
-    /// Runs the REPL interactively.
-    pub fn run(&mut self) {
-        let mut more = false;
-        let mut input = InputReader::new();
+    prog.push('\n');
+}
 
-        loop {
-            let res = if self.read_block {
-                self.read_block = false;
-                input.read_block_input(BLOCK_PROMPT)
-            } else {
-                input.read_input(if more { MORE_PROMPT } else { DEFAULT_PROMPT })
-            };
+/// Returns whether the items or statements of input contain a hole.
+fn has_holes(input: &Input) -> bool {
+    input.items.iter().chain(&input.statements).any(|code| hole::contains(code))
+}
+
+/// Returns the index of the snippet which defined the code at an offset
+/// within a program, given the program's layout.
+fn snippet_at(layout: &[(Range<usize>, usize)], offset: usize) -> Option<usize> {
+    layout.iter()
+        .find(|&&(ref range, _)| range.start <= offset && offset < range.end)
+        .map(|&(_, i)| i)
+}
+
+/// Normalizes an error code, given with or without its leading `E`
+/// and leading zeros; e.g. `e308` becomes `E0308`.
+fn error_code(s: &str) -> Option<String> {
+    let digits = s.trim_left_matches(|c| c == 'E' || c == 'e');
+
+    if digits.is_empty() || digits.len() > 4 || s.len() - digits.len() > 1 ||
+            !digits.chars().all(|c| c.is_digit(10)) {
+        return None;
+    }
+
+    Some(format!("E{:0>4}", digits))
+}
+
+/// Splits program arguments at whitespace, except within single or
+/// double quotes, which are removed.
+fn split_args(s: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = None;
+    let mut quote = None;
+
+    for ch in s.chars() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(ch);
+                arg.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    args.extend(arg);
+    args
+}
+
+/// Returns whether `name` may name an environment variable: it must be
+/// non-empty and contain neither `=` nor NUL, or `std::env` panics.
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('=') && !name.contains('\0')
+}
+
+/// Returns the displayed value of an on/off setting.
+fn bool_name(b: bool) -> &'static str {
+    if b { "on" } else { "off" }
+}
+
+/// Asks a question, returning whether it was answered yes.
+/// Only an answer beginning with `y` is taken as yes.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = stdout().flush();
+
+    let mut answer = String::new();
+
+    match stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => {
+            println!();
+            false
+        }
+        Ok(_) => answer.trim_left().starts_with(|c| c == 'y' || c == 'Y'),
+    }
+}
+
+/// Reports kept values, by index, whose bindings were removed along with their types.
+fn report_unbound(unbound: &[usize]) {
+    for i in unbound {
+        println!("res{} is no longer bound, as its type was removed", i);
+    }
+}
+
+impl Repl {
+    /// Constructs a new `Repl`.
+    pub fn new(sysroot: Option<PathBuf>) -> Repl {
+        Repl::new_with_libs(Vec::new(), sysroot)
+    }
+
+    /// Constructs a new `Repl` with additional library lookup paths.
+    pub fn new_with_libs(libs: Vec<String>, sysroot: Option<PathBuf>) -> Repl {
+        let argv0 = args().next().unwrap_or_else(|| "rusti".to_owned());
+
+        Repl {
+            argv0: argv0,
+            engine: ExecutionEngine::new(libs, sysroot),
+            analysis: AnalysisCache::default(),
+            snippets: Vec::new(),
+            entry: None,
+            read_block: false,
+            check_only: false,
+            deny_unsafe: false,
+            runner: None,
+            sandboxed: false,
+            offline: false,
+            search_results: Vec::new(),
+            unload_on_reset: false,
+            inputs: 0,
+            formatters: Formatters::new(),
+            max_output: None,
+            full_value: None,
+            paging: true,
+            pager: None,
+            graphics: None,
+            history: History::new(),
+            results: Results::new(),
+            prompt: prompt::DEFAULT_PROMPT.to_owned(),
+            key_bindings: Vec::new(),
+            line_editing: KeyBindings::Emacs,
+            program_args: Vec::new(),
+            stdin: None,
+            read_heredoc: None,
+            pty: false,
+            failed: None,
+            exercises: Vec::new(),
+            exercise: 0,
+            auto_import: false,
+            show_hints: false,
+            show_types: false,
+            jobs: Vec::new(),
+            started_jobs: 0,
+            speculate: false,
+            speculation: None,
+            speculated_error: None,
+            keep_artifacts: false,
+            artifact_max_age: artifacts::DEFAULT_MAX_AGE_HOURS,
+            quit: false,
+            transcript: Vec::new(),
+            recording: false,
+            recorded_output: String::new(),
+            session: MAIN_SESSION.to_owned(),
+            sessions: BTreeMap::new(),
+            deps: Vec::new(),
+            artifact_dir: None,
+            prelude: None,
+            checkpoints: BTreeMap::new(),
+            sources: BTreeMap::new(),
+        }
+    }
+
+    /// Returns a mutable reference to the options used to compile input.
+    pub fn exec_options_mut(&mut self) -> &mut ExecOptions {
+        self.engine.options_mut()
+    }
+
+    /// Runs the configured prelude, if any, in the current session.
+    ///
+    /// If a base image has been built from the prelude, by `--build-base`,
+    /// its items are imported from the image rather than compiled anew,
+    /// and only its statements are run.
+    pub fn run_prelude(&mut self) {
+        let prelude = match self.prelude.clone() {
+            Some(prelude) => prelude,
+            None => return,
+        };
+
+        match base::find(&prelude, self.engine.options()) {
+            Some(paths) => {
+                {
+                    let lib_paths = &mut self.engine.options_mut().lib_paths;
+
+                    for path in paths {
+                        if !lib_paths.contains(&path) {
+                            lib_paths.push(path);
+                        }
+                    }
+                }
+
+                self.eval(&base::session_input(&prelude));
+            }
+            None => {
+                self.eval(&prelude);
+            }
+        }
+    }
+
+    /// Applies configuration read from a file.
+    /// The prelude is not run; see `eval`. It is run by sessions created later.
+    pub fn configure(&mut self, config: &Config) {
+        self.prelude = config.prelude.clone();
+
+        if let Some(ref prompt) = config.prompt {
+            self.prompt = prompt.clone();
+        }
+        if let Some(color) = config.color {
+            self.engine.options_mut().color = color;
+        }
+
+        if let Some(hours) = config.artifact_max_age {
+            self.artifact_max_age = hours;
+        }
+
+        // `RUSTI_TARGET_DIR` takes precedence over configuration
+        if let (&Some(ref dir), None) = (&config.target_dir, artifacts::env_target_dir()) {
+            self.engine.options_mut().target_dir = Some(PathBuf::from(dir));
+        }
+
+        self.engine.options_mut().lib_paths.extend(config.lib_paths.iter().cloned());
+        self.key_bindings.extend(config.key_bindings.iter().cloned());
+
+        for &(ref name, ref value) in &config.settings {
+            self.set_command(Some(&format!("{} {}", name, value)));
+        }
+    }
+
+    /// Sets the runner used to run input compiled into an executable.
+    /// If `None`, input is run within the `rusti` process.
+    pub fn set_runner(&mut self, runner: Option<Runner>) {
+        self.runner = runner;
+    }
+
+    /// Runs input, and builds of dependencies, in a sandbox, as `--sandbox`.
+    /// The runner cannot be changed after.
+    pub fn set_sandbox(&mut self, policy: Policy) {
+        self.runner = Some(Runner::Sandbox(policy));
+        self.sandboxed = true;
+    }
+
+    /// Returns a flag which, while it is set, cancels compilation of input.
+    /// Input which is cancelled is neither run nor added to the session.
+    ///
+    /// Only compilation of input loaded into this process can be cancelled.
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.engine.cancel_flag()
+    }
+
+    /// Returns whether `.quit` has been run, ending the session.
+    pub fn is_quitting(&self) -> bool {
+        self.quit
+    }
+
+    /// Sets whether executables built to run input, and intermediate files
+    /// written while building them, are kept after they are used.
+    pub fn set_keep_artifacts(&mut self, keep: bool) {
+        self.keep_artifacts = keep;
+        self.engine.options_mut().save_temps = keep;
+    }
+
+    /// Removes artifacts left behind by sessions which ended abruptly,
+    /// which have not been modified within the configured age.
+    pub fn collect_artifacts(&self) -> io::Result<Collected> {
+        artifacts::collect(Duration::from_secs(self.artifact_max_age.saturating_mul(3600)),
+            self.engine.options().target_dir.as_ref())
+    }
+
+    /// Sets whether input is only type-checked, rather than compiled and run.
+    pub fn set_check_only(&mut self, check_only: bool) {
+        self.check_only = check_only;
+    }
+
+    /// Sets whether unsafe code is forbidden in input, as `--deny-unsafe`.
+    /// Crates exposing foreign functions may then not be used either.
+    pub fn set_deny_unsafe(&mut self, deny_unsafe: bool) {
+        self.deny_unsafe = deny_unsafe;
+    }
+
+    /// Sets whether `.search` is disabled, as `--offline`.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// Evaluates a single round of input, printing the result to `stdout`.
+    pub fn eval(&mut self, input: &str) -> EvalResult {
+        match parse_program(input, false, None) {
+            Program(i) => self.handle_input(i, false),
+            InputError(Some(e)) => {
+                println!("{}: {}", self.argv0, e);
+                EvalResult::default()
+            }
+            _ => EvalResult::default(),
+        }
+    }
+
+    /// Evaluates a single round of input, capturing output written while it
+    /// is compiled and run, and returns the result rather than printing it.
+    pub fn evaluate(&mut self, input: &str) -> EvalResult {
+        // Diagnostics are not colored just because `stderr` is a terminal
+        let color = self.engine.options().color;
+        let pty = self.pty;
+
+        if let (true, ColorConfig::Auto) = (pty, color) {
+            self.engine.options_mut().color = ColorConfig::Never;
+        }
+
+        let res = {
+            let run = || match parse_program(input, false, None) {
+                Program(i) => self.run_input(i, false),
+                _ => EvalResult::default(),
+            };
+
+            if pty { capture_tty(run) } else { capture(run) }
+        };
+
+        self.engine.options_mut().color = color;
+
+        match res {
+            Ok((mut result, output)) => {
+                let (stdout, display) = display::extract(&output.stdout);
+
+                result.stdout = stdout;
+                result.stderr = output.stderr;
+                result.display = display;
+                result
+            }
+            Err(e) => EvalResult {
+                stderr: format!("failed to capture output: {}", e),
+                ..EvalResult::default()
+            },
+        }
+    }
+
+    /// Returns the inferred type of the innermost expression or pattern of
+    /// `code` containing the byte offset `pos`, as if `code` were evaluated.
+    /// Returns `None` if there is no such expression or `code` fails to compile.
+    pub fn type_at(&mut self, code: &str, pos: usize) -> Option<String> {
+        let prefix = "#[allow(unused_must_use, path_statements)] { ";
+        let stmts = format!("{}{}\n; }}\nNone", prefix, code);
+        let prog = self.input_program(&Input::new(), &EntryPoint::new(self.inputs),
+            &self.bindings(&Input::new(), code), &stmts);
+        let start = prog.rfind(&stmts[..]).unwrap_or(0) + prefix.len();
+
+        let state = self.analysis_state();
+        self.analysis.update(&state);
+
+        let engine = &self.engine;
+
+        self.analysis.type_at(code, pos, || {
+            // Diagnostics of incomplete code are of no interest
+            match capture(|| engine.type_at(prog, start + pos)) {
+                Ok((res, _)) => res.and_then(|ty| ty),
+                Err(_) => None,
+            }
+        })
+    }
+
+    /// Returns the layout, impls and methods of a type, which may refer to
+    /// items defined in the session. The type is analyzed only once while
+    /// the session is unchanged.
+    fn type_info(&mut self, ty: &str) -> Option<TypeInfo> {
+        let name = "_RustiType";
+        let prog = self.build_program(None, &format!(
+            "#[allow(dead_code)] type {} = {};", name, ty));
+
+        let state = self.analysis_state();
+        self.analysis.update(&state);
+
+        let engine = &self.engine;
+        self.analysis.type_info(ty, || engine.type_info(prog, name))
+    }
+
+    /// Returns a description of everything on which the analysis of input
+    /// depends: the items and bindings of the session, and compile options.
+    fn analysis_state(&self) -> String {
+        format!("{:?}\n{}", self.engine.options(), self.input_program(&Input::new(),
+            &EntryPoint::new(self.inputs), &self.bindings(&Input::new(), ""), ""))
+    }
+
+    /// Forgets all attributes and items defined by previous input,
+    /// and drops the values of previous input expressions.
+    ///
+    /// Compiled code remains loaded, as it may still be in use, unless the
+    /// `unload` setting is enabled.
+    pub fn reset(&mut self) {
+        self.snippets.clear();
+        self.formatters.clear_functions();
+
+        // Kept values may be of types defined by the forgotten items.
+        // The code which kept them is still loaded at this point.
+        unsafe { self.results.clear() };
+
+        if self.unload_on_reset {
+            self.engine.unload_modules();
+        }
+    }
+
+    /// Returns the lines among which the finder selects: the names of
+    /// crates and items defined in the session, then past input which fits
+    /// on one line, most recent first.
+    fn finder_candidates(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+
+        let names = self.snippets.iter()
+            .flat_map(|s| s.view_items.iter().chain(&s.items))
+            .filter_map(|item| extern_crate_name(item).or_else(|| finder::item_name(item)));
+
+        let entries = self.history.iter().collect::<Vec<_>>().into_iter().rev()
+            .map(|(_, entry)| entry)
+            .filter(|entry| !entry.contains('\n'));
+
+        names.chain(entries)
+            .filter(|line| seen.insert(*line))
+            .map(|line| line.to_owned())
+            .collect()
+    }
+
+    /// Runs the REPL interactively.
+    pub fn run(&mut self) {
+        let mut more = false;
+        let mut input = InputReader::new();
+
+        input.set_highlight(use_color(self.engine.options().color));
+
+        for &(ref seq, ref command) in &self.key_bindings {
+            input.bind_sequence(seq, command);
+        }
+
+        // History is only persisted for interactive sessions
+        if input.is_tty() {
+            if let Some(path) = History::default_path() {
+                match History::load(path) {
+                    Ok(history) => self.history = history,
+                    Err(e) => println!("{}: failed to load history: {}", self.argv0, e),
+                }
+            }
+
+            input.load_history(&self.history);
+        }
+
+        self.recording = true;
+
+        // `.quit` may have been run by a file before the session began
+        while !self.quit {
+            self.reap_jobs();
+
+            if input.is_tty() {
+                input.set_candidates(self.finder_candidates());
+                input.set_key_bindings(self.line_editing);
+            }
+
+            if let Some(terminator) = self.read_heredoc.take() {
+                let prompt = self.render_prompt(Mode::Block);
+                self.stdin = input.read_heredoc(&prompt, &terminator).map(Stdin::Text);
+                continue;
+            }
+
+            let res = if self.read_block {
+                self.read_block = false;
+
+                let prompt = self.render_prompt(Mode::Block);
+                let res = input.read_block_input(&prompt, &mut |code| self.speculate(code));
+
+                self.speculation = None;
+                self.speculated_error = None;
+                res
+            } else {
+                let prompt = self.render_prompt(if more { Mode::More } else { Mode::New });
+                input.read_input(&prompt, &self.history)
+            };
+
+            match res {
+                Command(..) | Program(_) => {
+                    if let Err(e) = self.history.push(input.last_input()) {
+                        println!("{}: failed to save history: {}", self.argv0, e);
+                    }
+                }
+                _ => (),
+            }
+
+            // Items are associated with the history entry of their input,
+            // so that they can be replaced by editing the entry
+            self.entry = match res {
+                Program(_) => Some(self.history.len()),
+                _ => None,
+            };
+
+            let time = SystemTime::now();
 
             match res {
                 Command(name, args) => {
                     debug!("read command: {} {:?}", name, args);
 
                     more = false;
+
+                    // Exporting the transcript again would overwrite its file
+                    let export = lookup_command(&name).map_or(false, |cmd| cmd.name.starts_with("export-"));
+
                     self.handle_command(name, args);
+
+                    if !export {
+                        self.record(time, input.last_input(), true, None);
+                    }
                 }
-                Program(input) => {
-                    debug!("read program: {:?}", input);
+                Program(code) => {
+                    debug!("read program: {:?}", code);
 
                     more = false;
-                    self.handle_input(input, false);
+                    let value = self.handle_input(code, false).value;
+                    self.record(time, input.last_input(), false, value);
                 }
                 Empty => (),
                 More => {
@@ -204,6 +1347,34 @@ impl Repl {
         }
     }
 
+    /// Adds an input to the transcript, along with its recorded output.
+    fn record(&mut self, time: SystemTime, input: &str, command: bool, value: Option<String>) {
+        self.transcript.push(transcript::Entry{
+            time: time,
+            input: input.to_owned(),
+            command: command,
+            output: mem::replace(&mut self.recorded_output, String::new()),
+            value: value,
+        });
+    }
+
+    /// Returns the prompt shown when reading input in the given mode.
+    fn render_prompt(&self, mode: Mode) -> String {
+        let opts = self.engine.options();
+
+        let toolchain = opts.sysroot.file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        prompt::render(&self.prompt, &prompt::Context{
+            n: self.history.len() + 1,
+            mode: mode,
+            toolchain: &toolchain,
+            target: opts.target.as_ref().map(|s| &s[..]).unwrap_or("host"),
+            session: &self.session,
+        })
+    }
+
     /// Runs a single `rusti` command.
     pub fn run_command(&mut self, cmd: &str) {
         match parse_command(cmd, false) {
@@ -224,8 +1395,31 @@ impl Repl {
             }
         };
 
+        if !self.build_deps(path) {
+            return false;
+        }
+
         let mut input = FileReader::new(f, path.to_path_buf());
 
+        // Codegen units of a file's items are optimized as parallel jobs.
+        // Unoptimized code would gain nothing from being split.
+        let units = self.engine.options().codegen_units;
+
+        if self.engine.options().opt_level != OptLevel::No {
+            let jobs = self.engine.options().jobs.unwrap_or(LOAD_CODEGEN_UNITS);
+            self.engine.options_mut().codegen_units = LOAD_CODEGEN_UNITS.min(jobs);
+        }
+
+        let res = self.run_file_input(&mut input);
+
+        self.engine.options_mut().codegen_units = units;
+
+        res
+    }
+
+    /// Evaluates the inputs and commands read from a file.
+    /// Returns `false` if one could not be read.
+    fn run_file_input(&mut self, input: &mut FileReader) -> bool {
         loop {
             if self.read_block {
                 println!(
@@ -235,6 +1429,11 @@ impl Repl {
                 return false;
             }
 
+            if self.read_heredoc.take().is_some() {
+                println!("{}: `.stdin <<` is not supported when running a file", self.argv0);
+                return false;
+            }
+
             let input = input.read_input();
 
             match input {
@@ -248,6 +1447,62 @@ impl Repl {
                 Eof => break,
                 _ => unreachable!(),
             }
+
+            if self.quit {
+                break;
+            }
+        }
+
+        true
+    }
+
+    /// Builds the dependencies declared by the manifest header of the named
+    /// file, if any, and adds them to the library search path.
+    /// Returns `false` if they could not be built.
+    fn build_deps(&mut self, path: &Path) -> bool {
+        match deps::read_manifest(path) {
+            Ok(Some(manifest)) => self.add_deps(&manifest, &path.display().to_string()),
+            Ok(None) => true,
+            Err(e) => {
+                println!("{}: failed to read {}: {}", self.argv0, path.display(), e);
+                false
+            }
+        }
+    }
+
+    /// Builds the dependencies declared by a Cargo manifest, described in
+    /// messages as those of `source`, and adds them to the library search path.
+    /// Returns `false` if they could not be built.
+    fn add_deps(&mut self, manifest: &str, source: &str) -> bool {
+        if self.deny_unsafe {
+            let ffi_crates = deps::crate_names(manifest).into_iter()
+                .filter(|name| deps::is_ffi_crate(name))
+                .collect::<Vec<_>>();
+
+            if !ffi_crates.is_empty() {
+                println!("{}: {} depends on crates which may not be used with --deny-unsafe: {}",
+                    self.argv0, source, ffi_crates.join(", "));
+                return false;
+            }
+        }
+
+        let dir = match deps::build(manifest, self.engine.options(), self.sandboxed) {
+            Ok(dir) => dir.to_string_lossy().into_owned(),
+            Err(e) => {
+                println!("{}: failed to build dependencies of {}:\n{}",
+                    self.argv0, source, e);
+                return false;
+            }
+        };
+
+        if !self.deps.contains(&dir) {
+            self.deps.push(dir.clone());
+        }
+
+        let lib_paths = &mut self.engine.options_mut().lib_paths;
+
+        if !lib_paths.contains(&dir) {
+            lib_paths.push(dir);
         }
 
         true
@@ -257,138 +1512,2716 @@ impl Repl {
     /// optionally, those from an `Input` instance. The `statements` field of
     /// `input` will be ignored.
     fn build_program(&self, input: Option<&Input>, program: &str) -> String {
-        let (attrs, vitems, items) = if let Some(input) = input {
-            let attrs = self.attributes
-                .iter()
-                .map(|s| &s[..])
-                .chain(input.attributes.iter().map(|s| &s[..]))
-                .collect::<Vec<_>>();
+        self.build_program_layout(input, program).0
+    }
 
-            let vitems = self.view_items
-                .iter()
-                .map(|s| &s[..])
-                .chain(input.view_items.iter().map(|s| &s[..]))
-                .collect::<Vec<_>>();
+    /// Builds a program as `build_program` does, also returning the byte range
+    /// of each attribute and item within the program, along with the index of
+    /// the snippet which defined it. Those from `input` are given the index
+    /// following the last snippet.
+    fn build_program_layout(&self, input: Option<&Input>, program: &str)
+            -> (String, Vec<(Range<usize>, usize)>) {
+        let input = input.map(|i| Snippet{
+            entry: None,
+            attributes: i.attributes.clone(),
+            view_items: i.view_items.clone(),
+            items: i.items.clone(),
+        });
 
-            let items = self.items
-                .iter()
-                .map(|s| &s[..])
-                .chain(input.items.iter().map(|s| &s[..]))
-                .collect::<Vec<_>>();
+        let snippets = self.snippets.iter().chain(input.iter()).collect::<Vec<_>>();
 
-            (attrs, vitems, items)
-        } else {
-            let attrs = self.attributes.iter().map(|s| &s[..]).collect::<Vec<_>>();
+        let mut prog = String::from("#![feature(core_intrinsics)]\n");
+        let mut layout = Vec::new();
+
+        if self.deny_unsafe {
+            prog.push_str("#![feature(stmt_expr_attributes)]\n");
+        }
 
-            let vitems = self.view_items.iter().map(|s| &s[..]).collect::<Vec<_>>();
+        prog.push_str(self.formatters.attributes());
+        prog.push('\n');
 
-            let items = self.items.iter().map(|s| &s[..]).collect::<Vec<_>>();
+        // The code generated by rusti is unsafe, so only that of input is
+        // forbidden to be. An item's lint level cannot be changed within it.
+        let item_prefix = if self.deny_unsafe { FORBID_UNSAFE } else { "" };
 
-            (attrs, vitems, items)
-        };
+        push_section(&mut prog, &mut layout, &snippets, "", |s| &s.attributes);
+        push_section(&mut prog, &mut layout, &snippets, "", |s| &s.view_items);
+        push_section(&mut prog, &mut layout, &snippets, item_prefix, |s| &s.items);
 
-        let attrs = attrs.join("\n");
-        let vitems = vitems.join("\n");
-        let items = items.join("\n");
+        prog.push_str(&self.formatters.items());
+        prog.push('\n');
+        prog.push_str(program);
+        prog.push('\n');
 
-        format!(
-            r#"#![allow(dead_code, unused_imports, unused_features)]
-{attrs}
-{vitems}
-{items}
-{program}
-"#,
-            attrs = attrs,
-            vitems = vitems,
-            items = items,
-            program = program
-        )
+        (prog, layout)
     }
 
     /// Runs a single command input.
     fn handle_command(&mut self, cmd: String, args: Option<String>) {
         match lookup_command(&cmd).map(|c| c.name) {
+            Some("!") => {
+                if let Some(args) = args {
+                    self.shell_command(&args);
+                } else {
+                    println!("command `!` expects a shell command");
+                }
+            }
+            Some("allow") => {
+                self.lint_command(lint::Allow, args.as_ref().map(|s| &s[..]).unwrap_or(""));
+            }
+            Some("deny") => {
+                self.lint_command(lint::Deny, args.as_ref().map(|s| &s[..]).unwrap_or(""));
+            }
+            Some("warn") => {
+                self.lint_command(lint::Warn, args.as_ref().map(|s| &s[..]).unwrap_or(""));
+            }
+            Some("args") => {
+                self.args_command(args.as_ref().map(|s| &s[..]).unwrap_or(""));
+            }
             Some("block") => {
                 self.read_block = true;
             }
-            Some("help") => {
-                self.help_command(args.as_ref().map(|s| &s[..]));
-            }
-            Some("load") => {
-                if let Some(name) = args {
-                    self.run_file(Path::new(&name));
+            Some("bench") => {
+                if let Some(args) = args {
+                    self.bench_command(&args);
                 } else {
-                    println!("command `load` expects a filename");
+                    println!("command `bench` expects an expression");
                 }
             }
-            Some("print") => {
+            Some("checkpoint") => {
+                self.checkpoint_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("check") => {
                 if let Some(args) = args {
-                    self.print_command(args);
+                    self.check_command(args);
                 } else {
-                    println!("command `print` expects an expression");
+                    println!("command `check` expects code");
                 }
             }
-            /*Some("type") => {
-                if let Some(args) = args {
-                    self.type_command(args);
+            Some("cd") => {
+                self.cd_command(args.as_ref().map(|s| s.trim()));
+            }
+            Some("clippy") => {
+                self.clippy_command();
+            }
+            Some("copy") => {
+                self.copy_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("doc") => {
+                self.doc_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("edit") => {
+                self.edit_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("env") => {
+                self.env_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("explain") => {
+                self.explain_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("export-ipynb") => {
+                if let Some(name) = args {
+                    self.export_notebook(Path::new(name.trim()));
                 } else {
-                    println!("command `type` expects an expression");
+                    println!("command `export-ipynb` expects a filename");
+                }
+            }
+            Some("export-md") => {
+                if let Some(name) = args {
+                    self.export_markdown(Path::new(name.trim()));
+                } else {
+                    println!("command `export-md` expects a filename");
+                }
+            }
+            Some("exercise") => {
+                self.exercise_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("format") => {
+                self.format_command(args.as_ref().map(|s| &s[..]));
+            }
+            Some("fix") => {
+                self.fix_command();
+            }
+            Some("fmt") => {
+                self.fmt_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("fork") => {
+                self.fork_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("gc") => {
+                self.gc_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("help") => {
+                self.help_command(args.as_ref().map(|s| &s[..]));
+            }
+            Some("history") => {
+                self.history_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("impls") => {
+                self.impls_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("import") => {
+                self.import_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("jobs") => {
+                self.jobs_command();
+            }
+            Some("kill") => {
+                self.kill_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("layout") => {
+                self.layout_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("load") => {
+                if let Some(name) = args {
+                    self.run_file(Path::new(&name));
+                } else {
+                    println!("command `load` expects a filename");
+                }
+            }
+            Some("methods") => {
+                self.methods_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("mono") => {
+                self.mono_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("paste-eval") => {
+                self.paste_eval_command();
+            }
+            Some("print") => {
+                if let Some(args) = args {
+                    self.print_command(args);
+                } else {
+                    println!("command `print` expects an expression");
+                }
+            }
+            Some("profile") => {
+                if let Some(args) = args {
+                    self.profile_command(&args);
+                } else {
+                    println!("command `profile` expects an expression");
+                }
+            }
+            Some("pwd") => {
+                match current_dir() {
+                    Ok(dir) => println!("{}", dir.display()),
+                    Err(e) => println!("{}: failed to get working directory: {}", self.argv0, e),
+                }
+            }
+            Some("quit") => {
+                self.quit = true;
+            }
+            Some("reset") => {
+                self.reset();
+            }
+            Some("rollback") => {
+                self.rollback_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("set") => {
+                self.set_command(args.as_ref().map(|s| &s[..]));
+            }
+            Some("search") => {
+                self.search_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("session") => {
+                self.session_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("source") => {
+                self.source_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("show-full") => {
+                match self.full_value {
+                    Some(ref value) => self.print_value(value),
+                    None => println!("no value to show"),
+                }
+            }
+            Some("spawn") => {
+                if let Some(args) = args {
+                    self.spawn_command(args);
+                } else {
+                    println!("command `spawn` expects code");
+                }
+            }
+            Some("src") => {
+                self.src_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("stats") => {
+                self.stats_command();
+            }
+            Some("stdin") => {
+                match args {
+                    Some(ref args) if args.starts_with("<<") => {
+                        let terminator = args[2..].trim();
+                        self.read_heredoc = Some(if terminator.is_empty() {
+                            ".".to_owned()
+                        } else {
+                            terminator.to_owned()
+                        });
+                    }
+                    Some(ref args) if args == "--tty" => self.stdin = Some(Stdin::Terminal),
+                    Some(args) => self.stdin = Some(Stdin::Text(format!("{}\n", args))),
+                    None => self.stdin = None,
+                }
+            }
+            Some("submit") => {
+                self.submit_command();
+            }
+            /*Some("type") => {
+                if let Some(args) = args {
+                    self.type_command(args);
+                } else {
+                    println!("command `type` expects an expression");
                 }
             },*/
+            Some("test") => {
+                self.test_command(args.as_ref().map(|s| s.trim()).unwrap_or(""));
+            }
+            Some("targets") => {
+                self.targets_command();
+            }
+            Some("target-features") => {
+                self.target_features_command();
+            }
+            Some("watch") => {
+                self.watch_command(args.as_ref().map(|s| &s[..]).unwrap_or(""));
+            }
+            Some("why-impl") => {
+                if let Some(args) = args {
+                    self.why_impl_command(&args);
+                } else {
+                    println!("command `why-impl` expects an expression");
+                }
+            }
             _ => println!("unrecognized command `{}`", cmd),
         }
     }
 
-    /// Runs a single program input.
-    /// If `display` is `true`, an expression will be printed using the
-    /// `Display` trait; otherwise, it is printed as `Debug`.
-    fn handle_input(&mut self, mut input: Input, display: bool) {
-        let name = "_rusti_run";
+    /// Runs a single program input and prints its value, if any.
+    /// If `display` is `true`, an expression will be printed using the
+    /// `Display` trait; otherwise, it is printed as `Debug`.
+    fn handle_input(&mut self, input: Input, display: bool) -> EvalResult {
+        let ffi_crates = self.ffi_crates(&input);
+
+        if !ffi_crates.is_empty() {
+            for name in ffi_crates {
+                println!("{}: crate `{}` may not be used with --deny-unsafe", self.argv0, name);
+            }
+            return EvalResult::default();
+        }
+
+        if has_holes(&input) {
+            self.report_holes(&input);
+            return EvalResult::default();
+        }
+
+        let copy = input.clone();
+        let stdin = self.stdin.clone();
+        let bindings = self.bindings(&input, "");
+
+        let result = match self.stdin.take() {
+            Some(stdin) => {
+                let result = match stdin {
+                    Stdin::Text(text) =>
+                        capture::with_stdin(&text, || self.run_input_recorded(input, display)),
+                    Stdin::Terminal =>
+                        capture::with_terminal_stdin(|| self.run_input_recorded(input, display)),
+                };
+
+                match result {
+                    Ok(result) => result,
+                    Err(e) => {
+                        println!("{}: failed to supply stdin: {}", self.argv0, e);
+                        return EvalResult::default();
+                    }
+                }
+            }
+            None => self.run_input_recorded(input, display),
+        };
+
+        if let Some(ref value) = result.value {
+            self.print_value(value);
+        }
+
+        if result.compiled {
+            if self.show_types {
+                self.print_let_types(&copy, &bindings);
+            }
+
+            self.failed = None;
+            return result;
+        }
+
+        // Each retry adds imports, so retries come to an end
+        if self.auto_import {
+            let imports = self.suggested_imports(&copy);
+
+            if !imports.is_empty() {
+                self.add_imports(imports);
+                self.stdin = stdin;
+                return self.handle_input(copy, display);
+            }
+        }
+
+        if self.show_hints {
+            self.print_hints(&copy);
+        }
+
+        self.failed = Some(copy);
+        result
+    }
+
+    /// Prints hints for errors of input which failed to compile.
+    fn print_hints(&mut self, input: &Input) {
+        let (prog, _) = self.check_program(input);
+
+        if let Some(stderr) = self.check_json(prog) {
+            for hint in hints::hints(&stderr) {
+                print!("{}", hint);
+            }
+        }
+    }
+
+    /// Prints the type expected in place of each hole in input, along with
+    /// the values and functions which fit it, without running the input.
+    fn report_holes(&self, input: &Input) {
+        let mut filled = input.clone();
+
+        filled.items = input.items.iter().map(|c| hole::replace(c)).collect();
+        filled.statements = input.statements.iter().map(|c| hole::replace(c)).collect();
+        filled.items.push(hole::definition());
+
+        let stmts = filled.statements.join("\n");
+        let prog = self.input_program(&filled, &EntryPoint::new(self.inputs),
+            &self.bindings(&filled, ""), &stmts);
+
+        if let Some(holes) = self.engine.holes(prog) {
+            for (i, hole) in holes.iter().enumerate() {
+                println!("hole {}: {}", i + 1, hole);
+            }
+        }
+    }
+
+    /// Prints the inferred type of each variable bound by `let` statements
+    /// of input which has been run, with the bindings it was run with.
+    fn print_let_types(&self, input: &Input, bindings: &str) {
+        if input.statements.is_empty() {
+            return;
+        }
+
+        // Items of the input have been added to the session
+        let stmts = input.statements.join("\n");
+        let prog = self.input_program(&Input::new(), &EntryPoint::new(self.inputs),
+            bindings, &stmts);
+        let start = prog.rfind(&stmts[..]).unwrap_or(0);
+
+        if let Some(types) = self.engine.let_types(prog, start) {
+            for (name, ty) in types {
+                println!("let {}: {}", name, ty);
+            }
+        }
+    }
+
+    /// Runs a single program input, rendering its display data
+    /// if graphics are enabled.
+    fn run_input_any(&mut self, input: Input, display: bool) -> EvalResult {
+        match self.graphics {
+            Some(protocol) => self.run_input_graphics(input, display, protocol),
+            None => self.run_input(input, display),
+        }
+    }
+
+    /// Runs a single program input, capturing its output for the transcript
+    /// as it is written, if the session is recorded.
+    fn run_input_recorded(&mut self, input: Input, display: bool) -> EvalResult {
+        if !self.recording {
+            return self.run_input_any(input, display);
+        }
+
+        let copy = input.clone();
+
+        match tee(|| self.run_input_any(input, display)) {
+            Ok((result, output)) => {
+                self.recorded_output.push_str(&output.stdout);
+                self.recorded_output.push_str(&output.stderr);
+                result
+            }
+            Err(e) => {
+                debug!("failed to record output: {}", e);
+                self.run_input_any(copy, display)
+            }
+        }
+    }
+
+    /// Prints a value, highlighting it if colors are enabled.
+    fn print_value(&self, value: &str) {
+        let value = if use_color(self.engine.options().color) {
+            Cow::Owned(highlight(value))
+        } else {
+            Cow::Borrowed(value)
+        };
+
+        pager::print(&value, self.pager_command().as_ref().map(|s| &s[..]));
+    }
+
+    /// Returns the pager command used to show long values,
+    /// or `None` if paging is disabled.
+    fn pager_command(&self) -> Option<String> {
+        if self.paging {
+            Some(self.pager.clone().unwrap_or_else(pager::default_pager))
+        } else {
+            None
+        }
+    }
+
+    /// Runs a single program input, capturing its output so that images
+    /// in display data can be rendered in the terminal.
+    fn run_input_graphics(&mut self, input: Input, display: bool,
+            protocol: Protocol) -> EvalResult {
+        // Diagnostics are written to a file while output is captured;
+        // keep them colored if they would be written to the terminal in color.
+        let color = self.engine.options().color;
+
+        if use_color(color) {
+            self.engine.options_mut().color = ColorConfig::Always;
+        }
+
+        let pty = self.pty;
+        let run = || self.run_input(input, display);
+
+        let res = if pty { capture_tty(run) } else { capture(run) };
+
+        self.engine.options_mut().color = color;
+
+        let (result, output) = match res {
+            Ok(r) => r,
+            Err(e) => {
+                println!("{}: failed to capture output: {}", self.argv0, e);
+                return EvalResult::default();
+            }
+        };
+
+        let (text, display) = display::extract(&output.stdout);
+
+        eprint!("{}", output.stderr);
+        print!("{}", text);
+
+        let stdout = stdout();
+        let mut stdout = stdout.lock();
+
+        for data in &display {
+            match protocol.render(&mut stdout, data) {
+                Ok(true) => (),
+                Ok(false) => println!("[{} display data]", data.mime),
+                Err(e) => println!("{}: failed to render {}: {}", self.argv0, data.mime, e),
+            }
+        }
+
+        result
+    }
+
+    /// Runs a single program input.
+    /// If `display` is `true`, the value of an expression will be formatted
+    /// using the `Display` trait; otherwise, it is formatted as `Debug`.
+    fn run_input(&mut self, mut input: Input, display: bool) -> EvalResult {
+        let mut result = EvalResult::default();
+
+        if self.check_only {
+            let start = Instant::now();
+            result.compiled = self.check_input(input, true);
+            result.compile_time = start.elapsed();
+            return result;
+        }
+
+        let entry = EntryPoint::new(self.inputs);
+        self.inputs += 1;
+
+        let keep_stmts = self.value_statements(&mut input, display);
+        let stmts = input.statements.join("\n");
+
+        // Executables run outside of this process, where kept values don't exist
+        let prog = self.input_program(&input, &entry, "", &stmts);
+
+        self.record_source(&prog);
+
+        if let Some(runner) = self.runner.clone() {
+            return self.run_executable(input, prog, &entry, &runner);
+        }
+
+        // Without dynamic libraries, nothing can be loaded into the
+        // execution engine, but the program can run as a child process.
+        // Arguments can only be given to a program in a process of its own.
+        // Sanitized code needs its runtime from the start of a process.
+        if !self.engine.options().supports_dylib() || !self.program_args.is_empty() ||
+                self.engine.options().sanitizer.is_some() {
+            return self.run_executable(input, prog, &entry, &Runner::Local);
+        }
+
+        if self.engine.options().is_cross_compiling() {
+            println!("{}: cannot run code compiled for target `{}` without a runner",
+                self.argv0, self.engine.options().target.as_ref().unwrap());
+            return result;
+        }
+
+        let bindings = self.bindings(&input, "");
+
+        // Only values of `'static` types can be kept, so input which fails to
+        // compile while keeping its value is compiled again without it.
+        let start = Instant::now();
+        let mut module = None;
+
+        if let Some(keep) = keep_stmts {
+            let prog = self.input_program(&input, &entry, &bindings, &keep);
+            self.record_source(&prog);
+            module = self.add_module_quiet(prog);
+        }
+
+        if module.is_none() {
+            let prog = self.input_program(&input, &entry, &bindings, &stmts);
+            self.record_source(&prog);
+            module = self.engine.add_module(prog);
+        }
+        result.compile_time = start.elapsed();
+        result.cancelled = module.is_none() && self.engine.is_cancelled();
+
+        if let Some(_) = module {
+            result.compiled = true;
+
+            let start = Instant::now();
+            let (returned, frames) = backtrace::with_backtrace(|| self.engine.call_entry(&entry));
+            let returned = returned.unwrap();
+            result.run_time = start.elapsed();
+            result.executed = true;
+            result.failed = returned.status == EXIT_PANIC;
+
+            if self.engine.options().verbosity == Verbosity::Debug {
+                self.print_debug_timing();
+            }
+
+            // Panics caught by the input itself are not reported
+            if let (EXIT_PANIC, Some(frames)) = (returned.status, frames) {
+                let prog = self.input_program(&input, &entry, &bindings, &stmts);
+                self.print_backtrace(&input, prog, &frames);
+            }
+
+            result.value = match self.max_output {
+                Some(limit) => returned.value.as_ref().map(|v| truncate(v, limit)),
+                None => returned.value.clone(),
+            };
+
+            let binding = returned.kept.and_then(|kept| self.results.push(kept));
+
+            if let (Some(n), Some(value)) = (self.entry, returned.value.clone()) {
+                self.results.push_output(n, value, binding);
+            }
+
+            self.full_value = returned.value;
+
+            // NOTE: The module cannot be removed after it is run because tasks
+            // may still be running in the module code. This means that rusti's
+            // memory footprint will only grow over time.
+            // Hopefully, this will not be noticeable in normal use.
+
+            // Successful compile means we can add the new items to every program
+            self.add_items(input);
+        }
+
+        result
+    }
+
+    /// Sets the level of each of the given lints, whose names may contain
+    /// `-` in place of `_`, as `rustc` accepts them. With no lints, lists
+    /// those whose level was set to the given level.
+    fn lint_command(&mut self, level: lint::Level, args: &str) {
+        let opts = self.engine.options_mut();
+
+        if args.trim().is_empty() {
+            for &(ref name, l) in &opts.lints {
+                if l == level {
+                    println!("{}", name);
+                }
+            }
+            return;
+        }
+
+        for name in args.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()) {
+            let name = name.replace('-', "_");
+
+            opts.lints.retain(|&(ref n, _)| *n != name);
+            opts.lints.push((name, level));
+        }
+    }
+
+    /// Records the program compiled for the input being run,
+    /// if it was entered interactively.
+    fn record_source(&mut self, prog: &str) {
+        if let Some(n) = self.entry {
+            self.sources.insert(n, prog.to_owned());
+        }
+    }
+
+    /// Prints the program last compiled for the input with the given
+    /// history number, or for the last input if none is given.
+    fn source_command(&self, arg: &str) {
+        let source = if arg.is_empty() {
+            self.sources.values().next_back()
+        } else {
+            match arg.parse::<usize>() {
+                Ok(n) => self.sources.get(&n),
+                Err(_) => {
+                    println!("command `source` expects an input number");
+                    return;
+                }
+            }
+        };
+
+        match source {
+            Some(source) => println!("{}", source.trim_right()),
+            None if arg.is_empty() => println!("no input has been compiled"),
+            None => println!("no program was compiled for input {}", arg),
+        }
+    }
+
+    /// Writes the time spent in each phase of compiling the last input,
+    /// and in running it, to `stderr`.
+    fn print_debug_timing(&self) {
+        let phases = self.engine.last_phases();
+        let times = phases.phases().iter()
+            .map(|&(name, d)| format!("{} {}", name, format_millis(d)))
+            .collect::<Vec<_>>();
+
+        eprintln!("timing: {}; total {}", times.join(", "), format_millis(phases.total()));
+    }
+
+    /// Returns statements binding the results of previous input, for the
+    /// given input followed by `code`. `out` is only bound if the input
+    /// refers to it and no item of the session or the input is named `out`.
+    fn bindings(&self, input: &Input, code: &str) -> String {
+        let refers = input.items.iter()
+            .chain(input.statements.iter())
+            .any(|c| results::mentions(c, results::OUTPUTS)) ||
+            results::mentions(code, results::OUTPUTS);
+        let defined = self.snippets.iter()
+            .flat_map(|s| s.item_names())
+            .chain(input.items.iter().filter_map(|item| finder::item_name(item)))
+            .any(|name| name == results::OUTPUTS);
+
+        self.results.bindings(refers && !defined)
+    }
+
+    /// Builds the program run for an input, consisting of all items, the entry
+    /// point, and a function running the given statements, preceded by
+    /// the given bindings.
+    fn input_program(&self, input: &Input, entry: &EntryPoint,
+            bindings: &str, stmts: &str) -> String {
+        self.harness_program(input, entry, bindings, &self.forbid_unsafe(stmts))
+    }
+
+    /// Builds a program as `input_program` does, where the statements are
+    /// generated by rusti, and so may be unsafe.
+    fn harness_program(&self, input: &Input, entry: &EntryPoint,
+            bindings: &str, stmts: &str) -> String {
+        self.build_program(
+            Some(input),
+            &format!(
+                r#"{entry}
+#[allow(unused_variables)]
+fn _rusti_inner(ctx: *mut u8, keep: _RustiKeepFn) -> Option<String> {{
+{bindings}
+{stmts}
+}}
+"#,
+                entry = entry.define("_rusti_inner"),
+                bindings = bindings,
+                stmts = stmts
+            ),
+        )
+    }
+
+    /// Returns code of input, as a block forbidding unsafe code within it
+    /// if unsafe code is denied.
+    fn forbid_unsafe(&self, code: &str) -> String {
+        if self.deny_unsafe {
+            format!("{}{{\n{}\n}}", FORBID_UNSAFE, code)
+        } else {
+            code.to_owned()
+        }
+    }
+
+    /// Returns the crates which input imports, if unsafe code is denied,
+    /// that expose foreign functions.
+    fn ffi_crates(&self, input: &Input) -> Vec<String> {
+        if !self.deny_unsafe {
+            return Vec::new();
+        }
+
+        input.view_items.iter()
+            .filter_map(|item| extern_crate_name(item))
+            .filter(|name| deps::is_ffi_crate(name))
+            .map(|name| name.to_owned())
+            .collect()
+    }
+
+    /// Prints the frames of a panic backtrace which lie within functions
+    /// defined by the session, with the input which defined each function.
+    /// `prog` is the program which was run, containing `input`.
+    fn print_backtrace(&self, input: &Input, prog: String, frames: &[usize]) {
+        let paths = self.engine.function_names(frames).into_iter()
+            .filter_map(|name| name.and_then(|n| backtrace::demangle(&n)))
+            .filter(|path| path.contains(CRATE_PATH) &&
+                !path.contains("::_rusti_run_") && !path.contains("::_rusti_keep"))
+            .collect::<Vec<_>>();
+
+        if paths.is_empty() {
+            return;
+        }
+
+        let (_, layout) = self.build_program_layout(Some(input), "");
+        let inner = prog.rfind("fn _rusti_inner(").unwrap_or(prog.len());
+
+        // The program is compiled again to find definitions; its warnings were already shown
+        let offsets = match capture(|| self.engine.definition_offsets(prog, &paths)) {
+            Ok((Some(offsets), _)) => offsets,
+            _ => vec![None; paths.len()],
+        };
+
+        println!("backtrace:");
+
+        for (i, (path, offset)) in paths.iter().zip(offsets).enumerate() {
+            let name = path.replace(CRATE_PATH, "").replace("_rusti_inner", "<input>");
+
+            let defined_by = match offset {
+                Some(offset) if offset >= inner => None,
+                Some(offset) => match snippet_at(&layout, offset) {
+                    Some(n) if n < self.snippets.len() => Some(self.snippets[n].describe()),
+                    Some(_) => Some("this input".to_owned()),
+                    None => None,
+                },
+                None => None,
+            };
+
+            match defined_by {
+                Some(by) => println!("{:>4}: {} ({})", i, name, by),
+                None => println!("{:>4}: {}", i, name),
+            }
+        }
+    }
+
+    /// Adds a module to the execution engine, discarding diagnostics
+    /// if it fails to compile.
+    fn add_module_quiet(&mut self, prog: String) -> Option<llvm::ModuleRef> {
+        match capture(|| self.engine.add_module(prog)) {
+            Ok((Some(module), output)) => {
+                // Pass along any warnings
+                print!("{}", output.stdout);
+                eprint!("{}", output.stderr);
+                Some(module)
+            }
+            _ => None,
+        }
+    }
+
+    /// Replaces the final expression of input, if it has one, with statements
+    /// formatting its value, which the entry point returns.
+    /// Returns the statements of a program which also keeps the value.
+    fn value_statements(&self, input: &mut Input, display: bool) -> Option<String> {
+        if input.last_expr && !input.statements.is_empty() {
+            let expr = input.statements.pop().unwrap();
+            let fmt = if display {
+                r#"format!("{}", _rusti_value)"#.to_owned()
+            } else {
+                self.formatters.format_expr("_rusti_value")
+            };
+
+            let stmts = input.statements.join("\n");
+
+            // A `match` keeps temporaries in the expression alive
+            // until the value has been formatted.
+            let last = |keep: &str| format!(
+                r#"match {{ {expr} }} {{
+    _rusti_value => {{
+        let _rusti_str = {fmt};
+        {keep}
+        Some(_rusti_str)
+    }}
+}}"#,
+                expr = expr, fmt = fmt, keep = keep);
+
+            let keep_stmts = format!("{}\n{}", stmts,
+                last("_rusti_keep(ctx, keep, _rusti_value);"));
+            input.statements.push(last(""));
+            Some(keep_stmts)
+        } else {
+            input.statements.push("None".to_owned());
+            None
+        }
+    }
+
+    /// Compiles a program into an executable which calls the given entry point
+    /// and runs it using the given runner.
+    fn run_executable(&mut self, input: Input, prog: String,
+            entry: &EntryPoint, runner: &Runner) -> EvalResult {
+        let mut result = EvalResult::default();
+
+        let start = Instant::now();
+        let exe = self.compile_executable(prog, entry);
+        result.compile_time = start.elapsed();
+
+        let exe = match exe {
+            Some(exe) => exe,
+            None => return result,
+        };
+
+        result.compiled = true;
+        self.add_items(input);
+
+        let start = Instant::now();
+
+        match runner.run(exe.path(), &self.program_args) {
+            Ok(status) => {
+                result.executed = true;
+                result.failed = !status.success();
+
+                if !status.success() {
+                    println!("{}: program run with `{}` exited with {}",
+                        self.argv0, runner, status);
+
+                    // The program may have failed for want of access to a path
+                    if let Runner::Sandbox(ref policy) = *runner {
+                        println!("{}", policy.note());
+                    }
+                }
+            }
+            Err(e) => println!("{}: failed to run program with `{}`: {}",
+                self.argv0, runner, e),
+        }
+
+        result.run_time = start.elapsed();
+
+        result
+    }
+
+    /// Compiles a program into an executable calling the given entry point.
+    /// Returns `None` if the program fails to compile.
+    fn compile_executable(&mut self, prog: String, entry: &EntryPoint) -> Option<Artifact> {
+        let exe = self.executable_file()?;
+
+        let prog = format!("{}\n{}", prog, entry.define_main());
+
+        if self.engine.build_executable(prog, exe.path()) {
+            Some(exe)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a temporary file to which an executable may be written.
+    fn executable_file(&self) -> Option<Artifact> {
+        let res = match self.artifact_dir {
+            Some(ref dir) => Artifact::create_in(dir, self.keep_artifacts),
+            None => Artifact::create_in(
+                &artifacts::artifact_dir(self.engine.options().target_dir.as_ref()),
+                self.keep_artifacts),
+        };
+
+        match res {
+            Ok(f) => {
+                if f.is_kept() {
+                    println!("{}: keeping executable {}", self.argv0, f.path().display());
+                }
+                Some(f)
+            }
+            Err(e) => {
+                println!("{}: failed to create executable file: {}", self.argv0, e);
+                None
+            }
+        }
+    }
+
+    /// Returns the runner of executables run outside of the session,
+    /// which is the local system unless a runner is set.
+    fn executable_runner(&self) -> Option<Runner> {
+        match self.runner {
+            Some(ref runner) => Some(runner.clone()),
+            None if self.engine.options().is_cross_compiling() => {
+                println!("{}: cannot run code compiled for target `{}` without a runner",
+                    self.argv0, self.engine.options().target.as_ref().unwrap());
+                None
+            }
+            None => Some(Runner::Local),
+        }
+    }
+
+    /// Type-checks a single program input without translating or running it.
+    /// If `persist` is `true` and the input is free of errors, its items are
+    /// added to every following program.
+    /// Returns `true` if the input is free of errors.
+    fn check_input(&mut self, input: Input, persist: bool) -> bool {
+        let (prog, _) = self.check_program(&input);
+        let ok = self.engine.check(prog);
+
+        if ok && persist {
+            self.add_items(input);
+        }
+
+        ok
+    }
+
+    /// Checks the text of a `.block` entered so far, if the `speculate`
+    /// setting is enabled, so that errors are found before it is submitted.
+    ///
+    /// Syntax errors are reported at once. Input which parses is type-checked
+    /// in the background; errors are reported when the next line is entered,
+    /// so as not to disturb editing of the current line. Each error is
+    /// reported only once.
+    fn speculate(&mut self, code: &str) {
+        if !self.speculate {
+            return;
+        }
+
+        let finished = match self.speculation.as_ref().map(|rx| rx.try_recv()) {
+            Some(Ok(diagnostics)) => {
+                if let Some(msg) = first_error(&diagnostics) {
+                    self.report_speculation(msg);
+                }
+                true
+            }
+            Some(Err(TryRecvError::Disconnected)) => true,
+            Some(Err(TryRecvError::Empty)) | None => false,
+        };
+
+        if finished {
+            self.speculation = None;
+        }
+
+        match capture(|| parse_program(code, true, None)) {
+            Ok((Program(input), _)) => {
+                // Only one check runs at a time
+                if self.speculation.is_none() {
+                    let (prog, _) = self.check_program(&input);
+                    self.speculation = Some(self.engine.check_in_background(prog));
+                }
+            }
+            Ok((InputError(_), output)) => {
+                if let Some(msg) = output.stderr.lines().next() {
+                    self.report_speculation(msg.to_owned());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Reports an error found in an incomplete `.block`, unless it was
+    /// the last one reported.
+    fn report_speculation(&mut self, msg: String) {
+        if self.speculated_error.as_ref() == Some(&msg) {
+            return;
+        }
+
+        if use_color(self.engine.options().color) {
+            println!("\x1b[2m  ({})\x1b[0m", msg);
+        } else {
+            println!("  ({})", msg);
+        }
+
+        self.speculated_error = Some(msg);
+    }
+
+    /// Builds a program which type-checks input without running it.
+    /// Also returns the byte range within the program of each attribute,
+    /// view item, item and statement of the input, in that order.
+    fn check_program(&self, input: &Input) -> (String, Vec<Range<usize>>) {
+        let mut body = format!("\n#[allow(unused_variables)]\nfn _rusti_check() {{\n{}\n",
+            self.bindings(input, ""));
+        let mut stmt_ranges = Vec::new();
+
+        for (i, stmt) in input.statements.iter().enumerate() {
+            let start = body.len();
+            body.push_str(stmt);
+            stmt_ranges.push(start..body.len());
+
+            // The value of the input expression is discarded
+            if input.last_expr && i == input.statements.len() - 1 {
+                body.push(';');
+            }
+            body.push('\n');
+        }
+
+        body.push_str("}\n");
+
+        let (prog, layout) = self.build_program_layout(Some(input), &body);
+
+        // The body is followed only by a newline
+        let base = prog.len() - body.len() - 1;
+
+        let mut ranges = layout.into_iter()
+            .filter(|&(_, i)| i == self.snippets.len())
+            .map(|(range, _)| range)
+            .collect::<Vec<_>>();
+
+        ranges.extend(stmt_ranges.into_iter().map(|r| base + r.start..base + r.end));
+
+        (prog, ranges)
+    }
+
+    /// Applies suggestions made by diagnostics of the last input which failed
+    /// to compile, showing the changes, and runs the changed input.
+    fn fix_command(&mut self) {
+        let input = match self.failed.take() {
+            Some(input) => input,
+            None => {
+                println!("no failed input to fix");
+                return;
+            }
+        };
+
+        let (prog, ranges) = self.check_program(&input);
+
+        let stderr = match self.check_json(prog) {
+            Some(stderr) => stderr,
+            None => {
+                self.failed = Some(input);
+                return;
+            }
+        };
+
+        let imports = self.new_imports(fix::imports(&stderr));
+
+        let pieces = input.attributes.iter()
+            .chain(&input.view_items)
+            .chain(&input.items)
+            .chain(&input.statements)
+            .collect::<Vec<_>>();
+        let mut replacements = vec![Vec::new(); pieces.len()];
+
+        for r in fix::suggestions(&stderr) {
+            let pos = ranges.iter()
+                .position(|p| p.start <= r.range.start && r.range.end <= p.end);
+
+            if let Some(i) = pos {
+                let start = ranges[i].start;
+
+                replacements[i].push(Replacement{
+                    range: r.range.start - start..r.range.end - start,
+                    text: r.text,
+                });
+            }
+        }
+
+        if replacements.iter().all(|r| r.is_empty()) && imports.is_empty() {
+            println!("no suggestions to apply");
+            self.failed = Some(input);
+            return;
+        }
+
+        let old = pieces.iter().map(|p| &p[..]).collect::<Vec<_>>().join("\n");
+        let code = pieces.iter().zip(&replacements)
+            .map(|(p, r)| fix::apply(p, r))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // Imports are added to the session, before the input
+        let new = imports.iter().map(|i| &i[..]).chain(Some(&code[..])).collect::<Vec<_>>();
+        print!("{}", fix::diff(&old, &new.join("\n")));
+
+        if !confirm("apply the changes and run the input?") {
+            self.failed = Some(input);
+            return;
+        }
+
+        self.add_imports(imports);
+
+        // Fixed input is recorded so that it can be run or edited again
+        if let Err(e) = self.history.push(&code) {
+            println!("{}: failed to save history: {}", self.argv0, e);
+        }
+
+        if let Program(input) = parse_program(&format!("{}\n", code), false, None) {
+            self.entry = Some(self.history.len());
+            self.handle_input(input, false);
+        }
+    }
+
+    /// Type-checks a program, returning diagnostics emitted as JSON,
+    /// from which suggestions can be read.
+    fn check_json(&mut self, prog: String) -> Option<String> {
+        let json = self.engine.options().json_diagnostics;
+        self.engine.options_mut().json_diagnostics = true;
+        let output = capture(|| self.engine.check(prog));
+        self.engine.options_mut().json_diagnostics = json;
+
+        match output {
+            Ok((_, output)) => Some(output.stderr),
+            Err(e) => {
+                println!("{}: failed to capture diagnostics: {}", self.argv0, e);
+                None
+            }
+        }
+    }
+
+    /// Returns the imports suggested for input which failed to compile,
+    /// for names with a single candidate.
+    fn suggested_imports(&mut self, input: &Input) -> Vec<String> {
+        let (prog, _) = self.check_program(input);
+
+        match self.check_json(prog) {
+            Some(stderr) => self.new_imports(fix::imports(&stderr)),
+            None => Vec::new(),
+        }
+    }
+
+    /// Selects suggested imports with a single candidate which the session
+    /// does not already contain.
+    fn new_imports(&self, suggestions: Vec<Vec<String>>) -> Vec<String> {
+        let mut imports = Vec::new();
+
+        for mut candidates in suggestions {
+            if candidates.len() != 1 {
+                continue;
+            }
+
+            let import = candidates.pop().unwrap();
+            let exists = imports.contains(&import) ||
+                self.snippets.iter().any(|s| s.view_items.contains(&import));
+
+            if !exists {
+                imports.push(import);
+            }
+        }
+
+        imports
+    }
+
+    /// Adds `use` declarations to every following program.
+    fn add_imports(&mut self, imports: Vec<String>) {
+        for import in &imports {
+            println!("adding `{}`", import);
+        }
+
+        let mut input = Input::new();
+        input.view_items = imports;
+        self.add_items(input);
+    }
+
+    /// Adds attributes and items from an input to every following program.
+    fn add_items(&mut self, input: Input) {
+        if input.attributes.is_empty() && input.view_items.is_empty() &&
+                input.items.is_empty() {
+            return;
+        }
+
+        self.snippets.push(Snippet{
+            entry: self.entry,
+            attributes: input.attributes,
+            view_items: input.view_items,
+            items: input.items,
+        });
+    }
+
+    /// Runs input replacing the snippet at `pos`, then checks that the items
+    /// of each later snippet which depends on it still compile, removing
+    /// those which do not. If the input fails to compile, the snippet is
+    /// left unchanged.
+    fn replace_snippet(&mut self, pos: usize, input: Input) {
+        let affected = self.dependents(pos);
+        let later = self.snippets.split_off(pos + 1);
+        let old = self.snippets.pop().unwrap();
+
+        // Kept values of types the snippet defines cannot be bound to the
+        // types which replace them, whose layout may differ
+        let mut unbound = self.results.unbind_types(&old.item_names());
+
+        if !self.handle_input(input, false).compiled {
+            self.results.rebind(&unbound);
+            self.snippets.push(old);
+            self.snippets.extend(later);
+            return;
+        }
+
+        for (snippet, affected) in later.into_iter().zip(affected) {
+            if !affected {
+                self.snippets.push(snippet);
+                continue;
+            }
+
+            let prog = self.build_program(Some(&snippet.to_input()), "");
+
+            if self.engine.check(prog) {
+                self.snippets.push(snippet);
+            } else {
+                println!("{} no longer compiles and has been removed", snippet.describe());
+                unbound.extend(self.results.unbind_types(&snippet.item_names()));
+            }
+        }
+
+        report_unbound(&unbound);
+    }
+
+    /// Returns, for each snippet after the one at `pos`, whether it refers to
+    /// items of that snippet, either directly or through other snippets.
+    /// If references cannot be resolved, every later snippet is included.
+    fn dependents(&self, pos: usize) -> Vec<bool> {
+        let n = self.snippets.len();
+        let (prog, layout) = self.build_program_layout(None, "");
+
+        let paths = match self.engine.resolve_paths(prog) {
+            Some(paths) => paths,
+            None => return vec![true; n - pos - 1],
+        };
+
+        // Snippets referred to by each snippet
+        let mut deps = vec![Vec::new(); n];
+
+        for (from, to) in paths {
+            if let (Some(from), Some(to)) = (snippet_at(&layout, from), snippet_at(&layout, to)) {
+                if from != to {
+                    deps[from].push(to);
+                }
+            }
+        }
+
+        let mut affected = vec![false; n];
+        affected[pos] = true;
+
+        for i in pos + 1..n {
+            affected[i] = deps[i].iter().any(|&d| affected[d]);
+        }
+
+        affected.split_off(pos + 1)
+    }
+
+    /// Runs clippy over the items of the session, noting the input which
+    /// defined the code each lint refers to.
+    fn clippy_command(&mut self) {
+        if self.snippets.is_empty() {
+            println!("no items are defined");
+            return;
+        }
+
+        let (prog, layout) = self.build_program_layout(None, "");
+
+        let diagnostics = match clippy::lint(&prog, self.engine.options()) {
+            Ok(diagnostics) => diagnostics,
+            Err(e) => {
+                println!("{}: failed to run clippy: {}", self.argv0, e);
+                return;
+            }
+        };
+
+        for diag in diagnostics {
+            if let Some(i) = diag.offset.and_then(|offset| snippet_at(&layout, offset)) {
+                eprintln!("in {}:", self.snippets[i].describe());
+            }
+
+            eprint!("{}", diag.rendered);
+        }
+    }
+
+    /// Changes the working directory of the process, which is used by
+    /// code run in the session, shell commands and runners.
+    /// With no directory, changes to the home directory.
+    fn cd_command(&self, dir: Option<&str>) {
+        let dir = match dir {
+            Some("~") | None => home_dir(),
+            Some(d) if d.starts_with("~/") => home_dir().map(|h| h.join(&d[2..])),
+            Some(d) => Some(PathBuf::from(d)),
+        };
+
+        let dir = match dir {
+            Some(dir) => dir,
+            None => {
+                println!("{}: no home directory", self.argv0);
+                return;
+            }
+        };
+
+        if let Err(e) = set_current_dir(&dir) {
+            println!("{}: {}: {}", self.argv0, dir.display(), e);
+        }
+    }
+
+    /// Sets the arguments of programs run for input, telling the user when
+    /// input stops or starts running within rusti as a result.
+    fn args_command(&mut self, args: &str) {
+        let was_set = !self.program_args.is_empty();
+
+        self.program_args = split_args(args);
+
+        // Only a program in a process of its own can be given arguments
+        let in_process = self.runner.is_none() && self.engine.options().supports_dylib() &&
+            self.engine.options().sanitizer.is_none();
+
+        if !in_process || was_set == !self.program_args.is_empty() {
+            return;
+        }
+
+        if was_set {
+            println!("input runs within rusti again");
+        } else {
+            println!("input now runs as an executable in a process of its own, \
+                without the values of previous expressions; `.args` alone runs it \
+                within rusti again");
+        }
+    }
+
+    /// Tells the user that the linker is not used for input run within rusti,
+    /// which is linked in memory, when input would run that way.
+    fn note_linker_unused(&self) {
+        let in_process = self.runner.is_none() && self.engine.options().supports_dylib() &&
+            self.engine.options().sanitizer.is_none() && self.program_args.is_empty();
+
+        if in_process {
+            println!("note: input run within rusti is linked in memory; the linker is only \
+                used to build input into an executable, as with `.args`, a runner or a sanitizer");
+        }
+    }
+
+    fn bench_command(&mut self, expr: &str) {
+        let in_process = self.runner.is_none() && self.engine.options().supports_dylib() &&
+            !self.engine.options().is_cross_compiling();
+
+        if !in_process {
+            println!("{}: `.bench` is only supported for code run within rusti", self.argv0);
+            return;
+        }
+
+        let entry = EntryPoint::new(self.inputs);
+        self.inputs += 1;
+
+        let bindings = self.bindings(&Input::new(), expr);
+        let prog = self.harness_program(&Input::new(), &entry, &bindings,
+            &bench::statements(&self.forbid_unsafe(expr)));
+
+        // Debug builds are too slow to be worth measuring
+        let opt_level = mem::replace(&mut self.engine.options_mut().opt_level,
+            OptLevel::Aggressive);
+        let module = self.engine.add_module(prog);
+        self.engine.options_mut().opt_level = opt_level;
+
+        if module.is_none() {
+            return;
+        }
+
+        let returned = self.engine.call_entry(&entry).unwrap();
+
+        match returned.value.as_ref().and_then(|v| Stats::parse(v)) {
+            Some(stats) => println!("{}", stats),
+            None => println!("{}: benchmark did not complete", self.argv0),
+        }
+    }
+
+    fn profile_command(&mut self, expr: &str) {
+        if !cfg!(target_os = "linux") {
+            println!("{}: `.profile` requires `perf`, which is only available on Linux",
+                self.argv0);
+            return;
+        }
+
+        if self.runner.is_some() || self.engine.options().is_cross_compiling() {
+            println!("{}: `.profile` is only supported for code run on this system", self.argv0);
+            return;
+        }
+
+        let entry = EntryPoint::new(self.inputs);
+        self.inputs += 1;
+
+        // Executables run outside of this process, where kept values don't exist
+        let prog = self.harness_program(&Input::new(), &entry, "",
+            &profile::statements(&self.forbid_unsafe(expr)));
+
+        // Debug builds are too slow to be worth measuring
+        let opt_level = mem::replace(&mut self.engine.options_mut().opt_level,
+            OptLevel::Aggressive);
+        let exe = self.compile_executable(prog, &entry);
+        self.engine.options_mut().opt_level = opt_level;
+
+        let exe = match exe {
+            Some(exe) => exe,
+            None => return,
+        };
+
+        let (output, script) = match profile::record(exe.path()) {
+            Ok(r) => r,
+            Err(e) => {
+                println!("{}: failed to profile with `perf`: {}", self.argv0, e);
+                return;
+            }
+        };
+
+        let stacks = profile::fold(&script);
+        let samples = stacks.values().sum::<u64>();
+
+        if samples == 0 {
+            println!("{}: no samples were recorded", self.argv0);
+            return;
+        }
+
+        let svg = profile::flamegraph(&stacks, expr);
+
+        // Another session in this directory may write the same file at once;
+        // replacing the file whole keeps it from holding a mix of both.
+        let res = NamedTempFile::new_in(".")
+            .and_then(|mut f| f.write_all(svg.as_bytes()).map(|_| f))
+            .and_then(|f| f.persist(PROFILE_FILE).map_err(|e| e.error));
+
+        if let Err(e) = res {
+            println!("{}: failed to write {}: {}", self.argv0, PROFILE_FILE, e);
+            return;
+        }
+
+        println!("{} samples of {} evaluations; flame graph written to {}",
+            samples, output.trim(), PROFILE_FILE);
+
+        for (name, n) in profile::hottest(&stacks).into_iter().take(PROFILE_HOTTEST) {
+            println!("  {:>5.1}%  {}", n as f64 * 100.0 / samples as f64, name);
+        }
+    }
+
+    fn check_command(&mut self, code: String) {
+        if let Program(i) = parse_program(&code, false, None) {
+            self.check_input(i, false);
+        }
+    }
+
+    fn jobs_command(&mut self) {
+        self.reap_jobs();
+
+        for job in &self.jobs {
+            println!("[{}] {:<8} {}", job.id, job.child.id(), job.code);
+        }
+    }
+
+    fn kill_command(&mut self, args: &str) {
+        let pos = match args.parse() {
+            Ok(n) => self.jobs.iter().position(|j| j.id == n),
+            Err(_) => {
+                println!("command `kill` expects a job number");
+                return;
+            }
+        };
+
+        let mut job = match pos {
+            Some(pos) => self.jobs.remove(pos),
+            None => {
+                println!("no job numbered `{}`", args);
+                return;
+            }
+        };
+
+        match job.child.kill() {
+            Ok(()) => println!("[{}] killed  {}", job.id, job.code),
+            Err(e) => println!("{}: failed to kill job {}: {}", self.argv0, job.id, e),
+        }
+    }
+
+    /// Lists the impls which apply to a type, which may refer to items
+    /// defined in the session.
+    fn impls_command(&mut self, ty: &str) {
+        if ty.is_empty() {
+            println!("command `impls` expects a type");
+            return;
+        }
+
+        if let Some(info) = self.type_info(ty) {
+            for i in info.impls {
+                let name = if i.inherent { format!("impl {}", i.name) } else { i.name };
+
+                println!("{:<40} {}{}", name, i.krate, if i.auto { " (auto)" } else { "" });
+            }
+        }
+    }
+
+    /// Lists the methods which may be called on a type, or on the value
+    /// of a binding, such as `res`.
+    fn methods_command(&mut self, arg: &str) {
+        if arg.is_empty() {
+            println!("command `methods` expects a type or binding");
+            return;
+        }
+
+        let ty = self.results.binding_type(arg).unwrap_or(arg).to_owned();
+
+        let methods = match self.type_info(&ty) {
+            Some(info) => info.methods,
+            None => return,
+        };
+
+        if methods.is_empty() {
+            println!("no methods for `{}`", ty);
+            return;
+        }
+
+        let mut trait_name = None;
+
+        for method in methods {
+            if method.trait_name.is_some() && method.trait_name != trait_name {
+                println!("{}:", method.trait_name.as_ref().unwrap());
+                trait_name = method.trait_name.clone();
+            }
+
+            let indent = if trait_name.is_some() { "    " } else { "" };
+            println!("{}{}", indent, method.signature);
+        }
+    }
+
+    /// Explains which method is selected for the outermost method call or
+    /// operator of an expression, and how its receiver is adjusted.
+    fn why_impl_command(&mut self, expr: &str) {
+        let stmts = format!("#[allow(unused_must_use, path_statements)] {{ {}; }}\nNone", expr);
+        let prog = self.input_program(&Input::new(), &EntryPoint::new(self.inputs),
+            &self.bindings(&Input::new(), expr), &stmts);
+        let start = prog.rfind(&stmts[..]).unwrap_or(0);
+
+        match self.engine.explain_selection(prog, start) {
+            Some(Some(sel)) => println!("{}", sel),
+            Some(None) => println!("no method call or operator in `{}`", expr),
+            None => (),
+        }
+    }
+
+    /// Lists the instantiations of generic functions required by the last
+    /// input, or input n from history, which earlier input does not require,
+    /// largest first.
+    fn mono_command(&mut self, args: &str) {
+        let n = if args.is_empty() {
+            // The most recent entry is this command
+            self.history.len().saturating_sub(1)
+        } else {
+            match args.parse() {
+                Ok(n) => n,
+                Err(_) => {
+                    println!("command `mono` expects an input number");
+                    return;
+                }
+            }
+        };
+
+        let mut input = match self.history.get(n) {
+            Some(entry) if is_command(entry) => {
+                println!("input {} is a command", n);
+                return;
+            }
+            Some(entry) => match parse_program(&format!("{}\n", entry), false, None) {
+                Program(input) => input,
+                _ => return,
+            },
+            None => {
+                println!("no input numbered `{}` in history", n);
+                return;
+            }
+        };
+
+        self.value_statements(&mut input, false);
+
+        let entry = EntryPoint::new(self.inputs);
+        let bindings = self.bindings(&input, "");
+
+        // Items defined by the input are part of it, rather than earlier input
+        let pos = self.snippets.iter().position(|s| s.entry == Some(n));
+        let snippet = pos.map(|pos| self.snippets.remove(pos));
+
+        let with = self.input_program(&input, &entry, &bindings, &input.statements.join("\n"));
+        let without = self.input_program(&Input::new(), &entry, &bindings, "None");
+
+        if let (Some(pos), Some(snippet)) = (pos, snippet) {
+            self.snippets.insert(pos, snippet);
+        }
+
+        let before = match self.engine.instantiations(without) {
+            Some(items) => items.into_iter().map(|i| i.name).collect::<HashSet<_>>(),
+            None => return,
+        };
+
+        let mut items = match self.engine.instantiations(with) {
+            Some(items) => items,
+            None => return,
+        };
+
+        items.retain(|i| !before.contains(&i.name));
+        items.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+        for item in &items {
+            println!("{:>6}  {}", item.size, item.name);
+        }
+
+        println!("{} instantiations, estimated size {}",
+            items.len(), items.iter().map(|i| i.size).sum::<usize>());
+    }
+
+    /// Shows the documentation of an item, such as `std::vec::Vec::push`,
+    /// which may be defined in the session.
+    fn doc_command(&mut self, args: &str) {
+        let (open, path) = if args.starts_with("--open") {
+            (true, args["--open".len()..].trim())
+        } else {
+            (false, args)
+        };
+
+        if !is_path(path) {
+            println!("command `doc` expects a path, such as `std::vec::Vec`");
+            return;
+        }
+
+        let (prog, names) = self.item_imports(path);
+
+        // Errors of the imports which do not resolve are of no interest
+        let doc = match capture(|| self.engine.item_doc(prog, &names)) {
+            Ok((doc, _)) => doc.and_then(|doc| doc),
+            Err(_) => None,
+        };
+
+        let doc = match doc {
+            Some(doc) => doc,
+            None => {
+                println!("no item `{}`", path);
+                return;
+            }
+        };
+
+        if open {
+            match self.open_doc(&doc) {
+                Ok(()) => return,
+                Err(e) => println!("{}; showing it here", e),
+            }
+        }
+
+        let text = if doc.text.trim().is_empty() { "*no documentation*" } else { &doc.text[..] };
+        let text = format!("# `{}`\n\n{}", doc.path, text);
+        let color = use_color(self.engine.options().color);
+
+        pager::print(markdown::render(&text, &doc.page, &doc.module, color).trim_right(),
+            self.pager_command().as_ref().map(|s| &s[..]));
+    }
+
+    /// Returns a program importing the item named by `path`, or, where it
+    /// may name an associated item, its type or trait, together with the
+    /// names of the imports and of the associated item, as given to `item_doc`.
+    fn item_imports(&self, path: &str) -> (String, Vec<(String, Option<String>)>) {
+        let mut imports = vec![(path.to_owned(), None)];
+
+        if let Some(i) = path.rfind("::") {
+            imports.push((path[..i].to_owned(), Some(path[i + 2..].to_owned())));
+        }
+
+        let mut code = String::new();
+        let mut names = Vec::new();
+
+        for (p, assoc) in imports {
+            // Names of the prelude, such as `Vec`, cannot be imported from the crate root
+            let prelude = if p.contains("::") { None } else { Some(format!("std::prelude::v1::{}", p)) };
+
+            for p in Some(p).into_iter().chain(prelude) {
+                let name = format!("_RustiDoc{}", names.len());
+                code.push_str(&format!("#[allow(unused_imports)] use {} as {};\n", p, name));
+                names.push((name, assoc.clone()));
+            }
+        }
+
+        (self.build_program(None, &code), names)
+    }
+
+    /// Opens the page of local HTML documentation documenting an item in the
+    /// browser, documenting dependencies of the session as needed.
+    fn open_doc(&self, doc: &ItemDoc) -> Result<(), String> {
+        let file = match doc.file {
+            Some(ref file) => file,
+            None => return Err(format!("`{}` has no HTML documentation", doc.path)),
+        };
+
+        let opts = self.engine.options();
+
+        let dir = match docs::std_doc_dir(&opts.sysroot, docs::page_crate(file)) {
+            Some(dir) => Some(dir),
+            None => self.deps.iter()
+                .filter_map(|lib| deps::document(Path::new(lib), opts, self.sandboxed).ok())
+                .find(|dir| docs::has_page(dir, file)),
+        };
+
+        match dir {
+            Some(ref dir) if docs::has_page(dir, file) => docs::open(&docs::page_url(dir, file)),
+            _ => Err(format!("no HTML documentation of `{}` is installed", doc.path)),
+        }
+    }
+
+    /// Prints the source of an item, found in the session, in the source
+    /// of the standard library installed in the sysroot, or in that of a crate.
+    fn src_command(&mut self, path: &str) {
+        if !is_path(path) {
+            println!("command `src` expects a path, such as `std::vec::Vec::push`");
+            return;
+        }
+
+        let (prog, names) = self.item_imports(path);
+
+        let src = match capture(|| self.engine.item_source(prog, &names)) {
+            Ok((src, _)) => src.and_then(|src| src),
+            Err(_) => None,
+        };
+
+        let src = match src {
+            Some(src) => src,
+            None => {
+                println!("no item `{}`", path);
+                return;
+            }
+        };
+
+        let color = use_color(self.engine.options().color);
+
+        // Items of the session are shown as they were entered
+        if let Some(code) = src.code {
+            println!("{}", if color { highlight(&code) } else { code });
+            return;
+        }
+
+        let sysroot = &self.engine.options().sysroot;
+
+        let path = match source::locate(&src.file, sysroot) {
+            Some(path) => path,
+            None => {
+                println!("source of `{}` is not installed; it is in {}, which may be \
+                    installed with `rustup component add rust-src`", src.path, src.file);
+                return;
+            }
+        };
+
+        let mut text = String::new();
+
+        if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_string(&mut text)) {
+            println!("{}: failed to read {}: {}", self.argv0, path.display(), e);
+            return;
+        }
+
+        let (first, code) = source::excerpt(&text, src.lines.0, src.lines.1);
+        let code = if color { highlight(&code) } else { code };
+        let text = format!("{}:{}\n{}", path.display(), first, source::number_lines(&code, first));
+
+        pager::print(text.trim_right(), self.pager_command().as_ref().map(|s| &s[..]));
+    }
+
+    /// Prints the memory layout of a type, which may refer to items
+    /// defined in the session.
+    fn layout_command(&mut self, ty: &str) {
+        if ty.is_empty() {
+            println!("command `layout` expects a type");
+            return;
+        }
+
+        match self.type_info(ty).map(|info| info.layout) {
+            Some(Ok(layout)) => println!("{}", layout),
+            Some(Err(e)) => println!("cannot compute layout of `{}`: {}", ty, e),
+            None => (),
+        }
+    }
+
+    /// Compiles code into an executable and starts it in the background.
+    /// Items defined by the code persist, as for other input.
+    fn spawn_command(&mut self, code: String) {
+        let mut input = match parse_program(&code, false, None) {
+            Program(i) => i,
+            _ => return,
+        };
+
+        let runner = match self.executable_runner() {
+            Some(runner) => runner,
+            None => return,
+        };
+
+        let entry = EntryPoint::new(self.inputs);
+        self.inputs += 1;
+
+        self.value_statements(&mut input, false);
+
+        let stmts = input.statements.join("\n");
+        let prog = self.input_program(&input, &entry, "", &stmts);
+
+        let exe = match self.compile_executable(prog, &entry) {
+            Some(exe) => exe,
+            None => return,
+        };
+
+        self.add_items(input);
+
+        match runner.spawn(exe.path(), &self.program_args) {
+            Ok(child) => {
+                self.started_jobs += 1;
+                println!("[{}] {}", self.started_jobs, child.id());
+
+                self.jobs.push(Job{
+                    id: self.started_jobs,
+                    code: code.trim().lines().next().unwrap_or("").to_owned(),
+                    child: child,
+                    _exe: exe,
+                });
+            }
+            Err(e) => println!("{}: failed to run program with `{}`: {}",
+                self.argv0, runner, e),
+        }
+    }
+
+    /// Removes jobs which have finished, reporting their exit status.
+    fn reap_jobs(&mut self) {
+        let mut i = 0;
+
+        while i < self.jobs.len() {
+            match self.jobs[i].child.try_wait() {
+                Ok(None) => i += 1,
+                Ok(Some(status)) => {
+                    let job = self.jobs.remove(i);
+                    println!("[{}] done: {}  {}", job.id, status, job.code);
+                }
+                Err(e) => {
+                    let job = self.jobs.remove(i);
+                    println!("{}: failed to wait for job {}: {}", self.argv0, job.id, e);
+                }
+            }
+        }
+    }
+
+    /// Compiles the items of the session as a test harness and runs it,
+    /// giving the filter, if any, to the harness.
+    fn test_command(&mut self, filter: &str) {
+        let has_tests = self.snippets.iter()
+            .any(|s| s.items.iter().any(|item| item.contains("#[test]")));
+
+        if !has_tests {
+            println!("no tests are defined");
+            return;
+        }
+
+        let runner = match self.executable_runner() {
+            Some(runner) => runner,
+            None => return,
+        };
+
+        let exe = match self.executable_file() {
+            Some(exe) => exe,
+            None => return,
+        };
+
+        let prog = self.build_program(None, "");
+
+        if !self.engine.build_test_executable(prog, exe.path()) {
+            return;
+        }
+
+        let args = if filter.is_empty() {
+            Vec::new()
+        } else {
+            vec![filter.to_owned()]
+        };
+
+        // The harness reports the result of each test
+        if let Err(e) = runner.run(exe.path(), &args) {
+            println!("{}: failed to run tests with `{}`: {}", self.argv0, runner, e);
+        }
+    }
+
+    /// Loads a file each time it changes, until a line of input is entered.
+    /// Items defined by each version of the file replace those defined by
+    /// the previous version.
+    fn watch_command(&mut self, args: &str) {
+        let mut args = args.split_whitespace();
+
+        let path = match args.next() {
+            Some(path) => PathBuf::from(path),
+            None => {
+                println!("command `watch` expects a filename");
+                return;
+            }
+        };
+
+        let function = args.next();
+
+        // Input is read in another thread, as the file must be checked
+        // while waiting for it.
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            let mut line = String::new();
+            let _ = stdin().read_line(&mut line);
+            let _ = tx.send(());
+        });
+
+        println!("watching {}; press Enter to stop", path.display());
+
+        let snippets = self.snippets.len();
+        let mut modified = None;
+
+        loop {
+            let time = metadata(&path).and_then(|m| m.modified()).ok();
+
+            if time.is_some() && time != modified {
+                modified = time;
+                self.snippets.truncate(snippets);
+
+                if self.run_file(&path) {
+                    if let Some(f) = function {
+                        if let Program(input) = parse_program(&format!("{}();", f), false, None) {
+                            self.handle_input(input, false);
+                        }
+                    }
+                }
+            }
+
+            match rx.recv_timeout(Duration::from_millis(WATCH_INTERVAL_MS)) {
+                Err(RecvTimeoutError::Timeout) => (),
+                _ => break,
+            }
+        }
+    }
+
+    /// Begins working through exercises, showing the first.
+    pub fn start_exercises(&mut self, exercises: Vec<Exercise>) {
+        self.exercises = exercises;
+        self.exercise = 0;
+
+        if let Some(exercise) = self.exercises.first() {
+            exercise.print();
+        }
+    }
+
+    /// Shows the current exercise, or selects the named exercise.
+    fn exercise_command(&mut self, name: &str) {
+        if self.exercises.is_empty() {
+            println!("no exercises; start rusti with `--exercise DIR`");
+            return;
+        }
+
+        if !name.is_empty() {
+            match self.exercises.iter().position(|e| e.name == name) {
+                Some(i) => self.exercise = i,
+                None => {
+                    println!("no exercise named `{}`", name);
+                    return;
+                }
+            }
+        }
+
+        match self.exercises.get(self.exercise) {
+            Some(exercise) => exercise.print(),
+            None => println!("all exercises passed"),
+        }
+    }
+
+    /// Runs the check of the current exercise in the session, moving on
+    /// to the next exercise if it passes.
+    fn submit_command(&mut self) {
+        let exercise = match self.exercises.get(self.exercise) {
+            Some(exercise) => exercise.clone(),
+            None => {
+                println!("no exercise to submit");
+                return;
+            }
+        };
+
+        let (status, result) = match capture(|| batch::evaluate(self, &exercise.check)) {
+            Ok((res, _)) => res,
+            Err(e) => {
+                println!("{}: failed to capture output: {}", self.argv0, e);
+                return;
+            }
+        };
+
+        let mut output = result.stdout;
+        output.push_str(&transcript::strip_escapes(&result.stderr));
+
+        if status != "ok" {
+            println!("exercise `{}` failed: check {}", exercise.name, status);
+            print!("{}", output);
+            return;
+        }
+
+        if let Some(value) = result.value {
+            output.push_str(&value);
+            output.push('\n');
+        }
+
+        if let Some(ref expected) = exercise.output {
+            let expected = verify::normalize(expected);
+            let actual = verify::normalize(&output);
+
+            if expected != actual {
+                println!("exercise `{}` failed: output differs:", exercise.name);
+                print!("{}", verify::diff(&expected, &actual));
+                return;
+            }
+        }
+
+        println!("exercise `{}` passed", exercise.name);
+        self.exercise += 1;
+
+        match self.exercises.get(self.exercise) {
+            Some(next) => {
+                println!("");
+                next.print();
+            }
+            None => println!("all exercises passed"),
+        }
+    }
+
+    fn explain_command(&self, args: &str) {
+        let code = match error_code(args) {
+            Some(code) => code,
+            None => {
+                println!("command `explain` expects an error code, such as E0308");
+                return;
+            }
+        };
+
+        match explain_error(&code) {
+            Some(text) => pager::print(text.trim(), self.pager_command().as_ref().map(|s| &s[..])),
+            None => println!("no explanation for error code `{}`", code),
+        }
+    }
+
+    /// Shows the current session, lists sessions, or creates or switches to
+    /// a named session.
+    fn session_command(&mut self, args: &str) {
+        let mut words = args.split_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (None, _, _) => println!("{}", self.session),
+            (Some("list"), None, _) => {
+                let mut names = self.sessions.keys().collect::<Vec<_>>();
+                names.push(&self.session);
+                names.sort();
+
+                for name in names {
+                    let mark = if *name == self.session { '*' } else { ' ' };
+                    println!("{} {}", mark, name);
+                }
+            }
+            (Some("new"), Some(name), None) => self.new_session(name),
+            (Some("switch"), Some(name), None) => self.switch_session(name),
+            _ => println!("command `session` expects `list`, `new <name>` or `switch <name>`"),
+        }
+    }
+
+    /// Creates a session, with no items or values, and switches to it.
+    /// The prelude is run in the new session.
+    fn new_session(&mut self, name: &str) {
+        let mut formatters = self.formatters.clone();
+        formatters.clear_functions();
+
+        let session = Session{
+            formatters: formatters,
+            ..Session::default()
+        };
+
+        if !self.create_session(name, session) {
+            return;
+        }
+
+        self.run_prelude();
+    }
+
+    /// Creates a session with the items, formatters, dependencies and
+    /// checkpoints of the current one, but none of its values, and switches to it.
+    fn fork_command(&mut self, name: &str) {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            println!("command `fork` expects a session name");
+            return;
+        }
+
+        let session = Session{
+            snippets: self.snippets.clone(),
+            formatters: self.formatters.clone(),
+            deps: self.deps.clone(),
+            checkpoints: self.checkpoints.clone(),
+            ..Session::default()
+        };
+
+        self.create_session(name, session);
+    }
+
+    /// Switches to a session of the given state, with a directory of its own
+    /// for artifacts. Returns `false` if a session of the name exists.
+    fn create_session(&mut self, name: &str, mut session: Session) -> bool {
+        if name == self.session || self.sessions.contains_key(name) {
+            println!("session `{}` already exists", name);
+            return false;
+        }
+
+        match artifacts::session_dir(name, self.engine.options().target_dir.as_ref()) {
+            Ok(dir) => session.artifact_dir = Some(dir),
+            Err(e) => {
+                println!("{}: failed to create directory for session: {}", self.argv0, e);
+                return false;
+            }
+        }
+
+        let prev = self.swap_session(session);
+        let prev_name = mem::replace(&mut self.session, name.to_owned());
+
+        self.sessions.insert(prev_name, prev);
+        true
+    }
+
+    /// Switches to the named session, suspending the current one.
+    fn switch_session(&mut self, name: &str) {
+        if name == self.session {
+            return;
+        }
+
+        let next = match self.sessions.remove(name) {
+            Some(next) => next,
+            None => {
+                println!("no session named `{}`; create one with `.session new {}`", name, name);
+                return;
+            }
+        };
+
+        let prev = self.swap_session(next);
+        let prev_name = mem::replace(&mut self.session, name.to_owned());
+
+        self.sessions.insert(prev_name, prev);
+    }
+
+    /// Replaces the state of the current session, returning the state replaced.
+    fn swap_session(&mut self, mut session: Session) -> Session {
+        mem::swap(&mut self.snippets, &mut session.snippets);
+        mem::swap(&mut self.results, &mut session.results);
+        mem::swap(&mut self.formatters, &mut session.formatters);
+        mem::swap(&mut self.failed, &mut session.failed);
+        mem::swap(&mut self.artifact_dir, &mut session.artifact_dir);
+        mem::swap(&mut self.checkpoints, &mut session.checkpoints);
+
+        let deps = mem::replace(&mut session.deps, Vec::new());
+        session.deps = self.set_deps(deps);
+
+        session
+    }
+
+    /// Replaces the directories of dependencies of the current session,
+    /// returning those replaced. Dependencies are only found by the session
+    /// which declared them.
+    fn set_deps(&mut self, deps: Vec<String>) -> Vec<String> {
+        {
+            let old = &self.deps;
+            let lib_paths = &mut self.engine.options_mut().lib_paths;
+
+            lib_paths.retain(|path| !old.contains(path));
+            lib_paths.extend(deps.iter().cloned());
+        }
+
+        mem::replace(&mut self.deps, deps)
+    }
+
+    /// Adds the items, `use` declarations and attributes of another session,
+    /// and its dependencies, to the current session, unless they conflict
+    /// with those of the current session.
+    fn import_command(&mut self, name: &str) {
+        if name.is_empty() {
+            println!("command `import` expects a session name");
+            return;
+        } else if name == self.session {
+            println!("cannot import session `{}` into itself", name);
+            return;
+        }
+
+        let (snippets, deps) = match self.sessions.get(name) {
+            Some(session) => (session.snippets.clone(), session.deps.clone()),
+            None => {
+                println!("no session named `{}`", name);
+                return;
+            }
+        };
+
+        let mut all_deps = self.deps.clone();
+
+        for dep in deps {
+            if !all_deps.contains(&dep) {
+                all_deps.push(dep);
+            }
+        }
+
+        let n = self.snippets.len();
+        let prev_deps = self.set_deps(all_deps);
+
+        self.snippets.extend(snippets);
+
+        let prog = self.build_program(None, "");
+
+        if !self.engine.check(prog) {
+            self.snippets.truncate(n);
+            self.set_deps(prev_deps);
+            println!("items of session `{}` conflict with those of this session", name);
+        }
+    }
+
+    /// Saves the items of the session as the named checkpoint,
+    /// or lists checkpoints.
+    fn checkpoint_command(&mut self, name: &str) {
+        if name.is_empty() {
+            if self.checkpoints.is_empty() {
+                println!("no checkpoints");
+            }
+            for name in self.checkpoints.keys() {
+                println!("{}", name);
+            }
+            return;
+        }
+
+        if name.contains(char::is_whitespace) {
+            println!("command `checkpoint` expects a name");
+            return;
+        }
+
+        self.checkpoints.insert(name.to_owned(), Checkpoint{
+            snippets: self.snippets.clone(),
+            formatters: self.formatters.clone(),
+            results: self.results.addresses(),
+            deps: self.deps.clone(),
+        });
+    }
+
+    /// Restores the items of the session saved by the named checkpoint.
+    /// Values kept since the checkpoint was saved are dropped.
+    fn rollback_command(&mut self, name: &str) {
+        let checkpoint = match self.checkpoints.get(name) {
+            Some(checkpoint) => checkpoint.clone(),
+            None if name.is_empty() => {
+                println!("command `rollback` expects the name of a checkpoint");
+                return;
+            }
+            None => {
+                println!("no checkpoint named `{}`", name);
+                return;
+            }
+        };
+
+        // Values kept before the checkpoint, and not since forgotten, remain
+        let kept = self.results.addresses().iter().zip(&checkpoint.results)
+            .take_while(|&(a, b)| a == b)
+            .count();
+        let dropped = self.results.len() - kept;
+
+        // The code which kept the values is still loaded
+        unsafe { self.results.truncate(kept) };
+
+        // Items added or redefined since the checkpoint are removed
+        let unbound = {
+            let restored = checkpoint.snippets.iter()
+                .flat_map(|s| &s.items)
+                .collect::<HashSet<_>>();
+            let removed = self.snippets.iter()
+                .flat_map(|s| s.items.iter().filter(|item| !restored.contains(item)))
+                .filter_map(|item| finder::item_name(item))
+                .collect::<Vec<_>>();
+
+            self.results.unbind_types(&removed)
+        };
+
+        self.snippets = checkpoint.snippets;
+        self.formatters = checkpoint.formatters;
+        self.failed = None;
+        self.set_deps(checkpoint.deps);
+
+        if dropped != 0 {
+            println!("dropped {} values kept since checkpoint `{}`", dropped, name);
+        }
+
+        report_unbound(&unbound);
+    }
+
+    /// Writes the transcript of the session to the named file as a notebook.
+    fn export_notebook(&self, path: &Path) {
+        match notebook::write(path, &notebook::export(&self.transcript)) {
+            Ok(()) => println!("wrote {} cells to {}", self.transcript.len(), path.display()),
+            Err(e) => println!("{}: failed to write {}: {}", self.argv0, path.display(), e),
+        }
+    }
+
+    /// Writes the transcript of the session to the named file as Markdown.
+    fn export_markdown(&self, path: &Path) {
+        let text = transcript::to_markdown(&self.transcript);
+
+        match File::create(path).and_then(|mut f| f.write_all(text.as_bytes())) {
+            Ok(()) => println!("wrote {} inputs to {}", self.transcript.len(), path.display()),
+            Err(e) => println!("{}: failed to write {}: {}", self.argv0, path.display(), e),
+        }
+    }
+
+    /// Prints an input from history as formatted by `rustfmt`.
+    /// Items defined by the input are replaced by their formatted source.
+    fn fmt_command(&mut self, args: &str) {
+        let n = if args.is_empty() {
+            // The most recent entry is this command
+            self.history.len().saturating_sub(1)
+        } else {
+            match args.parse() {
+                Ok(n) => n,
+                Err(_) => {
+                    println!("command `fmt` expects an input number");
+                    return;
+                }
+            }
+        };
 
-        if input.last_expr && !input.statements.is_empty() {
-            let stmt = input.statements.last_mut().unwrap();
-            if display {
-                *stmt = format!(r#"println!("{{}}", {{ {} }});"#, stmt);
+        let text = match self.history.get(n) {
+            Some(entry) if is_command(entry) => {
+                println!("input {} is a command", n);
+                return;
+            }
+            Some(entry) => entry.to_owned(),
+            None => {
+                println!("no input numbered `{}` in history", n);
+                return;
+            }
+        };
+
+        let code = match rustfmt::format(&text) {
+            Ok(code) => code,
+            Err(e) => {
+                println!("{}: failed to format input: {}", self.argv0, e);
+                return;
+            }
+        };
+
+        println!("{}", code);
+
+        if let Some(pos) = self.snippets.iter().position(|s| s.entry == Some(n)) {
+            if let Program(input) = parse_program(&format!("{}\n", code), false, None) {
+                let snippet = &mut self.snippets[pos];
+
+                snippet.attributes = input.attributes;
+                snippet.view_items = input.view_items;
+                snippet.items = input.items;
+            }
+        }
+    }
+
+    fn edit_command(&mut self, args: &str) {
+        let n = args.parse().ok();
+
+        let text = if args.is_empty() {
+            String::new()
+        } else {
+            match n.and_then(|n| self.history.get(n)) {
+                Some(entry) => entry.to_owned(),
+                None => {
+                    println!("no input numbered `{}` in history", args);
+                    return;
+                }
+            }
+        };
+
+        let code = match editor::edit(&text, &editor::default_editor()) {
+            Ok(code) => code,
+            Err(e) => {
+                println!("{}: failed to edit input: {}", self.argv0, e);
+                return;
+            }
+        };
+
+        if code.trim().is_empty() {
+            return;
+        }
+
+        // Edited input is recorded so that it can be edited again
+        if let Err(e) = self.history.push(&code) {
+            println!("{}: failed to save history: {}", self.argv0, e);
+        }
+
+        let input = match parse_program(&code, false, None) {
+            Program(i) => i,
+            _ => return,
+        };
+
+        // Editing an input which defined items replaces those items
+        let pos = n.and_then(|n| self.snippets.iter().position(|s| s.entry == Some(n)));
+
+        self.entry = Some(self.history.len());
+
+        match pos {
+            Some(pos) => self.replace_snippet(pos, input),
+            None => {
+                self.handle_input(input, false);
+            }
+        }
+    }
+
+    /// Copies the last code input, its output and value, or an entry of
+    /// history to the clipboard.
+    fn copy_command(&self, args: &str) {
+        let last = self.transcript.iter().rev().find(|e| !e.command);
+
+        let text = match args {
+            "" | "input" => last.map(|e| e.input.clone()),
+            "output" => last.map(|e| {
+                let mut text = e.output.clone();
+
+                if let Some(ref value) = e.value {
+                    text.push_str(value);
+                    text.push('\n');
+                }
+
+                text
+            }),
+            _ => match args.parse() {
+                Ok(n) => match self.history.get(n) {
+                    Some(entry) => Some(entry.to_owned()),
+                    None => {
+                        println!("no input numbered `{}` in history", n);
+                        return;
+                    }
+                },
+                Err(_) => {
+                    println!("command `copy` expects `input`, `output` or a number");
+                    return;
+                }
+            },
+        };
+
+        let text = match text {
+            Some(text) => text,
+            None => {
+                println!("no input to copy");
+                return;
+            }
+        };
+
+        match clipboard::copy(&text) {
+            Ok(()) => println!("copied {} lines", text.lines().count()),
+            Err(e) => println!("{}: {}", self.argv0, e),
+        }
+    }
+
+    /// Evaluates code from the clipboard, showing it first, as if it had been
+    /// entered at the prompt.
+    fn paste_eval_command(&mut self) {
+        let code = match clipboard::paste() {
+            Ok(code) => code,
+            Err(e) => {
+                println!("{}: {}", self.argv0, e);
+                return;
+            }
+        };
+
+        if code.trim().is_empty() {
+            println!("the clipboard is empty");
+            return;
+        }
+
+        if use_color(self.engine.options().color) {
+            println!("{}", highlight(&code).trim_right());
+        } else {
+            println!("{}", code.trim_right());
+        }
+
+        // Pasted input is recorded so that it can be edited
+        if let Err(e) = self.history.push(&code) {
+            println!("{}: failed to save history: {}", self.argv0, e);
+        }
+
+        let input = match parse_program(&code, false, None) {
+            Program(i) => i,
+            InputError(Some(e)) => {
+                println!("{}: {}", self.argv0, e);
+                return;
+            }
+            _ => return,
+        };
+
+        self.entry = Some(self.history.len());
+        self.handle_input(input, false);
+    }
+
+    /// Shows or changes environment variables of the process, which are
+    /// seen by code run in the session, the compiler, and child processes.
+    fn env_command(&self, args: &str) {
+        if args.is_empty() {
+            let mut vars = vars_os().collect::<Vec<_>>();
+            vars.sort();
+
+            for (name, value) in vars {
+                println!("{}={}", name.to_string_lossy(), value.to_string_lossy());
+            }
+        } else if args.starts_with("-u ") {
+            for name in args[3..].split_whitespace() {
+                if is_var_name(name) {
+                    remove_var(name);
+                } else {
+                    println!("invalid variable name `{}`", name);
+                }
+            }
+        } else if let Some(pos) = args.find('=') {
+            let name = args[..pos].trim();
+            let value = &args[pos + 1..];
+
+            if name.is_empty() {
+                println!("command `env` expects a variable name");
+            } else if !is_var_name(name) {
+                println!("invalid variable name `{}`", name);
+            } else if value.contains('\0') {
+                println!("the value of a variable may not contain a NUL character");
             } else {
-                *stmt = format!(r#"println!("{{:?}}", {{ {} }});"#, stmt);
+                set_var(name, value);
+            }
+        } else if !is_var_name(args) {
+            println!("invalid variable name `{}`", args);
+        } else {
+            match var_os(args) {
+                Some(value) => println!("{}={}", args, value.to_string_lossy()),
+                None => println!("`{}` is not set", args),
             }
         }
+    }
 
-        let stmts = input.statements.join("\n");
+    fn format_command(&mut self, args: Option<&str>) {
+        let args = args.map(|s| s.trim()).unwrap_or("");
 
-        let prog = self.build_program(
-            Some(&input),
-            &format!(
-                r#"
-#[no_mangle]
-pub fn {name}() {{
-    let _ = std::panic::catch_unwind(_rusti_inner);
-}}
+        if args.is_empty() {
+            if self.formatters.is_empty() {
+                println!("no formatters registered; values are printed using Debug");
+            }
+            for f in self.formatters.iter() {
+                println!("  {:<24} {}", f.type_name, f.function);
+            }
+            return;
+        }
 
-fn _rusti_inner() {{
-{stmts}
-}}
-"#,
-                name = name,
-                stmts = stmts
-            ),
-        );
+        // The formatter is the last word; a type may itself contain spaces.
+        let (ty, function) = match args.rfind(char::is_whitespace) {
+            Some(pos) if is_path(args[pos..].trim()) =>
+                (args[..pos].trim(), Some(args[pos..].trim())),
+            _ => (args, None),
+        };
 
-        if let Some(_) = self.engine.add_module(prog) {
-            let fp = self.engine.get_function(name).unwrap();
-            let f: fn() = unsafe { transmute(fp) };
+        let function = match function {
+            Some(f) => f,
+            None => {
+                if !self.formatters.remove(ty) {
+                    println!("no formatter registered for `{}`", ty);
+                }
+                return;
+            }
+        };
 
-            f();
+        let prev = self.formatters.clone();
 
-            // NOTE: The module cannot be removed after it is run because tasks
-            // may still be running in the module code. This means that rusti's
-            // memory footprint will only grow over time.
-            // Hopefully, this will not be noticeable in normal use.
+        self.formatters.insert(ty, function);
 
-            // Successful compile means we can add the new items to every program
-            self.attributes.extend(input.attributes.into_iter());
-            self.view_items.extend(input.view_items.into_iter());
-            self.items.extend(input.items.into_iter());
+        // Check that the formatter can be used before accepting it
+        let prog = self.build_program(None, "");
+
+        if !self.engine.check(prog) {
+            println!("formatter `{}` cannot be used for `{}`", function, ty);
+            self.formatters = prev;
+        }
+    }
+
+    fn gc_command(&mut self, hours: &str) {
+        let hours = if hours.is_empty() {
+            self.artifact_max_age
+        } else {
+            match hours.parse::<u64>() {
+                Ok(h) => h,
+                Err(_) => {
+                    println!("command `gc` expects a number of hours");
+                    return;
+                }
+            }
+        };
+
+        match artifacts::collect(Duration::from_secs(hours.saturating_mul(3600)),
+                self.engine.options().target_dir.as_ref()) {
+            Ok(c) => println!("removed {} files, {} bytes", c.files, c.bytes),
+            Err(e) => println!("{}: failed to remove artifacts: {}", self.argv0, e),
+        }
+    }
+
+    fn search_command(&mut self, args: &str) {
+        if args.is_empty() {
+            println!("command `search` expects a query or the number of a result");
+            return;
+        }
+
+        if self.offline {
+            println!("crates.io cannot be searched with --offline");
+            return;
+        }
+
+        if let Ok(n) = args.parse::<usize>() {
+            let krate = match n.checked_sub(1).and_then(|i| self.search_results.get(i)) {
+                Some(krate) => krate.clone(),
+                None => {
+                    println!("no search result {}", n);
+                    return;
+                }
+            };
+
+            let manifest = format!("[dependencies]\n{}\n", krate.dependency());
+
+            if self.add_deps(&manifest, &format!("crate `{}`", krate.name)) {
+                println!("added `{}`; use it with `extern crate {};`",
+                    krate.dependency(), krate.crate_name());
+            }
+            return;
+        }
+
+        let crates = match search::search(args) {
+            Ok(crates) => crates,
+            Err(e) => {
+                println!("{}: {}", self.argv0, e);
+                return;
+            }
+        };
+
+        if crates.is_empty() {
+            println!("no crates match `{}`", args);
+        }
+
+        for (i, krate) in crates.iter().enumerate() {
+            match krate.description {
+                Some(ref desc) => println!("{:>2}. {} - {}", i + 1, krate.dependency(), desc),
+                None => println!("{:>2}. {}", i + 1, krate.dependency()),
+            }
+        }
+
+        self.search_results = crates;
+    }
+
+    fn history_command(&self, args: &str) {
+        let (values, pattern) = if args.starts_with("--values") {
+            (true, args["--values".len()..].trim())
+        } else {
+            (false, args)
+        };
+
+        let outputs = self.results.outputs();
+
+        for (n, entry) in self.history.iter() {
+            if !entry.contains(pattern) {
+                continue;
+            }
+
+            let output = outputs.get(&n);
+
+            if values && output.is_none() {
+                continue;
+            }
+
+            let mut lines = entry.lines();
+
+            println!("{:>5}  {}", n, lines.next().unwrap_or(""));
+
+            for line in lines {
+                println!("       {}", line);
+            }
+
+            if let (true, Some(output)) = (values, output) {
+                let mut lines = output.value.lines();
+
+                match output.binding {
+                    Some(ref b) => println!("    = {}: {}", b, lines.next().unwrap_or("")),
+                    None => println!("    = {}", lines.next().unwrap_or("")),
+                }
+
+                for line in lines {
+                    println!("      {}", line);
+                }
+            }
         }
     }
 
@@ -430,6 +4263,340 @@ fn _rusti_inner() {{
         }
     }
 
+    fn set_command(&mut self, args: Option<&str>) {
+        let args = args.map(|s| s.trim()).unwrap_or("");
+
+        if args.is_empty() {
+            self.print_settings();
+            return;
+        }
+
+        let mut words = args.splitn(2, ' ');
+        let name = words.next().unwrap();
+        let value = match words.next().map(|s| s.trim()) {
+            Some("") | None => None,
+            value => value,
+        };
+
+        match name {
+            "auto-import" => {
+                match value.map(parse_bool) {
+                    Some(Some(b)) => self.auto_import = b,
+                    Some(None) => println!("invalid value for `auto-import`: expected `on` or `off`"),
+                    None => self.auto_import = false,
+                }
+            }
+            "hints" => {
+                match value.map(parse_bool) {
+                    Some(Some(b)) => self.show_hints = b,
+                    Some(None) => println!("invalid value for `hints`: expected `on` or `off`"),
+                    None => self.show_hints = false,
+                }
+            }
+            "speculate" => {
+                match value.map(parse_bool) {
+                    Some(Some(b)) => self.speculate = b,
+                    Some(None) => println!("invalid value for `speculate`: expected `on` or `off`"),
+                    None => self.speculate = false,
+                }
+            }
+            "show-types" => {
+                match value.map(parse_bool) {
+                    Some(Some(b)) => self.show_types = b,
+                    Some(None) => println!("invalid value for `show-types`: expected `on` or `off`"),
+                    None => self.show_types = false,
+                }
+            }
+            "warnings" => {
+                match value.map(parse_warnings) {
+                    Some(Some(w)) => self.engine.options_mut().warnings = w,
+                    Some(None) => println!("invalid value for `warnings`: \
+                        expected one of deny, show, hide"),
+                    None => self.engine.options_mut().warnings = Warnings::Show,
+                }
+            }
+            "verbosity" => {
+                match value.map(parse_verbosity) {
+                    Some(Some(v)) => self.engine.options_mut().verbosity = v,
+                    Some(None) => println!("invalid value for `verbosity`: \
+                        expected one of quiet, normal, debug"),
+                    None => self.engine.options_mut().verbosity = Verbosity::Normal,
+                }
+            }
+            "opt-level" => {
+                match value.map(parse_opt_level) {
+                    Some(Some(level)) => self.engine.options_mut().opt_level = level,
+                    Some(None) => println!("invalid opt-level: expected one of 0, 1, 2, 3, s, z"),
+                    None => self.engine.options_mut().opt_level = OptLevel::No,
+                }
+            }
+            "rustc-wrapper" => {
+                self.engine.options_mut().rustc_wrapper = match value {
+                    None | Some("none") => None,
+                    Some(wrapper) => Some(PathBuf::from(wrapper)),
+                };
+            }
+            "runner" | "remote" if self.sandboxed => {
+                println!("`{}` cannot be changed in sandbox mode", name);
+            }
+            "runner" => {
+                self.runner = value.and_then(Runner::parse_command);
+            }
+            "thin-lto" => {
+                match value.map(parse_bool) {
+                    Some(Some(b)) => self.engine.options_mut().thin_lto = b,
+                    Some(None) => println!("invalid value for `thin-lto`: expected `on` or `off`"),
+                    None => self.engine.options_mut().thin_lto = true,
+                }
+            }
+            "unload" => {
+                match value.map(parse_bool) {
+                    Some(Some(b)) => self.unload_on_reset = b,
+                    Some(None) => println!("invalid value for `unload`: expected `on` or `off`"),
+                    None => self.unload_on_reset = false,
+                }
+            }
+            "remote" => {
+                self.runner = value.map(|host| Runner::Ssh(host.to_owned()));
+            }
+            "target" => {
+                self.engine.options_mut().target = value.map(|s| s.to_owned());
+            }
+            "sanitizer" => {
+                match value {
+                    None | Some("off") => self.engine.options_mut().sanitizer = None,
+                    Some(name) => match parse_sanitizer(name) {
+                        Some(s) => self.engine.options_mut().sanitizer = Some(s),
+                        None => println!("invalid value for `sanitizer`: \
+                            expected one of address, leak, thread, off"),
+                    },
+                }
+            }
+            "graphics" => {
+                match value {
+                    None | Some("off") => self.graphics = None,
+                    Some("auto") => {
+                        self.graphics = Protocol::detect();
+
+                        if self.graphics.is_none() {
+                            println!("no supported graphics protocol detected");
+                        }
+                    }
+                    Some(name) => match Protocol::parse(name) {
+                        Some(p) => self.graphics = Some(p),
+                        None => println!("invalid value for `graphics`: \
+                            expected one of auto, iterm, kitty, sixel, off"),
+                    },
+                }
+            }
+            "jobs" => {
+                match value.map(|v| v.parse::<usize>()) {
+                    Some(Ok(n)) if n > 0 => self.engine.options_mut().jobs = Some(n),
+                    Some(_) => println!("invalid value for `jobs`: expected a positive number"),
+                    None => self.engine.options_mut().jobs = None,
+                }
+            }
+            "keybindings" => {
+                match value.map(KeyBindings::parse) {
+                    Some(Some(k)) => self.line_editing = k,
+                    Some(None) => println!("invalid value for `keybindings`: \
+                        expected `emacs` or `vi`"),
+                    None => self.line_editing = KeyBindings::Emacs,
+                }
+            }
+            "link-arg" => {
+                match value {
+                    Some(arg) => self.engine.options_mut().link_args.push(arg.to_owned()),
+                    // The argument selecting `lld` belongs to the `linker` setting
+                    None => self.engine.options_mut().link_args
+                        .retain(|arg| arg.starts_with("-fuse-ld=")),
+                }
+
+                if value.is_some() {
+                    self.note_linker_unused();
+                }
+            }
+            "linker" => {
+                {
+                    let opts = self.engine.options_mut();
+
+                    opts.link_args.retain(|arg| !arg.starts_with("-fuse-ld="));
+
+                    match value {
+                        None => opts.linker = None,
+                        // `lld` is selected through the default linker driver,
+                        // which knows how to invoke it for the target.
+                        Some("lld") if !cfg!(windows) => {
+                            opts.linker = None;
+                            opts.link_args.push("-fuse-ld=lld".to_owned());
+                        }
+                        Some(linker) => opts.linker = Some(PathBuf::from(linker)),
+                    }
+                }
+
+                if value.is_some() {
+                    self.note_linker_unused();
+                }
+            }
+            "max-output" => {
+                match value.map(|v| v.parse::<usize>()) {
+                    Some(Ok(n)) => self.max_output = Some(n),
+                    Some(Err(_)) => println!("invalid max-output: expected a number"),
+                    None => self.max_output = None,
+                }
+            }
+            "pty" => {
+                match value.map(parse_bool) {
+                    Some(Some(b)) => self.pty = b,
+                    Some(None) => println!("invalid value for `pty`: expected `on` or `off`"),
+                    None => self.pty = false,
+                }
+            }
+            "prompt" => self.prompt = prompt::parse_template(value),
+            "pager" => {
+                match value {
+                    Some("off") => self.paging = false,
+                    Some(cmd) => {
+                        self.paging = true;
+                        self.pager = Some(cmd.to_owned());
+                    }
+                    None => {
+                        self.paging = true;
+                        self.pager = None;
+                    }
+                }
+            }
+            _ => println!("unrecognized setting: {}", name),
+        }
+    }
+
+    fn print_settings(&self) {
+        let opts = self.engine.options();
+
+        println!("  {:<16} {}", "auto-import", bool_name(self.auto_import));
+        println!("  {:<16} {}", "graphics", self.graphics.as_ref()
+            .map(|p| p.to_string()).unwrap_or_else(|| "off".to_owned()));
+        println!("  {:<16} {}", "hints", bool_name(self.show_hints));
+        println!("  {:<16} {}", "jobs", opts.jobs
+            .map(|n| n.to_string()).unwrap_or_else(|| "none".to_owned()));
+        println!("  {:<16} {}", "keybindings", self.line_editing.name());
+        println!("  {:<16} {}", "linker", opts.linker_name());
+        println!("  {:<16} {}", "max-output", self.max_output
+            .map(|n| n.to_string()).unwrap_or_else(|| "none".to_owned()));
+        println!("  {:<16} {}", "opt-level", opt_level_name(opts.opt_level));
+        println!("  {:<16} {}", "pager", if !self.paging {
+            "off"
+        } else {
+            self.pager.as_ref().map(|s| &s[..]).unwrap_or("default")
+        });
+        println!("  {:<16} {:?}", "prompt", self.prompt);
+        println!("  {:<16} {}", "pty", bool_name(self.pty));
+        println!("  {:<16} {}", "runner", self.runner.as_ref()
+            .map(|r| r.to_string()).unwrap_or_else(|| "none".to_owned()));
+        println!("  {:<16} {}", "rustc-wrapper", opts.rustc_wrapper.as_ref()
+            .map(|p| p.display().to_string()).unwrap_or_else(|| "none".to_owned()));
+        println!("  {:<16} {}", "sanitizer", opts.sanitizer.as_ref()
+            .map(sanitizer_name).unwrap_or("off"));
+        println!("  {:<16} {}", "show-types", bool_name(self.show_types));
+        println!("  {:<16} {}", "speculate", bool_name(self.speculate));
+        println!("  {:<16} {}", "target", opts.target.as_ref()
+            .map(|s| &s[..]).unwrap_or("host"));
+        println!("  {:<16} {}", "thin-lto", bool_name(opts.thin_lto));
+        println!("  {:<16} {}", "unload", bool_name(self.unload_on_reset));
+        println!("  {:<16} {}", "verbosity", verbosity_name(opts.verbosity));
+        println!("  {:<16} {}", "warnings", warnings_name(opts.warnings));
+    }
+
+    fn shell_command(&self, cmd: &str) {
+        if self.sandboxed {
+            println!("shell commands are disabled in sandbox mode");
+            return;
+        }
+
+        let mut command = if cfg!(windows) {
+            let mut c = process::Command::new("cmd");
+            c.arg("/C");
+            c
+        } else {
+            let mut c = process::Command::new("sh");
+            c.arg("-c");
+            c
+        };
+
+        // Output of the command may otherwise appear before earlier output
+        let _ = stdout().flush();
+
+        match command.arg(cmd).status() {
+            Ok(ref status) if status.success() => (),
+            Ok(status) => println!("{}: command exited with {}", self.argv0, status),
+            Err(e) => println!("{}: failed to run command: {}", self.argv0, e),
+        }
+    }
+
+    fn stats_command(&self) {
+        println!("  {:<16} {}", "modules", self.engine.module_count());
+        println!("  {:<16} {}", "libraries", self.engine.loaded_libraries().len());
+        println!("  {:<16} {}", "linker", self.engine.last_linker().unwrap_or("none"));
+
+        let last = self.engine.last_phases();
+        let total = self.engine.total_phases();
+
+        println!("");
+        println!("  {:<16} {:>12} {:>12}", "phase", "last", "total");
+
+        for (&(name, l), &(_, t)) in last.phases().iter().zip(total.phases().iter()) {
+            println!("  {:<16} {:>12} {:>12}", name, format_millis(l), format_millis(t));
+        }
+
+        println!("  {:<16} {:>12} {:>12}", "total",
+            format_millis(last.total()), format_millis(total.total()));
+
+        let last = self.engine.last_heap();
+        let total = self.engine.total_heap();
+
+        println!("");
+        println!("  {:<16} {:>12} {:>12}", "heap", "last", "total");
+        println!("  {:<16} {:>12} {:>12}", "allocations", last.allocations, total.allocations);
+        println!("  {:<16} {:>12} {:>12}", "bytes", last.bytes, total.bytes);
+        println!("  {:<16} {:>12} {:>12}", "peak bytes", last.peak, total.peak);
+    }
+
+    fn targets_command(&self) {
+        let current = self.engine.options().target_triple();
+
+        for triple in supported_targets() {
+            if triple == current {
+                println!("{} (current)", triple);
+            } else if triple == host_triple() {
+                println!("{} (host)", triple);
+            } else {
+                println!("{}", triple);
+            }
+        }
+    }
+
+    fn target_features_command(&self) {
+        let opts = self.engine.options().clone();
+
+        let enabled = match target_features(opts.clone()) {
+            Some(features) => features,
+            None => return,
+        };
+
+        println!("target:  {}", opts.target_triple());
+
+        if enabled.is_empty() {
+            println!("enabled: none");
+        } else {
+            println!("enabled: {}", enabled.join(", "));
+        }
+
+        println!("");
+        let _ = stdout().flush();
+
+        print_target_features(opts);
+    }
+
     /*fn expr_type(&self, fn_name: &str, prog: String) -> Option<String> {
         let fn_name = fn_name.to_owned();
 
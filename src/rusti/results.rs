@@ -0,0 +1,322 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Values of previous input expressions, kept alive for later input.
+//!
+//! When input ending in an expression is run in the execution engine,
+//! its value is moved to the heap and handed to rusti, along with the name
+//! of its type. Later input is compiled with bindings `res0`, `res1`, ...
+//! referring to each kept value, and `res` referring to the most recent one.
+//! Bindings are references, so values can be inspected but not moved out.
+//!
+//! The formatted value of each input is also kept, by the number of the
+//! input in history, and later input can refer to it as `out[N]`, a `str`.
+//! `out` is only bound for input which refers to it, and refers to the
+//! formatted values where rusti keeps them, rather than repeating them in
+//! the source of each input.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Name of the binding referring to the most recent value
+pub const LATEST: &'static str = "res";
+
+/// Name of the binding indexing formatted values by input number
+pub const OUTPUTS: &'static str = "out";
+
+/// Function which drops a value kept on the heap
+pub type DropFn = unsafe extern "C" fn(*mut u8);
+
+/// A value kept on the heap
+#[derive(Debug)]
+pub struct Kept {
+    /// Address of the value
+    pub ptr: *mut u8,
+    /// Drops the value and frees its memory
+    pub drop: DropFn,
+    /// Name of the value's type
+    pub type_name: String,
+}
+
+/// The formatted value of an input
+#[derive(Clone, Debug, PartialEq)]
+pub struct Output {
+    /// Value, as formatted by `Debug`
+    pub value: String,
+    /// Binding referring to the value itself, if it was kept
+    pub binding: Option<String>,
+}
+
+/// Values of previous input expressions
+#[derive(Debug, Default)]
+pub struct Results {
+    values: Vec<Kept>,
+    /// Indices of values which are kept but no longer bound,
+    /// as their type is no longer defined
+    unbound: BTreeSet<usize>,
+    outputs: BTreeMap<usize, Output>,
+}
+
+impl Results {
+    /// Returns an empty set of results.
+    pub fn new() -> Results {
+        Results::default()
+    }
+
+    /// Returns the number of kept values.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Adds a value, returning the name of its binding.
+    /// Values of the unit type are dropped rather than kept.
+    pub fn push(&mut self, kept: Kept) -> Option<String> {
+        if kept.type_name == "()" {
+            unsafe { (kept.drop)(kept.ptr) };
+            return None;
+        }
+
+        self.values.push(kept);
+        Some(format!("res{}", self.values.len() - 1))
+    }
+
+    /// Records the formatted value of input number `n`, along with the
+    /// binding referring to the value, if it was kept.
+    pub fn push_output(&mut self, n: usize, value: String, binding: Option<String>) {
+        self.outputs.insert(n, Output{
+            value: value,
+            binding: binding,
+        });
+    }
+
+    /// Returns the formatted values of inputs, by input number.
+    pub fn outputs(&self) -> &BTreeMap<usize, Output> {
+        &self.outputs
+    }
+
+    /// Drops all kept values.
+    ///
+    /// # Safety
+    ///
+    /// The code which kept the values must still be loaded, and no references
+    /// to the values may remain.
+    pub unsafe fn clear(&mut self) {
+        for kept in self.values.drain(..) {
+            (kept.drop)(kept.ptr);
+        }
+
+        self.unbound.clear();
+        self.outputs.clear();
+    }
+
+    /// Returns the address of each kept value, identifying it.
+    pub fn addresses(&self) -> Vec<usize> {
+        self.values.iter().map(|kept| kept.ptr as usize).collect()
+    }
+
+    /// Drops kept values following the first `len`, most recent first.
+    ///
+    /// # Safety
+    ///
+    /// As for `clear`.
+    pub unsafe fn truncate(&mut self, len: usize) {
+        while self.values.len() > len {
+            let kept = self.values.pop().unwrap();
+            (kept.drop)(kept.ptr);
+        }
+
+        self.unbound = self.unbound.iter().cloned().filter(|&i| i < len).collect();
+        self.forget_bindings();
+    }
+
+    /// Forgets all kept values without dropping them.
+    pub fn forget(&mut self) {
+        self.values.clear();
+        self.unbound.clear();
+        self.forget_bindings();
+    }
+
+    /// Removes the bindings of kept values whose type refers to any of the
+    /// named items, which are about to be removed or redefined, returning
+    /// the indices of the values unbound. The values remain kept, and are
+    /// dropped with the others.
+    pub fn unbind_types(&mut self, items: &[&str]) -> Vec<usize> {
+        let unbind = self.values.iter().enumerate()
+            .filter(|&(i, kept)| !self.unbound.contains(&i) &&
+                items.iter().any(|item| mentions(&kept.type_name, item)))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        self.unbound.extend(unbind.iter().cloned());
+        unbind
+    }
+
+    /// Restores the bindings of values unbound by `unbind_types`,
+    /// if the items they refer to were not removed after all.
+    pub fn rebind(&mut self, indices: &[usize]) {
+        for i in indices {
+            self.unbound.remove(i);
+        }
+    }
+
+    /// Forgets the bindings of outputs whose values are no longer kept.
+    fn forget_bindings(&mut self) {
+        let len = self.values.len();
+
+        for output in self.outputs.values_mut() {
+            let kept = output.binding.as_ref()
+                .and_then(|b| b[3..].parse::<usize>().ok())
+                .map_or(false, |i| i < len);
+
+            if !kept {
+                output.binding = None;
+            }
+        }
+    }
+
+    /// Returns statements binding a reference to each kept value
+    /// whose type can be named in source and, if `outputs` is `true`,
+    /// binding `out` to the formatted values.
+    pub fn bindings(&self, outputs: bool) -> String {
+        let mut res = String::new();
+        let mut latest = None;
+
+        for (i, kept) in self.values.iter().enumerate() {
+            if !is_nameable(&kept.type_name) || self.unbound.contains(&i) {
+                latest = None;
+                continue;
+            }
+
+            res.push_str(&format!(
+                "let res{i}: &{ty} = unsafe {{ &*({ptr} as *const {ty}) }};\n",
+                i = i, ty = kept.type_name, ptr = kept.ptr as usize));
+            latest = Some(i);
+        }
+
+        if let Some(i) = latest {
+            res.push_str(&format!("let {} = res{};\n", LATEST, i));
+        }
+
+        if outputs && !self.outputs.is_empty() {
+            res.push_str("struct _RustiOut;\n\
+                impl ::std::ops::Index<usize> for _RustiOut {\n\
+                type Output = str;\n\
+                fn index(&self, n: usize) -> &str {\n\
+                match n {\n");
+
+            // Values are kept until the results are cleared, so they outlive
+            // the code compiled with these bindings
+            for (n, output) in &self.outputs {
+                res.push_str(&format!("{} => unsafe {{ ::std::str::from_utf8_unchecked(\
+                    ::std::slice::from_raw_parts({} as *const u8, {})) }},\n",
+                    n, output.value.as_ptr() as usize, output.value.len()));
+            }
+
+            res.push_str(&format!("_ => panic!(\"no output numbered {{}}\", n),\n\
+                }}\n}}\n}}\nlet {} = _RustiOut;\n", OUTPUTS));
+        }
+
+        res
+    }
+
+    /// Returns the name of the type of the value referred to by a binding
+    /// made by `bindings`, or `None` if there is no such binding.
+    pub fn binding_type(&self, name: &str) -> Option<&str> {
+        let i = if name == LATEST {
+            self.values.len().checked_sub(1)
+        } else if name.starts_with("res") {
+            name[3..].parse::<usize>().ok()
+        } else {
+            None
+        };
+
+        match i.and_then(|i| self.values.get(i).map(|kept| (i, kept))) {
+            Some((i, kept)) if is_nameable(&kept.type_name) && !self.unbound.contains(&i) =>
+                Some(&kept.type_name),
+            _ => None,
+        }
+    }
+}
+
+/// Returns whether code, or a type name as produced by `type_name`, refers
+/// to `name` as a name of the crate root or a local binding, rather than
+/// within a path or as a field or method.
+pub fn mentions(code: &str, name: &str) -> bool {
+    let ident = |c: char| c.is_alphanumeric() || c == '_';
+
+    code.match_indices(name).any(|(i, _)| {
+        !code[..i].ends_with(|c: char| ident(c) || c == ':' || c == '.') &&
+            !code[i + name.len()..].starts_with(|c: char| ident(c))
+    })
+}
+
+/// Returns whether a type name, as produced by `type_name`,
+/// can be written in source; closures and other anonymous types cannot.
+fn is_nameable(ty: &str) -> bool {
+    !ty.is_empty() && !ty.contains('@') && !ty.contains('{') && !ty.contains("impl ")
+}
+
+#[cfg(test)]
+mod test {
+    use std::ptr;
+
+    use super::{mentions, Kept, Results};
+
+    unsafe extern "C" fn drop_nothing(_: *mut u8) {}
+
+    fn kept(type_name: &str) -> Kept {
+        Kept{
+            ptr: ptr::null_mut(),
+            drop: drop_nothing,
+            type_name: type_name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_outputs() {
+        let mut results = Results::new();
+        assert_eq!(results.bindings(true), "");
+
+        results.push_output(2, "\"a\\nb\"".to_owned(), None);
+
+        let value = &results.outputs()[&2].value;
+        let bindings = results.bindings(true);
+        assert!(bindings.contains(&format!("from_raw_parts({} as *const u8, 6)",
+            value.as_ptr() as usize)));
+        assert!(!bindings.contains("a\\nb"));
+        assert!(bindings.ends_with("let out = _RustiOut;\n"));
+        assert_eq!(results.bindings(false), "");
+    }
+
+    #[test]
+    fn test_unbind_types() {
+        let mut results = Results::new();
+        results.push(kept("i32"));
+        results.push(kept("std::vec::Vec<Point>"));
+
+        assert_eq!(results.unbind_types(&["Point"]), [1]);
+        assert_eq!(results.binding_type("res0"), Some("i32"));
+        assert_eq!(results.binding_type("res1"), None);
+        assert!(!results.bindings(false).contains("res1"));
+        assert!(!results.bindings(false).contains("let res ="));
+
+        results.rebind(&[1]);
+        assert_eq!(results.binding_type("res"), Some("std::vec::Vec<Point>"));
+    }
+
+    #[test]
+    fn test_mentions() {
+        assert!(mentions("Point", "Point"));
+        assert!(mentions("std::vec::Vec<Point>", "Point"));
+        assert!(mentions("shapes::Circle", "shapes"));
+        assert!(!mentions("Point3", "Point"));
+        assert!(!mentions("geo::Point", "Point"));
+        assert!(!mentions("&str", "Point"));
+        assert!(mentions("out[3].len()", "out"));
+        assert!(!mentions("stdout().flush(); x.out", "out"));
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Runs compiled executables outside of the rusti process.
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+
+use sandbox::Policy;
+
+/// Returns a shell script run on a remote host to receive an executable
+/// on `stdin`, run it with the given arguments, and remove it afterward
+fn remote_script(args: &[String]) -> String {
+    let args = args.iter()
+        .map(|a| format!(" '{}'", a.replace('\'', r"'\''")))
+        .collect::<String>();
+
+    format!(r#"f=$(mktemp) && cat > "$f" && chmod +x "$f" && "$f"{}; s=$?; rm -f "$f"; exit $s"#,
+        args)
+}
+
+/// Describes how a compiled executable is run
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Runner {
+    /// Run a local command with the path of the executable, followed by
+    /// its arguments; e.g. an emulator such as `qemu-arm`
+    Command(Vec<String>),
+    /// Run the executable directly, as a child process
+    Local,
+    /// Run the executable in a sandbox, without network access, which
+    /// may only access the paths which the policy allows
+    Sandbox(Policy),
+    /// Copy the executable to a remote host and run it there, using `ssh`
+    Ssh(String),
+}
+
+impl Runner {
+    /// Parses a runner command, consisting of a program name
+    /// and its arguments, separated by whitespace.
+    /// Returns `None` if the command is empty.
+    pub fn parse_command(cmd: &str) -> Option<Runner> {
+        let args = cmd.split_whitespace().map(|s| s.to_owned()).collect::<Vec<_>>();
+
+        if args.is_empty() {
+            None
+        } else {
+            Some(Runner::Command(args))
+        }
+    }
+
+    /// Runs the executable at the given path with the given arguments,
+    /// waiting for it to finish. Output from the executable is written to
+    /// rusti's `stdout` and `stderr` as it is produced.
+    pub fn run(&self, exe: &Path, exe_args: &[String]) -> io::Result<ExitStatus> {
+        self.command(exe, exe_args)?.status()
+    }
+
+    /// Starts the executable at the given path with the given arguments,
+    /// without waiting for it to finish. The executable does not read from
+    /// rusti's `stdin`, which remains in use by rusti.
+    pub fn spawn(&self, exe: &Path, exe_args: &[String]) -> io::Result<Child> {
+        let mut cmd = self.command(exe, exe_args)?;
+
+        // `ssh` reads the executable itself from `stdin`
+        match *self {
+            Runner::Ssh(_) => (),
+            _ => {
+                cmd.stdin(Stdio::null());
+            }
+        }
+
+        cmd.spawn()
+    }
+
+    /// Returns a command which runs the executable at the given path
+    fn command(&self, exe: &Path, exe_args: &[String]) -> io::Result<Command> {
+        let mut cmd;
+
+        match *self {
+            Runner::Command(ref args) => {
+                cmd = Command::new(&args[0]);
+                cmd.args(&args[1..]).arg(exe).args(exe_args);
+            }
+            Runner::Local => {
+                cmd = Command::new(exe);
+                cmd.args(exe_args);
+            }
+            Runner::Sandbox(ref policy) => {
+                // Only the executable itself is mounted; its directory may
+                // hold files of other users or sessions, such as in `/tmp`
+                cmd = policy.command(exe, &[exe]);
+                cmd.args(exe_args);
+            }
+            Runner::Ssh(ref host) => {
+                cmd = Command::new("ssh");
+                cmd.arg(host)
+                    .arg(remote_script(exe_args))
+                    .stdin(File::open(exe)?);
+            }
+        }
+
+        Ok(cmd)
+    }
+}
+
+impl fmt::Display for Runner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Runner::Command(ref args) => write!(f, "{}", args.join(" ")),
+            Runner::Local => f.write_str("local"),
+            Runner::Sandbox(ref policy) => write!(f, "sandbox {}", policy.dir.display()),
+            Runner::Ssh(ref host) => write!(f, "ssh {}", host),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Formats input using `rustfmt`.
+//!
+//! `rustfmt` formats whole source files, in which statements may not appear
+//! outside of a function. Input is therefore formatted as the body of a
+//! function, which is removed from the output.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Command run to format code
+const RUSTFMT: &'static str = "rustfmt";
+
+/// Name of the function wrapping formatted input
+const WRAPPER: &'static str = "_rusti_fmt";
+
+/// Indentation added to the body of a function by `rustfmt`
+const INDENT: &'static str = "    ";
+
+/// Returns input formatted by `rustfmt`.
+pub fn format(input: &str) -> io::Result<String> {
+    let mut child = Command::new(RUSTFMT)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        write!(stdin, "fn {}() {{\n{}\n}}\n", WRAPPER, input)?;
+    }
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        let msg = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::new(io::ErrorKind::Other,
+            format!("`{}` exited with {}: {}", RUSTFMT, output.status, msg.trim())));
+    }
+
+    unwrap(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+            format!("unexpected output from `{}`", RUSTFMT)))
+}
+
+/// Removes the wrapping function from formatted output.
+fn unwrap(output: &str) -> Option<String> {
+    let output = output.trim_right();
+
+    if output == format!("fn {}() {{}}", WRAPPER) {
+        return Some(String::new());
+    }
+
+    if !output.starts_with(&format!("fn {}() {{\n", WRAPPER)) || !output.ends_with("\n}") {
+        return None;
+    }
+
+    let body = &output[output.find('\n').unwrap() + 1..output.len() - 2];
+    let lines = body.lines()
+        .map(|line| if line.starts_with(INDENT) { &line[INDENT.len()..] } else { line })
+        .collect::<Vec<_>>();
+
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::unwrap;
+
+    #[test]
+    fn test_unwrap() {
+        assert_eq!(unwrap("fn _rusti_fmt() {}\n"), Some(String::new()));
+        assert_eq!(unwrap("fn _rusti_fmt() {\n    fn foo() {\n        1\n    }\n    foo()\n}\n"),
+            Some("fn foo() {\n    1\n}\nfoo()".to_owned()));
+        assert_eq!(unwrap("fn foo() {}\n"), None);
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Isolation of untrusted code, as by `--sandbox`.
+//!
+//! Programs are run by Bubblewrap in namespaces of their own, without
+//! network access. Directories of the system are mounted read-only, and
+//! other paths only as a `Policy` allows; those which are not mounted do
+//! not exist within the sandbox.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Program which runs commands in a sandbox
+pub const BWRAP: &'static str = "bwrap";
+
+/// Directories of the system mounted, read-only, in the sandbox, if they exist
+const SYSTEM_DIRS: &'static [&'static str] = &["/bin", "/etc", "/lib", "/lib32", "/lib64",
+    "/usr"];
+
+/// Paths which code run in a sandbox may access, besides those of the system
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Policy {
+    /// Working directory, which may be written
+    pub dir: PathBuf,
+    /// Further paths which may be read
+    pub read: Vec<PathBuf>,
+    /// Further paths which may be read and written
+    pub write: Vec<PathBuf>,
+}
+
+impl Policy {
+    /// Returns a policy under which only `dir` may be accessed.
+    pub fn new(dir: PathBuf) -> Policy {
+        Policy{
+            dir: dir,
+            read: Vec::new(),
+            write: Vec::new(),
+        }
+    }
+
+    /// Returns a command which runs `program` in a sandbox, where it may
+    /// also read the paths in `readable`.
+    pub fn command<S: AsRef<OsStr>>(&self, program: S, readable: &[&Path]) -> Command {
+        let mut cmd = Command::new(BWRAP);
+
+        // A session of its own keeps the program from injecting terminal input
+        cmd.args(&["--unshare-all", "--die-with-parent", "--new-session"]);
+
+        for d in SYSTEM_DIRS {
+            cmd.args(&["--ro-bind-try", d, d]);
+        }
+
+        cmd.args(&["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp"]);
+
+        for path in readable.iter().cloned().chain(self.read.iter().map(|p| p.as_path())) {
+            cmd.arg("--ro-bind").arg(path).arg(path);
+        }
+
+        for path in Some(&self.dir).into_iter().chain(&self.write) {
+            cmd.arg("--bind").arg(path).arg(path);
+        }
+
+        cmd.arg("--chdir").arg(&self.dir)
+            .arg("--")
+            .arg(program);
+
+        cmd
+    }
+
+    /// Returns a note explaining which paths may be accessed, shown when
+    /// a program run in the sandbox fails, as it may have been denied access.
+    pub fn note(&self) -> String {
+        let join = |paths: &[&Path]| paths.iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut write = vec![self.dir.as_path()];
+        write.extend(self.write.iter().map(|p| p.as_path()));
+
+        let mut res = format!("note: code in the sandbox may only write to {}", join(&write));
+
+        if !self.read.is_empty() {
+            let read = self.read.iter().map(|p| p.as_path()).collect::<Vec<_>>();
+            res.push_str(&format!(", and read {}", join(&read)));
+        }
+
+        res.push_str(", besides reading directories of the system; \
+            other paths do not exist within it");
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use super::Policy;
+
+    #[test]
+    fn test_command() {
+        let mut policy = Policy::new(PathBuf::from("/home/u/s"));
+        policy.write.push(PathBuf::from("/srv/out"));
+
+        let cmd = format!("{:?}", policy.command("/tmp/exe", &[Path::new("/tmp/exe")]));
+
+        assert!(cmd.starts_with(r#""bwrap" "--unshare-all""#));
+        assert!(cmd.ends_with(r#""--ro-bind" "/tmp/exe" "/tmp/exe" "--bind" "/home/u/s" "/home/u/s" "--bind" "/srv/out" "/srv/out" "--chdir" "/home/u/s" "--" "/tmp/exe""#));
+    }
+
+    #[test]
+    fn test_note() {
+        let mut policy = Policy::new(PathBuf::from("/s"));
+
+        assert_eq!(policy.note(), "note: code in the sandbox may only write to /s, besides \
+            reading directories of the system; other paths do not exist within it");
+
+        policy.read.push(PathBuf::from("/data"));
+        policy.write.push(PathBuf::from("/out"));
+
+        assert!(policy.note().starts_with("note: code in the sandbox may only write to /s, /out, \
+            and read /data, besides"));
+    }
+}
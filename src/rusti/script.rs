@@ -0,0 +1,232 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Running Rust programs as scripts, as `#!/usr/bin/env rusti`.
+//!
+//! A file beginning with a shebang line is a script: a complete program
+//! defining `main`, rather than rusti input. It is compiled into an
+//! executable, which is cached under the hash of the script's contents,
+//! and executed with the arguments following the script's path.
+//!
+//! Comment lines at the start of a script, beginning with `// rusti:`,
+//! give options used to compile it. The only option is `-L PATH`, which adds
+//! a directory, relative to that of the script, to the library search path.
+//! Crates from crates.io may be declared in a manifest header; see `deps`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{create_dir_all, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::Command;
+
+use tempfile::NamedTempFileOptions;
+
+use artifacts::{build_dir, env_target_dir};
+use deps;
+use exec::{build_program, ExecOptions};
+use version;
+
+/// Prefix of comment lines giving options used to compile a script
+const OPTIONS_PREFIX: &'static str = "// rusti:";
+
+/// Returns whether the named file is a script; that is, whether its first
+/// line is a shebang rather than an inner attribute.
+pub fn is_script(path: &Path) -> bool {
+    let mut line = String::new();
+
+    match File::open(path) {
+        Ok(f) => if BufReader::new(f).read_line(&mut line).is_err() {
+            return false;
+        },
+        Err(_) => return false,
+    }
+
+    line.starts_with("#!") && !line.starts_with("#![")
+}
+
+/// Compiles the script at `path`, unless it is cached, and executes it with
+/// the given arguments. Returns desired process exit status, if the script's
+/// executable cannot replace this process.
+pub fn run(argv0: &str, path: &Path, args: &[String]) -> i32 {
+    let mut text = String::new();
+
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut text)) {
+        println!("{}: failed to read {}: {}", argv0, path.display(), e);
+        return 1;
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+
+    let libs = match lib_paths(&text, dir) {
+        Ok(libs) => libs,
+        Err(e) => {
+            println!("{}: {}: {}", argv0, path.display(), e);
+            return 1;
+        }
+    };
+
+    let cache = match build_dir(env_target_dir().as_ref()) {
+        Some(cache) => cache.join("scripts"),
+        None => {
+            println!("{}: cannot find a directory in which to cache scripts", argv0);
+            return 1;
+        }
+    };
+
+    if let Err(e) = create_dir_all(&cache) {
+        println!("{}: failed to create {}: {}", argv0, cache.display(), e);
+        return 1;
+    }
+
+    let exe = cache.join(format!("{:016x}", cache_key(&text, &libs)));
+
+    if !exe.is_file() && !build(argv0, path, &exe, libs, deps::manifest(&text)) {
+        return 1;
+    }
+
+    execute(argv0, &exe, args)
+}
+
+/// Compiles a script into an executable at the path `exe`, after building
+/// the dependencies declared by its manifest header, if any.
+/// The executable is written elsewhere and moved into place once complete,
+/// so that a script run concurrently never sees a partial executable.
+fn build(argv0: &str, script: &Path, exe: &Path, libs: Vec<String>,
+        manifest: Option<String>) -> bool {
+    let dir = exe.parent().expect("no cache directory");
+
+    let file = match NamedTempFileOptions::new().prefix(".build-").create_in(dir) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("{}: failed to create executable file: {}", argv0, e);
+            return false;
+        }
+    };
+
+    let mut opts = ExecOptions::new(libs, None);
+
+    if let Some(manifest) = manifest {
+        match deps::build(&manifest, &opts, false) {
+            Ok(dir) => opts.lib_paths.push(dir.to_string_lossy().into_owned()),
+            Err(e) => {
+                println!("{}: failed to build dependencies: {}", argv0, e);
+                return false;
+            }
+        }
+    }
+
+    if !build_program(script.to_path_buf(), file.path(), opts) {
+        return false;
+    }
+
+    match file.persist(exe) {
+        Ok(_) => true,
+        Err(e) => {
+            println!("{}: failed to write {}: {}", argv0, exe.display(), e.error);
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn execute(argv0: &str, exe: &Path, args: &[String]) -> i32 {
+    use std::os::unix::process::CommandExt;
+
+    let e = Command::new(exe).args(args).exec();
+
+    println!("{}: failed to execute {}: {}", argv0, exe.display(), e);
+    1
+}
+
+#[cfg(not(unix))]
+fn execute(argv0: &str, exe: &Path, args: &[String]) -> i32 {
+    match Command::new(exe).args(args).status() {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(e) => {
+            println!("{}: failed to execute {}: {}", argv0, exe.display(), e);
+            1
+        }
+    }
+}
+
+/// Returns the key under which the executable built from a script is cached.
+/// Scripts are rebuilt by each version of rusti.
+fn cache_key(text: &str, libs: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    version().hash(&mut hasher);
+    text.hash(&mut hasher);
+    libs.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+/// Returns the library search paths given by the leading comments of a
+/// script, relative to the directory `dir`.
+fn lib_paths(text: &str, dir: &Path) -> Result<Vec<String>, String> {
+    let mut libs = Vec::new();
+
+    // The shebang line is skipped
+    for line in text.lines().skip(1) {
+        let line = line.trim();
+
+        if !line.starts_with("//") {
+            break;
+        }
+
+        if !line.starts_with(OPTIONS_PREFIX) {
+            continue;
+        }
+
+        let mut args = line[OPTIONS_PREFIX.len()..].split_whitespace();
+
+        while let Some(arg) = args.next() {
+            match arg {
+                "-L" => match args.next() {
+                    Some(path) => libs.push(dir.join(path).to_string_lossy().into_owned()),
+                    None => return Err("option `-L` expects a path".to_owned()),
+                },
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+    }
+
+    Ok(libs)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{cache_key, lib_paths};
+
+    #[test]
+    fn test_lib_paths() {
+        let dir = Path::new("/scripts");
+
+        assert_eq!(lib_paths("#!/usr/bin/env rusti\nfn main() {}\n", dir), Ok(vec![]));
+        assert_eq!(lib_paths("#!/usr/bin/env rusti\n// A script\n\
+            // rusti: -L deps -L /opt/lib\n// rusti: -L ../lib\n\
+            fn main() {}\n// rusti: -L ignored\n", dir),
+            Ok(vec!["/scripts/deps".to_owned(), "/opt/lib".to_owned(),
+                "/scripts/../lib".to_owned()]));
+        assert!(lib_paths("#!/usr/bin/env rusti\n// rusti: -L\n", dir).is_err());
+        assert!(lib_paths("#!/usr/bin/env rusti\n// rusti: --foo\n", dir).is_err());
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(cache_key("fn main() {}", &[]), cache_key("fn main() {}", &[]));
+        assert!(cache_key("fn main() {}", &[]) != cache_key("fn main() { }", &[]));
+        assert!(cache_key("fn main() {}", &[]) != cache_key("fn main() {}", &["a".to_owned()]));
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Search of crates.io, as by `.search`.
+//!
+//! Requests to the crates.io API are made by `curl`, so that rusti need not
+//! depend on an HTTP client. A crate found may then be added as a dependency
+//! of the session, built as the dependencies of files are (see `deps`).
+
+use std::process::Command;
+
+use serialize::json::Json;
+
+use version;
+
+/// Program which makes requests to the crates.io API
+pub const CURL: &'static str = "curl";
+
+/// Endpoint of the crates.io API which searches crates
+const SEARCH_URL: &'static str = "https://crates.io/api/v1/crates";
+
+/// Number of results requested by a search
+pub const MAX_RESULTS: usize = 10;
+
+/// A crate found by a search
+#[derive(Clone, Debug, PartialEq)]
+pub struct Crate {
+    /// Name of the crate, as published
+    pub name: String,
+    /// Latest version of the crate
+    pub version: String,
+    /// Description of the crate, if it has one
+    pub description: Option<String>,
+}
+
+impl Crate {
+    /// Returns the name of the crate as it is named in code,
+    /// with `_` in place of `-`.
+    pub fn crate_name(&self) -> String {
+        self.name.replace('-', "_")
+    }
+
+    /// Returns the line of a Cargo manifest declaring a dependency
+    /// on the latest version of the crate.
+    pub fn dependency(&self) -> String {
+        format!("{} = \"{}\"", self.name, self.version)
+    }
+}
+
+/// Searches crates.io for crates matching `query`, most relevant first.
+pub fn search(query: &str) -> Result<Vec<Crate>, String> {
+    let out = Command::new(CURL)
+        .args(&["--silent", "--show-error", "--fail", "--location", "--get"])
+        .arg("--user-agent").arg(format!("rusti/{}", version()))
+        .arg("--data-urlencode").arg(format!("q={}", query))
+        .arg("--data").arg(format!("per_page={}", MAX_RESULTS))
+        .arg(SEARCH_URL)
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", CURL, e))?;
+
+    if !out.status.success() {
+        return Err(format!("failed to search crates.io: {}",
+            String::from_utf8_lossy(&out.stderr).trim()));
+    }
+
+    parse_results(&String::from_utf8_lossy(&out.stdout))
+}
+
+/// Parses the response of the crates.io API to a search.
+fn parse_results(response: &str) -> Result<Vec<Crate>, String> {
+    let json = Json::from_str(response)
+        .map_err(|e| format!("invalid response from crates.io: {}", e))?;
+
+    let crates = match json.find("crates").and_then(|c| c.as_array()) {
+        Some(crates) => crates,
+        None => return Err("invalid response from crates.io: no `crates`".to_owned()),
+    };
+
+    let string = |c: &Json, key: &str| c.find(key).and_then(|s| s.as_string())
+        .map(|s| s.to_owned());
+
+    Ok(crates.iter()
+        .filter_map(|c| Some(Crate{
+            name: string(c, "name")?,
+            version: string(c, "max_version")?,
+            description: string(c, "description")
+                .map(|d| d.split_whitespace().collect::<Vec<_>>().join(" "))
+                .and_then(|d| if d.is_empty() { None } else { Some(d) }),
+        }))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_results, Crate};
+
+    #[test]
+    fn test_parse_results() {
+        let response = r#"{"crates": [
+            {"name": "regex", "max_version": "0.2.2", "description": "An implementation of\n  regular expressions for Rust."},
+            {"name": "regex-syntax", "max_version": "0.4.1", "description": null},
+            {"name": "broken"}
+        ], "meta": {"total": 3}}"#;
+
+        let crates = parse_results(response).unwrap();
+
+        assert_eq!(crates, [
+            Crate{
+                name: "regex".to_owned(),
+                version: "0.2.2".to_owned(),
+                description: Some("An implementation of regular expressions for Rust.".to_owned()),
+            },
+            Crate{
+                name: "regex-syntax".to_owned(),
+                version: "0.4.1".to_owned(),
+                description: None,
+            },
+        ]);
+
+        assert_eq!(crates[1].crate_name(), "regex_syntax");
+        assert_eq!(crates[1].dependency(), "regex-syntax = \"0.4.1\"");
+        assert!(parse_results("{}").is_err());
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Explanation of the method selected for a method call or operator.
+
+use std::fmt;
+
+/// The method selected for a method call or operator, and how it is called
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    /// Path of the called method, such as `<Vec<i32> as Clone>::clone`
+    pub method: String,
+    /// Header of the impl providing the method, such as
+    /// `impl<T> Clone for Vec<T>`, or `None` if it is provided by the
+    /// compiler or called through a trait object
+    pub impl_header: Option<String>,
+    /// Name of the crate defining the impl, or the method if there is no impl
+    pub krate: String,
+    /// Names of generic parameters of the impl and method, with their arguments
+    pub generics: Vec<(String, String)>,
+    /// Type of the receiver, or of the first operand of an operator
+    pub receiver: String,
+    /// Adjustments of the receiver, such as autoref, each with its description
+    /// and the type it results in
+    pub adjustments: Vec<(String, String)>,
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "method:   {}", self.method)?;
+
+        match self.impl_header {
+            Some(ref header) => write!(f, "\nimpl:     {} ({})", header, self.krate)?,
+            None => write!(f, "\nimpl:     none; provided by the compiler ({})", self.krate)?,
+        }
+
+        for (i, &(ref name, ref arg)) in self.generics.iter().enumerate() {
+            let label = if i == 0 { "where:" } else { "" };
+            write!(f, "\n{:<10}{} = {}", label, name, arg)?;
+        }
+
+        write!(f, "\nreceiver: {}", self.receiver)?;
+
+        for &(ref step, ref ty) in &self.adjustments {
+            write!(f, "\n  {}: {}", step, ty)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Selection;
+
+    #[test]
+    fn test_display() {
+        let sel = Selection{
+            method: "<std::vec::Vec<i32> as std::clone::Clone>::clone".to_owned(),
+            impl_header: Some("impl<T> std::clone::Clone for std::vec::Vec<T>".to_owned()),
+            krate: "alloc".to_owned(),
+            generics: vec![("T".to_owned(), "i32".to_owned())],
+            receiver: "std::vec::Vec<i32>".to_owned(),
+            adjustments: vec![("autoref".to_owned(), "&std::vec::Vec<i32>".to_owned())],
+        };
+
+        assert_eq!(sel.to_string(), "\
+method:   <std::vec::Vec<i32> as std::clone::Clone>::clone
+impl:     impl<T> std::clone::Clone for std::vec::Vec<T> (alloc)
+where:    T = i32
+receiver: std::vec::Vec<i32>
+  autoref: &std::vec::Vec<i32>");
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serves a single session over a TCP or Unix domain socket.
+//!
+//! Each connection exchanges newline-delimited requests and responses,
+//! as described in the `json` module. Connections are served one at a time,
+//! and all of them share the same session. An evaluation request supersedes
+//! evaluation requests made before it whose code has not finished compiling.
+//!
+//! Anyone able to connect may run code as the user running rusti, so TCP
+//! sockets are only served on loopback addresses. Unix domain sockets are
+//! protected by their file permissions.
+
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+
+use json::serve;
+use repl::Repl;
+
+/// Prefix of an address naming a Unix domain socket
+pub const UNIX_PREFIX: &'static str = "unix:";
+
+/// Listens on the given address, serving connections until an error occurs.
+///
+/// The address is either a TCP socket address, such as `127.0.0.1:7878`,
+/// or `unix:` followed by the path of a Unix domain socket.
+/// Returns desired process exit status.
+pub fn run(repl: &mut Repl, addr: &str) -> i32 {
+    repl.exec_options_mut().json_diagnostics = true;
+
+    let res = if addr.starts_with(UNIX_PREFIX) {
+        serve_unix(repl, &addr[UNIX_PREFIX.len()..])
+    } else {
+        serve_tcp(repl, addr)
+    };
+
+    match res {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("rusti: server error on {}: {}", addr, e);
+            1
+        }
+    }
+}
+
+fn serve_tcp(repl: &mut Repl, addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+
+    if !local.ip().is_loopback() {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+            "only loopback addresses, such as 127.0.0.1, may be served, as any client \
+            can run code in the session; use a Unix domain socket or an SSH tunnel \
+            to serve other users"));
+    }
+
+    info!("listening on {}", local);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let writer = stream.try_clone()?;
+
+        serve_connection(repl, stream, writer);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn serve_unix(repl: &mut Repl, path: &str) -> io::Result<()> {
+    use std::fs::{remove_file, symlink_metadata};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    // A socket left behind by a server which has exited is replaced
+    let stale = symlink_metadata(path).map_or(false, |m| m.file_type().is_socket()) &&
+        UnixStream::connect(path).is_err();
+
+    if stale {
+        remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    info!("listening on {}", path);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let writer = stream.try_clone()?;
+
+        serve_connection(repl, stream, writer);
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_repl: &mut Repl, _path: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other,
+        "Unix domain sockets are not supported on this platform"))
+}
+
+/// Handles requests from a single connection until it is closed.
+fn serve_connection<R, W>(repl: &mut Repl, reader: R, writer: W)
+        where R: Read + Send + 'static, W: Write {
+    if let Err(e) = serve(repl, reader, writer, true) {
+        debug!("connection closed: {}", e);
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Named sessions, shared by editors which send code blocks to them.
+//!
+//! `--session NAME` serves a session, as `--server` does, on a Unix domain
+//! socket whose path is given by the session's name. `--send NAME` evaluates
+//! code read from standard input in that session, printing its output as
+//! plain text, as Emacs org-babel and slime-style integrations expect.
+//! `--block ID` names the source block which the code came from; it is
+//! copied into the response, as the `id` of a request is.
+
+use std::env::var_os;
+use std::fs::create_dir_all;
+use std::io::{stdin, Read};
+use std::path::PathBuf;
+
+use artifacts::cache_dir;
+use server::UNIX_PREFIX;
+
+/// Returns the path of the socket on which the named session is served.
+///
+/// Sockets are within `$XDG_RUNTIME_DIR/rusti` or, if that is not set,
+/// within the `sessions` directory of the cache directory.
+pub fn socket_path(name: &str) -> Result<PathBuf, String> {
+    if !is_valid_name(name) {
+        return Err(format!("invalid session name `{}`", name));
+    }
+
+    let dir = match var_os("XDG_RUNTIME_DIR") {
+        Some(ref d) if !d.is_empty() => PathBuf::from(d).join("rusti"),
+        _ => match cache_dir() {
+            Some(dir) => dir.join("sessions"),
+            None => return Err("cannot find a directory for session sockets".to_owned()),
+        },
+    };
+
+    Ok(dir.join(format!("{}.sock", name)))
+}
+
+/// Returns the address on which `--server` serves the named session,
+/// creating the directory containing its socket.
+pub fn server_addr(name: &str) -> Result<String, String> {
+    let path = socket_path(name)?;
+
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    }
+
+    Ok(format!("{}{}", UNIX_PREFIX, path.display()))
+}
+
+/// Returns whether a session name may be used in the name of a file.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') &&
+        name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Evaluates code read from standard input in the named session, writing
+/// its output and value to `stdout` and diagnostics to `stderr`.
+/// Returns desired process exit status: `1` if the code fails to compile.
+#[cfg(unix)]
+pub fn send(argv0: &str, name: &str, block: Option<String>) -> i32 {
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::Shutdown;
+    use std::os::unix::net::UnixStream;
+
+    use serialize::json::Json;
+
+    let path = match socket_path(name) {
+        Ok(path) => path,
+        Err(e) => {
+            println!("{}: {}", argv0, e);
+            return 1;
+        }
+    };
+
+    let mut code = String::new();
+
+    if let Err(e) = stdin().read_to_string(&mut code) {
+        println!("{}: failed to read code: {}", argv0, e);
+        return 1;
+    }
+
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(_) => {
+            println!("{}: no session named `{}`; start one with `{} --session {}`",
+                argv0, name, argv0, name);
+            return 1;
+        }
+    };
+
+    let mut req = BTreeMap::new();
+
+    req.insert("code".to_owned(), Json::String(code));
+
+    if let Some(block) = block {
+        req.insert("block".to_owned(), Json::String(block));
+    }
+
+    let mut line = String::new();
+
+    let res = writeln!(stream, "{}", Json::Object(req))
+        .and_then(|_| stream.shutdown(Shutdown::Write))
+        .and_then(|_| BufReader::new(&stream).read_line(&mut line));
+
+    if let Err(e) = res {
+        println!("{}: session `{}`: {}", argv0, name, e);
+        return 1;
+    }
+
+    let response = match Json::from_str(&line) {
+        Ok(response) => response,
+        Err(_) => {
+            println!("{}: session `{}` closed the connection", argv0, name);
+            return 1;
+        }
+    };
+
+    if let Some(e) = response.find("error").and_then(|e| e.as_string()) {
+        println!("{}: {}", argv0, e);
+        return 1;
+    }
+
+    let text = |key: &str| response.find(key).and_then(|s| s.as_string()).unwrap_or("");
+
+    print!("{}", text("stdout"));
+    eprint!("{}", text("stderr"));
+
+    if let Some(value) = response.find("value").and_then(|v| v.as_string()) {
+        println!("{}", value);
+    }
+
+    match response.find("compiled").and_then(|c| c.as_boolean()) {
+        Some(true) => 0,
+        _ => 1,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn send(argv0: &str, _name: &str, _block: Option<String>) -> i32 {
+    println!("{}: sessions are not supported on this platform", argv0);
+    1
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_valid_name;
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("notes"));
+        assert!(is_valid_name("my-notes_2.org"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name(".hidden"));
+        assert!(!is_valid_name("../notes"));
+        assert!(!is_valid_name("a b"));
+    }
+}
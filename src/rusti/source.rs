@@ -0,0 +1,109 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Source of items of other crates, as shown by `.src`.
+//!
+//! The compiler records the name each source file had when a crate was
+//! built. Crates built on this machine, such as dependencies, name files
+//! which still exist; the standard library names files on the machine which
+//! built it, whose source is found instead in the sysroot, as installed by
+//! the `rust-src` component of rustup.
+
+use std::path::{Path, PathBuf};
+
+/// Directory of the sysroot in which the source of the standard library is installed
+const RUST_SRC_DIR: &'static str = "lib/rustlib/src/rust";
+
+/// Directories of the Rust source tree containing the standard library
+const LIBRARY_DIRS: &'static [&'static str] = &["/src/lib", "/library/"];
+
+/// Returns the path of a source file named as recorded by the compiler,
+/// if it exists either as named or in the source installed in the sysroot.
+pub fn locate(file: &str, sysroot: &Path) -> Option<PathBuf> {
+    let path = Path::new(file);
+
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+
+    let path = sysroot.join(RUST_SRC_DIR).join(rust_src_path(file)?);
+
+    if path.is_file() { Some(path) } else { None }
+}
+
+/// Returns the path of a file of the standard library within the Rust
+/// source tree, such as `src/liballoc/vec.rs`.
+fn rust_src_path(file: &str) -> Option<&str> {
+    LIBRARY_DIRS.iter()
+        .filter_map(|dir| file.find(dir))
+        .min()
+        .map(|i| &file[i + 1..])
+}
+
+/// Returns the lines of `text` from `first` to `last`, counted from 1,
+/// extended to include the doc comments and attributes preceding them,
+/// together with the number of the first line returned.
+pub fn excerpt(text: &str, first: usize, last: usize) -> (usize, String) {
+    let lines = text.lines().collect::<Vec<_>>();
+    let last = last.min(lines.len());
+    let mut first = first.max(1).min(last.max(1));
+
+    while first > 1 {
+        let line = lines[first - 2].trim_left();
+
+        if line.starts_with("///") || line.starts_with("#[") || line.starts_with("//!") {
+            first -= 1;
+        } else {
+            break;
+        }
+    }
+
+    let mut res = String::new();
+
+    for line in lines.get(first - 1..last).unwrap_or(&[]) {
+        res.push_str(line);
+        res.push('\n');
+    }
+
+    (first, res)
+}
+
+/// Returns code with each line preceded by its number, starting with `first`.
+pub fn number_lines(code: &str, first: usize) -> String {
+    let width = (first + code.lines().count()).saturating_sub(1).to_string().len();
+    let mut res = String::new();
+
+    for (i, line) in code.lines().enumerate() {
+        res.push_str(&format!("{:>w$} | {}\n", first + i, line, w = width));
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::{excerpt, number_lines, rust_src_path};
+
+    #[test]
+    fn test_rust_src_path() {
+        assert_eq!(rust_src_path("/checkout/src/liballoc/vec.rs"), Some("src/liballoc/vec.rs"));
+        assert_eq!(rust_src_path("/rustc/abc/library/alloc/src/vec.rs"),
+            Some("library/alloc/src/vec.rs"));
+        assert_eq!(rust_src_path("/home/u/.cargo/registry/src/regex-0.2.2/src/re.rs"), None);
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let text = "use x;\n\n/// Doubles.\n#[inline]\nfn double(x: i32) -> i32 {\n    x * 2\n}\n\nfn f() {}\n";
+
+        assert_eq!(excerpt(text, 5, 7),
+            (3, "/// Doubles.\n#[inline]\nfn double(x: i32) -> i32 {\n    x * 2\n}\n".to_owned()));
+        assert_eq!(excerpt(text, 9, 12), (9, "fn f() {}\n".to_owned()));
+        assert_eq!(number_lines("a\nb\n", 9), " 9 | a\n10 | b\n");
+    }
+}
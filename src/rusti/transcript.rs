@@ -0,0 +1,264 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Record of the input of a session and its output, which `.export-md`
+//! writes as Markdown.
+//!
+//! Each input is written as a fenced code block, preceded by the time at
+//! which it was entered. Output written while running the input, including
+//! compiler diagnostics, and its value are written in a quoted block.
+//! `parse_markdown` reads the entries back, as `--verify` does.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An input entered during a session
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// Time at which the input was entered
+    pub time: SystemTime,
+    /// Text of the input
+    pub input: String,
+    /// Whether the input is a command, rather than code
+    pub command: bool,
+    /// Output written while running the input
+    pub output: String,
+    /// Formatted value of the input, if it had one
+    pub value: Option<String>,
+}
+
+/// Returns a session's entries as a Markdown document.
+pub fn to_markdown(entries: &[Entry]) -> String {
+    let mut res = "# rusti session\n".to_owned();
+
+    for entry in entries {
+        res.push_str(&format!("\n*{}*\n\n", format_time(entry.time)));
+        push_block(&mut res, if entry.command { "text" } else { "rust" },
+            entry.input.trim_right(), "");
+
+        let mut output = strip_escapes(&entry.output);
+
+        if let Some(ref value) = entry.value {
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            output.push_str(value);
+        }
+
+        let output = output.trim_right();
+
+        if !output.is_empty() {
+            res.push('\n');
+            push_block(&mut res, "text", output, "> ");
+        }
+    }
+
+    res
+}
+
+/// Reads the entries of a Markdown document written by `to_markdown`.
+/// The output of each entry includes its value, which is `None`;
+/// times are not read.
+pub fn parse_markdown(text: &str) -> Vec<Entry> {
+    let mut entries = Vec::<Entry>::new();
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        if let Some((fence, lang)) = parse_fence(line, "") {
+            entries.push(Entry{
+                time: UNIX_EPOCH,
+                input: read_block(&mut lines, fence, ""),
+                command: lang == "text",
+                output: String::new(),
+                value: None,
+            });
+        } else if let Some((fence, _)) = parse_fence(line, "> ") {
+            let output = read_block(&mut lines, fence, "> ");
+
+            if let Some(entry) = entries.last_mut() {
+                entry.output = output;
+            }
+        }
+    }
+
+    entries
+}
+
+/// Parses a line opening a fenced code block, with the given prefix,
+/// returning its fence and language.
+pub fn parse_fence<'a>(line: &'a str, prefix: &str) -> Option<(&'a str, &'a str)> {
+    if !line.starts_with(prefix) {
+        return None;
+    }
+
+    let line = &line[prefix.len()..];
+    let n = line.len() - line.trim_left_matches('`').len();
+
+    if n < 3 {
+        None
+    } else {
+        Some((&line[..n], &line[n..]))
+    }
+}
+
+/// Reads the lines of a fenced code block, up to its closing fence.
+pub fn read_block<'a, I>(lines: &mut I, fence: &str, prefix: &str) -> String
+        where I: Iterator<Item=&'a str> {
+    let mut res = String::new();
+
+    for line in lines {
+        // Trailing spaces of empty quoted lines may have been removed
+        let line = if line.starts_with(prefix) {
+            &line[prefix.len()..]
+        } else if line == prefix.trim_right() {
+            ""
+        } else {
+            line
+        };
+
+        if line == fence {
+            break;
+        }
+
+        res.push_str(line);
+        res.push('\n');
+    }
+
+    res
+}
+
+/// Appends a fenced code block to `s`, with each line beginning with `prefix`.
+fn push_block(s: &mut String, lang: &str, text: &str, prefix: &str) {
+    // A fence must be longer than any run of backticks within the block
+    let longest = text.split(|ch| ch != '`').map(|run| run.len()).max().unwrap_or(0);
+    let fence = "`".repeat(if longest < 3 { 3 } else { longest + 1 });
+
+    s.push_str(&format!("{}{}{}\n", prefix, fence, lang));
+
+    for line in text.lines() {
+        s.push_str(prefix);
+        s.push_str(line);
+        s.push('\n');
+    }
+
+    s.push_str(&format!("{}{}\n", prefix, fence));
+}
+
+/// Removes ANSI escape sequences, such as those coloring diagnostics,
+/// and carriage returns from text.
+pub fn strip_escapes(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x1b' => match chars.next() {
+                // Control sequences end with a character in `@` to `~`
+                Some('[') => {
+                    while let Some(ch) = chars.next() {
+                        if ch >= '@' && ch <= '~' {
+                            break;
+                        }
+                    }
+                }
+                // Operating system commands end with BEL or `ESC \`
+                Some(']') => {
+                    while let Some(ch) = chars.next() {
+                        if ch == '\x07' {
+                            break;
+                        } else if ch == '\x1b' {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                _ => (),
+            },
+            '\r' => (),
+            ch => res.push(ch),
+        }
+    }
+
+    res
+}
+
+/// Formats a time as `YYYY-MM-DD HH:MM:SS UTC`.
+fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Converts days since 1970-01-01 to a date in the proleptic Gregorian calendar
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{format_time, parse_markdown, strip_escapes, to_markdown, Entry};
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(951782400 + 3661)),
+            "2000-02-29 01:01:01 UTC");
+        assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(1792108799)),
+            "2026-10-15 23:59:59 UTC");
+    }
+
+    #[test]
+    fn test_strip_escapes() {
+        assert_eq!(strip_escapes("\x1b[1;31merror\x1b[0m: x\r\n"), "error: x\n");
+        assert_eq!(strip_escapes("a\x1b]0;title\x07b\x1b]0;t\x1b\\c"), "abc");
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let entries = [
+            Entry{
+                time: UNIX_EPOCH,
+                input: ".set opt-level 2\n".to_owned(),
+                command: true,
+                output: String::new(),
+                value: None,
+            },
+            Entry{
+                time: UNIX_EPOCH + Duration::from_secs(61),
+                input: "println!(\"```\"); 1\n".to_owned(),
+                command: false,
+                output: "```\n".to_owned(),
+                value: Some("1".to_owned()),
+            },
+        ];
+
+        let md = to_markdown(&entries);
+
+        assert_eq!(md, "# rusti session\n\
+            \n*1970-01-01 00:00:00 UTC*\n\n```text\n.set opt-level 2\n```\n\
+            \n*1970-01-01 00:01:01 UTC*\n\n````rust\nprintln!(\"```\"); 1\n````\n\
+            \n> ````text\n> ```\n> 1\n> ````\n");
+
+        let parsed = parse_markdown(&md);
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!((&parsed[0].input[..], parsed[0].command, &parsed[0].output[..]),
+            (".set opt-level 2\n", true, ""));
+        assert_eq!((&parsed[1].input[..], parsed[1].command, &parsed[1].output[..]),
+            ("println!(\"```\"); 1\n", false, "```\n1\n"));
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checking transcripts written by `.export-md`, as by `--verify`.
+//!
+//! Each input of the transcript is run again, and its output, diagnostics
+//! and value are compared with those recorded. Output which differs from
+//! one run to the next, such as addresses and timings, is normalized before
+//! it is compared. The output of commands is not recorded, so commands are
+//! run without their output being compared.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use batch;
+use capture::capture;
+use repl::Repl;
+use transcript::{parse_markdown, strip_escapes};
+
+/// Runs the inputs of the transcript at `path`, printing the differences
+/// between their output and the recorded output.
+/// Returns desired process exit status.
+pub fn run(argv0: &str, repl: &mut Repl, path: &Path) -> i32 {
+    let mut text = String::new();
+
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut text)) {
+        println!("{}: failed to read {}: {}", argv0, path.display(), e);
+        return 1;
+    }
+
+    let entries = parse_markdown(&text);
+    let mut differ = 0;
+
+    for (i, entry) in entries.iter().enumerate() {
+        let res = capture(|| batch::evaluate(repl, &entry.input));
+
+        let (_, result) = match res {
+            Ok((res, _)) => res,
+            Err(e) => {
+                println!("{}: failed to capture output: {}", argv0, e);
+                return 1;
+            }
+        };
+
+        if entry.command {
+            continue;
+        }
+
+        let mut output = result.stdout;
+        output.push_str(&strip_escapes(&result.stderr));
+
+        if let Some(value) = result.value {
+            output.push_str(&value);
+            output.push('\n');
+        }
+
+        let expected = normalize(&entry.output);
+        let actual = normalize(&output);
+
+        if expected != actual {
+            differ += 1;
+
+            println!("{}: input {} differs:", path.display(), i + 1);
+
+            for line in entry.input.lines() {
+                println!("    {}", line);
+            }
+
+            print!("{}", diff(&expected, &actual));
+        }
+    }
+
+    println!("verified {} inputs; {} differ", entries.len(), differ);
+
+    if differ == 0 { 0 } else { 1 }
+}
+
+/// Replaces text which differs from one run to the next, such as
+/// hexadecimal addresses and durations, and removes trailing whitespace.
+pub fn normalize(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+
+    for line in text.lines() {
+        res.push_str(&normalize_line(line.trim_right()));
+        res.push('\n');
+    }
+
+    res
+}
+
+/// Units of durations, in the order in which they are matched
+const TIME_UNITS: &'static [&'static str] = &["ns", "us", "µs", "ms", "s"];
+
+fn normalize_line(line: &str) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(ch) = rest.chars().next() {
+        // Numbers within words, such as `E0308` or `u32`, are left as they are
+        let in_word = res.chars().next_back().map_or(false, |c| c.is_alphanumeric() || c == '_');
+
+        if rest.starts_with("0x") && !in_word {
+            let n = 2 + rest[2..].len() - rest[2..].trim_left_matches(|c: char| c.is_digit(16)).len();
+
+            if n > 2 {
+                res.push_str("0x<addr>");
+                rest = &rest[n..];
+                continue;
+            }
+        }
+
+        if ch.is_digit(10) && !in_word {
+            let n = rest.len() - rest.trim_left_matches(|c: char| c.is_digit(10) || c == '.').len();
+            let after = &rest[n..];
+
+            let unit = TIME_UNITS.iter().find(|unit| after.starts_with(*unit) &&
+                !after[unit.len()..].chars().next().map_or(false, |c| c.is_alphanumeric()));
+
+            if let Some(unit) = unit {
+                res.push_str("<time>");
+                rest = &after[unit.len()..];
+                continue;
+            }
+        }
+
+        res.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+
+    res
+}
+
+/// Returns the lines of `expected` missing from `actual`, prefixed by `-`,
+/// and lines of `actual` not in `expected`, prefixed by `+`, in order.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let a = expected.lines().collect::<Vec<_>>();
+    let b = actual.lines().collect::<Vec<_>>();
+
+    // Length of the longest common subsequence of `a[i..]` and `b[j..]`
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut res = String::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            res.push_str(&format!("  {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            res.push_str(&format!("- {}\n", a[i]));
+            i += 1;
+        } else {
+            res.push_str(&format!("+ {}\n", b[j]));
+            j += 1;
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff, normalize};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("0x7ffd5e8c 0x 12ms 1.5s 3 items  \n"),
+            "0x<addr> 0x <time> <time> 3 items\n");
+        assert_eq!(normalize("error[E0308]: u32 x1s 10sec\n"), "error[E0308]: u32 x1s 10sec\n");
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("a\nb\nc\n", "a\nc\nd\n"), "  a\n- b\n  c\n+ d\n");
+        assert_eq!(diff("", "a\n"), "+ a\n");
+    }
+}
@@ -0,0 +1,436 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Browser playground served over HTTP, as by `--web`.
+//!
+//! `GET /` returns a page with an editor pane, an output pane and the
+//! history of the session. The page makes the following requests:
+//!
+//! * `POST /eval` evaluates the request body, responding with a JSON object
+//!   as the `json` module describes for `"eval"` requests.
+//! * `GET /history` responds with a JSON array of the inputs evaluated in
+//!   the session, each an object with members `"code"` and `"result"`.
+//! * `POST /reset` forgets all items defined in the session, and its history.
+//!
+//! Requests are served one at a time, and share a single session.
+//!
+//! As any page the user visits may make requests of a server on the local
+//! machine, every request must name the server by its address, or
+//! `localhost`, in `Host`, and come from the playground itself, if
+//! `Origin` is given. Each request must also carry a token chosen when the
+//! server starts: `GET /` in the query string of the printed URL, and other
+//! requests in the `X-Rusti-Token` header, which requires a preflight
+//! request the server does not allow from other origins.
+
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use serialize::json::Json;
+
+use json::eval_response;
+use repl::Repl;
+
+/// Largest request body which is read, in bytes
+const MAX_BODY: usize = 1 << 20;
+
+/// Header carrying the token of the server, in lowercase
+const TOKEN_HEADER: &'static str = "x-rusti-token";
+
+/// Time allowed to read a request or write its response, in seconds
+const TIMEOUT_SECS: u64 = 10;
+
+/// A request read from a client
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    /// Headers, by lowercase name
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+/// Listens on the given TCP address, serving the playground until an error
+/// occurs. Returns desired process exit status.
+pub fn run(argv0: &str, repl: &mut Repl, addr: &str) -> i32 {
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("{}: failed to listen on {}: {}", argv0, addr, e);
+            return 1;
+        }
+    };
+
+    // The port chosen by the system is shown if the address gives none
+    let local = match listener.local_addr() {
+        Ok(local) => local,
+        Err(e) => {
+            println!("{}: failed to listen on {}: {}", argv0, addr, e);
+            return 1;
+        }
+    };
+
+    let token = new_token();
+
+    println!("serving playground on http://{}/?token={}", local, token);
+
+    let mut history = Vec::new();
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                debug!("failed to accept connection: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = serve_connection(repl, &mut history, &local, &token, stream) {
+            debug!("connection closed: {}", e);
+        }
+    }
+
+    0
+}
+
+/// Returns a token which cannot be guessed, for clients to prove they were
+/// given the URL printed by the server.
+fn new_token() -> String {
+    // Each `RandomState` is keyed randomly by the system
+    (0..2).map(|_| format!("{:016x}", RandomState::new().build_hasher().finish()))
+        .collect()
+}
+
+/// Reads a single request from a connection and writes its response.
+fn serve_connection(repl: &mut Repl, history: &mut Vec<Json>, local: &SocketAddr, token: &str,
+        stream: TcpStream) -> io::Result<()> {
+    // A client which sends nothing must not hold up the others
+    let timeout = Some(Duration::from_secs(TIMEOUT_SECS));
+
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    let mut writer = stream.try_clone()?;
+
+    let req = match read_request(&mut BufReader::new(stream)) {
+        Ok(req) => req,
+        Err(e) => return write_response(&mut writer, "400 Bad Request", "text/plain",
+            &format!("{}\n", e)),
+    };
+
+    if let Err(e) = authorize(&req, local.port(), token) {
+        return write_response(&mut writer, "403 Forbidden", "text/plain", &format!("{}\n", e));
+    }
+
+    let Request{method, path, body, ..} = req;
+    let path = path.split('?').next().unwrap_or("");
+
+    match (&method[..], path) {
+        ("GET", "/") => write_response(&mut writer, "200 OK", "text/html; charset=utf-8", PAGE),
+        ("GET", "/history") => {
+            let body = Json::Array(history.clone()).to_string();
+            write_response(&mut writer, "200 OK", "application/json", &body)
+        }
+        ("POST", "/eval") => {
+            let result = eval_response(repl.evaluate(&body));
+
+            let mut entry = BTreeMap::new();
+            entry.insert("code".to_owned(), Json::String(body));
+            entry.insert("result".to_owned(), result.clone());
+            history.push(Json::Object(entry));
+
+            write_response(&mut writer, "200 OK", "application/json", &result.to_string())
+        }
+        ("POST", "/reset") => {
+            repl.reset();
+            history.clear();
+            write_response(&mut writer, "204 No Content", "text/plain", "")
+        }
+        (_, "/") | (_, "/history") | (_, "/eval") | (_, "/reset") =>
+            write_response(&mut writer, "405 Method Not Allowed", "text/plain",
+                "method not allowed\n"),
+        _ => write_response(&mut writer, "404 Not Found", "text/plain", "not found\n"),
+    }
+}
+
+/// Checks that a request names this server, comes from the playground,
+/// and carries the token of the server.
+fn authorize(req: &Request, port: u16, token: &str) -> Result<(), &'static str> {
+    let host = match req.headers.get("host") {
+        Some(host) => host,
+        None => return Err("missing Host header"),
+    };
+
+    // A name other than `localhost` may have been rebound by an attacker
+    let (name, host_port) = match host.rfind(':') {
+        Some(i) => (&host[..i], host[i + 1..].parse::<u16>().ok()),
+        None => (&host[..], Some(80)),
+    };
+    let name = name.trim_left_matches('[').trim_right_matches(']');
+
+    if host_port != Some(port) || (name != "localhost" && name.parse::<IpAddr>().is_err()) {
+        return Err("invalid Host header");
+    }
+
+    if let Some(origin) = req.headers.get("origin") {
+        if *origin != format!("http://{}", host) {
+            return Err("requests from other origins are not allowed");
+        }
+    }
+
+    let given = if req.method == "GET" && req.path.split('?').next() == Some("/") {
+        req.path.splitn(2, '?').nth(1).unwrap_or("").split('&')
+            .filter_map(|param| {
+                let mut parts = param.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some("token"), value) => value,
+                    _ => None,
+                }
+            })
+            .next()
+    } else {
+        req.headers.get(TOKEN_HEADER).map(|t| &t[..])
+    };
+
+    if given == Some(token) {
+        Ok(())
+    } else {
+        Err("missing or invalid token; use the URL printed by rusti")
+    }
+}
+
+/// Reads the request line, headers and body of a request.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let mut line = String::new();
+
+    reader.read_line(&mut line)?;
+
+    let (method, path) = {
+        let mut words = line.split_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (Some(method), Some(path), Some(version)) if version.starts_with("HTTP/") =>
+                (method.to_owned(), path.to_owned()),
+            _ => return Err(invalid("invalid request line")),
+        }
+    };
+
+    let mut headers = BTreeMap::new();
+
+    loop {
+        line.clear();
+
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of headers"));
+        }
+
+        let header = line.trim_right();
+
+        if header.is_empty() {
+            break;
+        }
+
+        let mut parts = header.splitn(2, ':');
+
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+        }
+    }
+
+    let len = match headers.get("content-length") {
+        Some(len) => len.parse().map_err(|_| invalid("invalid Content-Length"))?,
+        None => 0,
+    };
+
+    if len > MAX_BODY {
+        return Err(invalid("request body is too large"));
+    }
+
+    let mut body = vec![0; len];
+
+    reader.read_exact(&mut body)?;
+
+    let body = String::from_utf8(body).map_err(|_| invalid("request body is not UTF-8"))?;
+
+    Ok(Request{
+        method: method,
+        path: path,
+        headers: headers,
+        body: body,
+    })
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_response<W: Write>(writer: &mut W, status: &str, content_type: &str, body: &str)
+        -> io::Result<()> {
+    write!(writer, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+        Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body)?;
+    writer.flush()
+}
+
+/// The playground page
+const PAGE: &'static str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>rusti playground</title>
+<style>
+body { margin: 0; font-family: sans-serif; display: flex; height: 100vh; }
+main { flex: 3; display: flex; flex-direction: column; padding: 1em; }
+aside { flex: 1; overflow: auto; padding: 1em; background: #f4f4f4; }
+textarea { flex: 1; font: 14px monospace; padding: .5em; }
+pre { margin: 0; white-space: pre-wrap; font: 14px monospace; }
+#output { flex: 1; overflow: auto; border: 1px solid #ccc; padding: .5em; margin-top: .5em; }
+.stderr { color: #b00; }
+.value { color: #05a; }
+#history pre { cursor: pointer; border-bottom: 1px solid #ddd; padding: .25em 0; }
+</style>
+</head>
+<body>
+<main>
+<textarea id="code" spellcheck="false" placeholder="Rust code; Ctrl+Enter to run"></textarea>
+<p><button id="run">Run</button> <button id="reset">Reset session</button></p>
+<div id="output"></div>
+</main>
+<aside>
+<h3>History</h3>
+<div id="history"></div>
+</aside>
+<script>
+var code = document.getElementById("code");
+var output = document.getElementById("output");
+var historyPane = document.getElementById("history");
+var token = new URLSearchParams(location.search).get("token");
+
+function request(method, path, onload) {
+  var req = new XMLHttpRequest();
+  req.open(method, path);
+  req.setRequestHeader("X-Rusti-Token", token);
+  req.onload = function () { onload(req); };
+  return req;
+}
+
+function text(cls, s) {
+  var pre = document.createElement("pre");
+  pre.className = cls;
+  pre.textContent = s;
+  return pre;
+}
+
+function show(result) {
+  output.innerHTML = "";
+  if (result.stdout) output.appendChild(text("stdout", result.stdout));
+  if (result.stderr) output.appendChild(text("stderr", result.stderr));
+  result.display.forEach(function (d) {
+    var el;
+    if (d.mime == "text/html") {
+      el = document.createElement("div");
+      el.innerHTML = d.data;
+    } else if (d.mime.indexOf("image/") == 0) {
+      el = document.createElement("img");
+      el.src = "data:" + d.mime + ";base64," + d.data.replace(/\s/g, "");
+    } else {
+      el = text("stdout", d.data);
+    }
+    output.appendChild(el);
+  });
+  if (result.value !== null) output.appendChild(text("value", result.value));
+}
+
+function addHistory(entry) {
+  var pre = text("", entry.code);
+  pre.onclick = function () { code.value = entry.code; show(entry.result); };
+  historyPane.insertBefore(pre, historyPane.firstChild);
+}
+
+function run() {
+  var req = request("POST", "/eval", function (req) {
+    var result = JSON.parse(req.responseText);
+    show(result);
+    addHistory({ code: code.value, result: result });
+  });
+  output.textContent = "Running...";
+  req.send(code.value);
+}
+
+document.getElementById("run").onclick = run;
+code.onkeydown = function (e) {
+  if (e.key == "Enter" && e.ctrlKey) { e.preventDefault(); run(); }
+};
+document.getElementById("reset").onclick = function () {
+  request("POST", "/reset", function () {
+    historyPane.innerHTML = "";
+    output.innerHTML = "";
+  }).send();
+};
+
+request("GET", "/history", function (req) {
+  JSON.parse(req.responseText).forEach(addHistory);
+}).send();
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    use super::{authorize, read_request, Request};
+
+    #[test]
+    fn test_read_request() {
+        let mut req = Cursor::new("POST /eval HTTP/1.1\r\nHost: x\r\ncontent-length: 5\r\n\r\n1 + 1trailing");
+
+        let mut headers = BTreeMap::new();
+        headers.insert("host".to_owned(), "x".to_owned());
+        headers.insert("content-length".to_owned(), "5".to_owned());
+
+        assert_eq!(read_request(&mut req).unwrap(), Request{
+            method: "POST".to_owned(),
+            path: "/eval".to_owned(),
+            headers: headers,
+            body: "1 + 1".to_owned(),
+        });
+
+        assert!(read_request(&mut Cursor::new("GET /\r\n\r\n")).is_err());
+        assert!(read_request(&mut Cursor::new("GET / HTTP/1.1\r\nHost: x\r\n")).is_err());
+    }
+
+    #[test]
+    fn test_authorize() {
+        let req = |method: &str, path: &str, headers: &[(&str, &str)]| Request{
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers: headers.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect(),
+            body: String::new(),
+        };
+
+        assert_eq!(authorize(&req("GET", "/?token=t", &[("host", "127.0.0.1:80")]), 80, "t"), Ok(()));
+        assert_eq!(authorize(&req("POST", "/eval",
+            &[("host", "localhost:80"), ("origin", "http://localhost:80"), ("x-rusti-token", "t")]),
+            80, "t"), Ok(()));
+
+        assert!(authorize(&req("GET", "/?token=x", &[("host", "127.0.0.1:80")]), 80, "t").is_err());
+        assert!(authorize(&req("POST", "/eval", &[("host", "127.0.0.1:80")]), 80, "t").is_err());
+        assert!(authorize(&req("POST", "/eval",
+            &[("host", "evil.example:80"), ("x-rusti-token", "t")]), 80, "t").is_err());
+        assert!(authorize(&req("POST", "/eval",
+            &[("host", "127.0.0.1:80"), ("origin", "http://evil.example"), ("x-rusti-token", "t")]),
+            80, "t").is_err());
+        assert!(authorize(&req("GET", "/", &[]), 80, "t").is_err());
+    }
+}
@@ -0,0 +1,451 @@
+// Copyright 2014-2016 Rusti Project
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Evaluation of input in a worker process.
+//!
+//! With `--isolate`, the `rusti` process reading input is only a frontend.
+//! Code and commands are evaluated by a worker process, which is `rusti`
+//! itself run with `--json`; see the `json` module. The frontend records
+//! each request which defined something in the session: input which compiled
+//! and commands which change settings. When the worker exits without
+//! responding, such as when compiled input crashes, a new worker is started
+//! and the recorded requests are replayed, restoring the session.
+
+use std::collections::BTreeMap;
+use std::env::current_exe;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{self, Child, ChildStdin, ChildStdout, ExitStatus, Stdio};
+
+use serialize::json::Json;
+use syntax::errors::ColorConfig;
+
+use config::Config;
+use highlight::use_color;
+use history::History;
+use input::{InputReader, KeyBindings};
+use input::InputResult::*;
+use prompt::{self, Context, Mode, DEFAULT_PROMPT};
+use repl::lookup_command;
+
+/// Commands whose effects are restored by replaying them in a new worker
+const REPLAYED_COMMANDS: &'static [&'static str] = &["cd", "env", "load", "set"];
+
+/// Settings which concern reading input, kept by the frontend as well as
+/// the worker
+struct Frontend {
+    /// Prompt template; see `prompt`
+    prompt: String,
+    /// Key bindings used to edit input
+    line_editing: KeyBindings,
+}
+
+impl Frontend {
+    /// Returns the settings given by configuration, including its `[settings]`.
+    fn new(config: &Config) -> Frontend {
+        let mut frontend = Frontend{
+            prompt: config.prompt.clone().unwrap_or_else(|| DEFAULT_PROMPT.to_owned()),
+            line_editing: KeyBindings::Emacs,
+        };
+
+        for &(ref name, ref value) in &config.settings {
+            frontend.set(&format!("{} {}", name, value));
+        }
+
+        frontend
+    }
+
+    /// Applies the arguments of a `.set` command. Settings which only concern
+    /// the worker, and invalid values, which the worker reports, are ignored.
+    fn set(&mut self, args: &str) {
+        let mut words = args.trim().splitn(2, ' ');
+        let name = words.next().unwrap();
+        let value = match words.next().map(|s| s.trim()) {
+            Some("") | None => None,
+            value => value,
+        };
+
+        match name {
+            "keybindings" => {
+                match value.map(KeyBindings::parse) {
+                    Some(Some(k)) => self.line_editing = k,
+                    Some(None) => (),
+                    None => self.line_editing = KeyBindings::Emacs,
+                }
+            }
+            "prompt" => self.prompt = prompt::parse_template(value),
+            _ => (),
+        }
+    }
+}
+
+/// A `rusti` process evaluating requests
+pub struct Worker {
+    /// Arguments given to each worker process, besides `--json`
+    args: Vec<String>,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// Requests replayed in a new worker process, in the order they were made
+    recorded: Vec<Json>,
+}
+
+impl Worker {
+    /// Starts a worker process, passing it the given arguments.
+    pub fn spawn(args: Vec<String>) -> io::Result<Worker> {
+        let (child, stdin, stdout) = start(&args)?;
+
+        Ok(Worker{
+            args: args,
+            child: child,
+            stdin: stdin,
+            stdout: stdout,
+            recorded: Vec::new(),
+        })
+    }
+
+    /// Sends a request to the worker, returning its response.
+    /// Returns `None` if the worker exited before responding.
+    pub fn request(&mut self, req: &Json) -> io::Result<Option<Json>> {
+        // A worker which has exited no longer reads its input
+        if writeln!(self.stdin, "{}", req).and_then(|_| self.stdin.flush()).is_err() {
+            return Ok(None);
+        }
+
+        let mut line = String::new();
+
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        Json::from_str(&line).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+            format!("invalid response from worker: {}", e)))
+    }
+
+    /// Records a request, to be replayed if the worker is restarted.
+    pub fn record(&mut self, req: Json) {
+        self.recorded.push(req);
+    }
+
+    /// Forgets all recorded requests.
+    pub fn forget(&mut self) {
+        self.recorded.clear();
+    }
+
+    /// Returns the number of recorded requests.
+    pub fn recorded(&self) -> usize {
+        self.recorded.len()
+    }
+
+    /// Replaces a worker which has exited with a new one, replaying recorded
+    /// requests. Returns the exit status of the old worker.
+    ///
+    /// A recorded request which the new worker exits without responding to
+    /// is forgotten, and replay continues in another worker.
+    pub fn restart(&mut self) -> io::Result<ExitStatus> {
+        let status = self.replace()?;
+        let mut i = 0;
+
+        while i < self.recorded.len() {
+            let req = self.recorded[i].clone();
+
+            match self.request(&req)? {
+                Some(_) => i += 1,
+                None => {
+                    self.recorded.remove(i);
+                    self.replace()?;
+                }
+            }
+        }
+
+        Ok(status)
+    }
+
+    /// Asks the worker to end its session, dropping the values it holds,
+    /// and waits for it to exit.
+    pub fn quit(&mut self) -> io::Result<ExitStatus> {
+        self.request(&command_request(".quit"))?;
+        self.child.wait()
+    }
+
+    /// Waits for the worker process to exit and starts a new one.
+    fn replace(&mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait()?;
+        let (child, stdin, stdout) = start(&self.args)?;
+
+        self.child = child;
+        self.stdin = stdin;
+        self.stdout = stdout;
+
+        Ok(status)
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn start(args: &[String]) -> io::Result<(Child, ChildStdin, BufReader<ChildStdout>)> {
+    let mut child = process::Command::new(current_exe()?)
+        .arg("--json")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let stdin = child.stdin.take().expect("no worker stdin");
+    let stdout = child.stdout.take().expect("no worker stdout");
+
+    Ok((child, stdin, BufReader::new(stdout)))
+}
+
+/// Reads input interactively, evaluating it in a worker process started with
+/// the given arguments. The prelude of `config` is evaluated before any input,
+/// and its prompt, key bindings and settings apply to reading input.
+/// Returns desired process exit status.
+pub fn run(argv0: &str, args: Vec<String>, target: Option<String>,
+        config: &Config, color: ColorConfig) -> i32 {
+    let mut worker = match Worker::spawn(args) {
+        Ok(worker) => worker,
+        Err(e) => {
+            println!("{}: failed to start worker: {}", argv0, e);
+            return 1;
+        }
+    };
+
+    if let Some(ref prelude) = config.prelude {
+        let req = eval_request(prelude);
+
+        match evaluate(&mut worker, argv0, req) {
+            Some(true) => (),
+            _ => return 1,
+        }
+    }
+
+    let mut frontend = Frontend::new(config);
+    let mut input = InputReader::new();
+    let mut history = History::new();
+    let mut more = false;
+
+    input.set_highlight(use_color(color));
+
+    for &(ref seq, ref command) in &config.key_bindings {
+        input.bind_sequence(seq, command);
+    }
+
+    // History is only persisted for interactive sessions
+    if input.is_tty() {
+        if let Some(path) = History::default_path() {
+            match History::load(path) {
+                Ok(h) => history = h,
+                Err(e) => println!("{}: failed to load history: {}", argv0, e),
+            }
+        }
+
+        input.load_history(&history);
+    }
+
+    loop {
+        if input.is_tty() {
+            input.set_key_bindings(frontend.line_editing);
+        }
+
+        let prompt = prompt::render(&frontend.prompt, &Context{
+            n: history.len() + 1,
+            mode: if more { Mode::More } else { Mode::New },
+            toolchain: "",
+            target: target.as_ref().map(|s| &s[..]).unwrap_or("host"),
+            session: "",
+        });
+
+        let res = input.read_input(&prompt, &history);
+
+        match res {
+            Command(..) | Program(_) => {
+                if let Err(e) = history.push(input.last_input()) {
+                    println!("{}: failed to save history: {}", argv0, e);
+                }
+            }
+            _ => (),
+        }
+
+        match res {
+            Command(name, args) => {
+                more = false;
+
+                let name = lookup_command(&name).map_or("", |cmd| cmd.name);
+
+                if name == "quit" {
+                    break;
+                }
+
+                if name == "reset" {
+                    worker.forget();
+                }
+
+                let req = command_request(input.last_input());
+
+                if let Some(true) = evaluate(&mut worker, argv0, req.clone()) {
+                    if REPLAYED_COMMANDS.contains(&name) {
+                        worker.record(req);
+                    }
+
+                    if name == "set" {
+                        frontend.set(args.as_ref().map_or("", |s| &s[..]));
+                    }
+                }
+            }
+            Program(_) => {
+                more = false;
+
+                let req = eval_request(input.last_input());
+
+                if let Some(true) = evaluate(&mut worker, argv0, req.clone()) {
+                    worker.record(req);
+                }
+            }
+            Empty => (),
+            More => {
+                more = true;
+            }
+            Eof => {
+                if input.is_tty() {
+                    println!("");
+                }
+                break;
+            }
+            InputError(err) => {
+                if let Some(err) = err {
+                    println!("{}", err);
+                }
+                more = false;
+            }
+        }
+    }
+
+    if let Err(e) = worker.quit() {
+        println!("{}: {}", argv0, e);
+    }
+
+    0
+}
+
+/// Sends a request to the worker and prints its response.
+/// Returns whether the request succeeded, or `None` if the worker exited
+/// before responding, in which case it is restarted.
+fn evaluate(worker: &mut Worker, argv0: &str, req: Json) -> Option<bool> {
+    match worker.request(&req) {
+        Ok(Some(response)) => Some(print_response(&response)),
+        Ok(None) => {
+            match worker.restart() {
+                Ok(status) => println!("{}: worker exited with {}; \
+                    restarted it and replayed {} requests", argv0, status, worker.recorded()),
+                Err(e) => println!("{}: failed to restart worker: {}", argv0, e),
+            }
+            None
+        }
+        Err(e) => {
+            println!("{}: {}", argv0, e);
+            None
+        }
+    }
+}
+
+fn eval_request(code: &str) -> Json {
+    let mut obj = BTreeMap::new();
+
+    obj.insert("op".to_owned(), Json::String("eval".to_owned()));
+    obj.insert("code".to_owned(), Json::String(code.to_owned()));
+
+    Json::Object(obj)
+}
+
+fn command_request(cmd: &str) -> Json {
+    let mut obj = BTreeMap::new();
+
+    obj.insert("op".to_owned(), Json::String("command".to_owned()));
+    obj.insert("command".to_owned(), Json::String(cmd.to_owned()));
+
+    Json::Object(obj)
+}
+
+/// Prints the output of a response as if the request had been evaluated by
+/// this process. Returns whether the request succeeded; that is, whether
+/// code compiled, as commands always succeed.
+fn print_response(response: &Json) -> bool {
+    if let Some(error) = response.find("error").and_then(|e| e.as_string()) {
+        println!("{}", error);
+        return false;
+    }
+
+    let text = |name: &str| response.find(name).and_then(|s| s.as_string()).unwrap_or("");
+
+    print!("{}", text("stdout"));
+    eprint!("{}", text("stderr"));
+
+    for diag in response.find("diagnostics").and_then(|d| d.as_array()).into_iter().flat_map(|d| d) {
+        if let Some(rendered) = diag.find("rendered").and_then(|r| r.as_string()) {
+            eprint!("{}", rendered);
+        }
+    }
+
+    if let Some(value) = response.find("value").and_then(|v| v.as_string()) {
+        println!("{}", value);
+    }
+
+    let _ = io::stdout().flush();
+
+    response.find("compiled").and_then(|c| c.as_boolean()).unwrap_or(true)
+}
+
+#[cfg(test)]
+mod test {
+    use serialize::json::Json;
+
+    use config::Config;
+    use input::KeyBindings;
+    use prompt::DEFAULT_PROMPT;
+    use super::{command_request, eval_request, print_response, Frontend};
+
+    #[test]
+    fn test_requests() {
+        assert_eq!(eval_request("1 + 1").to_string(), r#"{"code":"1 + 1","op":"eval"}"#);
+        assert_eq!(command_request(".set opt-level 2").to_string(),
+            r#"{"command":".set opt-level 2","op":"command"}"#);
+    }
+
+    #[test]
+    fn test_print_response() {
+        assert!(print_response(&Json::from_str(r#"{"compiled":true,"stdout":""}"#).unwrap()));
+        assert!(!print_response(&Json::from_str(r#"{"compiled":false}"#).unwrap()));
+        assert!(print_response(&Json::from_str(r#"{"stdout":"","stderr":""}"#).unwrap()));
+        assert!(!print_response(&Json::from_str(r#"{"error":"invalid request"}"#).unwrap()));
+    }
+
+    #[test]
+    fn test_frontend() {
+        let config = Config::parse("prompt = \"rust> \"\n[settings]\nkeybindings = \"vi\"\n").unwrap();
+        let mut frontend = Frontend::new(&config);
+
+        assert_eq!(frontend.prompt, "rust> ");
+        assert_eq!(frontend.line_editing, KeyBindings::Vi);
+
+        frontend.set("prompt \"[{n}] \"");
+        frontend.set("keybindings bogus");
+        assert_eq!(frontend.prompt, "[{n}] ");
+        assert_eq!(frontend.line_editing, KeyBindings::Vi);
+
+        frontend.set("prompt");
+        frontend.set("keybindings");
+        frontend.set("opt-level 2");
+        assert_eq!(frontend.prompt, DEFAULT_PROMPT);
+        assert_eq!(frontend.line_editing, KeyBindings::Emacs);
+    }
+}
@@ -2,7 +2,7 @@ extern crate rusti;
 
 use std::mem::transmute;
 
-use rusti::exec::ExecutionEngine;
+use rusti::exec::{CompileHooks, ExecutionEngine};
 
 fn new_ee(code: &str) -> ExecutionEngine {
     ExecutionEngine::new_with_input(code, Vec::new(), None)
@@ -146,3 +146,29 @@ pub fn thread_spawn() {
 
     f();
 }
+
+#[ignore]
+#[test]
+fn test_hooks() {
+    let mut ee = new_ee("");
+    let mut parsed = 0;
+    let mut lowered = 0;
+    let mut analyzed = false;
+    let mut translated = false;
+
+    {
+        let hooks = CompileHooks::new()
+            .after_parse(|krate| parsed = krate.module.items.len())
+            .after_hir_lowering(|map| lowered = map.krate().items.len())
+            .after_analysis(|tcx| analyzed = !tcx.sess.has_errors())
+            .after_llvm(|llmod| translated = !llmod.is_null());
+
+        ee.add_module_with_hooks("pub fn a() {}\npub fn b() {}", hooks)
+            .expect("could not compile module");
+    }
+
+    assert_eq!(parsed, 2);
+    assert!(lowered >= 2);
+    assert!(analyzed);
+    assert!(translated);
+}
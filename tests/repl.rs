@@ -33,8 +33,12 @@ fn repl_file(path: &str) -> String {
 }
 
 fn repl_input(input: &str) -> String {
+    repl_run_input(&["--no-rc"], input)
+}
+
+fn repl_run_input(args: &[&str], input: &str) -> String {
     let mut cmd = match rusti_cmd()
-        .arg("--no-rc")
+        .args(args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .spawn()
@@ -58,6 +62,21 @@ fn repl_input(input: &str) -> String {
     }
 }
 
+fn repl_edit(editor: &str, input: &str) -> String {
+    let mut cmd = rusti_cmd()
+        .args(&["--no-rc"])
+        .env_remove("VISUAL")
+        .env("EDITOR", editor)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    cmd.stdin.as_mut().unwrap().write_all(input.as_bytes()).unwrap();
+
+    String::from_utf8(cmd.wait_with_output().unwrap().stdout).unwrap()
+}
+
 #[test]
 fn test_eval() {
     assert_eq!(
@@ -70,6 +89,17 @@ fn test_eval() {
     assert_eq!(repl_eval("fn foo() -> u32 { 3 }; foo()"), "3\n");
 }
 
+#[test]
+fn test_eval_status() {
+    let status = |code: &str| rusti_cmd().args(&["--no-rc", "-e", code])
+        .stdout(Stdio::null()).stderr(Stdio::null()).status().unwrap().code();
+
+    assert_eq!(status("1 + 1"), Some(0));
+    assert_eq!(status("1 +"), Some(1));
+    assert_eq!(status("undefined()"), Some(1));
+    assert_eq!(status("panic!()"), Some(101));
+}
+
 #[test]
 fn test_file() {
     assert_eq!(repl_file("data/test_run.rs"), "foo\n123 = i32\nbar\n");
@@ -83,6 +113,28 @@ fn test_load() {
     );
 }
 
+#[test]
+fn test_load_optimized() {
+    assert_eq!(
+        repl_input(".set opt-level 2\n.load data/test_load.rs\nhello(\"world\");"),
+        "Hello, world!\n"
+    );
+}
+
+#[test]
+fn test_source() {
+    let out = repl_input("fn double(x: i32) -> i32 { x * 2 }\ndouble(21)\n.source 2\n.source\n.source 1\n.source 9\n");
+    let mut parts = out.splitn(2, "\n");
+
+    assert_eq!(parts.next(), Some("42"));
+
+    let rest = parts.next().unwrap();
+
+    assert!(rest.contains("fn double(x: i32) -> i32 { x * 2 }"));
+    assert_eq!(rest.matches("fn _rusti_inner(").count(), 3);
+    assert!(rest.ends_with("no program was compiled for input 9\n"));
+}
+
 #[test]
 fn test_print() {
     assert_eq!(repl_cmd(".print 1"), "1\n");
@@ -97,6 +149,17 @@ fn test_rc() {
     );
 }
 
+#[test]
+fn test_config() {
+    let out = rusti_cmd()
+        .env("XDG_CONFIG_HOME", "data/config")
+        .args(&["-e", "greeting()"])
+        .output()
+        .unwrap();
+
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "rc says hi\n\"hell...\n");
+}
+
 #[test]
 fn test_type() {
     assert_eq!(repl_cmd(".type 1"), "1 = i32\n");
@@ -107,3 +170,1025 @@ fn test_type() {
         "vec![1u32] = std::vec::Vec<u32>\n"
     );
 }
+
+#[test]
+fn test_set() {
+    let settings = repl_input(".set\n");
+
+    for line in &["  auto-import      off\n", "  hints            off\n", "  jobs             none\n",
+            "  keybindings      emacs\n", "  linker           default\n", "  opt-level        0\n", "  target           host\n",
+            "  thin-lto         on\n", "  warnings         show\n"] {
+        assert!(settings.contains(line), "missing {:?} in:\n{}", line, settings);
+    }
+
+    assert!(repl_input(".set linker foo-ld\n.set link-arg -v\n.set\n")
+        .contains("  linker           foo-ld -v\n"));
+    assert!(repl_input(".set linker foo-ld\n.set linker\n.set\n")
+        .contains("  linker           default\n"));
+    assert!(repl_input(".set link-arg -v\n")
+        .contains("note: input run within rusti is linked in memory"));
+    assert!(!repl_input(".args x\n.set link-arg -v\n").contains("linked in memory"));
+
+    if !cfg!(windows) {
+        assert!(repl_input(".set linker lld\n.set link-arg -v\n.set link-arg\n.set\n")
+            .contains("  linker           default -fuse-ld=lld\n"));
+    }
+    assert!(repl_input(".set opt-level 3\n.set\n").contains("  opt-level        3\n"));
+    assert!(repl_input(".set jobs 2\n.set\n").contains("  jobs             2\n"));
+    assert!(repl_input(".set thin-lto off\n.set\n").contains("  thin-lto         off\n"));
+    assert!(repl_input(".set keybindings vi\n.set\n").contains("  keybindings      vi\n"));
+    assert!(repl_input(".set keybindings ed\n")
+        .contains("invalid value for `keybindings`: expected `emacs` or `vi`"));
+    assert!(repl_input(".set rustc-wrapper sccache\n.set\n")
+        .contains("  rustc-wrapper    sccache\n"));
+    assert!(
+        repl_input(".set prompt \"rusti[{n}]> \"\n.set\n")
+            .contains("  prompt           \"rusti[{n}]> \"\n")
+    );
+}
+
+#[test]
+fn test_library_collisions() {
+    let out = rusti_cmd().args(&["--no-rc", "-e", "vec![1]"]).output().unwrap();
+
+    // The allocator shim of each input is also defined by the standard library
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("also defined by a loaded library"));
+}
+
+#[test]
+fn test_warnings() {
+    assert_eq!(repl_input(".set warnings deny\nlet mut a = 1; a\n\
+        .allow unused-mut\nlet mut b = 2; b\n.allow\n.deny\n"), "2\nunused_mut\n");
+    assert_eq!(repl_input(".set warnings loud\n"),
+        "invalid value for `warnings`: expected one of deny, show, hide\n");
+}
+
+#[test]
+fn test_verbosity() {
+    let stderr = |input: &str| {
+        let mut child = rusti_cmd().arg("--no-rc")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+        String::from_utf8(child.wait_with_output().unwrap().stderr).unwrap()
+    };
+
+    assert!(stderr("let mut a = 1;\na\n").contains("does not need to be mutable"));
+    assert!(!stderr(".set verbosity quiet\nlet mut a = 1;\na\n").contains("does not need to be mutable"));
+
+    let debug = stderr(".set verbosity debug\n1 + 1\n");
+
+    assert!(debug.contains("rustc args: rustc --crate-type dylib --sysroot "));
+    assert!(debug.contains("source:\n"));
+    assert!(debug.contains("fn _rusti_inner("));
+    assert!(debug.contains("timing: parse "));
+
+    assert_eq!(repl_input(".set verbosity loud\n"),
+        "invalid value for `verbosity`: expected one of quiet, normal, debug\n");
+}
+
+#[test]
+fn test_show_types() {
+    assert_eq!(repl_input(".set show-types on\nlet (a, b) = (1u8, \"x\"); let _rusti_x = 0;\n"),
+        "let a: u8\nlet b: &'static str\n");
+    assert_eq!(repl_input(".set show-types on\nfn f() { let x = 1u8; }\n"), "");
+    assert_eq!(repl_input(".set show-types x\n"),
+        "invalid value for `show-types`: expected `on` or `off`\n");
+}
+
+#[test]
+fn test_format() {
+    assert_eq!(repl_input(".format Vec<i32> pretty\nvec![1]\n"), "[\n    1\n]\n");
+    assert_eq!(
+        repl_input("fn hex(n: &u32) -> String { format!(\"{:#x}\", n) }\n.format u32 hex\n255u32\n"),
+        "0xff\n"
+    );
+    assert_eq!(repl_input(".format u32 pretty\n.format u32\n1u32\n"), "1\n");
+}
+
+#[test]
+fn test_graphics() {
+    assert_eq!(
+        repl_input(".set graphics iterm\nprintln!(\"RUSTI_BEGIN_CONTENT image/png\\nAAAA\\nRUSTI_END_CONTENT\");\n"),
+        "\x1b]1337;File=inline=1;size=3:AAAA\x07\n"
+    );
+    assert_eq!(
+        repl_input(".set graphics iterm\nprintln!(\"RUSTI_BEGIN_CONTENT text/html\\n<b>\\nRUSTI_END_CONTENT\");\n"),
+        "[text/html display data]\n"
+    );
+}
+
+#[test]
+fn test_max_output() {
+    assert_eq!(repl_input(".set max-output 3\n\"abcdef\"\n"), "\"ab...\n");
+    assert_eq!(
+        repl_input(".set max-output 3\n\"abcdef\"\n.show-full\n"),
+        "\"ab...\n\"abcdef\"\n"
+    );
+    assert_eq!(repl_input(".show-full\n"), "no value to show\n");
+}
+
+#[test]
+fn test_history() {
+    assert_eq!(
+        repl_input("1\n!!\n.history\n"),
+        "1\n1\n1\n    1  1\n    2  .history\n"
+    );
+    assert_eq!(
+        repl_input("fn foo() -> i32 {\n2\n}\nfoo()\n.history foo\n!2\n"),
+        "2\n    1  fn foo() -> i32 {\n       2\n       }\n    2  foo()\n    3  .history foo\nfoo()\n2\n"
+    );
+    assert_eq!(repl_input("!5\n"), "!5: event not found\n");
+}
+
+#[test]
+fn test_shell() {
+    let out = repl_input(".cd data\n.pwd\n.! ls test_run.rs\n.!echo hi\n");
+
+    assert!(out.ends_with("/data\ntest_run.rs\nhi\n"));
+}
+
+#[test]
+fn test_args() {
+    assert_eq!(
+        repl_input(".args foo \"bar baz\" --qux\nstd::env::args().skip(1).collect::<Vec<_>>()\n\
+            .args a\n.args\n"),
+        "input now runs as an executable in a process of its own, without the values of \
+            previous expressions; `.args` alone runs it within rusti again\n\
+            [\"foo\", \"bar baz\", \"--qux\"]\ninput runs within rusti again\n"
+    );
+}
+
+#[test]
+fn test_panic_backtrace() {
+    let out = repl_input("fn check(n: i32) { assert!(n > 0) }\n\
+        fn run(v: &[i32]) { for &n in v { check(n) } }\n\
+        run(&[1, -1])\n");
+
+    assert!(out.ends_with("backtrace:\n   0: check (input 1)\n   1: run (input 2)\n   2: <input>\n"));
+
+    // Panics caught by input are not reported
+    assert_eq!(repl_input("std::panic::catch_unwind(|| panic!()).is_err()\n"), "true\n");
+}
+
+#[test]
+fn test_stdin() {
+    assert_eq!(
+        repl_input(".stdin hello\nlet mut s = String::new(); \
+            std::io::stdin().read_line(&mut s).unwrap(); s\n"),
+        "\"hello\\n\"\n"
+    );
+    assert_eq!(
+        repl_input(".stdin <<END\na\nb\nEND\nuse std::io::Read; let mut s = String::new(); \
+            std::io::stdin().read_to_string(&mut s).unwrap(); s\n"),
+        "\"a\\nb\\n\"\n"
+    );
+}
+
+#[test]
+fn test_test() {
+    assert_eq!(repl_input(".test\n"), "no tests are defined\n");
+
+    let out = repl_input("#[test] fn passes() {}\n#[test] fn fails() { panic!() }\n\
+        .test pass\n");
+
+    assert!(out.contains("test passes ... ok\n"));
+    assert!(out.contains("1 passed; 0 failed"));
+    assert!(!out.contains("fails"));
+}
+
+#[test]
+fn test_watch() {
+    // The empty line ends the watch
+    assert_eq!(
+        repl_input(".watch data/test_watch.rs watched\n\nwatched();\n"),
+        "watching data/test_watch.rs; press Enter to stop\nwatched\nwatched\n"
+    );
+}
+
+#[test]
+fn test_fix() {
+    assert_eq!(repl_input(".fix\n"), "no failed input to fix\n");
+    assert_eq!(repl_input("fn foo() {}\n.fix\n"), "no failed input to fix\n");
+
+    let input = "HashMap::<i32, i32>::new().len()\n.fix\n";
+    let diff = "+use std::collections::HashMap;\n HashMap::<i32, i32>::new().len()\n\
+        apply the changes and run the input? [y/N] ";
+
+    assert_eq!(repl_input(&format!("{}y\n", input)),
+        format!("{}adding `use std::collections::HashMap;`\n0\n", diff));
+    assert_eq!(repl_input(&format!("{}n\n.fix\n", input)), format!("{}{}\n", diff, diff));
+}
+
+#[test]
+fn test_auto_import() {
+    assert_eq!(
+        repl_input(".set auto-import on\nHashMap::<i32, i32>::new().len()\nHashSet::<i32>::new()\n"),
+        "adding `use std::collections::HashMap;`\n0\nadding `use std::collections::HashSet;`\n{}\n"
+    );
+}
+
+#[test]
+fn test_hints() {
+    let out = repl_input(".set hints on\n{ let a = String::new(); let b = a; a.len() }\n");
+
+    assert!(out.starts_with("hint: a value can only have one owner."));
+    assert!(out.contains("for example:\n    let a = String::from(\"hi\");\n"));
+    assert!(repl_input(".set hints on\n{ let n = 1; n = 2; }\n").contains("`let mut`"));
+    assert_eq!(repl_input("{ let n = 1; n = 2; }\n"), "");
+}
+
+#[test]
+fn test_explain() {
+    assert!(repl_input(".explain e308\n").contains("mismatch"));
+    assert_eq!(repl_input(".explain E9999\n"), "no explanation for error code `E9999`\n");
+    assert_eq!(repl_input(".explain x\n"),
+        "command `explain` expects an error code, such as E0308\n");
+}
+
+#[test]
+fn test_impls() {
+    let out = repl_input("#[derive(Clone)] struct Foo;\nimpl Foo { fn f() {} }\n.impls Foo\n");
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert!(lines[0].starts_with("impl Foo "));
+    assert!(lines.iter().any(|l| l.starts_with("std::clone::Clone ")));
+    assert!(lines.iter().any(|l| l.starts_with("std::marker::Send ") && l.ends_with(" (auto)")));
+    assert!(!lines.iter().any(|l| l.starts_with("std::marker::Copy ")));
+
+    assert_eq!(repl_input(".impls\n"), "command `impls` expects a type\n");
+}
+
+#[test]
+fn test_methods() {
+    let out = repl_input("#[derive(Clone)] struct Foo;\n\
+        impl Foo { fn f(&self, x: u32) -> u32 { x } }\n.methods Foo\n");
+
+    assert!(out.starts_with("fn f(&self, x: u32) -> u32\n"));
+    assert!(out.contains("std::clone::Clone:\n    fn clone(&self) -> Self\n"));
+
+    assert!(repl_input("vec![1u8]\n.methods res\n").contains("\nfn len(&self) -> usize\n"));
+    assert_eq!(repl_input(".methods\n"), "command `methods` expects a type or binding\n");
+}
+
+#[test]
+fn test_holes() {
+    assert_eq!(repl_input("fn square(n: u32) -> u32 { n * n }\n\
+        let x = 3u32; let y = 1u8; let s: u32 = __?; println!(\"__?\");\n"),
+        "hole 1: expected `u32`\n  x: u32\n  fn square(n: u32) -> u32\n");
+    assert_eq!(repl_input("let v: Vec<u8> = vec![__?];\n"), "hole 1: expected `u8`\n");
+}
+
+#[test]
+fn test_why_impl() {
+    let out = repl_input("struct Foo;\nimpl Foo { fn f(&self) -> u8 { 1 } }\n.why-impl Foo.f()\n");
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines[0], "method:   Foo::f");
+    assert!(lines[1].starts_with("impl:     impl Foo ("));
+    assert_eq!(&lines[2..], ["receiver: Foo", "  autoref: &Foo"]);
+
+    assert!(repl_input(".why-impl vec![1, 2].clone()\n").contains("\nwhere:    T = i32\n"));
+    assert_eq!(repl_input(".why-impl 1\n"), "no method call or operator in `1`\n");
+}
+
+#[test]
+fn test_mono() {
+    let out = repl_input("fn id<T>(x: T) -> T { x }\nid(1u8); id(\"a\");\n.mono\n");
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert!(lines.iter().any(|l| l.contains("id::<u8>")));
+    assert!(lines.iter().any(|l| l.contains("id::<&str>")));
+    assert!(lines.last().unwrap().contains(" instantiations, estimated size "));
+
+    assert_eq!(repl_input(".mono x\n"), "command `mono` expects an input number\n");
+}
+
+#[test]
+fn test_stats() {
+    let out = repl_input("1\n.stats\n");
+    let phases = out.lines()
+        .skip_while(|l| !l.starts_with("  phase "))
+        .map(|l| l.split_whitespace().next().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(phases, ["phase", "parse", "expand", "typeck", "trans", "link", "jit", "total"]);
+    assert!(out.lines().any(|l| l.starts_with("  total ") && l.ends_with(" ms")));
+    assert!(out.contains("  linker           none\n"));
+
+    // Only input built into an executable is linked by the linker
+    assert!(repl_input(".args x\n1\n.stats\n").contains("  linker           default\n"));
+}
+
+#[test]
+fn test_stats_heap() {
+    // One allocation of 50 bytes, then a reallocation to 100 bytes
+    let out = repl_input("{ let mut v: Vec<[u16; 25]> = Vec::with_capacity(1); \
+        v.push([0; 25]); v.push([0; 25]); }\n.stats\n");
+    let row = |name: &str| out.lines()
+        .find(|l| l.starts_with(name))
+        .map(|l| l.split_whitespace().rev().nth(1).unwrap().parse::<u64>().unwrap());
+
+    assert_eq!(row("  allocations "), Some(2));
+    assert_eq!(row("  bytes "), Some(150));
+    assert_eq!(row("  peak bytes "), Some(100));
+}
+
+#[test]
+fn test_sanitizer() {
+    assert!(repl_input(".set sanitizer address\n.set\n").contains("  sanitizer        address\n"));
+    assert!(repl_input(".set sanitizer thread\n.set sanitizer off\n.set\n")
+        .contains("  sanitizer        off\n"));
+    assert_eq!(repl_input(".set sanitizer memory\n"),
+        "invalid value for `sanitizer`: expected one of address, leak, thread, off\n");
+}
+
+#[test]
+fn test_targets() {
+    let out = repl_input(".targets\n");
+
+    assert!(out.lines().any(|l| l.ends_with(" (current)")));
+    assert!(out.lines().any(|l| l == "wasm32-unknown-unknown"));
+}
+
+#[test]
+fn test_target_features() {
+    let out = repl_input(".target-features\n");
+
+    assert!(out.starts_with("target:  "));
+    assert!(out.lines().nth(1).unwrap().starts_with("enabled: "));
+    assert!(out.contains("Available features for this target:"));
+}
+
+#[test]
+fn test_layout() {
+    assert_eq!(repl_input("#[repr(C)] struct Foo { a: u8, b: u32 }\n.layout Foo\n"), "\
+Foo: size 8, align 4
+     0  a: u8 (size 1, align 1)
+     1  (3 bytes of padding)
+     4  b: u32 (size 4, align 4)
+");
+    assert!(repl_input(".layout Option<u32>\n")
+        .starts_with("std::option::Option<u32>: size 8, align 4, tag of 4 bytes at offset 0\n"));
+    assert_eq!(repl_input(".layout\n"), "command `layout` expects a type\n");
+}
+
+#[test]
+fn test_bench() {
+    let out = repl_input(".bench (0..100u64).sum::<u64>()\n");
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("mean:    "));
+    assert!(lines[1].starts_with("median:  "));
+    assert!(lines[2].starts_with("std dev: "));
+    assert!(lines[3].ends_with(" iterations)"));
+}
+
+#[test]
+fn test_spawn() {
+    let out = repl_input(".spawn std::thread::sleep(std::time::Duration::from_secs(60))\n\
+        .kill 1\n.jobs\n.kill 1\n");
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("[1] "));
+    assert_eq!(lines[1], "[1] killed  std::thread::sleep(std::time::Duration::from_secs(60))");
+    assert_eq!(lines[2], "no job numbered `1`");
+}
+
+#[test]
+fn test_env() {
+    assert_eq!(
+        repl_input(".env RUSTI_TEST=a b\nstd::env::var(\"RUSTI_TEST\")\n.env RUSTI_TEST\n\
+            .env -u RUSTI_TEST\n.env RUSTI_TEST\n"),
+        "Ok(\"a b\")\nRUSTI_TEST=a b\n`RUSTI_TEST` is not set\n"
+    );
+    assert_eq!(repl_input(".env -u RUSTI_TEST=a\n.env RUSTI\0TEST=a\n1\n"),
+        "invalid variable name `RUSTI_TEST=a`\ninvalid variable name `RUSTI\0TEST`\n1\n");
+}
+
+#[test]
+fn test_edit() {
+    assert_eq!(
+        repl_edit("sed -i s/1/2/", "1 + 1\n.edit 1\n.edit 5\n"),
+        "2\n3\nno input numbered `5` in history\n"
+    );
+    assert_eq!(
+        repl_edit("sed -i s/1/2/", "fn foo() -> i32 { 1 }\nfn bar() -> i32 { foo() + 1 }\n.edit 1\nbar()\n"),
+        "3\n"
+    );
+    assert_eq!(
+        repl_edit("sed -i s/i32/u8/", "fn foo() -> i32 { 1 }\nfn bar() -> i32 { foo() }\n.edit 1\nfoo()\n"),
+        "input 2 no longer compiles and has been removed\n1\n"
+    );
+    assert_eq!(
+        repl_edit("sed -i s/i32/u8/", "fn foo() -> i32 { 1 }\nfn bar() -> i32 { foo() }\n\
+            fn baz() -> i32 { 2 }\nfn qux() -> i32 { bar() }\n.edit 1\nbaz()\n"),
+        "input 2 no longer compiles and has been removed\n\
+            input 4 no longer compiles and has been removed\n2\n"
+    );
+    assert_eq!(
+        repl_edit("sed -i s/i32/u8/", "#[derive(Debug)] struct P(i32);\nP(1)\n.edit 1\nres0\n")
+            .lines().take(2).collect::<Vec<_>>(),
+        ["P(1)", "res0 is no longer bound, as its type was removed"]
+    );
+}
+
+#[test]
+fn test_results() {
+    assert_eq!(
+        repl_input("vec![3, 1, 2]\nres0.iter().max().cloned()\nres.unwrap() * 2\n"),
+        "[3, 1, 2]\nSome(3)\n6\n"
+    );
+    assert_eq!(repl_input("1\n.reset\nres\n").lines().next(), Some("1"));
+}
+
+#[test]
+fn test_outputs() {
+    assert_eq!(repl_input("vec![1, 2]\nlet x = 1;\nout[1].len()\n.history --values\n"),
+        "[1, 2]\n6\n    1  vec![1, 2]\n    = res0: [1, 2]\n    3  out[1].len()\n    = res1: 6\n");
+
+    // An item named `out` is not shadowed by the outputs
+    assert_eq!(repl_input("1\nfn out() -> i32 { 2 }\nout()\n"), "1\n2\n");
+    assert_eq!(repl_input(&format!("\"{}\".len()\nout[1].len()\n", "x".repeat(100000))),
+        "100000\n6\n");
+}
+
+#[test]
+fn test_check() {
+    assert_eq!(repl_cmd(r#".check println!("not run");"#), "");
+    assert_eq!(
+        repl_run(&["--no-rc", "--check-only", "-e", r#"println!("not run");"#]),
+        ""
+    );
+}
+
+#[test]
+fn test_deny_unsafe() {
+    let args = ["--no-rc", "--deny-unsafe"];
+
+    assert_eq!(repl_run_input(&args, "unsafe { 1 }\n1 + 1\n"), "2\n");
+    assert_eq!(repl_run_input(&args, "unsafe fn one() -> i32 { 1 }\n2\n"), "2\n");
+    assert!(repl_run_input(&args, "extern crate libc;\n")
+        .contains("crate `libc` may not be used with --deny-unsafe"));
+    assert_eq!(repl_run_input(&args, "vec![1, 2]\n.bench res0.len()\n").lines().next(),
+        Some("[1, 2]"));
+}
+
+#[test]
+fn test_sandbox() {
+    assert_eq!(
+        repl_run_input(&["--no-rc", "--sandbox"], ".set runner qemu-arm\n.! echo escaped\n"),
+        "`runner` cannot be changed in sandbox mode\nshell commands are disabled in sandbox mode\n"
+    );
+    assert!(repl_run(&["--no-rc", "--allow-read", "/"]).ends_with(
+        ": --allow-read and --allow-write require --sandbox\n"));
+
+    let bwrap = Command::new("bwrap").args(&["--ro-bind", "/", "/", "true"]).status();
+
+    // Files beside the executable, as in `/tmp`, are hidden from code in the sandbox
+    if bwrap.map(|s| s.success()).unwrap_or(false) {
+        let marker = std::env::temp_dir().join("rusti-test-sandbox-marker");
+        std::fs::File::create(&marker).unwrap();
+
+        let out = repl_run_input(&["--no-rc", "--sandbox"],
+            &format!("println!(\"{{}}\", std::path::Path::new({:?}).exists());\n", marker));
+
+        std::fs::remove_file(&marker).unwrap();
+        assert_eq!(out, "false\n");
+    }
+}
+
+#[test]
+fn test_json() {
+    let out = repl_run_input(
+        &["--json"],
+        "{\"id\": 1, \"code\": \"println!(\\\"hi\\\");\"}\n\"1 +\"\n",
+    );
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains(r#""compiled":true"#));
+    assert!(lines[0].contains(r#""executed":true"#));
+    assert!(lines[0].contains(r#""id":1"#));
+    assert!(lines[0].contains(r#""stdout":"hi\n""#));
+    assert!(lines[0].contains(r#""value":null"#));
+    assert!(lines[1].contains(r#""compiled":false"#));
+
+    let out = repl_run_input(&["--json"], "{\"id\": 1, \"op\": \"interrupt\"}\n");
+    assert!(out.contains(r#""interrupted":false"#));
+}
+
+#[test]
+fn test_jsonrpc() {
+    let out = repl_run_input(
+        &["--jsonrpc"],
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"code": "1 + 1", "block": "b1"}}
+{"jsonrpc": "2.0", "id": 2, "method": "type_at", "params": {"code": "let s = \"a\"; s.len()", "pos": 13}}
+{"jsonrpc": "2.0", "id": 3, "method": "type_at", "params": {"code": "let s = \"a\"; s.len()", "pos": 15}}
+{"jsonrpc": "2.0", "method": "interrupt"}
+{"jsonrpc": "2.0", "id": 4, "method": "nope"}
+"#,
+    );
+    let lines = out.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].contains(r#""block":"b1""#));
+    assert!(lines[0].contains(r#""value":"2""#));
+    assert!(lines[0].contains(r#""id":1"#));
+    assert_eq!(lines[1], r#"{"id":2,"jsonrpc":"2.0","result":{"type":"&str"}}"#);
+    assert_eq!(lines[2], r#"{"id":3,"jsonrpc":"2.0","result":{"type":"usize"}}"#);
+    assert!(lines[3].contains(r#""code":-32601"#));
+}
+
+#[test]
+fn test_json_display() {
+    let out = repl_run_input(
+        &["--json"],
+        "\"println!(\\\"RUSTI_BEGIN_CONTENT text/html\\\\n<b>hi</b>\\\\nRUSTI_END_CONTENT\\\");\"\n",
+    );
+
+    assert!(out.contains(r#""display":[{"data":"<b>hi</b>","mime":"text/html"}]"#));
+    assert!(out.contains(r#""stdout":"""#));
+}
+
+#[test]
+fn test_json_pty() {
+    let code = r#""extern \"C\" { fn isatty(fd: i32) -> i32; } println!(\"{}\", unsafe { isatty(1) });""#;
+
+    let mut cmd = rusti_cmd()
+        .env("XDG_CONFIG_HOME", "data/pty")
+        .arg("--json")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    writeln!(cmd.stdin.as_mut().unwrap(), "{}", code).unwrap();
+
+    let out = String::from_utf8(cmd.wait_with_output().unwrap().stdout).unwrap();
+
+    assert!(out.contains(r#""stdout":"1\n""#));
+}
+
+#[test]
+fn test_json_value() {
+    let out = repl_run_input(&["--json"], "\"vec![1, 2] \"\n");
+
+    assert!(out.contains(r#""value":"[1, 2]""#));
+    assert!(out.contains(r#""stdout":"""#));
+}
+
+#[test]
+fn test_json_command() {
+    let out = repl_run_input(&["--json"], "{\"op\": \"command\", \"command\": \".set opt-level 2\"}\n");
+
+    assert_eq!(out, "{\"stderr\":\"\",\"stdout\":\"\"}\n");
+}
+
+#[test]
+fn test_isolate() {
+    let out = repl_run_input(&["--no-rc", "--isolate"],
+        "fn two() -> i32 { 2 }\n::std::process::abort();\ntwo() * 21\n");
+
+    assert!(out.contains("worker exited with"));
+    assert!(out.contains("replayed 1 requests"));
+    assert!(out.ends_with("42\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_server_superseded() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    let path = "data/test-server.sock";
+    let _ = std::fs::remove_file(path);
+
+    let mut server = rusti_cmd()
+        .args(&["--no-rc", "--server", &format!("unix:{}", path)])
+        .spawn()
+        .unwrap();
+
+    let mut stream = None;
+
+    for _ in 0..100 {
+        match UnixStream::connect(path) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(_) => sleep(Duration::from_millis(100)),
+        }
+    }
+
+    let mut stream = stream.expect("failed to connect to server");
+
+    stream.write_all(b"{\"id\": 1, \"code\": \"1\"}\n{\"id\": 2, \"code\": \"2\"}\n").unwrap();
+
+    // Commands are not superseded by later requests
+    stream.write_all(b"{\"id\": 3, \"op\": \"command\", \"command\": \".load data/test_load.rs\"}\n\
+        {\"id\": 4, \"code\": \"hello(\\\"x\\\")\"}\n").unwrap();
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut lines = (&mut reader).lines().take(4)
+        .collect::<Result<Vec<_>, _>>().unwrap();
+
+    stream.write_all(b"{\"id\": 5, \"code\": \"let x: u8 = 1000;\"}\n").unwrap();
+    lines.push(reader.lines().next().unwrap().unwrap());
+
+    server.kill().unwrap();
+    server.wait().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    assert!(lines[0].contains(r#""cancelled":true"#));
+    assert!(lines[0].contains(r#""compiled":false"#));
+    assert!(lines[1].contains(r#""cancelled":false"#));
+    assert!(lines[1].contains(r#""value":"2""#));
+    assert!(lines[3].contains(r#""stdout":"Hello, x!\n""#));
+
+    // Compiler messages are reported as diagnostics rather than text
+    assert!(lines[4].contains(r#""diagnostics":[{"#));
+}
+
+#[test]
+fn test_server_loopback() {
+    let out = rusti_cmd().args(&["--no-rc", "--server", "0.0.0.0:0"]).output().unwrap();
+
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("only loopback addresses"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_session() {
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    let send = |code: &str, block: &str| {
+        let mut cmd = rusti_cmd();
+
+        cmd.env("XDG_RUNTIME_DIR", "data")
+            .args(&["--send", "test-session", "--block", block])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = cmd.spawn().unwrap();
+
+        child.stdin.take().unwrap().write_all(code.as_bytes()).unwrap();
+
+        let out = child.wait_with_output().unwrap();
+
+        (String::from_utf8(out.stdout).unwrap(), String::from_utf8(out.stderr).unwrap(),
+            out.status.code())
+    };
+
+    let mut server = rusti_cmd()
+        .env("XDG_RUNTIME_DIR", "data")
+        .args(&["--no-rc", "--session", "test-session"])
+        .spawn()
+        .unwrap();
+
+    let mut setup = send("let x = 21;", "setup");
+
+    for _ in 0..100 {
+        if setup.2 == Some(0) {
+            break;
+        }
+        sleep(Duration::from_millis(100));
+        setup = send("let x = 21;", "setup");
+    }
+
+    let value = send("x * 2", "value");
+    let error = send("let y: u32 = \"\";", "error");
+
+    server.kill().unwrap();
+    server.wait().unwrap();
+    let _ = std::fs::remove_dir_all("data/rusti");
+
+    assert_eq!(setup, (String::new(), String::new(), Some(0)));
+    assert_eq!(value, ("42\n".to_owned(), String::new(), Some(0)));
+    assert!(error.1.contains("mismatched types"));
+    assert_eq!(error.2, Some(1));
+}
+
+#[test]
+fn test_web() {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpStream;
+
+    let mut server = rusti_cmd()
+        .args(&["--web", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut line = String::new();
+    BufReader::new(server.stdout.as_mut().unwrap()).read_line(&mut line).unwrap();
+
+    let url = line.trim().trim_left_matches("serving playground on http://").to_owned();
+    let (addr, token) = {
+        let mut parts = url.splitn(2, "/?token=");
+        (parts.next().unwrap().to_owned(), parts.next().unwrap().to_owned())
+    };
+
+    let request = |req: &str| {
+        let mut stream = TcpStream::connect(&addr[..]).unwrap();
+        let mut res = String::new();
+
+        stream.write_all(req.as_bytes()).unwrap();
+        stream.read_to_string(&mut res).unwrap();
+        res
+    };
+
+    let headers = format!("Host: {}\r\nX-Rusti-Token: {}\r\n", addr, token);
+
+    let page = request(&format!("GET /?token={} HTTP/1.1\r\nHost: {}\r\n\r\n", token, addr));
+    let eval = request(&format!("POST /eval HTTP/1.1\r\n{}Content-Length: 5\r\n\r\n1 + 2", headers));
+    let history = request(&format!("GET /history HTTP/1.1\r\n{}\r\n", headers));
+    let missing = request(&format!("GET /nope HTTP/1.1\r\n{}\r\n", headers));
+    let no_token = request(&format!("POST /eval HTTP/1.1\r\nHost: {}\r\n\
+        Content-Type: text/plain\r\nContent-Length: 5\r\n\r\n1 + 2", addr));
+    let rebound = request(&format!("POST /eval HTTP/1.1\r\nHost: evil.example\r\n\
+        X-Rusti-Token: {}\r\nContent-Length: 5\r\n\r\n1 + 2", token));
+    let cross_origin = request(&format!("POST /eval HTTP/1.1\r\n{}\
+        Origin: http://evil.example\r\nContent-Length: 5\r\n\r\n1 + 2", headers));
+
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert!(page.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(page.contains("<title>rusti playground</title>"));
+    assert!(eval.contains(r#""value":"3""#));
+    assert!(history.contains(r#""code":"1 + 2""#));
+    assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(no_token.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    assert!(rebound.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    assert!(cross_origin.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+}
+
+#[test]
+fn test_speculate() {
+    assert_eq!(repl_input(".set speculate on\n.block\nlet a = 1 + ;\nlet b = 3;\n.q\n"),
+        "  (error: expected expression, found `;`)\n");
+}
+
+#[test]
+fn test_gc() {
+    assert!(repl_cmd(".gc 1000000").starts_with("removed "));
+    assert!(repl_cmd(".gc 99999999999999999").starts_with("removed "));
+    assert_eq!(repl_cmd(".gc x"), "command `gc` expects a number of hours\n");
+}
+
+#[test]
+fn test_session_command() {
+    assert_eq!(repl_input("fn answer() -> i32 { 42 }\n.session new scratch\nanswer()\n\
+        .session list\n.session new main\n.session switch main\nanswer()\n.session\n\
+        .session switch nope\n"),
+        "  main\n* scratch\nsession `main` already exists\n42\nmain\n\
+        no session named `nope`; create one with `.session new nope`\n");
+}
+
+#[test]
+fn test_checkpoint() {
+    assert_eq!(repl_input("fn area(w: u32, h: u32) -> u32 { w * h }\n.checkpoint\n\
+        .checkpoint good\nfn double(x: u32) -> u32 { x * 3 }\n1\n.rollback good\n\
+        fn double(x: u32) -> u32 { x * 2 }\narea(double(1), 3)\n.rollback bad\n.checkpoint\n"),
+        "no checkpoints\n1\ndropped 1 values kept since checkpoint `good`\n6\n\
+        no checkpoint named `bad`\ngood\n");
+}
+
+#[test]
+fn test_fork() {
+    assert_eq!(repl_input("fn answer() -> i32 { 42 }\n.fork other\nanswer()\n.session\n\
+        .fork other\n"),
+        "42\nother\nsession `other` already exists\n");
+}
+
+#[test]
+fn test_import() {
+    assert_eq!(repl_input(".session new toolkit\nfn answer() -> i32 { 42 }\n\
+        .session switch main\n.import toolkit\nanswer()\n.import main\n.import nope\n"),
+        "42\ncannot import session `main` into itself\nno session named `nope`\n");
+    assert_eq!(repl_input(".session new toolkit\nfn answer() -> i32 { 42 }\n\
+        .session switch main\nfn answer() -> i32 { 1 }\n.import toolkit\nanswer()\n"),
+        "items of session `toolkit` conflict with those of this session\n1\n");
+}
+
+#[test]
+fn test_quit() {
+    assert_eq!(repl_input("1\n.quit\n2\n"), "1\n");
+
+    let out = repl_input("#[derive(Debug)] struct D;\n\
+        impl Drop for D { fn drop(&mut self) { println!(\"dropped\"); } }\n\
+        D\n");
+
+    assert_eq!(out, "D\ndropped\n");
+
+    // Values of sessions other than the current one are also dropped
+    let out = repl_input(".session new other\n#[derive(Debug)] struct D(u8);\n\
+        impl Drop for D { fn drop(&mut self) { println!(\"dropped {}\", self.0); } }\n\
+        D(1)\n.session switch main\n");
+
+    assert_eq!(out, "D(1)\ndropped 1\n");
+}
+
+#[test]
+fn test_batch() {
+    let out = repl_run_input(&["--batch"], "let a = 1;\n---\n.set opt-level 1\na + 1\n---\na(\n---\na\n");
+
+    assert_eq!(out, "[1] ok\n[2] ok\n2\n[3] failed to compile\n");
+
+    let mut cmd = rusti_cmd().args(&["--batch"]).stdin(Stdio::piped()).stdout(Stdio::null())
+        .stderr(Stdio::null()).spawn().unwrap();
+
+    cmd.stdin.as_mut().unwrap().write_all(b"panic!()\n---\n1\n").unwrap();
+    assert_eq!(cmd.wait().unwrap().code(), Some(1));
+}
+
+#[test]
+fn test_script() {
+    let cache = std::env::temp_dir().join("rusti-test-script-cache");
+    let _ = std::fs::remove_dir_all(&cache);
+
+    for _ in 0..2 {
+        let out = rusti_cmd().args(&["data/test_script.rs", "a", "--b"])
+            .env("XDG_CACHE_HOME", &cache).output().unwrap();
+
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), "a --b\n");
+        assert_eq!(out.status.code(), Some(3));
+    }
+
+    assert_eq!(std::fs::read_dir(cache.join("rusti/scripts")).unwrap().count(), 1);
+    std::fs::remove_dir_all(&cache).unwrap();
+}
+
+#[test]
+fn test_export_md() {
+    use std::io::Read;
+
+    let path = std::env::temp_dir().join("rusti-test-export.md");
+    let out = repl_input(&format!("println!(\"hi\"); 1\n.set opt-level 1\n\
+        .export-md {}\n", path.display()));
+
+    assert_eq!(out, format!("hi\n1\nwrote 2 inputs to {}\n", path.display()));
+
+    let mut text = String::new();
+    std::fs::File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(text.starts_with("# rusti session\n"));
+    assert!(text.contains("\n```rust\nprintln!(\"hi\"); 1\n```\n\n> ```text\n> hi\n> 1\n> ```\n"));
+    assert!(text.contains("\n```text\n.set opt-level 1\n```\n"));
+}
+
+#[test]
+fn test_notebook() {
+    use std::io::Read;
+
+    let path = std::env::temp_dir().join("rusti-test-notebook.ipynb");
+    let out = repl_input(&format!("let a = 20;\nprintln!(\"hi\"); a + 1\n\
+        .export-ipynb {}\n", path.display()));
+
+    assert_eq!(out, format!("hi\n21\nwrote 2 cells to {}\n", path.display()));
+
+    let out = repl_run(&["--no-rc", "--run", path.to_str().unwrap()]);
+
+    assert_eq!(out, format!("ran 2 cells of {}\n", path.display()));
+
+    let mut text = String::new();
+    std::fs::File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(text.contains("\"nbformat\": 4"));
+    assert!(text.contains("\"text/plain\": [\n"));
+    assert!(text.contains("\"21\""));
+    assert!(text.contains("\"hi\\n\""));
+}
+
+#[test]
+fn test_verify() {
+    use std::io::Read;
+
+    let path = std::env::temp_dir().join("rusti-test-verify.md");
+    let path_str = path.to_str().unwrap();
+
+    repl_input(&format!("let a = 20;\nprintln!(\"{{:p}}\", &a); a + 1\n.export-md {}\n", path_str));
+
+    assert_eq!(repl_run(&["--no-rc", "--verify", path_str]), "verified 2 inputs; 0 differ\n");
+
+    let mut text = String::new();
+    std::fs::File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+    std::fs::File::create(&path).unwrap()
+        .write_all(text.replace("> 21\n", "> 22\n").as_bytes()).unwrap();
+
+    let out = rusti_cmd().args(&["--no-rc", "--verify", path_str]).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8(out.stdout).unwrap().ends_with(
+        "- 22\n+ 21\nverified 2 inputs; 1 differ\n"));
+}
+
+#[test]
+fn test_exercise() {
+    let dir = std::env::temp_dir().join("rusti-test-exercises");
+    let dir_str = dir.to_str().unwrap();
+
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::File::create(dir.join("double.md")).unwrap()
+        .write_all(b"Write `double`.\n\n```rust\nfn double(x: i32) -> i32 {\n}\n```\n\n\
+            ```check\ndouble(21)\n```\n\n```output\n42\n```\n").unwrap();
+
+    let prompt = "exercise `double`\n\nWrite `double`.\n\n    fn double(x: i32) -> i32 {\n    }\n";
+
+    assert_eq!(repl_run_input(&["--no-rc", "--exercise", dir_str],
+        "fn double(x: i32) -> i32 { x * 2 }\n.submit\n"),
+        format!("{}exercise `double` passed\nall exercises passed\n", prompt));
+
+    assert_eq!(repl_run_input(&["--no-rc", "--exercise", dir_str],
+        "fn double(x: i32) -> i32 { x }\n.submit\n"),
+        format!("{}exercise `double` failed: output differs:\n- 42\n+ 21\n", prompt));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_build_base() {
+    let out = rusti_cmd().args(&["--no-rc", "--build-base"]).output().unwrap();
+
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8(out.stdout).unwrap().ends_with(": no prelude is configured\n"));
+}
+
+#[test]
+fn test_doc() {
+    assert!(repl_cmd(".doc std::mem::swap").starts_with("`std::mem::swap`\n================\n"));
+    assert!(repl_input("/// Doubles a number.\nfn double(x: i32) -> i32 { x * 2 }\n.doc double\n")
+        .contains("Doubles a number."));
+    assert!(repl_input("/// Doubles a number.\nfn double(x: i32) -> i32 { x * 2 }\n\
+        .doc --open double\n").contains("has no HTML documentation; showing it here\n"));
+    assert_eq!(repl_cmd(".doc no::such::item"), "no item `no::such::item`\n");
+    assert_eq!(repl_cmd(".doc"), "command `doc` expects a path, such as `std::vec::Vec`\n");
+}
+
+#[test]
+fn test_search() {
+    assert_eq!(repl_cmd(".search"), "command `search` expects a query or the number of a result\n");
+    assert_eq!(repl_cmd(".search 1"), "no search result 1\n");
+    assert_eq!(repl_run_input(&["--no-rc", "--offline"], ".search regex\n"),
+        "crates.io cannot be searched with --offline\n");
+}
+
+#[test]
+fn test_src() {
+    assert_eq!(repl_input("fn double(x: i32) -> i32 { x * 2 }\n.src double\n"),
+        "fn double(x: i32) -> i32 { x * 2 }\n");
+    assert_eq!(repl_cmd(".src no::such::item"), "no item `no::such::item`\n");
+    assert_eq!(repl_cmd(".src"), "command `src` expects a path, such as `std::vec::Vec::push`\n");
+}
+
+#[test]
+fn test_copy() {
+    assert_eq!(repl_cmd(".copy"), "no input to copy\n");
+    assert_eq!(repl_cmd(".copy last"), "command `copy` expects `input`, `output` or a number\n");
+    assert_eq!(repl_cmd(".copy 1000000"), "no input numbered `1000000` in history\n");
+}
+
+#[test]
+fn test_jobs() {
+    assert!(repl_run_input(&["--no-rc", "-j", "2"], ".set\n").contains("  jobs             2\n"));
+    assert_eq!(repl_cmd(".set jobs 0"), "invalid value for `jobs`: expected a positive number\n");
+}
+
+#[test]
+fn test_rustc_wrapper() {
+    assert!(repl_input(".set rustc-wrapper sccache\n.set\n")
+        .contains("  rustc-wrapper    sccache\n"));
+    assert!(repl_input(".set rustc-wrapper none\n.set\n").contains("  rustc-wrapper    none\n"));
+}
+
+#[test]
+fn test_target_dir() {
+    let dir = std::env::temp_dir().join("rusti-test-target-dir");
+
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::File::create(dir.join("rusti-old")).unwrap().write_all(b"1234").unwrap();
+
+    let out = rusti_cmd().env("RUSTI_TARGET_DIR", &dir)
+        .args(&["--no-rc", "-c", ".gc 0"]).output().unwrap();
+
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "removed 1 files, 4 bytes\n");
+    assert!(!dir.join("rusti-old").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}